@@ -1,14 +1,15 @@
-use crate::error::report_accom_warning;
+use crate::error::{
+  report_accom_warning, MizError, report_dropped_notations, report_redundant_constructors,
+  report_vocabulary_conflict,
+};
 use crate::parser::{catch_missing, ParseError, PathResult};
-use crate::reader::DefiniensId;
 use crate::types::*;
-use crate::{mk_id, CmpStyle, MizPath, VisitMut};
+use crate::{mk_id, outln, CmpStyle, MizPath, VisitMut};
 use std::collections::HashMap;
-use std::io;
+use std::path::{Path, PathBuf};
 
 mk_id! {
   VocId(u32),
-  SigId(u32),
 }
 
 #[derive(Debug, Default)]
@@ -46,12 +47,6 @@ impl VocBuilder {
   }
 }
 
-#[derive(Debug, Default)]
-pub struct SigBuilder {
-  pub sig: IdxVec<SigId, (Article, ConstructorsBase)>,
-  pub base: ConstructorsBase,
-}
-
 impl SigBuilder {
   fn push(&mut self, constrs: Option<&mut Constructors>, art: Article) -> PathResult<SigId> {
     let mut dco = Default::default();
@@ -96,10 +91,6 @@ impl SigBuilder {
     }
   }
 
-  pub fn hi(&self, id: SigId) -> &ConstructorsBase {
-    self.sig.get(SigId(id.0 + 1)).map_or(&self.base, |(_, base)| base)
-  }
-
   fn rename<'a>(
     &mut self, sig: &[Article], ctx: Option<&'a Constructors>,
   ) -> PathResult<RenameConstr<'a>> {
@@ -122,13 +113,15 @@ impl SigBuilder {
 
 #[derive(Debug, Default)]
 pub struct Accomodator {
-  article: Article,
+  pub article: Article,
   pub dirs: Directives,
   pub sig: SigBuilder,
   pub articles_vec: IdxVec<ArticleId, Option<Article>>,
   pub articles: HashMap<Article, ArticleId>,
   dict: VocBuilder,
   pub has_errors: bool,
+  /// Warn about directives that have no effect, or whose notations are dropped
+  pub warn_directives: bool,
 }
 
 #[derive(Debug, Default)]
@@ -252,29 +245,64 @@ impl Accomodator {
     }
   }
 
-  /// ProcessVocabularies
+  /// Reports the symbols of `voc`, read from the local vocabulary file at `path`,
+  /// on which `mml.vct` disagrees
+  fn check_local_vocabulary(path: &Path, art: Article, mml_vct: &[u8], voc: &Vocabulary<'_>) {
+    let mut mml = Default::default();
+    if !matches!(art.read_vct(mml_vct, &mut mml), Ok(true)) {
+      return
+    }
+    for SymbolData { kind, token } in &voc.symbols {
+      for sym in mml.symbols.iter().filter(|sym| sym.token == *token) {
+        if sym.kind != *kind {
+          report_vocabulary_conflict(path, art, token, kind, &sym.kind)
+        }
+      }
+    }
+  }
+
+  /// ProcessVocabularies. `local_vcts` are the contents of the local vocabulary files,
+  /// in the order they are searched: the article-local `<article>.vct`, then the `mml.vct`
+  /// of each `--vocabulary-dir`. They have the same format as `mml.vct`, and a vocabulary
+  /// found in one of them takes precedence over the one in `mml.vct`.
   pub fn accom_symbols<'a>(
-    &mut self, mml_vct: &'a [u8], syms: &mut Symbols, priority: &mut Vec<(PriorityKind, u32)>,
+    &mut self, mml_vct: &'a [u8], local_vcts: &'a [(PathBuf, Vec<u8>)], syms: &mut Symbols,
+    priority: &mut Vec<(PriorityKind, u32)>,
     mut infinitives: Option<&mut Vec<(PredSymId, &'a str)>>,
   ) {
     #[allow(clippy::indexing_slicing)]
-    for &(_, art) in &self.dirs.0[DirectiveKind::Vocabularies] {
+    'next: for &(_, art) in &self.dirs.0[DirectiveKind::Vocabularies] {
       let mut voc = Default::default();
-      match art.read_vct(mml_vct, &mut voc) {
-        Ok(true) => {}
-        Ok(false) => {
-          // TODO: private vocabularies
-          println!(
-            "error: {}: vocabulary for {art} not found (TODO: private vocabularies)",
-            crate::mml_vct_path()
-          );
-          self.has_errors = true;
-          continue
+      let mut local = None;
+      for (path, buf) in local_vcts {
+        match art.read_vct(buf, &mut voc) {
+          Ok(false) => {}
+          Ok(true) => {
+            local = Some(path);
+            break
+          }
+          Err(e) => {
+            e.report(path);
+            self.has_errors = true;
+            continue 'next
+          }
         }
-        Err(e) => {
-          e.report(crate::mml_vct_path().as_ref());
-          self.has_errors = true;
-          continue
+      }
+      if let Some(path) = local {
+        Self::check_local_vocabulary(path, art, mml_vct, &voc)
+      } else {
+        match art.read_vct(mml_vct, &mut voc) {
+          Ok(true) => {}
+          Ok(false) => {
+            outln!("error: {}: vocabulary for {art} not found", crate::mml_vct_path());
+            self.has_errors = true;
+            continue
+          }
+          Err(e) => {
+            e.report(crate::mml_vct_path().as_ref());
+            self.has_errors = true;
+            continue
+          }
         }
       }
       let hidden = self.dict.voc.is_empty();
@@ -327,8 +355,11 @@ impl Accomodator {
 
   /// ProcessConstructors
   #[allow(clippy::indexing_slicing)]
-  pub fn accom_constructors(&mut self, constrs: &mut Constructors) -> io::Result<()> {
+  pub fn accom_constructors(&mut self, constrs: &mut Constructors) -> Result<(), MizError> {
     for &(pos, art) in &self.dirs.0[DirectiveKind::Constructors] {
+      if self.warn_directives && self.sig.sig.0.iter().any(|p| p.0 == art) {
+        report_redundant_constructors(self.article, pos, art)
+      }
       let mut dco = Default::default();
       let result = MizPath { art }.read_dco(false, &mut dco, true);
       if try_p!(self, pos => Constructors, result).is_none() {
@@ -348,7 +379,7 @@ impl Accomodator {
   #[allow(clippy::indexing_slicing)]
   pub fn accom_requirements(
     &mut self, ctx: &Constructors, idx: &mut RequirementIndexes,
-  ) -> io::Result<()> {
+  ) -> Result<(), MizError> {
     for &(pos, art) in &self.dirs.0[DirectiveKind::Requirements] {
       let mut dre = Default::default();
       if try_p!(self, pos => Requirements, MizPath { art }.read_dre(&mut dre)).is_none() {
@@ -368,7 +399,9 @@ impl Accomodator {
 
   /// ProcessClusters
   #[allow(clippy::indexing_slicing)]
-  pub fn accom_clusters(&mut self, ctx: &Constructors, clusters: &mut Clusters) -> io::Result<()> {
+  pub fn accom_clusters(
+    &mut self, ctx: &Constructors, clusters: &mut Clusters,
+  ) -> Result<(), MizError> {
     for &(_, art) in &self.dirs.0[DirectiveKind::Registrations] {
       let mut dcl = Default::default();
       let result = MizPath { art }.read_dcl(false, &mut dcl);
@@ -403,7 +436,7 @@ impl Accomodator {
   pub fn accom_notations(
     &mut self, fmt_map: &mut HashMap<Format, FormatId>, mut fmts: Option<&mut Formats>,
     pats: &mut Vec<Pattern>,
-  ) -> io::Result<()> {
+  ) -> Result<(), MizError> {
     if let Some(fmts) = &mut fmts {
       assert_eq!(fmts.formats.push(Format::Attr(FormatAttr::STRICT)), FormatId::STRICT);
       fmt_map.insert(Format::Attr(FormatAttr::STRICT), FormatId::STRICT);
@@ -420,6 +453,7 @@ impl Accomodator {
       }
       let sig_len = self.sig.sig.len();
       let Some(mut rename) = try_p!(self, self.sig.rename(&dno.sig, None)) else { continue };
+      let mut dropped = 0;
       for Pattern { article, abs_nr, mut kind, mut fmt, mut primary, visible, pos } in dno.pats {
         fmt.visit_mut(|k, c| s_rename.apply(k, c));
         if s_rename.ok() {
@@ -431,10 +465,16 @@ impl Accomodator {
             primary.visit(&mut rename);
             if rename.ok() {
               pats.push(Pattern { article, abs_nr, kind, fmt, primary, visible, pos })
+            } else {
+              dropped += 1
             }
           }
         }
       }
+      if self.warn_directives && dropped != 0 {
+        let missing = self.sig.sig.0[sig_len..].iter().map(|p| p.0).collect::<Vec<_>>();
+        report_dropped_notations(self.article, pos, art, dropped, &missing)
+      }
       self.dict.truncate(dict_len);
       self.sig.truncate(sig_len);
     }
@@ -446,7 +486,7 @@ impl Accomodator {
   #[allow(clippy::indexing_slicing)]
   pub fn accom_identify_regs(
     &mut self, ctx: &Constructors, ids: &mut Vec<IdentifyFunc>,
-  ) -> io::Result<()> {
+  ) -> Result<(), MizError> {
     for &(_, art) in &self.dirs.0[DirectiveKind::Registrations] {
       let (mut sig, mut did) = Default::default();
       let result = MizPath { art }.read_did(false, &mut sig, &mut did);
@@ -468,7 +508,7 @@ impl Accomodator {
   #[allow(clippy::indexing_slicing)]
   pub fn accom_reduction_regs(
     &mut self, ctx: &Constructors, reds: &mut Vec<Reduction>,
-  ) -> io::Result<()> {
+  ) -> Result<(), MizError> {
     for &(_, art) in &self.dirs.0[DirectiveKind::Registrations] {
       let (mut sig, mut drd) = Default::default();
       let result = MizPath { art }.read_drd(false, &mut sig, &mut drd);
@@ -490,7 +530,7 @@ impl Accomodator {
   #[allow(clippy::indexing_slicing)]
   pub fn accom_properties(
     &mut self, ctx: &Constructors, props: &mut Vec<Property>,
-  ) -> io::Result<()> {
+  ) -> Result<(), MizError> {
     for &(_, art) in &self.dirs.0[DirectiveKind::Registrations] {
       let (mut sig, mut dpr) = Default::default();
       let result = MizPath { art }.read_dpr(false, &mut sig, &mut dpr);
@@ -512,7 +552,7 @@ impl Accomodator {
   #[allow(clippy::indexing_slicing)]
   pub fn accom_definitions(
     &mut self, ctx: &Constructors, kind: DirectiveKind, defs: &mut Vec<Definiens>,
-  ) -> io::Result<()> {
+  ) -> Result<(), MizError> {
     for &(pos, art) in &self.dirs.0[kind] {
       let (mut sig, mut def) = Default::default();
       let result = MizPath { art }.read_def(false, &mut sig, &mut def);
@@ -535,7 +575,7 @@ impl Accomodator {
   pub fn accom_theorems(
     &mut self, write_eth: bool, ctx: &Constructors, def_map: &mut HashMap<DefRef, DefiniensId>,
     libs: &mut Libraries,
-  ) -> io::Result<()> {
+  ) -> Result<(), MizError> {
     let mut w = write_eth.then(|| MizPath { art: self.article }.write_eth());
     let mut defthms = DefiniensId::default();
     for &(pos, art) in &self.dirs.0[DirectiveKind::Theorems] {
@@ -585,7 +625,7 @@ impl Accomodator {
   #[allow(clippy::indexing_slicing)]
   pub fn accom_schemes(
     &mut self, write_esh: bool, ctx: &Constructors, libs: &mut Libraries,
-  ) -> io::Result<()> {
+  ) -> Result<(), MizError> {
     let mut w = write_esh.then(|| MizPath { art: self.article }.write_esh());
     for &(pos, art) in &self.dirs.0[DirectiveKind::Schemes] {
       let mut schs = Default::default();
@@ -615,3 +655,69 @@ impl Accomodator {
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ast;
+  use crate::parser::MizParser;
+
+  /// Parses `a + b * c` with the vocabulary `testvoc`, which `mml.vct` gives
+  /// the usual priorities, and returns the symbol at the root of the parse tree
+  fn root_symbol(local_vcts: &[(PathBuf, Vec<u8>)]) -> String {
+    let mml_vct = b"#TESTVOC\nG0 K0 L0 M0 O2 R0 U0 V0 \nO+ 64\nO* 70\n";
+    let art = Article::from_lower(b"testvoc").unwrap();
+    let mut accom = Accomodator::default();
+    accom.dirs.0[DirectiveKind::Vocabularies].push((Position::default(), art));
+    let (mut syms, mut prio) = Default::default();
+    accom.accom_symbols(mml_vct, local_vcts, &mut syms, &mut prio, None);
+    assert!(!accom.has_errors);
+    let mut p = MizParser::new(art, None, b"", Default::default());
+    p.load_symbols(&syms, &[], &prio);
+    for &(kind, _) in &syms {
+      if let SymbolKind::Func(sym) = kind {
+        let fmt = FormatFunc::Func { sym, left: 1, right: 1 };
+        p.push_format(Position::default(), Format::Func(fmt))
+      }
+    }
+    match *p.parse_standalone_term(b"a + b * c").unwrap() {
+      ast::Term::Infix { sym: (_, spelling), .. } => spelling,
+      ref t => panic!("expected an infix term, got {t:?}"),
+    }
+  }
+
+  #[test]
+  fn local_vocabulary_priority() {
+    assert_eq!(root_symbol(&[]), "+");
+    let local = b"#TESTVOC\nG0 K0 L0 M0 O2 R0 U0 V0 \nO+ 64\nO* 32\n".to_vec();
+    assert_eq!(root_symbol(&[("testvoc.vct".into(), local)]), "*");
+  }
+
+  /// The `.sgl` and `.aco` files written for a signature read back to the same articles,
+  /// counts and constructors.
+  #[test]
+  fn sgl_aco_round_trip() {
+    let constrs = crate::replay::test_env().g.constrs;
+    let mut sig = SigBuilder::default();
+    let hidden = ConstructorsBase { mode: 2, predicate: 2, ..Default::default() };
+    let art = Article::from_lower(b"roundtrip").unwrap();
+    for (art, counts) in [(Article::HIDDEN, hidden), (art, constrs.len() - hidden)] {
+      let mut dco = DepConstructors { counts, ..Default::default() };
+      sig.push_from(None, art, &mut dco);
+    }
+    assert_eq!(sig.base, constrs.len());
+    let dir = std::env::temp_dir();
+    let path = |ext| dir.join(format!("mizar-rs-{}-roundtrip.{ext}", std::process::id()));
+    crate::write::write_sgl_to(std::fs::File::create(path("sgl")).unwrap(), &sig);
+    crate::write::write_aco_to(std::fs::File::create(path("aco")).unwrap(), &sig, &constrs);
+    let (mut sgl, mut aco) = (vec![], AccumConstructors::default());
+    crate::parser::read_sgl_from(path("sgl"), &mut sgl).unwrap();
+    crate::parser::read_aco_from(path("aco"), &mut aco).unwrap();
+    std::fs::remove_file(path("sgl")).unwrap();
+    std::fs::remove_file(path("aco")).unwrap();
+    assert_eq!(sgl, [Article::HIDDEN, art]);
+    assert_eq!(aco.sig.sig, sig.sig);
+    assert_eq!(aco.sig.base, sig.base);
+    assert_eq!(aco.constrs, constrs);
+  }
+}
@@ -1,19 +1,60 @@
 use crate::ast::{
   CaseKind, FormulaBinder, FormulaBinop, Pragma, PrivFuncKind, PrivPredKind, ResGroupId,
-  ReservedId, VarKind,
+  FraenkelNameckResult, NameckScope, ReservedId, VarKind,
 };
-use crate::error::MizError;
+use crate::equate::Footprint;
+use crate::error::{ArticlePos, MizError, SkeletonStep};
 use crate::export::Exporter;
 use crate::parser::{MizParser, MsmParser, PathResult};
-use crate::reader::{DefiniensId, Reader};
+use crate::reader::Reader;
 use crate::types::{PatternKindClass as PKC, *};
 use crate::write::OWriteXml;
 use crate::*;
+use enum_map::EnumMap;
+use itertools::Itertools;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::ops::Range;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 const MAX_EXPANSIONS: usize = 20;
 
+/// Receives item-level progress notifications from [`Reader::run_analyzer`].
+/// All methods do nothing by default.
+pub trait ProgressSink {
+  /// Called before top level item number `idx` is elaborated.
+  fn on_item_start(&mut self, _idx: usize, _it: &ast::Item) {}
+  /// Called after top level item number `idx` is elaborated.
+  /// `failed` is true if the item reported any errors.
+  fn on_item_done(&mut self, _idx: usize, _failed: bool, _elapsed: Duration) {}
+  /// Called after `on_item_done`, with the largest tables the equalizer built
+  /// for the inferences of the item (all zero if the checker did not run).
+  fn on_item_footprint(&mut self, _idx: usize, _fp: &Footprint) {}
+  /// Called at the end of the article, with the number of top level items.
+  fn on_article_done(&mut self, _art: Article, _items: usize, _has_errors: bool) {}
+}
+
+impl ProgressSink for () {}
+
+/// A [`ProgressSink`] which prints a line to stderr for each top level item.
+#[derive(Default)]
+pub struct StderrProgress(Position);
+
+impl ProgressSink for StderrProgress {
+  fn on_item_start(&mut self, _: usize, it: &ast::Item) { self.0 = it.pos }
+
+  fn on_item_done(&mut self, idx: usize, failed: bool, elapsed: Duration) {
+    let status = if failed { "failed" } else { "ok" };
+    eprintln!("item {idx} at {:?}: {status} in {:.3}s", self.0, elapsed.as_secs_f32())
+  }
+
+  fn on_article_done(&mut self, art: Article, items: usize, has_errors: bool) {
+    let status = if has_errors { "failed" } else { "ok" };
+    eprintln!("{art}: {items} items, {status}")
+  }
+}
+
 #[derive(Clone, Default, Debug)]
 struct NameLookup {
   /// does not contain VarKind::Reserved
@@ -22,6 +63,22 @@ struct NameLookup {
   pred: im::HashMap<(Rc<str>, u32), PrivPredKind>,
 }
 
+impl NameLookup {
+  /// Returns true if the variables of a term built from functors, selectors and aggregates
+  /// are all in scope, so that it can be elaborated here. Used by `--explain-neq`.
+  fn resolves(&self, tm: &ast::Term) -> bool {
+    match tm {
+      ast::Term::Var { kind, spelling, .. } => kind.is_some() || self.var.contains_key(&**spelling),
+      ast::Term::Numeral { .. } => true,
+      ast::Term::Infix { args, .. }
+      | ast::Term::Bracket { args, .. }
+      | ast::Term::Aggregate { args, .. } => args.iter().all(|tm| self.resolves(tm)),
+      ast::Term::Selector { arg, .. } => self.resolves(arg),
+      _ => false,
+    }
+  }
+}
+
 #[derive(Debug)]
 struct ResGroup {
   ty: Type,
@@ -56,6 +113,8 @@ pub struct Analyzer<'a> {
   pub notations_base: EnumMap<PatternKindClass, u32>,
   write_xml: OWriteXml,
   pub export: Exporter,
+  /// The terms of `--explain-neq`, parsed with the formats of the current item
+  explain_neq_ast: Option<Box<[ast::Term; 2]>>,
 }
 impl<'a> std::ops::Deref for Analyzer<'a> {
   type Target = &'a mut Reader;
@@ -72,6 +131,7 @@ macro_rules! try_p {
       Err((path, e)) => {
         e.report(&path);
         $self.has_errors = true;
+        $self.error_count += 1;
         return
       }
     }
@@ -96,7 +156,34 @@ impl Reader {
     }
   }
 
-  pub fn run_analyzer(&mut self, path: &MizPath, parser: Option<&mut MizParser<'_>>) {
+  /// Parses the terms of `--explain-neq`, which is only supported when reading a .miz file.
+  /// This is done once, with the symbols of the environment, and errors are reported.
+  fn parse_explain_neq(
+    &mut self, parser: &mut Result<&mut MizParser<'_>, MsmParser>,
+  ) -> Option<Box<[ast::Term; 2]>> {
+    let (t1, t2) = self.g.cfg.explain_neq.as_ref()?;
+    let Ok(parser) = parser else { return None };
+    std::mem::swap(&mut parser.formats, &mut self.lc.formatter.formats);
+    std::mem::swap(&mut parser.format_lookup, &mut self.formats);
+    let res = [t1, t2].map(|tm| (tm, parser.parse_standalone_term(tm.as_bytes())));
+    std::mem::swap(&mut self.lc.formatter.formats, &mut parser.formats);
+    std::mem::swap(&mut self.formats, &mut parser.format_lookup);
+    match res {
+      [(_, Ok(t1)), (_, Ok(t2))] => Some(Box::new([*t1, *t2])),
+      res => {
+        for (tm, why) in res.iter().filter_map(|(tm, r)| Some((tm, r.as_ref().err()?))) {
+          crate::error::report_bad_explain_neq(self.article, tm, why)
+        }
+        self.has_errors = true;
+        self.error_count += 1;
+        None
+      }
+    }
+  }
+
+  pub fn run_analyzer(
+    &mut self, path: &MizPath, parser: Option<&mut MizParser<'_>>, sink: &mut dyn ProgressSink,
+  ) {
     let mut parser = match parser {
       Some(parser) => {
         parser.write_json.on(|w| w.start_main());
@@ -138,6 +225,7 @@ impl Reader {
       notations_base: Default::default(),
       export: Default::default(),
       write_xml,
+      explain_neq_ast: None,
     };
     #[allow(clippy::indexing_slicing)]
     for (i, pats) in &elab.r.notations {
@@ -149,19 +237,28 @@ impl Reader {
     if elab.g.cfg.exporter_enabled {
       elab.export.clusters_base = elab.g.clusters.len();
       elab.export.definitions_base = elab.definitions.peek();
-      elab.export.identify_base = elab.identify.len() as u32;
-      elab.export.reductions_base = elab.reductions.len() as u32;
+      elab.export.identify_base = elab.session.identify.len() as u32;
+      elab.export.reductions_base = elab.session.reductions.len() as u32;
       elab.export.properties_base = elab.properties.len() as u32;
     }
+    let mut idx = 0;
+    elab.explain_neq_ast = elab.r.parse_explain_neq(&mut parser);
     while try_p!(elab, elab.r.push_parse_item(&mut parser, &mut items)) {
       for it in items.iter_mut() {
         if elab.g.cfg.top_item_header {
           eprintln!("item {:?}: {:?}", it.pos, it.kind);
         }
+        sink.on_item_start(idx, it);
+        let _loc = ArticlePos { article: elab.article, item: Some(idx), pos: it.pos }.enter();
+        let (start, errors) = (Instant::now(), elab.error_count);
         elab.elab_top_item(it);
+        sink.on_item_done(idx, elab.error_count > errors, start.elapsed());
+        sink.on_item_footprint(idx, &std::mem::take(&mut elab.lc.footprint));
+        idx += 1;
       }
       items.clear()
     }
+    sink.on_article_done(elab.article, idx, elab.has_errors);
     if elab.g.cfg.json_parse {
       if let Ok(parser) = &mut parser {
         parser.write_json.finish();
@@ -186,6 +283,9 @@ impl Reader {
     if elab.g.cfg.exporter_enabled {
       elab.export()
     }
+    if elab.g.cfg.analyze_only {
+      elab.print_statements()
+    }
   }
 }
 
@@ -315,6 +415,30 @@ impl Analyzer<'_> {
   }
 
   fn elab_top_item(&mut self, it: &mut ast::Item) {
+    let saved_cfg = match it.kind {
+      ast::ItemKind::Pragma(_) => None,
+      _ => self.apply_item_options(),
+    };
+    self.elab_top_item_inner(it);
+    if let Some(cfg) = saved_cfg {
+      self.g.cfg = cfg
+    }
+  }
+
+  /// Applies the options of the `::$name=value` pragmas before the current item,
+  /// returning the configuration to restore after it.
+  fn apply_item_options(&mut self) -> Option<Config> {
+    if self.item_options.is_empty() {
+      return None
+    }
+    let saved = self.g.cfg.clone();
+    for (name, value) in std::mem::take(&mut self.item_options) {
+      self.g.cfg.set_option(&name, &value).unwrap()
+    }
+    Some(saved)
+  }
+
+  fn elab_top_item_inner(&mut self, it: &mut ast::Item) {
     match &it.kind {
       // ast::ItemKind::Section { .. } => self.item_header(it, "Section"),
       ast::ItemKind::Pragma { .. } => self.item_header(it, "Pragma"),
@@ -354,7 +478,7 @@ impl Analyzer<'_> {
           self.push_prop(label, f)
         }
         assert!(self.reserved_extra_depth == 0);
-        if self.g.cfg.exporter_enabled {
+        if self.collects_theorems() {
           self.export.theorems.push(Theorem { pos: it.pos, kind: TheoremKind::Thm, stmt: f })
         }
       }
@@ -413,6 +537,11 @@ impl Analyzer<'_> {
         match *pragma {
           Pragma::Canceled(k, n) => self.elab_canceled(it.pos, k, n),
           Pragma::SetVerify(b) => self.no_suppress_checker = b,
+          Pragma::SetOption(ref name, ref value) =>
+            match self.g.cfg.clone().set_option(name, value) {
+              Ok(()) => self.item_options.push((name.clone(), value.clone())),
+              Err(why) => crate::error::report_ignored_option(self.article, it.pos, name, &why),
+            },
           Pragma::ThmDesc(_) | Pragma::Insert(_) => {}
           // This is intentionally stricter than necessary to ensure that MML has no weird
           // pragmas. The line below should be uncommented to allow pragmas for general use.
@@ -857,7 +986,19 @@ impl Analyzer<'_> {
           refs: self.elab_references(refs),
         };
         self.write_xml.on(|w| w.write_inference(&it));
-        self.r.read_inference(thesis, &it)
+        if let Some(tms) = self.explain_neq_ast.take() {
+          if tms.iter().all(|tm| self.lookup.resolves(tm)) {
+            self.r.explain_neq = Some(Box::new(tms.each_ref().map(|tm| self.elab_term(tm))))
+          }
+          self.explain_neq_ast = Some(tms)
+        }
+        self.r.reserved_consts.extend(self.reserved_lookup.values().filter_map(|k| match *k {
+          VarKind::Const(c) => Some(c),
+          _ => None,
+        }));
+        self.r.read_inference(thesis, &it);
+        self.r.explain_neq = None;
+        self.r.reserved_consts.clear()
       }
       ast::Justification::Block { pos, items } =>
         self.elab_proof_intro_reserved(label, thesis, items, *pos, block),
@@ -868,6 +1009,14 @@ impl Analyzer<'_> {
     &mut self, mut cc: CorrConds, conds: &mut [ast::CorrCond], corr: &mut Option<ast::Correctness>,
   ) {
     if self.g.cfg.analyzer_full {
+      let in_corr_cond = std::mem::replace(&mut self.r.in_corr_cond, true);
+      if self.g.cfg.corr_conds {
+        for (kind, f) in &cc.0 {
+          if let Some(f) = f {
+            vprintln!("{kind:?} condition: {f:?}")
+          }
+        }
+      }
       for cond in conds {
         let mut thesis = cc.0[cond.kind].take().unwrap();
         self.write_xml.on(|w| {
@@ -896,6 +1045,7 @@ impl Analyzer<'_> {
         self.write_xml.on(|w| w.skip_correctness());
       }
       assert!(cc.0.iter().all(|p| p.1.is_none()));
+      self.r.in_corr_cond = in_corr_cond
     }
   }
 
@@ -932,12 +1082,33 @@ impl Analyzer<'_> {
     }
   }
 
+  /// Whether the theorems of the article are collected in `export.theorems`
+  fn collects_theorems(&self) -> bool { self.g.cfg.exporter_enabled || self.g.cfg.analyze_only }
+
+  /// Prints the theorems collected for `--analyze-only`, as one JSON object per line.
+  fn print_statements(&self) {
+    let mut w = crate::testing::JsonLines::stdout();
+    let thms = &self.export.theorems;
+    for (name, thm) in crate::export::theorem_names(thms).zip(thms) {
+      let canceled = matches!(thm.kind, TheoremKind::CanceledThm | TheoremKind::CanceledDef);
+      let stmt = (!canceled).then(|| self.lc.pp(&thm.stmt).to_string());
+      let entry = serde_json::json!({
+        "article": self.article.as_str(),
+        "name": name,
+        "line": thm.pos.line,
+        "col": thm.pos.col,
+        "statement": stmt,
+      });
+      w.write(&entry).unwrap()
+    }
+  }
+
   fn elab_canceled(&mut self, pos: Position, kind: CancelKind, n: u32) {
     match kind {
       CancelKind::Def => {
         // canceled defs outside a block don't create a DefTheorem, so they don't increment self.defthms
         // self.defthms.0 += n;
-        if self.g.cfg.exporter_enabled {
+        if self.collects_theorems() {
           self.export.theorems.extend((0..n).map(|_| Theorem {
             pos,
             kind: TheoremKind::CanceledDef,
@@ -946,7 +1117,7 @@ impl Analyzer<'_> {
         }
       }
       CancelKind::Thm =>
-        if self.g.cfg.exporter_enabled {
+        if self.collects_theorems() {
           self.export.theorems.extend((0..n).map(|_| Theorem {
             pos,
             kind: TheoremKind::CanceledThm,
@@ -1092,7 +1263,7 @@ impl Analyzer<'_> {
           Some(kind) => self.elab_var_kind(kind),
           None => panic!("{pos:?}: unresolved variable '{spelling}'"),
         },
-      ast::Term::Numeral { value, .. } => Term::Numeral(value),
+      ast::Term::Numeral { ref value, .. } => Term::Numeral(value.clone()),
       ast::Term::Infix { ref sym, left, ref args, .. } => self.elab_functor_term(
         FormatFunc::Func { sym: sym.0, left, right: args.len() as u8 - left },
         args,
@@ -1139,7 +1310,7 @@ impl Analyzer<'_> {
         }
         panic!("type error")
       }
-      ast::Term::SubAggr { ref sym, ref arg, .. } => {
+      ast::Term::SubAggr { pos, ref sym, ref arg } => {
         let arg = self.elab_term_qua(arg);
         let fmt = self.formats[&Format::SubAggr(sym.0)];
         for pat in self.notations[PKC::SubAggr].iter().rev() {
@@ -1148,7 +1319,15 @@ impl Analyzer<'_> {
           {
             let PatternKind::SubAggr(nr) = pat.kind else { unreachable!() };
             let ty = arg.get_type_uncached(&self.g, &self.lc);
-            return Term::mk_aggr(&self.g, &self.lc, nr, &arg.strip_qua(), &ty)
+            let arg = arg.strip_qua();
+            match Term::mk_aggr(&self.g, &self.lc, nr, &arg, &ty) {
+              Some(tm) => return tm,
+              None => {
+                self.err(pos, MizError::NoSubAggregate);
+                // keep going with the argument itself, which is at least a term
+                return arg
+              }
+            }
           }
         }
         panic!("type error")
@@ -1418,8 +1597,8 @@ impl Analyzer<'_> {
         use Term::*;
         let res = match (t1, t2) {
           (Bound(BoundId(n1)), Bound(BoundId(n2)))
-          | (Const(ConstId(n1)), Const(ConstId(n2)))
-          | (Numeral(n1), Numeral(n2)) => Self::bool(n1 == n2),
+          | (Const(ConstId(n1)), Const(ConstId(n2))) => Self::bool(n1 == n2),
+          (Numeral(n1), Numeral(n2)) => Self::bool(n1 == n2),
           (Infer(InferId(n1)), Infer(InferId(n2))) if n1 == n2 => Self::bool(true),
           (Functor { nr: n1, args: args1 }, Functor { nr: n2, args: args2 }) =>
             Self::then(n1 == n2, || Self::eq_terms(ctx, ic, args1, args2)),
@@ -1834,15 +2013,22 @@ impl Analyzer<'_> {
       ast::Formula::Binder { kind: FormulaBinder::Exists, vars, st, scope, .. } =>
         self.elab_forall(vars, st.as_deref(), scope, false).maybe_neg(!positive),
       ast::Formula::False { .. } => Formula::True.maybe_neg(!positive),
-      ast::Formula::Thesis { pos: loc } => self
-        .thesis
-        .as_deref()
-        // this step is super sketchy, but Mizar actually lets you access the
-        // `thesis` of outer `proof` scopes in a `now` block
-        .or_else(|| self.thesis_stack.iter().rev().find_map(Option::as_deref))
-        .unwrap_or_else(|| panic!("at {}:{loc:?}: thesis is not known", self.article))
-        .clone()
-        .maybe_neg(positive),
+      ast::Formula::Thesis { pos: loc } => {
+        let thesis = (self.thesis.as_deref())
+          // this step is super sketchy, but Mizar actually lets you access the
+          // `thesis` of outer `proof` scopes in a `now` block
+          .or_else(|| self.thesis_stack.iter().rev().find_map(Option::as_deref))
+          .cloned();
+        match thesis {
+          Some(f) => f.maybe_neg(positive),
+          None => {
+            self.err(*loc, MizError::ThesisUnknown);
+            // `True` whatever the polarity, so that the error does not make any
+            // assumption false and the following steps vacuous
+            Formula::True
+          }
+        }
+      }
     };
     vprintln!("elab_formula {positive:?} {f:?}\n -> {res:?}");
     res
@@ -2502,20 +2688,6 @@ impl ReserveBlock {
   }
 }
 
-#[derive(Clone, Debug)]
-pub struct NameckScope {
-  uses: im::OrdMap<ReservedId, bool>,
-  reserved: im::HashMap<ReservedId, (VarKind, u32)>,
-}
-
-#[derive(Clone, Debug)]
-pub struct FraenkelNameckResult {
-  /// The scope for the scope part of the term
-  pub scope: NameckScope,
-  /// The scope for the compr part of the term
-  pub compr: NameckScope,
-}
-
 struct CollectReserved<'a> {
   reserved: &'a IdxVec<ReservedId, (IdentId, ResGroupId)>,
   res_groups: &'a IdxVec<ResGroupId, ResGroup>,
@@ -3231,10 +3403,21 @@ impl ReadProof for WithThesis {
   }
 
   fn assume(&mut self, elab: &mut Analyzer, conjs: Vec<Formula>, log: bool) {
-    let thesis = elab.thesis.take().unwrap().mk_neg().into_conjuncts();
+    let orig = elab.thesis.take().unwrap();
+    let thesis = orig.clone().mk_neg().into_conjuncts();
     let mut expansions = Default::default();
     let eref = elab.write_xml.on(|_| Some(&mut expansions));
-    let args = elab.and_telescope(conjs, true, thesis, eref).unwrap_or_else(|t| panic!("{t:?}"));
+    let args = match elab.and_telescope(conjs, true, thesis, eref) {
+      Ok(args) => args,
+      Err(e) => {
+        let TypeMismatch { got, want } = *e;
+        let (step, pos) = (SkeletonStep::Assumption, elab.pos);
+        elab.err(pos, MizError::SkeletonMismatch { step, got: got.into(), want: want.into() });
+        // keep the thesis, so that the rest of the proof is not also rejected
+        elab.thesis = Some(orig);
+        return
+      }
+    };
     let f = Formula::mk_and(args).mk_neg();
     if log {
       elab.write_xml.on(|w| w.write_thesis(&elab.r.lc, &f, &expansions))
@@ -3253,10 +3436,20 @@ impl ReadProof for WithThesis {
   }
 
   fn thus(&mut self, elab: &mut Analyzer, f: Vec<Formula>) {
-    let thesis = elab.thesis.take().unwrap().into_conjuncts();
+    let orig = elab.thesis.take().unwrap();
+    let thesis = orig.clone().into_conjuncts();
     let mut expansions = Default::default();
     let eref = elab.write_xml.on(|_| Some(&mut expansions));
-    let args = elab.and_telescope(f, false, thesis, eref).unwrap_or_else(|t| panic!("{t:?}"));
+    let args = match elab.and_telescope(f, false, thesis, eref) {
+      Ok(args) => args,
+      Err(e) => {
+        let TypeMismatch { got, want } = *e;
+        let (step, pos) = (SkeletonStep::Conclusion, elab.pos);
+        elab.err(pos, MizError::SkeletonMismatch { step, got: got.into(), want: want.into() });
+        elab.thesis = Some(orig);
+        return
+      }
+    };
     let f = Formula::mk_and(args);
     elab.write_xml.on(|w| w.write_thesis(&elab.r.lc, &f, &expansions));
     elab.thesis = Some(Box::new(f))
@@ -3733,10 +3926,10 @@ impl BlockReader {
           }
           elab.push_prop(label, thm2);
         }
-        if elab.g.cfg.exporter_enabled {
+        if elab.collects_theorems() {
           elab.export.theorems.push(Theorem { pos, kind: TheoremKind::Def(kind), stmt: *thm })
         }
-      } else if elab.g.cfg.exporter_enabled {
+      } else if elab.collects_theorems() {
         elab.export.theorems.push(Theorem {
           pos,
           kind: TheoremKind::CanceledDef,
@@ -3771,7 +3964,8 @@ impl BlockReader {
               let var = &elab.lc.fixed_var[self.to_const[LocusId(al.0 as u8)]];
               let mut ty = var.ty.visit_cloned(l);
               ty.visit(&mut al);
-              f = Box::new(Formula::ForAll { id: var.id, dom: Box::new(ty), scope: f })
+              let scope = Box::new(std::mem::take(&mut *f));
+              *f = Formula::ForAll { id: var.id, dom: Box::new(ty), scope }
             },
           ReconstructAssum::Assum(assums) => {
             let f2 = f.mk_neg();
@@ -4555,6 +4749,65 @@ impl BlockReader {
     elab.write_xml.on(|w| w.end_registration());
   }
 
+  /// Elaborates an attribute of a cluster of `ty`, and its conjunct in the coherence
+  /// condition, about `Bound(0)`
+  fn elab_cluster_attr(elab: &mut Analyzer, attr: &ast::Attr, ty: &mut Type) -> (Attr, Formula) {
+    let attr = elab.elab_attr(attr, true, ty);
+    let args = attr.args.iter().cloned().chain([Term::Bound(BoundId(0))]).collect();
+    let f = Formula::Attr { nr: attr.nr, args }.maybe_neg(attr.pos);
+    (attr, f)
+  }
+
+  /// With `--share-cluster-antecedents`, checks the coherence of the conditional clusters
+  /// at the start of `items` that have the type and antecedent of the first one, as one
+  /// inference ahead of their registration. Only the clusters whose coherence is a `by`
+  /// without references are grouped, since the others do not have the same premises.
+  /// Returns the number of clusters in the group.
+  fn share_coherence(&mut self, elab: &mut Analyzer, items: &[ast::Item]) -> usize {
+    type Parts<'a> = (&'a [ast::Attr], &'a [ast::Attr], &'a ast::Type, Position);
+    fn simple_cond(it: &ast::Item) -> Option<Parts<'_>> {
+      let ast::ItemKind::Cluster(cl) = &it.kind else { return None };
+      let ast::ClusterDeclKind::Cond { antecedent, concl, ty } = &cl.kind else { return None };
+      let ([cond], None) = (&*cl.conds, &cl.corr) else { return None };
+      match &cond.just {
+        ast::Justification::Inference { pos, kind: ast::InferenceKind::By { link: None }, refs }
+          if cond.kind == CorrCondKind::Coherence && refs.is_empty() =>
+          Some((antecedent, concl, ty, *pos)),
+        _ => None,
+      }
+    }
+    let Some((antecedent, concl, ty, pos)) = items.first().and_then(simple_cond) else { return 1 };
+    let mut ty = elab.elab_type(ty);
+    let base = ty.clone();
+    let ante = antecedent.iter().map(|a| Self::elab_cluster_attr(elab, a, &mut ty).1).collect_vec();
+    let mut concls =
+      concl.iter().map(|a| Self::elab_cluster_attr(elab, a, &mut ty).1).collect_vec();
+    let mut positions = vec![pos];
+    for it in &items[1..] {
+      let Some((antecedent2, concl2, ty2, pos2)) = simple_cond(it) else { break };
+      let mut ty2 = elab.elab_type(ty2);
+      if ty2 != base {
+        break
+      }
+      let ante2 = antecedent2.iter().map(|a| Self::elab_cluster_attr(elab, a, &mut ty2).1);
+      if !ante2.eq(ante.iter().cloned()) {
+        break
+      }
+      concls.extend(concl2.iter().map(|a| Self::elab_cluster_attr(elab, a, &mut ty).1));
+      positions.push(pos2)
+    }
+    if positions.len() > 1 {
+      let f = Formula::mk_and_with(|conjs| {
+        conjs.extend(ante);
+        Formula::mk_and(concls).mk_neg().append_conjuncts_to(conjs)
+      });
+      let mut thesis = Formula::forall0(ty, f.mk_neg());
+      thesis.visit(&mut elab.intern_const());
+      elab.r.read_shared_coherence(&thesis, &positions)
+    }
+    positions.len()
+  }
+
   fn elab_cond_reg(
     &mut self, elab: &mut Analyzer, antecedent: &[ast::Attr], concl: &[ast::Attr], ty: &ast::Type,
     conds: &mut [ast::CorrCond], corr: &mut Option<ast::Correctness>,
@@ -4572,16 +4825,14 @@ impl BlockReader {
     let (mut attrs1, mut attrs2) = (ty.attrs.0.clone(), ty.attrs.0.clone());
     let f = Formula::mk_and_with(|conjs| {
       for attr in antecedent {
-        let attr = elab.elab_attr(attr, true, &mut ty);
-        let args = attr.args.iter().cloned().chain([Term::Bound(BoundId(0))]).collect();
-        conjs.push(Formula::Attr { nr: attr.nr, args }.maybe_neg(attr.pos));
+        let (attr, f) = Self::elab_cluster_attr(elab, attr, &mut ty);
+        conjs.push(f);
         attrs1.insert(Some(&elab.g.constrs), &elab.lc, attr);
       }
       let f = Formula::mk_and_with(|conjs| {
         for attr in concl {
-          let attr = elab.elab_attr(attr, true, &mut ty);
-          let args = attr.args.iter().cloned().chain([Term::Bound(BoundId(0))]).collect();
-          conjs.push(Formula::Attr { nr: attr.nr, args }.maybe_neg(attr.pos));
+          let (attr, f) = Self::elab_cluster_attr(elab, attr, &mut ty);
+          conjs.push(f);
           attrs2.insert(Some(&elab.g.constrs), &elab.lc, attr);
         }
       });
@@ -4614,6 +4865,10 @@ impl BlockReader {
     elab.write_xml.on(|w| w.end_registration());
   }
 
+  /// The coherence condition of `cluster term -> attrs` is `term is attrs`, and with
+  /// `for ty` it is `for x being ty st x = term holds x is attrs`. The loci are the
+  /// constants of the enclosing `let`s here, so their types reach the checker as the
+  /// types of those constants, and need not be part of the condition itself.
   fn elab_func_reg(
     &mut self, elab: &mut Analyzer, term: &ast::Term, concl: &[ast::Attr], oty: Option<&ast::Type>,
     conds: &mut [ast::CorrCond], corr: &mut Option<ast::Correctness>,
@@ -4812,7 +5067,7 @@ impl BlockReader {
     let red = Reduction { primary, terms: [from, to] };
     elab.write_xml.on(|w| w.start_reduction(&red));
     elab.elab_corr_conds(cc, &mut it.conds, &mut it.corr);
-    elab.r.reductions.push(red);
+    elab.r.session.reductions.push(red);
     elab.write_xml.on(|w| w.end_reduction());
   }
 
@@ -4971,7 +5226,7 @@ impl BlockReader {
 
   fn elab_canceled_def(&mut self, elab: &mut Analyzer, loc: Position, n: u32) {
     elab.defthms.0 += n;
-    if elab.g.cfg.exporter_enabled {
+    if elab.collects_theorems() {
       self.defs.extend((0..n).map(|_| (loc, None)))
     }
   }
@@ -5016,6 +5271,24 @@ impl ReadProof for BlockReader {
 
   fn new_supposes(&mut self, _: &mut Analyzer) -> Self::SupposeRecv { panic!("invalid item") }
 
+  fn elab_proof(&mut self, elab: &mut Analyzer, items: &mut [ast::Item], end: Position) {
+    let share = matches!(self.kind, BlockKind::Registration)
+      && elab.g.cfg.share_cluster_antecedents
+      && elab.g.cfg.analyzer_full;
+    let mut grouped = 0;
+    for i in 0..items.len() {
+      if share && grouped == 0 {
+        grouped = self.share_coherence(elab, &items[i..])
+      }
+      grouped = grouped.saturating_sub(1);
+      if !self.elab_item(elab, &mut items[i], end) {
+        break
+      }
+    }
+    elab.write_xml.on(|w| w.end_pos(end));
+    self.end_block(elab, end)
+  }
+
   fn end_block(&mut self, elab: &mut Analyzer, _: Position) {
     if self.needs_round_up {
       let mut attrs = elab.g.numeral_type.attrs.1.clone();
@@ -33,6 +33,20 @@ pub enum VarKind {
   Reserved(ReservedId),
 }
 
+#[derive(Clone, Debug)]
+pub struct NameckScope {
+  pub uses: im::OrdMap<ReservedId, bool>,
+  pub reserved: im::HashMap<ReservedId, (VarKind, u32)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FraenkelNameckResult {
+  /// The scope for the scope part of the term
+  pub scope: NameckScope,
+  /// The scope for the compr part of the term
+  pub compr: NameckScope,
+}
+
 #[derive(Copy, Clone, Debug, Serialize)]
 pub enum PrivFuncKind {
   PrivFunc(PrivFuncId),
@@ -47,7 +61,7 @@ pub enum Term {
   },
   Numeral {
     pos: Position,
-    value: u32,
+    value: Numeral,
   },
   Var {
     pos: Position,
@@ -115,7 +129,7 @@ pub enum Term {
     #[serde(skip_serializing_if = "Option::is_none")]
     compr: Option<Box<Formula>>,
     #[serde(skip)]
-    nameck: Option<Box<crate::analyze::FraenkelNameckResult>>,
+    nameck: Option<Box<FraenkelNameckResult>>,
   },
   It {
     pos: Position,
@@ -866,6 +880,8 @@ pub enum Pragma {
   Insert(String),
   /// $V-, $V+
   SetVerify(bool),
+  /// $name=value, overriding a checker option for the next item
+  SetOption(String, String),
   Other(String),
 }
 
@@ -889,6 +905,11 @@ impl FromStr for Pragma {
       Pragma::ThmDesc(s.trim_start().to_owned())
     } else if let Some(s) = spelling.strip_prefix("$INSERT") {
       Pragma::Insert(s.trim_start().to_owned())
+    } else if let Some((name, value)) = (spelling.strip_prefix('$'))
+      .and_then(|s| s.split_once('='))
+      .filter(|(name, _)| name.trim().bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_'))
+    {
+      Pragma::SetOption(name.trim().to_owned(), value.trim().to_owned())
     } else {
       match spelling {
         "$V-" => Pragma::SetVerify(false),
@@ -899,8 +920,9 @@ impl FromStr for Pragma {
   }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Default, Serialize)]
 pub enum ItemKind {
+  #[default]
   Section,
   Block {
     kind: BlockKind,
@@ -985,6 +1007,18 @@ pub enum ItemKind {
   Statement(Statement),
 }
 
-impl Default for ItemKind {
-  fn default() -> Self { Self::Section }
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn option_pragmas() {
+    let parse = |s: &str| s.parse::<Pragma>().unwrap();
+    let Pragma::SetOption(name, value) = parse("$unify_budget_ms = 100") else { panic!() };
+    assert_eq!((&*name, &*value), ("unify_budget_ms", "100"));
+    assert!(matches!(parse("$V-"), Pragma::SetVerify(false)));
+    assert!(matches!(parse("$CT 2"), Pragma::Canceled(CancelKind::Thm, 2)));
+    assert!(matches!(parse("$N x=1"), Pragma::ThmDesc(s) if s == "x=1"));
+    assert!(matches!(parse("$not an option=1"), Pragma::Other(_)));
+  }
 }
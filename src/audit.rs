@@ -0,0 +1,326 @@
+//! A naive refutation procedure, used by `--double-check` to re-verify the successes of
+//! the checker by a path that shares nothing with the equalizer and unifier.
+//!
+//! It only knows about ground congruence closure: the equalities in the conjunct, the
+//! definitions of the inference constants, and clashes between literals with congruent
+//! arguments. Universal premises are used by trying them on the ground terms of the
+//! conjunct, one premise at a time, where the unifier would have found the instance.
+//! It does not know about clusters, so it confirms only a subset of the successes of the
+//! real checker; everything else is reported as unconfirmed rather than as an error.
+use crate::checker::{Atoms, Conjunct, Dnf};
+use crate::types::*;
+use crate::{Global, Inst0, LocalContext, Visit};
+#[cfg(test)]
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// How many instances of the universal premises `refutes` tries
+const INSTANCE_BUDGET: usize = 1000;
+
+#[cfg(test)]
+thread_local! {
+  /// A hook for the tests of `--double-check`: while it is set, the checker claims to refute
+  /// every conjunct on this thread, which seeds the discrepancies to be reported.
+  pub(crate) static BROKEN_FAST_PATH: Cell<bool> = const { Cell::new(false) };
+  /// The dumps of `report_unconfirmed` on this thread, for the same tests
+  pub(crate) static UNCONFIRMED: RefCell<Vec<serde_json::Value>> = const { RefCell::new(vec![]) };
+}
+
+#[derive(Clone, PartialEq, Eq)]
+enum Head {
+  Func(FuncId),
+  Sel(SelId),
+  Aggr(AggrId),
+  SchFunc(SchFuncId),
+  Numeral(Numeral),
+  /// Any other term, compared syntactically
+  Opaque(Term),
+}
+
+impl Hash for Head {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    std::mem::discriminant(self).hash(state);
+    match self {
+      Head::Func(nr) => nr.hash(state),
+      Head::Sel(nr) => nr.hash(state),
+      Head::Aggr(nr) => nr.hash(state),
+      Head::SchFunc(nr) => nr.hash(state),
+      Head::Numeral(n) => n.hash(state),
+      // Only the kind of term, and the constant if it is one, which agrees with `==`
+      Head::Opaque(tm) => {
+        std::mem::discriminant(tm).hash(state);
+        if let Term::Const(c) = tm {
+          c.hash(state)
+        }
+      }
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Lit {
+  Pred(PredId),
+  Attr(AttrId),
+  SchPred(SchPredId),
+  /// Any other atom, which can only clash with itself
+  Atom(AtomId),
+}
+
+struct Audit<'a> {
+  g: &'a Global,
+  lc: &'a LocalContext,
+  nodes: Vec<(Head, Vec<usize>)>,
+  /// The position of each node in `nodes`
+  index: HashMap<(Head, Vec<usize>), usize>,
+  parent: Vec<usize>,
+  infers: HashMap<InferId, usize>,
+  eqs: Vec<(usize, usize)>,
+}
+
+impl<'a> Audit<'a> {
+  fn intern(&mut self, head: Head, args: Vec<usize>) -> usize {
+    match self.index.entry((head, args)) {
+      Entry::Occupied(e) => *e.get(),
+      Entry::Vacant(e) => {
+        let i = self.nodes.len();
+        self.nodes.push(e.key().clone());
+        self.parent.push(i);
+        *e.insert(i)
+      }
+    }
+  }
+
+  fn nodes(&mut self, args: &[Term]) -> Vec<usize> {
+    args.iter().map(|tm| self.node(tm)).collect()
+  }
+
+  fn node(&mut self, tm: &Term) -> usize {
+    match tm {
+      &Term::Infer(nr) => {
+        if let Some(&i) = self.infers.get(&nr) {
+          return i
+        }
+        let i = self.intern(Head::Opaque(tm.clone()), vec![]);
+        self.infers.insert(nr, i);
+        let (def, eq_const) = {
+          let asgn = &self.lc.infer_const.borrow()[nr];
+          (asgn.def.clone(), asgn.eq_const.clone())
+        };
+        let j = self.node(&def);
+        self.eqs.push((i, j));
+        for z in eq_const {
+          let j = self.node(&Term::Infer(z));
+          self.eqs.push((i, j))
+        }
+        i
+      }
+      Term::Functor { nr, args } => {
+        let (nr, args) = Term::adjust(*nr, args, Some(&self.g.constrs));
+        let args = self.nodes(args);
+        self.intern(Head::Func(nr), args)
+      }
+      Term::Selector { nr, args } => {
+        let args = self.nodes(args);
+        self.intern(Head::Sel(*nr), args)
+      }
+      Term::Aggregate { nr, args } => {
+        let args = self.nodes(args);
+        self.intern(Head::Aggr(*nr), args)
+      }
+      Term::SchFunc { nr, args } => {
+        let args = self.nodes(args);
+        self.intern(Head::SchFunc(*nr), args)
+      }
+      Term::PrivFunc { value, .. } => self.node(value),
+      Term::Numeral(n) => self.intern(Head::Numeral(n.clone()), vec![]),
+      _ => self.intern(Head::Opaque(tm.clone()), vec![]),
+    }
+  }
+
+  fn find(&mut self, mut i: usize) -> usize {
+    while self.parent[i] != i {
+      self.parent[i] = self.parent[self.parent[i]];
+      i = self.parent[i]
+    }
+    i
+  }
+
+  fn union(&mut self, i: usize, j: usize) -> bool {
+    let (i, j) = (self.find(i), self.find(j));
+    self.parent[i.max(j)] = i.min(j);
+    i != j
+  }
+
+  fn congruent(&mut self, i: usize, j: usize) -> bool {
+    let (args1, args2) = (self.nodes[i].1.clone(), self.nodes[j].1.clone());
+    args1.len() == args2.len()
+      && args1.into_iter().zip(args2).all(|(a, b)| self.find(a) == self.find(b))
+  }
+
+  /// Computes the congruence closure of the pending equations, by brute force
+  fn close(&mut self) {
+    for (i, j) in std::mem::take(&mut self.eqs) {
+      self.union(i, j);
+    }
+    loop {
+      let mut changed = false;
+      for i in 0..self.nodes.len() {
+        for j in i + 1..self.nodes.len() {
+          if self.find(i) != self.find(j)
+            && self.nodes[i].0 == self.nodes[j].0
+            && !self.nodes[i].1.is_empty()
+            && self.congruent(i, j)
+          {
+            changed |= self.union(i, j)
+          }
+        }
+      }
+      if !changed {
+        return
+      }
+    }
+  }
+}
+
+/// Returns true if the conjunct could be refuted by congruence closure, possibly after
+/// adding an instance of one of its universal premises.
+pub fn refutes(
+  g: &Global, lc: &LocalContext, atoms: &Atoms, conj: &Conjunct<AtomId, bool>,
+) -> bool {
+  if refutes_ground(g, lc, atoms, conj) {
+    return true
+  }
+  let mut terms = GroundTerms(vec![]);
+  for &id in conj.0.keys() {
+    if !matches!(atoms[id], Formula::ForAll { .. }) {
+      terms.visit_formula(&atoms[id])
+    }
+  }
+  let mut budget = INSTANCE_BUDGET;
+  for (&id, &pos) in &conj.0 {
+    if !pos || !matches!(atoms[id], Formula::ForAll { .. }) {
+      continue
+    }
+    let mut insts = vec![atoms[id].clone()];
+    // instantiate the leading quantifiers (at most two), checking the types
+    for _ in 0..2 {
+      if !insts.first().is_some_and(|f| matches!(f, Formula::ForAll { .. })) {
+        break
+      }
+      let mut next = vec![];
+      for f in &insts {
+        let Formula::ForAll { dom, scope, .. } = f else { unreachable!() };
+        for tm in &terms.0 {
+          if dom.is_wider_than(g, lc, &tm.get_type(g, lc, false)) {
+            next.push((**scope).visit_cloned(&mut Inst0(0, tm)))
+          }
+        }
+      }
+      insts = next
+    }
+    for f in insts {
+      let Some(b) = budget.checked_sub(1) else { return false };
+      budget = b;
+      let mut atoms = atoms.clone();
+      let Ok(dnf) = atoms.normalize(g, lc, f, true) else { continue };
+      let disjs = match dnf {
+        Dnf::True => continue,
+        Dnf::Or(disjs) => disjs,
+      };
+      let refuted = disjs.into_iter().all(|disj| {
+        let mut conj = conj.clone();
+        disj.0.into_iter().any(|(a, v)| conj.0.insert(a, v).is_some_and(|v2| v2 != v))
+          || refutes_ground(g, lc, &atoms, &conj)
+      });
+      if refuted {
+        return true
+      }
+    }
+  }
+  false
+}
+
+/// The closed terms of a formula, outside of its binders
+struct GroundTerms(Vec<Term>);
+
+impl Visit for GroundTerms {
+  fn visit_term(&mut self, tm: &Term) {
+    match tm {
+      Term::Bound(_) | Term::FreeVar(_) | Term::Fraenkel { .. } | Term::The { .. } => {}
+      _ => {
+        self.super_visit_term(tm);
+        if !self.0.contains(tm) {
+          self.0.push(tm.clone())
+        }
+      }
+    }
+  }
+  fn visit_formula(&mut self, f: &Formula) {
+    if !matches!(f, Formula::ForAll { .. }) {
+      self.super_visit_formula(f)
+    }
+  }
+}
+
+/// Returns true if the conjunct could be refuted by congruence closure alone.
+fn refutes_ground(
+  g: &Global, lc: &LocalContext, atoms: &Atoms, conj: &Conjunct<AtomId, bool>,
+) -> bool {
+  let mut a = Audit {
+    g,
+    lc,
+    nodes: vec![],
+    index: Default::default(),
+    parent: vec![],
+    infers: Default::default(),
+    eqs: vec![],
+  };
+  let (mut neqs, mut lits) = (vec![], vec![]);
+  for (&id, &pos) in &conj.0 {
+    match &atoms[id] {
+      Formula::Pred { nr, args } => {
+        let (nr, args) = Formula::adjust_pred(*nr, args, Some(&g.constrs));
+        match args {
+          [x, y] if g.reqs.equals_to() == Some(nr) => {
+            let (x, y) = (a.node(x), a.node(y));
+            if pos { a.eqs.push((x, y)) } else { neqs.push((x, y)) }
+          }
+          _ => lits.push((Lit::Pred(nr), a.nodes(args), pos)),
+        }
+      }
+      Formula::Attr { nr, args } => {
+        let (nr, args) = Formula::adjust_attr(*nr, args, Some(&g.constrs));
+        lits.push((Lit::Attr(nr), a.nodes(args), pos))
+      }
+      Formula::SchPred { nr, args } => lits.push((Lit::SchPred(*nr), a.nodes(args), pos)),
+      _ => lits.push((Lit::Atom(id), vec![], pos)),
+    }
+  }
+  a.close();
+  let mut numerals = HashMap::new();
+  for i in 0..a.nodes.len() {
+    if let Head::Numeral(n) = &a.nodes[i].0 {
+      let n = n.clone();
+      if *numerals.entry(a.find(i)).or_insert_with(|| n.clone()) != n {
+        return true
+      }
+    }
+  }
+  if neqs.into_iter().any(|(x, y)| a.find(x) == a.find(y)) {
+    return true
+  }
+  for (i, (lit1, args1, pos1)) in lits.iter().enumerate() {
+    for (lit2, args2, pos2) in &lits[i + 1..] {
+      if lit1 == lit2
+        && pos1 != pos2
+        && args1.len() == args2.len()
+        && args1.iter().zip(args2).all(|(&x, &y)| a.find(x) == a.find(y))
+      {
+        return true
+      }
+    }
+  }
+  false
+}
@@ -1,10 +1,12 @@
+use crate::types::Numeral;
 use num_bigint::BigInt;
 use num_traits::sign::Signed;
+use serde_derive::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::fmt::{Debug, Display};
 
-#[derive(PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 enum Integer {
   Small(i32),
   Large(Box<BigInt>),
@@ -146,7 +148,7 @@ impl PartialOrd for Integer {
   fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
 }
 
-#[derive(PartialEq, Eq, Clone)]
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Rational {
   num: Integer,
   den: Integer, // Invariant: positive
@@ -264,7 +266,7 @@ impl PartialOrd for Rational {
   fn ge(&self, other: &Self) -> bool { !self.lt(other) }
 }
 
-#[derive(PartialEq, Eq, Clone, Default)]
+#[derive(PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
 pub struct Complex {
   pub re: Rational,
   pub im: Rational,
@@ -324,7 +326,19 @@ impl From<Rational> for Complex {
   fn from(value: Rational) -> Self { Self::real(value) }
 }
 impl From<u32> for Complex {
-  fn from(value: u32) -> Self { Self::int(value.into()) }
+  fn from(value: u32) -> Self { (&Numeral::Small(value)).into() }
+}
+impl From<&Numeral> for Complex {
+  fn from(value: &Numeral) -> Self {
+    let num = match *value {
+      Numeral::Small(n) => match n.try_into() {
+        Ok(n) => Integer::Small(n),
+        Err(_) => Integer::large(n.into()),
+      },
+      Numeral::Large(ref n) => Integer::large((**n).clone().into()),
+    };
+    Self::real(Rational { num, den: Integer::ONE })
+  }
 }
 
 impl std::ops::Add for Complex {
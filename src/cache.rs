@@ -33,7 +33,7 @@ pub fn init_cache<'a>(articles: impl Iterator<Item = (&'a str, bool)>) {
     .map(|x| (x, false))
     .chain(articles)
     .map(|(s, wait)| {
-      (Article::from_lower(s.as_bytes()).unwrap(), Cache { wait, ..Cache::default() })
+      (MizPath::new(s).unwrap().art, Cache { wait, ..Cache::default() })
     })
     .collect();
   let reqs = ["arithm", "boole", "hidden", "numerals", "real", "subset"]
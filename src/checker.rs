@@ -1,63 +1,433 @@
-use crate::equate::Equalizer;
+use crate::equate::{Equalizer, Footprint};
+use crate::error::{ArticlePos, ErrFile, MizError};
 use crate::types::*;
+#[cfg(feature = "diagnostics")]
+use crate::testing::{Outcome, Snapshot};
 use crate::unify::Unifier;
-use crate::util::RetainMutFrom;
+use crate::util::{RetainMutFrom, Shuffle};
 #[allow(unused)]
-use crate::vprintln;
+use crate::{outln, vprintln};
 use crate::{
-  set_verbose, stat, Assignment, CheckBound, ExpandPrivFunc, FixedVar, Global, Inst, Inst0,
-  InternConst, LocalContext, OnVarMut, Visit, VisitMut, WithGlobalLocal,
+  peak, set_verbose, stat, Assignment, CheckBound, CmpStyle, ExpandPrivFunc, FixedVar, Global,
+  Inst, Inst0, InternConst, LocalContext, OnVarMut, Visit, VisitMut, WithGlobalLocal,
 };
 use itertools::Itertools;
 use std::borrow::Cow;
-use std::collections::BTreeMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
 use std::marker::PhantomData;
 
-pub struct Checker<'a> {
-  pub g: &'a Global,
-  pub lc: &'a mut LocalContext,
+/// The parts of the environment that stay fixed while checking an article, shared by the
+/// checker, the equalizer and the unifier.
+#[derive(Clone, Copy)]
+pub struct CheckerCtx<'a> {
   pub expansions: &'a [Definiens],
   pub equals: &'a BTreeMap<ConstrKind, Vec<EqualsDef>>,
   pub identify: &'a [IdentifyFunc],
   pub func_ids: &'a BTreeMap<ConstrKind, Vec<usize>>,
   pub reductions: &'a [Reduction],
+}
+
+impl<'a> CheckerCtx<'a> {
+  pub fn intern_const(self, g: &'a Global, lc: &'a LocalContext) -> InternConst<'a> {
+    InternConst::new(g, lc, self.equals, self.identify, self.func_ids)
+  }
+}
+
+/// The checker's state for a whole article, owned by the reader: the tables of
+/// [`CheckerCtx`], which grow as the article makes definitions, and the caches that
+/// outlive one inference.
+#[derive(Default)]
+pub struct CheckerSession {
+  /// ExDefinientia
+  pub expansions: Vec<Definiens>,
+  /// gIdentifications
+  pub identify: Vec<IdentifyFunc>,
+  /// gReductions
+  pub reductions: Vec<Reduction>,
+  pub equals: BTreeMap<ConstrKind, Vec<EqualsDef>>,
+  pub func_ids: BTreeMap<ConstrKind, Vec<usize>>,
+  expand_cache: RefCell<ExpandCache>,
+}
+
+impl CheckerSession {
+  pub fn ctx(&self) -> CheckerCtx<'_> {
+    CheckerCtx {
+      expansions: &self.expansions,
+      equals: &self.equals,
+      identify: &self.identify,
+      func_ids: &self.func_ids,
+      reductions: &self.reductions,
+    }
+  }
+
+  /// The checker for the inference at `pos`
+  pub fn checker<'a>(
+    &'a self, g: &'a Global, lc: &'a mut LocalContext, article: Article, pos: Position,
+  ) -> Checker<'a> {
+    Checker {
+      g,
+      lc,
+      ctx: self.ctx(),
+      expand_cache: Some(&self.expand_cache),
+      article,
+      pos,
+      explain_neq: None,
+      reserved_consts: &[],
+    }
+  }
+}
+
+/// The expanded forms of the closed premises, such as the theorems and definitions that
+/// are referenced, which are the same at every inference as long as the environment does
+/// not change. Like `RoundUpCache`, it is dropped when the article adds a constructor,
+/// a cluster or a definition.
+#[derive(Default)]
+pub struct ExpandCache {
+  /// The size of the environment when the cache was filled: the constructors,
+  /// the (registered, functor, conditional) clusters and the expansions
+  base: (ConstructorsBase, (usize, usize, usize), usize),
+  /// sorted by `Formula::cmp` (CmpStyle::Strict), the premises and their conjuncts
+  /// after expansion. The comparison does not look at everything (the upper attributes
+  /// of a type, say), so a hit must also be equal to the premise.
+  formulas: Vec<(Formula, Vec<Formula>)>,
+}
+
+impl ExpandCache {
+  fn find(&self, f: &Formula) -> Result<usize, usize> {
+    self.formulas.binary_search_by(|a| a.0.cmp(None, None, f, CmpStyle::Strict))
+  }
+
+  fn get(&mut self, g: &Global, ctx: &CheckerCtx<'_>, f: &Formula) -> Option<&[Formula]> {
+    let cl = &g.clusters;
+    let clusters = (cl.registered.len(), cl.functor.len(), cl.conditional.len());
+    let base = (g.constrs.len(), clusters, ctx.expansions.len());
+    if self.base != base {
+      self.base = base;
+      self.formulas.clear();
+      return None
+    }
+    let (f2, conjs) = &self.formulas[self.find(f).ok()?];
+    (f2 == f).then_some(conjs)
+  }
+
+  fn insert(&mut self, f: &Formula, conjs: &[Formula]) {
+    if let Err(i) = self.find(f) {
+      if self.formulas.len() > crate::GC_THRESHOLD {
+        stat("gc", false);
+        self.formulas.clear();
+        return
+      }
+      self.formulas.insert(i, (f.clone(), conjs.to_vec()))
+    }
+  }
+}
+
+/// Whether a premise mentions nothing of the local context, so that its expansion can be
+/// cached for the whole article.
+struct CheckClosed(bool);
+impl Visit for CheckClosed {
+  fn abort(&self) -> bool { !self.0 }
+  fn visit_term(&mut self, tm: &Term) {
+    match tm {
+      Term::Bound(_) | Term::Numeral(_) | Term::Functor { .. } | Term::Selector { .. }
+      | Term::Aggregate { .. } | Term::Fraenkel { .. } | Term::The { .. } =>
+        self.super_visit_term(tm),
+      _ => self.0 = false,
+    }
+  }
+  fn visit_formula(&mut self, f: &Formula) {
+    match f {
+      Formula::SchPred { .. } | Formula::PrivPred { .. } => self.0 = false,
+      _ => self.super_visit_formula(f),
+    }
+  }
+}
+
+pub struct Checker<'a> {
+  pub g: &'a Global,
+  pub lc: &'a mut LocalContext,
+  pub ctx: CheckerCtx<'a>,
+  /// The expansions of the closed premises, kept from one inference to the next
+  pub expand_cache: Option<&'a RefCell<ExpandCache>>,
   pub article: Article,
   pub pos: Position,
+  /// The terms of `--explain-neq`, if they make sense at this inference
+  pub explain_neq: Option<&'a [Term; 2]>,
+  /// The constants that stand for reserved variables in the analyzer
+  pub reserved_consts: &'a [ConstId],
+}
+
+/// A conjunct that `Checker::refute` could not refute, with the diagnostics requested
+/// for it. These are rendered eagerly, while the constants they mention are still in scope.
+struct Failure {
+  conjunct: usize,
+  notes: Vec<String>,
+  /// Whether the conjunct failed because `--equate-budget-ms` or `--unify-budget-ms`
+  /// ran out
+  timed_out: bool,
+}
+
+/// The size of the problem solved by `Checker::refute`, for `--snapshot`
+#[derive(Default)]
+#[cfg_attr(not(feature = "diagnostics"), allow(dead_code))]
+struct Size {
+  atoms: usize,
+  eq_classes: usize,
 }
 
 impl<'a> Checker<'a> {
   fn intern_const(&self) -> InternConst<'_> {
-    InternConst::new(self.g, self.lc, self.equals, self.identify, self.func_ids)
+    self.ctx.intern_const(self.g, self.lc)
   }
 
-  pub fn justify(&mut self, premises: Vec<&'a Formula>) {
+  /// Reports a failure and returns false if the premises are nested more deeply
+  /// than `max_term_depth`, since the checker passes would overflow the stack on them.
+  fn check_depth(&self, premises: &[&Formula]) -> bool {
+    let max = self.g.cfg.max_term_depth;
+    if within_depth(premises, max) {
+      return true
+    }
+    stat("failure", true);
+    ErrFile::push(self.pos, Some(ErrFile::INFERENCE_NOT_ACCEPTED), String::new);
+    outln!(
+      "failed to justify {:?}:{:?}: nesting depth exceeds {max}",
+      self.article, self.pos
+    );
+    if self.g.cfg.panic_on_fail {
+      panic!("failed to justify {:?}:{:?}", self.article, self.pos);
+    }
+    false
+  }
+
+  /// Drops the premises equal to an earlier one, as when the same statement is cited twice
+  /// (by `then` and by its label), so that it is only expanded and normalized once.
+  /// Returns the premises left, each with the positions of all its citations in `premises`.
+  fn dedup(&self, premises: Vec<&'a Formula>) -> (Vec<&'a Formula>, Vec<Vec<PremiseId>>) {
+    let (mut out, mut cited) = (Vec::<&Formula>::new(), Vec::<Vec<PremiseId>>::new());
+    for (i, f) in premises.into_iter().enumerate() {
+      let i = PremiseId(i as u32);
+      match out.iter().position(|g| self.g.eq(self.lc, *g, f)) {
+        Some(j) => {
+          stat("duplicate premise", false);
+          cited[j].push(i)
+        }
+        None => {
+          out.push(f);
+          cited.push(vec![i])
+        }
+      }
+    }
+    (out, cited)
+  }
+
+  fn not_accepted(&self) -> MizError {
+    MizError::NotAccepted(ArticlePos { article: self.article, item: None, pos: self.pos })
+  }
+
+  /// Checks that the premises are contradictory. Returns an error (after reporting)
+  /// if this fails.
+  pub fn justify(&mut self, premises: Vec<&'a Formula>) -> Result<(), MizError> {
     if let Some(n) = self.g.cfg.first_verbose_line {
       set_verbose(self.pos.line >= n);
     }
     if self.g.cfg.skip_to_verbose && !crate::verbose() {
+      return Ok(())
+    }
+    if !self.check_depth(&premises) {
+      return Err(self.not_accepted())
+    }
+    let (premises, cited) = self.dedup(premises);
+    let _loc = ArticlePos { article: self.article, item: None, pos: self.pos }.enter();
+    let (failure, size) = self.refute(&premises);
+    let timed_out = failure.as_ref().is_some_and(|f| f.timed_out);
+    self.snapshot(self.pos, failure.is_none(), timed_out, &size);
+    match failure {
+      None => {
+        stat("success", false);
+        if self.g.cfg.warn_vacuous {
+          self.check_vacuous(&premises, &cited)
+        }
+        Ok(())
+      }
+      Some(Failure { conjunct: i, notes, timed_out }) => {
+        stat("failure", true);
+        let code = if timed_out { ErrFile::TIMED_OUT } else { ErrFile::INFERENCE_NOT_ACCEPTED };
+        ErrFile::push(self.pos, Some(code), String::new);
+        outln!("failed to justify {:?}:{:?}.{i}", self.article, self.pos);
+        for note in notes {
+          outln!("{note}")
+        }
+        self.print_reserved(&premises);
+        #[cfg(feature = "diagnostics")]
+        if let Some(dir) = &self.g.cfg.dump_failures_dir {
+          crate::replay::dump(self, dir, &premises)
+        }
+        #[cfg(feature = "diagnostics")]
+        if self.g.cfg.minimize_failures {
+          self.minimize(&premises, &cited)
+        }
+        if self.g.cfg.panic_on_fail {
+          panic!("failed to justify {:?}:{:?}.{i}", self.article, self.pos);
+        }
+        Err(self.not_accepted())
+      }
+    }
+  }
+
+  /// Warns if the inference also holds without the negated thesis, which is the first
+  /// premise, as this usually means that the author's premises are wrong. `cited` are the
+  /// citations of each premise, as returned by `dedup`.
+  fn check_vacuous(&mut self, premises: &[&Formula], cited: &[Vec<PremiseId>]) {
+    let Some((_, rest)) = premises.split_first() else { return };
+    if rest.is_empty() || !self.refute_quiet(rest) {
       return
     }
-    self.lc.term_cache.get_mut().open_scope();
-    let infer_const = self.lc.infer_const.get_mut().len();
-    let fixed_var = self.lc.fixed_var.len();
+    stat("vacuous inference", false);
+    let conjs = self.shrink(rest, 0, true);
+    let conjs = conjs.into_iter().map(|(i, f)| (f, &*cited[i + 1])).collect_vec();
+    crate::error::report_vacuous_inference(self.article, self.pos, &conjs)
+  }
+
+  /// Prints the reserved variables that occur in the premises, with their types,
+  /// since the constants they became are otherwise hard to recognize.
+  fn print_reserved(&self, premises: &[&Formula]) {
+    if self.reserved_consts.is_empty() {
+      return
+    }
+    let (mut cc, mut ci) = (CollectConsts(Default::default()), CollectInfers(Default::default()));
+    for f in premises {
+      cc.visit_formula(f);
+      ci.visit_formula(f)
+    }
+    // the constants of the interned premises are in the definitions of their infer consts
+    let (infer_const, mut seen) = (self.lc.infer_const.borrow(), BTreeSet::new());
+    while let Some(n) = ci.0.pop_first() {
+      if seen.insert(n) {
+        cc.visit_term(&infer_const[n].def);
+        ci.visit_term(&infer_const[n].def)
+      }
+    }
+    for c in cc.0 {
+      if self.reserved_consts.contains(&c) {
+        let v = &self.lc.fixed_var[c];
+        let name = self.lc.formatter.idents.get(v.id).map_or("?", |s| s);
+        outln!("  {name} (c{}) is reserved as {:?}", c.0, v.ty)
+      }
+    }
+  }
+
+  #[cfg(feature = "diagnostics")]
+  fn export_tptp(&self, dir: &str, i: usize, atoms: &Atoms, conj: &Conjunct<AtomId, bool>) {
+    let (line, col) = (self.pos.line, self.pos.col);
+    let header = format!("{}:{line}:{col}.{i}", self.article);
+    let out = crate::tptp::export(self.g, self.lc, atoms, conj, &header);
+    let path = format!("{dir}/{}-{line}-{col}-{i}.p", self.article);
+    if let Err(e) = std::fs::create_dir_all(dir).and_then(|()| std::fs::write(&path, out)) {
+      eprintln!("failed to write {path}: {e}")
+    }
+  }
+
+  /// Attempts to refute the conjunction of the premises. Returns the first conjunct
+  /// of the normal form that could not be refuted, if any.
+  fn refute(&mut self, premises: &[&Formula]) -> (Option<Failure>, Size) {
+    self.refute_inner(premises, false, false)
+  }
+
+  /// Like `refute`, but for the speculative runs of `--warn-vacuous` and
+  /// `--minimize-failures`: it only returns whether the premises were refuted, and
+  /// records no stats, exports no problems and double-checks nothing.
+  fn refute_quiet(&mut self, premises: &[&Formula]) -> bool {
+    crate::without_stats(|| self.refute_inner(premises, true, false).0.is_none())
+  }
+
+  /// Checks the coherence of a group of conditional clusters with the same antecedent, as
+  /// one inference whose conjuncts share the literals of the antecedent, which are pushed
+  /// to the equalizer only once (see `--share-cluster-antecedents`). It is quiet like
+  /// `refute_quiet`, so that if it fails the clusters can be checked and reported one by
+  /// one. If it succeeds, the inferences at `positions` are recorded as accepted.
+  pub fn refute_shared(&mut self, premises: Vec<&'a Formula>, positions: &[Position]) -> bool {
+    if let Some(n) = self.g.cfg.first_verbose_line {
+      set_verbose(self.pos.line >= n);
+    }
+    if self.g.cfg.skip_to_verbose && !crate::verbose() {
+      return false
+    }
+    if !within_depth(&premises, self.g.cfg.max_term_depth) {
+      return false
+    }
+    let (premises, _) = self.dedup(premises);
+    let (failure, size) = crate::without_stats(|| self.refute_inner(&premises, true, true));
+    if failure.is_some() {
+      return false
+    }
+    for &pos in positions {
+      stat("success", false);
+      stat("shared cluster antecedent", false);
+      self.snapshot(pos, true, false, &size)
+    }
+    true
+  }
+
+  /// Records the outcome of the inference at `pos` for `--snapshot`.
+  #[cfg_attr(not(feature = "diagnostics"), allow(unused_variables))]
+  fn snapshot(&self, pos: Position, ok: bool, timed_out: bool, size: &Size) {
+    #[cfg(feature = "diagnostics")]
+    if self.g.cfg.snapshot.is_some() {
+      let (Position { line, col }, &Size { atoms, eq_classes }) = (pos, size);
+      Snapshot::push(Outcome { line, col, ok, timed_out, atoms, eq_classes })
+    }
+  }
+
+  fn refute_inner(
+    &mut self, premises: &[&Formula], quiet: bool, share: bool,
+  ) -> (Option<Failure>, Size) {
+    let snap = self.lc.snapshot();
+    // With `--shuffle-seed`, the permutations depend only on the seed and the position,
+    // so that they do not change with the order in which the inferences are checked
+    let mut shuffle = (self.g.cfg.shuffle_seed)
+      .map(|seed| Shuffle::new(seed ^ ((self.pos.line as u64) << 32) ^ self.pos.col as u64));
+    let mut premises = premises.to_vec();
+    if let Some(sh) = &mut shuffle {
+      sh.shuffle(&mut premises)
+    }
 
     if self.g.cfg.checker_inputs {
       eprintln!();
     }
     let mut check_f = Formula::mk_and_with(|conjs| {
-      for f in premises {
+      for &f in &premises {
         if self.g.cfg.checker_inputs {
           eprintln!("input: {f:?}");
         }
-        let mut f = f.clone();
-        Expand { g: self.g, lc: self.lc, expansions: self.expansions }.expand(&mut f, true);
+        let cache = self.expand_cache.filter(|_| {
+          if !self.lc.bound_var.is_empty() {
+            return false
+          }
+          let mut cc = CheckClosed(true);
+          cc.visit_formula(f);
+          cc.0
+        });
+        if let Some(cache) = cache {
+          if let Some(cached) = cache.borrow_mut().get(self.g, &self.ctx, f) {
+            stat("expansion cache hit", false);
+            conjs.extend_from_slice(cached);
+            continue
+          }
+        }
+        let start = conjs.len();
+        let mut f2 = f.clone();
+        Expand { g: self.g, lc: self.lc, expansions: self.ctx.expansions }.expand(&mut f2, true);
         if self.g.cfg.legacy_flex_handling {
-          ExpandLegacyFlex { depth: 0 }.visit_formula(&mut f);
+          ExpandLegacyFlex { depth: 0 }.visit_formula(&mut f2);
+        }
+        // vprintln!("expand: {f2:?}");
+        f2.distribute_quantifiers(&self.g.constrs, self.lc, 0);
+        // vprintln!("distributed: {f2:?}");
+        f2.append_conjuncts_to(conjs);
+        if let Some(cache) = cache {
+          cache.borrow_mut().insert(f, &conjs[start..])
         }
-        // vprintln!("expand: {f:?}");
-        f.distribute_quantifiers(&self.g.constrs, self.lc, 0);
-        // vprintln!("distributed: {f:?}");
-        f.append_conjuncts_to(conjs);
       }
     });
     if self.g.cfg.checker_header {
@@ -69,65 +439,309 @@ impl<'a> Checker<'a> {
 
     check_f.visit(&mut self.intern_const());
     // vprintln!("interned {:?}:{:?}:\n  {check_f:?}", self.article, self.pos);
+    let explain_neq = self.explain_neq.map(|tms| {
+      tms.clone().map(|mut tm| {
+        tm.visit(&mut self.intern_const());
+        tm
+      })
+    });
 
     let mut atoms = Atoms::default();
     let Dnf::Or(mut normal_form) = atoms.normalize(self.g, self.lc, check_f, true).unwrap() else {
       panic!("it is not true")
     };
     // vprintln!("normalized {:?}:{:?}:\n  {normal_form:?}", self.article, self.pos);
+    if let Some(sh) = &mut shuffle {
+      sh.shuffle(&mut normal_form)
+    }
 
     self.process_is(&mut atoms, &mut normal_form).unwrap();
+    peak("checker atoms", atoms.len());
+    peak("checker conjuncts", normal_form.len());
     // vprintln!("process_is {:?}:{:?}:\n  {normal_form:?}", self.article, self.pos);
 
-    let mut err = false;
-    for (i, f) in normal_form.into_iter().enumerate() {
+    // With `--incremental-equalizer` (and for `refute_shared`), the literals shared by all
+    // the conjuncts are pushed to the equalizer only once, and the resulting state is
+    // restored for each conjunct
+    let mut shared = None;
+    if (share || self.g.cfg.incremental_equalizer) && normal_form.len() > 1 {
+      let (first, rest) = normal_form.split_first().unwrap();
+      let common = Conjunct(
+        (first.0.iter())
+          .filter(|(a, val)| rest.iter().all(|conj| conj.0.get(a) == Some(val)))
+          .map(|(&a, &val)| (a, val))
+          .collect(),
+      );
+      if !common.0.is_empty() {
+        let mut eq = Equalizer::new(self);
+        let snap = eq.push_premise(&atoms, &common).map(|()| eq.snapshot());
+        // a state cut short by `--max-marks` is not a refutation of the common literals
+        if !eq.marks_exceeded && !eq.memory_exceeded && !eq.timed_out {
+          shared = Some((common, snap))
+        }
+      }
+    }
+
+    let mut size = Size { atoms: atoms.len(), eq_classes: 0 };
+    // The equalizer and the unifier on one conjunct: `Err(Unsat)` if it is refuted, and
+    // otherwise the notes on the failure and whether it ran out of time
+    let mut refute_conjunct = |this: &mut Self, atoms: &Atoms, f: &Conjunct<AtomId, bool>| {
+      let (mut notes, mut timed_out) = (vec![], false);
+      let sat: OrUnsat<()> = (|| {
+        let g = this.g;
+        let start = std::time::Instant::now();
+        let mut eq = Equalizer::new(this);
+        let res = match &shared {
+          Some((common, snap)) => (|| {
+            stat("incremental equalizer", false);
+            eq.rollback(snap.clone()?);
+            let rest = f.0.iter().filter(|p| !common.0.contains_key(p.0));
+            eq.push_premise(atoms, &Conjunct(rest.map(|(&a, &v)| (a, v)).collect()))?;
+            eq.saturate()
+          })(),
+          None => eq.run(atoms, f),
+        };
+        size.eq_classes += eq.next_eq_class.0 as usize;
+        let fp = eq.footprint();
+        fp.record();
+        if !quiet {
+          eq.lc.footprint.peak_with(&fp)
+        }
+        if eq.marks_exceeded {
+          stat("max marks exceeded", true);
+          let Footprint { marks, eq_classes, ty_class, .. } = fp;
+          notes.push(format!(
+            "  gave up: the equalizer made {marks} marks \
+            ({eq_classes} classes, {ty_class} types)"
+          ));
+          return Ok(())
+        }
+        // as with `--max-marks`, the equalizer cannot be trusted once it stopped early;
+        // otherwise a result over the budget is not passed on to the unifier
+        let over_budget = g.cfg.memory_budget_mb.is_some_and(|mb| fp.bytes() > mb << 20);
+        if eq.memory_exceeded || (res.is_ok() && over_budget) {
+          stat("memory budget exceeded", true);
+          notes.push(format!("  gave up: the equalizer took about {} MB", fp.bytes() >> 20));
+          return Ok(())
+        }
+        if eq.timed_out {
+          timed_out = true;
+          stat("equate budget exceeded", true);
+          notes.push(format!("  gave up: the equalizer took {} ms", start.elapsed().as_millis()));
+          return Ok(())
+        }
+        let res = res?;
+        if let Some([t1, t2]) = &explain_neq {
+          notes.push(format!("  not equal: {}", eq.explain_neq(t1, t2)))
+        }
+        let mut u = Unifier::new(eq, &res);
+        if let Some(sh) = &mut shuffle {
+          u.shuffle_classes(sh)
+        }
+        u.run()?;
+        if u.timed_out {
+          timed_out = true;
+          stat("unify budget exceeded", true);
+          notes.push("  gave up: the unifier ran out of time".into())
+        }
+        if g.cfg.failure_hints {
+          let conj = atoms.literals(f);
+          notes.push(u.failure_hint(conj).to_string())
+        }
+        Ok(())
+      })();
+      (sat, notes, timed_out)
+    };
+    let (mut err, mut rescued) = (None, false);
+    if self.g.cfg.checker_conjuncts {
+      eprintln!("atoms of {:?}:{:?}:\n{}", self.article, self.pos, atoms.fmt_with(self.lc))
+    }
+    for (i, mut f) in normal_form.into_iter().enumerate() {
       if self.g.cfg.checker_conjuncts {
         eprintln!(
           "falsifying {:?}:{:?}.{i}: {:#?}",
           self.article,
           self.pos,
-          f.0.iter().map(|(&a, &val)| atoms.0[a].clone().maybe_neg(val)).collect_vec()
+          atoms.literals(&f)
         );
       }
-      let sat = (|| {
-        let mut eq = Equalizer::new(self);
-        let res = eq.run(&atoms, &f)?;
-        Unifier::new(eq, &res).run()
-      })();
+      let (mut sat, notes, timed_out) = refute_conjunct(self, &atoms, &f);
+      if sat.is_ok() && self.g.cfg.retry_with_equals {
+        // A second chance, which Mizar does not have: the equals definitions of the
+        // functors in the conjunct are added to it as equations
+        let mut f2 = f.clone();
+        let retry = match self.add_equals_equations(&mut atoms, &mut f2) {
+          Ok(true) => refute_conjunct(self, &atoms, &f2).0,
+          res => res.map(|_| ()),
+        };
+        if retry.is_err() {
+          (rescued, sat, f) = (true, Err(Unsat), f2)
+        }
+      }
+      #[cfg(all(test, feature = "diagnostics"))]
+      if crate::audit::BROKEN_FAST_PATH.get() {
+        sat = Err(Unsat)
+      }
       // assert!(sat.is_err(), "failed to justify");
+      #[cfg(feature = "diagnostics")]
+      if sat.is_err() && self.g.cfg.double_check && !quiet {
+        if crate::audit::refutes(self.g, self.lc, &atoms, &f) {
+          stat("double check: confirmed", false)
+        } else {
+          stat("double check: unconfirmed", false);
+          let lits = atoms.literals(&f);
+          crate::error::report_unconfirmed(self.article, self.pos, i, self.lc, &lits)
+        }
+      }
       if sat.is_err() {
         if self.g.cfg.checker_result {
           eprintln!(
             "proved {:?}:{:?}.{i}! {:#?}",
             self.article,
             self.pos,
-            f.0.iter().map(|(&a, &val)| atoms.0[a].clone().maybe_neg(val)).collect_vec()
+            atoms.literals(&f)
           );
         }
       } else {
-        err = true;
+        #[cfg(feature = "diagnostics")]
+        if let Some(dir) = self.g.cfg.export_unsolved_tptp.as_ref().filter(|_| !quiet) {
+          self.export_tptp(dir, i, &atoms, &f)
+        }
+        err = Some(Failure { conjunct: i, notes, timed_out });
         if self.g.cfg.checker_result {
           eprintln!(
             "FAILED TO JUSTIFY {:?}:{:?}.{i}: {:#?}",
             self.article,
             self.pos,
-            f.0.iter().map(|(&a, &val)| atoms.0[a].clone().maybe_neg(val)).collect_vec()
+            atoms.literals(&f)
           );
         }
-        stat("failure", true);
-        println!("failed to justify {:?}:{:?}.{i}", self.article, self.pos);
-        if self.g.cfg.panic_on_fail {
-          panic!("failed to justify {:?}:{:?}.{i}", self.article, self.pos);
-        }
         break
       }
     }
-    if !err {
-      stat("success", false);
+    if err.is_none() && rescued {
+      stat("rescued by retry with equals", false)
     }
-    self.lc.fixed_var.0.truncate(fixed_var);
-    self.lc.infer_const.get_mut().truncate(infer_const);
-    self.lc.term_cache.get_mut().close_scope();
+    self.lc.rollback(snap);
+    (err, size)
+  }
+
+  /// Adds to the conjunct the equations `t = t'` of `--retry-with-equals`, where `t` is
+  /// a functor term in it (or equal to one in it) and `t'` is the instance of one of the
+  /// equals definitions of its functor, for up to `RETRY_UNFOLDINGS` rounds of unfolding.
+  /// Returns whether any equation was added, or `Unsat` if one contradicts the conjunct.
+  fn add_equals_equations(
+    &mut self, atoms: &mut Atoms, conj: &mut Conjunct<AtomId, bool>,
+  ) -> OrUnsat<bool> {
+    const RETRY_UNFOLDINGS: usize = 2;
+    let mut todo = CollectInfers(Default::default());
+    conj.0.keys().for_each(|&a| todo.visit_formula(&atoms[a]));
+    let (mut seen, mut added) = (BTreeSet::new(), false);
+    for _ in 0..RETRY_UNFOLDINGS {
+      let mut next = CollectInfers(Default::default());
+      while let Some(n) = todo.0.pop_first() {
+        if !seen.insert(n) {
+          continue
+        }
+        let Term::Functor { nr, args } = self.lc.infer_const.borrow()[n].def.clone() else {
+          continue
+        };
+        let eq_const = {
+          let asgn = &self.lc.infer_const.borrow()[n];
+          args.iter().for_each(|tm| todo.visit_term(tm));
+          todo.0.extend(&asgn.eq_const);
+          asgn.eq_const.clone()
+        };
+        let (nr, args) = Term::adjust(nr, &args, Some(&self.g.constrs));
+        for eq_def in self.ctx.equals.get(&ConstrKind::Func(nr)).into_iter().flatten() {
+          let Some(mut tm) = eq_def.expand_if_equal(self.g, self.lc, args, 0) else { continue };
+          ExpandPrivFunc(&self.g.constrs, self.lc).visit_term(&mut tm);
+          self.intern_const().visit_term(&mut tm);
+          let Term::Infer(m) = tm else { continue };
+          if m == n || eq_const.contains(&m) {
+            continue
+          }
+          let Some(f) = self.g.reqs.try_mk_eq(Term::Infer(n), Term::Infer(m)) else { continue };
+          match conj.0.entry(atoms.insert(self.g, self.lc, Cow::Owned(f), Polarity::Pos)) {
+            std::collections::btree_map::Entry::Vacant(e) => {
+              e.insert(true);
+              added = true
+            }
+            std::collections::btree_map::Entry::Occupied(e) if !*e.get() => return Err(Unsat),
+            _ => {}
+          }
+          next.0.insert(m);
+        }
+      }
+      todo = next
+    }
+    Ok(added)
+  }
+
+  /// Shrinks the conjuncts of the premises for as long as the outcome of `refute`
+  /// (success or failure, according to `refuted`) stays the same: first by dropping whole
+  /// conjuncts, and then by dropping the conjuncts of the conjunctions inside the rest.
+  /// The conjuncts of the first `pinned` premises are only shrunk, never dropped.
+  /// This is deterministic, and bounded by `MINIMIZE_BUDGET` calls to `refute_quiet`.
+  /// Each conjunct left comes with the index of its premise in `premises`.
+  fn shrink(
+    &mut self, premises: &[&Formula], pinned: usize, refuted: bool,
+  ) -> Vec<(usize, Formula)> {
+    const MINIMIZE_BUDGET: usize = 200;
+    let (mut conjs, mut origin) = (vec![], vec![]);
+    for (j, &f) in premises.iter().enumerate() {
+      f.clone().append_conjuncts_to(&mut conjs);
+      origin.resize(conjs.len(), j)
+    }
+    let pinned = origin.iter().take_while(|&&j| j < pinned).count();
+    let mut budget = MINIMIZE_BUDGET;
+    let mut i = conjs.len();
+    while i > pinned && budget > 0 {
+      i -= 1;
+      budget -= 1;
+      let removed = conjs.remove(i);
+      if self.refute_quiet(&conjs.iter().collect_vec()) != refuted {
+        conjs.insert(i, removed)
+      } else {
+        origin.remove(i);
+      }
+    }
+    let mut i = 0;
+    while i < conjs.len() && budget > 0 {
+      let mut smaller = vec![];
+      drop_one_conjunct(&conjs[i], &mut smaller);
+      let mut shrunk = false;
+      for f in smaller.into_iter().take(budget) {
+        budget -= 1;
+        let old = std::mem::replace(&mut conjs[i], f);
+        if self.refute_quiet(&conjs.iter().collect_vec()) == refuted {
+          shrunk = true;
+          break
+        }
+        conjs[i] = old
+      }
+      // try to shrink the same conjunct again if it got smaller
+      i += !shrunk as usize
+    }
+    origin.into_iter().zip(conjs).collect()
+  }
+
+  /// Shrinks the premises of a failed inference, and prints the result as a JSON line.
+  /// The negated thesis, which is the first premise, is only shrunk, so that what remains
+  /// of it is the part of the thesis that fails. `cited` are the citations of each premise,
+  /// as returned by `dedup`, and those of the premises that remain are printed too.
+  #[cfg(feature = "diagnostics")]
+  fn minimize(&mut self, premises: &[&Formula], cited: &[Vec<PremiseId>]) {
+    let orig_len = premises.iter().map(|f| f.conjuncts().count()).sum::<usize>();
+    let conjs = self.shrink(premises, 1, false);
+    let ids = conjs.iter().flat_map(|&(i, _)| &cited[i]).map(|p| p.0).sorted().dedup();
+    let out = serde_json::json!({
+      "minimized": format!("{}:{}:{}", self.article, self.pos.line, self.pos.col),
+      "of": orig_len,
+      "premises": conjs.iter().map(|(_, f)| format!("{f:?}")).collect_vec(),
+      "premise_ids": ids.collect_vec(),
+    });
+    crate::testing::JsonLines::stdout().write(&out).unwrap()
   }
 
   fn process_is(
@@ -137,7 +751,7 @@ impl<'a> Checker<'a> {
     while i < len {
       let conj = &normal_form[i];
       let mut is_ats = vec![];
-      for (a, f) in atoms.0.enum_iter() {
+      for (a, f) in atoms.enum_iter() {
         if let (Some(false), Formula::Is { .. }) = (conj.0.get(&a), f) {
           is_ats.push(a)
         }
@@ -152,11 +766,11 @@ impl<'a> Checker<'a> {
       }
       let mut inst = Dnf::single(conj1);
       for a in is_ats {
-        let Formula::Is { term, ty } = &atoms.0[a].clone() else { unreachable!() };
+        let Formula::Is { term, ty } = &atoms[a].clone() else { unreachable!() };
         let Attrs::Consistent(attrs) = ty.attrs.0.clone() else { unreachable!() };
         let ty2 = Type { kind: ty.kind, attrs: Default::default(), args: ty.args.clone() };
         let f2 = Formula::Is { term: term.clone(), ty: Box::new(ty2) };
-        let a2 = atoms.insert(self.g, self.lc, Cow::Owned(f2));
+        let a2 = atoms.insert(self.g, self.lc, Cow::Owned(f2), Polarity::Neg);
         let mut inst1 = vec![];
         for attr in attrs {
           let c = &self.g.constrs.attribute[attr.nr];
@@ -167,7 +781,7 @@ impl<'a> Checker<'a> {
           attrs.visit(&mut self.intern_const());
           let ty3 = Type { kind: ty.kind, attrs: (attrs.clone(), attrs), args: ty.args.clone() };
           let f3 = Formula::Is { term: term.clone(), ty: Box::new(ty3) };
-          let a3 = atoms.insert(self.g, self.lc, Cow::Owned(f3));
+          let a3 = atoms.insert(self.g, self.lc, Cow::Owned(f3), Polarity::Pos);
           Dnf::insert_and_absorb(&mut inst1, Conjunct::single(a3, true))?;
         }
         let mut inst2 = vec![Conjunct::single(a2, false)];
@@ -182,7 +796,7 @@ impl<'a> Checker<'a> {
                 term: Box::new(Term::mk_select(self.g, self.lc, sel, term, w)),
                 ty: Box::new(tm2.get_type_uncached(self.g, self.lc)),
               };
-              let a3 = atoms.insert(self.g, self.lc, Cow::Owned(f3));
+              let a3 = atoms.insert(self.g, self.lc, Cow::Owned(f3), Polarity::Neg);
               Dnf::insert_and_absorb(&mut inst3, Conjunct::single(a3, false))?;
             }
             Dnf::mk_and_core(&mut inst2, &inst3)?
@@ -200,12 +814,19 @@ impl<'a> Checker<'a> {
     Ok(())
   }
 
-  pub fn justify_scheme(&mut self, sch: &Scheme, premises: Vec<&'a Formula>, thesis: &'a Formula) {
+  /// Checks that the thesis and premises are an instance of the scheme.
+  /// Returns an error (after reporting) if this fails.
+  pub fn justify_scheme(
+    &mut self, sch: &Scheme, premises: Vec<&'a Formula>, thesis: &'a Formula,
+  ) -> Result<(), MizError> {
     if let Some(n) = self.g.cfg.first_verbose_line {
       set_verbose(self.pos.line >= n);
     }
     if self.g.cfg.skip_to_verbose && !crate::verbose() {
-      return
+      return Ok(())
+    }
+    if !self.check_depth(&premises) {
+      return Err(self.not_accepted())
     }
     self.lc.term_cache.get_mut().open_scope();
 
@@ -220,31 +841,46 @@ impl<'a> Checker<'a> {
     assert!(premises.len() == sch.prems.len());
     let mut ctx =
       SchemeCtx { primary: &sch.sch_funcs, g: self.g, lc: self.lc, subst: Default::default() };
-    if ctx.eq_formula(&sch.thesis, thesis, true)
-      && (sch.prems.iter().zip(premises.iter())).all(|(f1, f2)| ctx.eq_formula(f1, f2, true))
-    {
+    let ok = ctx.eq_formula(&sch.thesis, thesis, true)
+      && (sch.prems.iter().zip(premises.iter())).all(|(f1, f2)| ctx.eq_formula(f1, f2, true));
+    if ok {
       stat("success", false);
       if self.g.cfg.checker_result {
         eprintln!("proved sch {:?}:{:?}!", self.article, self.pos);
       }
     } else {
       stat("failure", true);
+      ErrFile::push(self.pos, Some(ErrFile::INFERENCE_NOT_ACCEPTED), String::new);
       if self.g.cfg.checker_result {
         eprintln!("FAILED TO JUSTIFY sch {:?}:{:?}", self.article, self.pos);
       }
-      println!("failed to justify sch {:?}:{:?}", self.article, self.pos);
+      outln!("failed to justify sch {:?}:{:?}", self.article, self.pos);
       if self.g.cfg.panic_on_fail {
         panic!("failed to justify sch {:?}:{:?}", self.article, self.pos);
       }
     }
     self.lc.term_cache.get_mut().close_scope();
+    if ok {
+      Ok(())
+    } else {
+      Err(self.not_accepted())
+    }
   }
 }
 
+/// Not really an error: the (sub)problem was found to be contradictory, which is
+/// what the checker is trying to show. It is used with `?` to exit early.
 #[derive(Copy, Clone, Debug)]
 pub struct Unsat;
 pub type OrUnsat<T> = Result<T, Unsat>;
 
+impl std::fmt::Display for Unsat {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "contradiction found")
+  }
+}
+impl std::error::Error for Unsat {}
+
 struct Expand<'a> {
   g: &'a Global,
   lc: &'a mut LocalContext,
@@ -316,37 +952,8 @@ impl Expand<'_> {
 
   /// ExpandFlex
   fn expand_flex(&mut self, terms: &[Term; 2], scope: &Formula, conjs: &mut Vec<Formula>) {
-    fn get_number<'a>(
-      g: &Global, ic: &'a IdxVec<InferId, Assignment>, mut tm: &'a Term, zero: &mut Option<Term>,
-    ) -> Option<u32> {
-      loop {
-        match tm {
-          Term::Functor { nr, args }
-            if Some(Term::adjust(*nr, args, Some(&g.constrs)).0) == g.reqs.zero_number() =>
-          {
-            *zero = Some(tm.clone());
-            return Some(0)
-          }
-          &Term::Numeral(nr) => return Some(nr),
-          &Term::Infer(nr) => {
-            tm = &ic[nr].def;
-            continue
-          }
-          _ => return None,
-        }
-      }
-    }
     assert!(self.lc.bound_var.is_empty());
-    let mut zero = None;
-    let ic = self.lc.infer_const.borrow();
-    let Some(left) = get_number(self.g, &ic, &terms[0], &mut zero) else { return };
-    let Some(right) = get_number(self.g, &ic, &terms[1], &mut zero) else { return };
-    if right.saturating_sub(left) <= 100 {
-      for i in left..=right {
-        let i = if i == 0 { zero.take().unwrap() } else { Term::Numeral(i) };
-        scope.visit_cloned(&mut Inst0(0, &i)).append_conjuncts_to(conjs);
-      }
-    }
+    expand_flex_numerals(self.g, self.lc, terms, scope, conjs);
   }
 
   fn well_matched_expansions(&self, kind: ConstrKind, args: &[Term]) -> Vec<Formula> {
@@ -365,6 +972,194 @@ impl Expand<'_> {
   }
 }
 
+/// If the endpoints of the flex-and `terms` are concrete numerals `a` and `b`
+/// (and the range is not too large), pushes the instances `scope(a), ..., scope(b)`
+/// to `conjs` and returns true. `scope` is expected to be at binder depth 0.
+fn expand_flex_numerals(
+  g: &Global, lc: &LocalContext, terms: &[Term; 2], scope: &Formula, conjs: &mut Vec<Formula>,
+) -> bool {
+  fn get_number<'a>(
+    g: &Global, lc: &'a LocalContext, ic: &'a IdxVec<InferId, Assignment>, mut tm: &'a Term,
+    zero: &mut Option<Term>,
+  ) -> Option<u32> {
+    loop {
+      match tm {
+        Term::Functor { nr, args }
+          if Some(Term::adjust(*nr, args, Some(&g.constrs)).0) == g.reqs.zero_number() =>
+        {
+          *zero = Some(tm.clone());
+          return Some(0)
+        }
+        &Term::Numeral(Numeral::Small(nr)) => return Some(nr),
+        &Term::Infer(nr) => tm = &ic[nr].def,
+        &Term::EqMark(m) => tm = &lc.marks[m].0,
+        _ => return None,
+      }
+    }
+  }
+  let mut zero = None;
+  let ic = lc.infer_const.borrow();
+  let Some(left) = get_number(g, lc, &ic, &terms[0], &mut zero) else { return false };
+  let Some(right) = get_number(g, lc, &ic, &terms[1], &mut zero) else { return false };
+  if right.saturating_sub(left) > 100 {
+    return false
+  }
+  for i in left..=right {
+    let i = if i == 0 { zero.take().unwrap() } else { Term::Numeral(i.into()) };
+    scope.visit_cloned(&mut Inst0(0, &i)).append_conjuncts_to(conjs);
+  }
+  true
+}
+
+/// A term, formula or type on the stack of `within_depth`
+enum Node<'a> {
+  Term(&'a Term),
+  Formula(&'a Formula),
+  Type(&'a Type),
+}
+
+/// Whether the terms and formulas of `premises` are nested at most `max` deep, to reject
+/// inputs that would overflow the stack in the (recursive) checker passes. The traversal
+/// uses an explicit stack, so that it does not overflow on such inputs itself, and stops
+/// as soon as `max` is exceeded. Types do not count, but the terms in them do.
+fn within_depth(premises: &[&Formula], max: u32) -> bool {
+  fn terms<'a>(stack: &mut Vec<(Node<'a>, u32)>, tms: &'a [Term], d: u32) {
+    stack.extend(tms.iter().map(|tm| (Node::Term(tm), d)))
+  }
+  let mut stack = premises.iter().map(|&f| (Node::Formula(f), 1)).collect_vec();
+  while let Some((node, depth)) = stack.pop() {
+    if depth > max {
+      return false
+    }
+    let d = depth + 1;
+    match node {
+      Node::Type(ty) => {
+        for attrs in [&ty.attrs.0, &ty.attrs.1] {
+          if let Attrs::Consistent(attrs) = attrs {
+            attrs.iter().for_each(|attr| terms(&mut stack, &attr.args, d))
+          }
+        }
+        terms(&mut stack, &ty.args, d)
+      }
+      Node::Term(tm) => match tm {
+        Term::SchFunc { args, .. }
+        | Term::Aggregate { args, .. }
+        | Term::Functor { args, .. }
+        | Term::Selector { args, .. } => terms(&mut stack, args, d),
+        Term::PrivFunc { args, value, .. } => {
+          terms(&mut stack, args, d);
+          stack.push((Node::Term(value), d))
+        }
+        Term::The { ty } => stack.push((Node::Type(ty), depth)),
+        Term::Fraenkel { args, scope, compr } => {
+          stack.extend(args.iter().map(|(_, ty)| (Node::Type(ty), depth)));
+          stack.push((Node::Term(scope), d));
+          stack.push((Node::Formula(compr), d))
+        }
+        Term::Qua { value, ty } => {
+          stack.push((Node::Term(value), d));
+          stack.push((Node::Type(ty), depth))
+        }
+        Term::Locus(_)
+        | Term::Bound(_)
+        | Term::Const(_)
+        | Term::EqClass(_)
+        | Term::EqMark(_)
+        | Term::Infer(_)
+        | Term::FreeVar(_)
+        | Term::It
+        | Term::Numeral(_) => {}
+      },
+      Node::Formula(f) => match f {
+        Formula::SchPred { args, .. } | Formula::Pred { args, .. } | Formula::Attr { args, .. } =>
+          terms(&mut stack, args, d),
+        Formula::PrivPred { args, value, .. } => {
+          terms(&mut stack, args, d);
+          stack.push((Node::Formula(value), d))
+        }
+        Formula::Is { term, ty } => {
+          stack.push((Node::Term(term), d));
+          stack.push((Node::Type(ty), depth))
+        }
+        Formula::Neg { f } => stack.push((Node::Formula(f), d)),
+        Formula::And { args } => stack.extend(args.iter().map(|f| (Node::Formula(f), d))),
+        Formula::ForAll { dom, scope, .. } => {
+          stack.push((Node::Type(dom), depth));
+          stack.push((Node::Formula(scope), d))
+        }
+        Formula::FlexAnd { nat, terms: tms, scope, .. } => {
+          stack.push((Node::Type(nat), depth));
+          terms(&mut stack, &**tms, d);
+          stack.push((Node::Formula(scope), d))
+        }
+        Formula::LegacyFlexAnd { orig, terms: tms, expansion } => {
+          stack.extend(orig.iter().map(|f| (Node::Formula(f), d)));
+          terms(&mut stack, &**tms, d);
+          stack.push((Node::Formula(expansion), d))
+        }
+        Formula::True => {}
+      },
+    }
+  }
+  true
+}
+struct CollectConsts(BTreeSet<ConstId>);
+
+struct CollectInfers(BTreeSet<InferId>);
+
+impl Visit for CollectInfers {
+  fn visit_term(&mut self, tm: &Term) {
+    self.super_visit_term(tm);
+    if let Term::Infer(n) = *tm {
+      self.0.insert(n);
+    }
+  }
+}
+
+impl Visit for CollectConsts {
+  fn visit_term(&mut self, tm: &Term) {
+    self.super_visit_term(tm);
+    if let Term::Const(c) = *tm {
+      self.0.insert(c);
+    }
+  }
+}
+
+/// Pushes the formulas obtained from `f` by dropping one conjunct of one of the
+/// conjunctions in it, looking through negations and universal quantifiers.
+fn drop_one_conjunct(f: &Formula, out: &mut Vec<Formula>) {
+  match f {
+    Formula::Neg { f } => {
+      let start = out.len();
+      drop_one_conjunct(f, out);
+      out[start..].iter_mut().for_each(|f| *f = std::mem::take(f).mk_neg())
+    }
+    Formula::ForAll { id, dom, scope } => {
+      let start = out.len();
+      drop_one_conjunct(scope, out);
+      for f in &mut out[start..] {
+        *f = Formula::ForAll { id: *id, dom: dom.clone(), scope: Box::new(std::mem::take(f)) }
+      }
+    }
+    Formula::And { args } =>
+      for i in 0..args.len() {
+        let mut rest = args.clone();
+        rest.remove(i);
+        out.push(Formula::mk_and(rest));
+        let mut smaller = vec![];
+        drop_one_conjunct(&args[i], &mut smaller);
+        out.extend(smaller.into_iter().map(|f| {
+          Formula::mk_and_with(|conjs| {
+            conjs.extend(args[..i].iter().cloned());
+            f.append_conjuncts_to(conjs);
+            conjs.extend(args[i + 1..].iter().cloned());
+          })
+        }))
+      },
+    _ => {}
+  }
+}
+
 struct ExpandLegacyFlex {
   depth: u32,
 }
@@ -523,20 +1318,112 @@ impl<O: Open + ?Sized> VisitMut for SetVar<O> {
   }
 }
 
-#[derive(Default, Debug)]
-pub struct Atoms(pub IdxVec<AtomId, Formula>);
+/// Which basis a formula is in: `Pos` for those assumed true, `Neg` for those assumed false.
+/// The bases are indexed by this rather than by a `bool`, so that it cannot be confused with
+/// the value of a literal or the polarity of an attribute.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, enum_map::Enum)]
+pub enum Polarity {
+  Neg,
+  Pos,
+}
+
+impl Polarity {
+  pub fn flip(self) -> Self {
+    match self {
+      Polarity::Neg => Polarity::Pos,
+      Polarity::Pos => Polarity::Neg,
+    }
+  }
+
+  pub fn is_pos(self) -> bool { self == Polarity::Pos }
+}
+
+impl From<bool> for Polarity {
+  fn from(pos: bool) -> Self {
+    if pos {
+      Polarity::Pos
+    } else {
+      Polarity::Neg
+    }
+  }
+}
+
+/// A list of atoms, with the polarity in which each was first added, and an index of the
+/// predicate atoms by their adjusted predicate, so that the requirement loops of the
+/// equalizer need not scan all of them. Atoms are only added with `push` or `insert`,
+/// which keep the index up to date.
+#[derive(Clone, Default, Debug)]
+pub struct Atoms {
+  atoms: IdxVec<AtomId, Formula>,
+  origin: IdxVec<AtomId, Polarity>,
+  preds: BTreeMap<PredId, Vec<AtomId>>,
+}
+
+impl std::ops::Index<AtomId> for Atoms {
+  type Output = Formula;
+  fn index(&self, a: AtomId) -> &Formula { &self.atoms[a] }
+}
 
 impl Atoms {
+  pub fn len(&self) -> usize { self.atoms.len() }
+  pub fn is_empty(&self) -> bool { self.atoms.is_empty() }
+  pub fn iter(&self) -> std::slice::Iter<'_, Formula> { self.atoms.0.iter() }
+  pub fn enum_iter(&self) -> impl Iterator<Item = (AtomId, &Formula)> { self.atoms.enum_iter() }
+
+  /// The polarity of the literal or basis that first added the atom
+  pub fn origin(&self, a: AtomId) -> Polarity { self.origin[a] }
+
   pub fn find(&self, g: &Global, lc: &LocalContext, f: &Formula) -> Option<AtomId> {
-    self.0.enum_iter().find(|(_, atom)| g.eq(lc, f, atom)).map(|p| p.0)
+    self.atoms.enum_iter().find(|(_, atom)| g.eq(lc, f, atom)).map(|p| p.0)
+  }
+
+  pub fn push(&mut self, ctx: &Constructors, f: Formula, origin: Polarity) -> AtomId {
+    let pred = match &f {
+      Formula::Pred { nr, args } => Some(Formula::adjust_pred(*nr, args, Some(ctx)).0),
+      _ => None,
+    };
+    let a = self.atoms.push(f);
+    self.origin.push(origin);
+    if let Some(nr) = pred {
+      self.preds.entry(nr).or_default().push(a)
+    }
+    a
   }
 
-  pub fn insert(&mut self, g: &Global, lc: &LocalContext, f: Cow<'_, Formula>) -> AtomId {
+  pub fn insert(
+    &mut self, g: &Global, lc: &LocalContext, f: Cow<'_, Formula>, origin: Polarity,
+  ) -> AtomId {
     match self.find(g, lc, &f) {
       Some(i) => i,
-      None => self.0.push(f.into_owned()),
+      None => self.push(&g.constrs, f.into_owned(), origin),
     }
   }
+
+  /// The atoms `P[args]` where `P` adjusts to `nr`, or none if `nr` is `None`
+  /// (as when the requirement is not available).
+  pub fn preds(&self, nr: Option<PredId>) -> impl Iterator<Item = &Formula> {
+    (nr.and_then(|nr| self.preds.get(&nr)).into_iter().flatten()).map(|&a| &self.atoms[a])
+  }
+
+  /// The literals of a conjunct over these atoms, for diagnostics
+  pub fn literals(&self, conj: &Conjunct<AtomId, bool>) -> Vec<Formula> {
+    conj.0.iter().map(|(&a, &val)| self.atoms[a].clone().maybe_neg(val)).collect()
+  }
+
+  /// Lists the atoms one per line, with their origins, for diagnostics
+  pub fn fmt_with<'a>(&'a self, lc: &'a LocalContext) -> impl std::fmt::Display + 'a {
+    struct Listing<'a>(&'a Atoms, &'a LocalContext);
+    impl std::fmt::Display for Listing<'_> {
+      fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (a, atom) in self.0.enum_iter() {
+          let origin = if self.0.origin(a).is_pos() { "+" } else { "-" };
+          writeln!(f, "  a{a:?} ({origin}): {}", self.1.pp(atom))?
+        }
+        Ok(())
+      }
+    }
+    Listing(self, lc)
+  }
 }
 
 /// A conjunction is a map from atoms to true or false, so
@@ -578,6 +1465,13 @@ impl<K: std::fmt::Debug, V: Idx> std::fmt::Debug for Conjunct<K, V> {
   }
 }
 
+impl<K: Idx, V> Conjunct<K, V> {
+  /// A bitset of the atoms in this conjunct (hashed into 64 bits), used as a quick
+  /// necessary condition for `weaker_than`: if `a.weaker_than(b)` then
+  /// `a.signature() & !b.signature() == 0`.
+  fn signature(&self) -> u64 { self.0.keys().fold(0, |s, k| s | 1 << (k.into_usize() % 64)) }
+}
+
 impl<K: Ord + Clone, V: PartialEq + Clone> Conjunct<K, V>
 where Self: std::fmt::Debug
 {
@@ -633,7 +1527,14 @@ where Conjunct<K, V>: std::fmt::Debug
 #[derive(Debug)]
 pub struct Overflow;
 
-impl<K: Ord + Clone, V: PartialEq + Clone> Dnf<K, V>
+impl std::fmt::Display for Overflow {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "normal form is too large")
+  }
+}
+impl std::error::Error for Overflow {}
+
+impl<K: Idx, V: PartialEq + Clone> Dnf<K, V>
 where Conjunct<K, V>: std::fmt::Debug
 {
   pub const FALSE: Dnf<K, V> = Dnf::Or(vec![]);
@@ -662,17 +1563,40 @@ where Conjunct<K, V>: std::fmt::Debug
   pub fn insert_and_absorb(
     this: &mut Vec<Conjunct<K, V>>, conj: Conjunct<K, V>,
   ) -> Result<bool, Overflow> {
+    let mut sigs = this.iter().map(Conjunct::signature).collect();
+    Self::insert_and_absorb_sig(this, &mut sigs, conj)
+  }
+
+  /// Same as `insert_and_absorb`, where `sigs` holds the signatures of `this` and is
+  /// kept in sync with it. Most pairs of conjuncts are ruled out by their signatures
+  /// without looking at the maps, which matters when `this` is large.
+  fn insert_and_absorb_sig(
+    this: &mut Vec<Conjunct<K, V>>, sigs: &mut Vec<u64>, conj: Conjunct<K, V>,
+  ) -> Result<bool, Overflow> {
+    let sig = conj.signature();
     for (i, conj1) in this.iter_mut().enumerate() {
-      if conj1.weaker_than(&conj) {
+      if sigs[i] & !sig == 0 && conj1.weaker_than(&conj) {
         return Ok(false)
       }
-      if conj.weaker_than(conj1) {
-        this.retain_mut_from(i + 1, |conj2| !conj.weaker_than(conj2));
-        this[i] = conj;
+      if sig & !sigs[i] == 0 && conj.weaker_than(conj1) {
+        // compact `sigs` in step with the retained conjuncts
+        let (mut j, mut k) = (i, i);
+        this.retain_mut_from(i + 1, |conj2| {
+          j += 1;
+          let keep = sig & !sigs[j] != 0 || !conj.weaker_than(conj2);
+          if keep {
+            k += 1;
+            sigs[k] = sigs[j]
+          }
+          keep
+        });
+        sigs.truncate(k + 1);
+        (this[i], sigs[i]) = (conj, sig);
         return Ok(true)
       }
     }
     this.push(conj);
+    sigs.push(sig);
     if this.len() > MAX_DISJUNCTS {
       return Err(Overflow)
     }
@@ -686,8 +1610,9 @@ where Conjunct<K, V>: std::fmt::Debug
       *self = Dnf::True;
       return Ok(())
     };
+    let mut sigs = this.iter().map(Conjunct::signature).collect();
     other.into_iter().try_for_each(|conj| {
-      Self::insert_and_absorb(this, conj)?;
+      Self::insert_and_absorb_sig(this, &mut sigs, conj)?;
       Ok(())
     })
   }
@@ -707,7 +1632,7 @@ where Conjunct<K, V>: std::fmt::Debug
     match self {
       Dnf::True => *self = Self::Or(vec![Conjunct::single(k, v)]),
       Dnf::Or(conjs) => conjs.iter_mut().for_each(|conj| {
-        conj.0.insert(k.clone(), v.clone());
+        conj.0.insert(k, v.clone());
       }),
     }
   }
@@ -717,11 +1642,12 @@ where Conjunct<K, V>: std::fmt::Debug
       this.retain_mut(|conj1| conj1.mk_and(conj2).is_ok())
     } else {
       let this1 = std::mem::take(this);
+      let mut sigs = vec![];
       for conj2 in other {
         for conj1 in &this1 {
           let mut conj = conj1.clone();
           if let Ok(()) = conj.mk_and(conj2) {
-            Self::insert_and_absorb(this, conj)?;
+            Self::insert_and_absorb_sig(this, &mut sigs, conj)?;
             if this.len() > MAX_DISJUNCTS {
               return Err(Overflow)
             }
@@ -796,8 +1722,22 @@ impl Atoms {
         Ok(res)
       }
       Formula::True => Ok(Dnf::mk_bool(pos)),
+      Formula::FlexAnd { nat, le, terms, scope } => {
+        let mut conjs = vec![];
+        if expand_flex_numerals(g, lc, &terms, &scope, &mut conjs) {
+          return self.normalize(g, lc, Formula::mk_and_flat(conjs), pos)
+        }
+        // Keep the flex-and as an atom, but also supply the equivalent quantified
+        // expansion so that the unifier can work with its contents
+        let exp = Global::expand_flex_and(nat.clone(), le, (*terms).clone(), scope.clone(), 0);
+        let flex_and = Formula::FlexAnd { nat, le, terms, scope };
+        let a = self.insert(g, lc, Cow::Owned(flex_and), pos.into());
+        let mut res = Dnf::Or(vec![Conjunct::single(a, pos)]);
+        res.mk_and_then(|| self.normalize(g, lc, exp, pos))?;
+        Ok(res)
+      }
       _ => {
-        let a = self.insert(g, lc, Cow::Owned(f));
+        let a = self.insert(g, lc, Cow::Owned(f), pos.into());
         Ok(Dnf::Or(vec![Conjunct::single(a, pos)]))
       }
     }
@@ -982,8 +1922,8 @@ impl<'a> SchemeCtx<'a> {
       (Locus(LocusId(n1)), Locus(LocusId(n2))) => n1 == n2,
       (Bound(BoundId(n1)), Bound(BoundId(n2)))
       | (Const(ConstId(n1)), Const(ConstId(n2)))
-      | (FreeVar(FVarId(n1)), FreeVar(FVarId(n2)))
-      | (Numeral(n1), Numeral(n2)) => n1 == n2,
+      | (FreeVar(FVarId(n1)), FreeVar(FVarId(n2))) => n1 == n2,
+      (Numeral(n1), Numeral(n2)) => n1 == n2,
       (Infer(InferId(n1)), Infer(InferId(n2))) if n1 == n2 => true,
       (Functor { nr: n1, args: args1 }, Functor { nr: n2, args: args2 }) => {
         let (n1, args1) = Term::adjust(*n1, args1, Some(&self.g.constrs));
@@ -1071,3 +2011,415 @@ impl<'a> SchemeCtx<'a> {
       && args1.iter().zip(args2).all(|(f1, f2)| self.eq_formula(f1, f2, true))
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The unifier takes the universal premises from the positive basis and instantiates
+  /// them against the negative one: `for x holds x in c1` refutes `not c0 in c1`, but not
+  /// `c0 in c1`, which only goes to the other basis.
+  #[test]
+  fn basis_assignment() {
+    let c1 = Term::Const(ConstId(1));
+    let member = |tm| Formula::Pred { nr: PredId(1), args: Box::new([tm, c1.clone()]) };
+    let object = || Box::new(Type::new(TypeKind::Mode(ModeId(0))));
+    let all = Formula::ForAll {
+      id: IdentId::NONE,
+      dom: object(),
+      scope: Box::new(member(Term::Bound(BoundId(0)))),
+    };
+    let justify = |premises: &[Formula]| {
+      let mut env = crate::replay::test_env();
+      for mode in [0, 1] {
+        let ty = Type::new(TypeKind::Mode(ModeId(mode)));
+        env.lc.fixed_var.push(FixedVar { id: IdentId::NONE, ty, def: None });
+      }
+      env.checker().justify(premises.iter().collect()).is_ok()
+    };
+    assert!(justify(&[all.clone(), member(c0()).mk_neg()]));
+    assert!(!justify(&[all, member(c0())]));
+  }
+
+  /// A discrepancy between the checker and `--double-check` is dumped as JSON, with all
+  /// that is needed to make sense of the literals. The discrepancy is seeded by making the
+  /// checker claim to refute everything.
+  #[test]
+  #[cfg(feature = "diagnostics")]
+  fn double_check_dumps_discrepancy() {
+    let mut env = crate::replay::test_env();
+    env.g.cfg.double_check = true;
+    let premises = std::mem::take(&mut env.premises);
+    crate::audit::BROKEN_FAST_PATH.set(true);
+    let res = env.checker().justify(premises.iter().collect());
+    crate::audit::BROKEN_FAST_PATH.set(false);
+    assert!(res.is_ok());
+    let [dump] = &*crate::audit::UNCONFIRMED.take() else { panic!("not one dump") };
+    assert_eq!(dump["unconfirmed"], "blobenv:19:16");
+    assert_eq!(dump["conjunct"], 0);
+    assert_eq!(dump["literals"], serde_json::json!(["¬P0[?1, ?2]"]));
+    assert_eq!(dump["fixed"].as_array().map(|v| v.len()), Some(2), "{dump}");
+    assert!(dump["infer"].is_array(), "{dump}");
+  }
+
+  /// A term nested far beyond `max_term_depth` is rejected, without overflowing the stack
+  /// of a thread much smaller than a recursive traversal of it would need.
+  #[test]
+  fn deep_term() {
+    let run = || {
+      let mut tm = c0();
+      for _ in 0..100_000 {
+        tm = Term::Functor { nr: FuncId(0), args: Box::new([tm]) }
+      }
+      let f = Formula::Pred { nr: PredId(0), args: Box::new([tm, c0()]) };
+      assert!(!within_depth(&[&f], 2000));
+      assert!(within_depth(&[&f], 100_002));
+      assert!(!within_depth(&[&f], 100_001));
+      let mut env = crate::replay::test_env();
+      assert!(env.checker().justify(vec![&f]).is_err());
+      // dropping the term recursively would overflow as well
+      let Formula::Pred { args, .. } = f else { unreachable!() };
+      let mut stack = Vec::from(args);
+      while let Some(tm) = stack.pop() {
+        if let Term::Functor { args, .. } = tm {
+          stack.extend(Vec::from(args))
+        }
+      }
+    };
+    std::thread::Builder::new().stack_size(1 << 20).spawn(run).unwrap().join().unwrap()
+  }
+
+  /// An inference that is accepted without limits fails when the equalizer gives up on
+  /// it, with the code of a timeout if it ran out of time.
+  #[test]
+  fn limits_give_up() {
+    let c = |n| Term::Const(ConstId(n));
+    let equals = |a, b| Formula::Pred { nr: PredId(0), args: Box::new([a, b]) };
+    let run = |set: fn(&mut crate::Config)| {
+      let mut env = crate::replay::test_env();
+      set(&mut env.g.cfg);
+      for _ in 0..3 {
+        let ty = Type::new(TypeKind::Mode(ModeId(0)));
+        env.lc.fixed_var.push(FixedVar { id: IdentId::NONE, ty, def: None });
+      }
+      // c0 = c1 & c1 = c2 implies c0 = c2
+      let fs = [equals(c(0), c(2)).mk_neg(), equals(c(0), c(1)), equals(c(1), c(2))];
+      ErrFile::start();
+      let ok = env.checker().justify(fs.iter().collect()).is_ok();
+      (ok, ErrFile::errors().into_iter().map(|(_, code)| code).collect::<Vec<_>>())
+    };
+    assert_eq!(run(|_| {}), (true, vec![]));
+    let not_accepted = (false, vec![ErrFile::INFERENCE_NOT_ACCEPTED]);
+    assert_eq!(run(|cfg| cfg.max_marks = Some(4)), not_accepted);
+    assert_eq!(run(|cfg| cfg.memory_budget_mb = Some(0)), not_accepted);
+    assert_eq!(run(|cfg| cfg.equate_budget_ms = Some(0)), (false, vec![ErrFile::TIMED_OUT]));
+  }
+
+  /// An existential premise is a universal quantifier in negative position, so
+  /// `OpenAsConst` opens it as a witness constant with the type of the quantifier, here
+  /// with no sethood needed: `ex x being set st x = c0` proves `c0 is set`.
+  #[test]
+  fn existential_witness() {
+    let set = || Box::new(Type::new(TypeKind::Mode(ModeId(1))));
+    let eq_c0 = Formula::Pred { nr: PredId(0), args: Box::new([Term::Bound(BoundId(0)), c0()]) };
+    let ex = Formula::ForAll { id: IdentId::NONE, dom: set(), scope: Box::new(eq_c0.mk_neg()) };
+    let thesis = Formula::Is { term: Box::new(c0()), ty: set() };
+    let justify = |premises: &[Formula]| {
+      let mut env = crate::replay::test_env();
+      let ty = Type::new(TypeKind::Mode(ModeId(0)));
+      env.lc.fixed_var.push(FixedVar { id: IdentId::NONE, ty, def: None });
+      env.checker().justify(premises.iter().collect()).is_ok()
+    };
+    assert!(justify(&[thesis.clone().mk_neg(), ex.mk_neg()]));
+    assert!(!justify(&[thesis.mk_neg()]));
+  }
+
+  /// `dedup` keeps the first of the equal premises, in order, and remembers where each of
+  /// them was cited.
+  #[test]
+  fn dedup_cited() {
+    let mut env = crate::replay::test_env();
+    let pred = |nr| Formula::Pred { nr: PredId(nr), args: Box::new([num(1), num(2)]) };
+    let (a, b, c) = (pred(0), pred(1), pred(0).mk_neg());
+    let ck = env.checker();
+    let (premises, cited) = ck.dedup(vec![&a, &b, &a, &c, &b]);
+    assert_eq!(premises, [&a, &b, &c]);
+    let ids = |ids: &[u32]| ids.iter().map(|&i| PremiseId(i)).collect_vec();
+    assert_eq!(cited, [ids(&[0, 2]), ids(&[1, 4]), ids(&[3])]);
+  }
+
+  /// `replay::test_env` with the modes `M2 -> set`, `M3 -> set`, `M4 -> M3`, `M5 -> set`,
+  /// `E of X -> set` and `F of X -> E of X` (`ModeId(2)` to `ModeId(7)`), and the
+  /// constants `c0`, `c1` and `c2`
+  fn widening_env() -> crate::replay::Replay {
+    let mut env = crate::replay::test_env();
+    let set = Type::new(TypeKind::Mode(ModeId(1)));
+    let of = |n, args| Type { args, ..Type::new(TypeKind::Mode(ModeId(n))) };
+    let loci = |n| vec![Term::Locus(LocusId(0)); n];
+    for (n, ty) in [(0, of(1, vec![])), (0, of(1, vec![])), (0, of(3, vec![])), (0, of(1, vec![]))]
+      .into_iter()
+      .chain([(1, of(1, vec![])), (1, of(6, loci(1)))])
+    {
+      let c = Constructor::new(vec![set.clone(); n].into());
+      env.g.constrs.mode.push(TyConstructor { c, ty });
+    }
+    for _ in 0..3 {
+      let ty = Type::new(TypeKind::Mode(ModeId(0)));
+      env.lc.fixed_var.push(FixedVar { id: IdentId::NONE, ty, def: None });
+    }
+    env
+  }
+
+  /// `for x holds x is M` refutes `not c0 is N` exactly when `N` is on the widening chain
+  /// of `M` computed one step at a time, also when the unifier compares the chain with
+  /// several such literals at once, and with the arguments of each step.
+  #[test]
+  fn is_widening_chain() {
+    let ty = |n, args: &[Term]| {
+      Box::new(Type { args: args.to_vec(), ..Type::new(ModeId(n).into()) })
+    };
+    let is = |n, args: &[Term]| Formula::Is { term: Box::new(c0()), ty: ty(n, args) };
+    let all_is = |n, args: &[Term]| {
+      let scope = Formula::Is { term: Box::new(Term::Bound(BoundId(0))), ty: ty(n, args) };
+      Formula::ForAll { id: IdentId::NONE, dom: ty(0, &[]), scope: Box::new(scope) }
+    };
+    let justify = |fs: &[Formula]| widening_env().checker().justify(fs.iter().collect()).is_ok();
+    let chain = |mut ty: Box<Type>| {
+      let env = widening_env();
+      let mut chain = vec![];
+      while let TypeKind::Mode(n) = ty.kind {
+        chain.push((n, ty.args.clone()));
+        let Some(next) = ty.widening(&env.g, &env.lc) else { break };
+        ty = next
+      }
+      chain
+    };
+    let m4 = chain(ty(4, &[]));
+    assert_eq!(m4.iter().map(|p| p.0 .0).collect_vec(), [4, 3, 1, 0]);
+    for n in 0..6 {
+      let on_chain = m4.iter().any(|p| p.0 == ModeId(n));
+      assert_eq!(justify(&[is(n, &[]).mk_neg(), all_is(4, &[])]), on_chain, "M{n}");
+    }
+    let several = |ns: &[u32]| {
+      ns.iter().map(|&n| is(n, &[]).mk_neg()).chain([all_is(4, &[])]).collect_vec()
+    };
+    assert!(justify(&several(&[2, 5, 3])));
+    assert!(!justify(&several(&[2, 5])));
+
+    let c = |n| Term::Const(ConstId(n));
+    let f = chain(ty(7, &[c(1)]));
+    assert_eq!(f[1], (ModeId(6), vec![c(1)]));
+    assert!(justify(&[is(6, &[c(1)]).mk_neg(), all_is(7, &[c(1)])]));
+    assert!(!justify(&[is(6, &[c(2)]).mk_neg(), all_is(7, &[c(1)])]));
+  }
+
+  /// With the requirements `empty` (`AttrId(1)`), `Element of X` (`ModeId(2)`) and `c=`
+  /// (`PredId(2)`), `for t holds not t is Element of c1` is refuted by `c2: Element of c0`
+  /// through `c0 c= c1`, which needs `c0` to be non empty.
+  #[test]
+  fn element_via_inclusion() {
+    let justify = |non_empty: bool, fs: &[Formula]| {
+      let mut env = crate::replay::test_env();
+      let set = Type::new(TypeKind::Mode(ModeId(1)));
+      fn of_set<I>(set: &Type) -> TyConstructor<I> {
+        TyConstructor { c: Constructor::new(Box::new([set.clone()])), ty: set.clone() }
+      }
+      let c = &mut env.g.constrs;
+      let (empty, element) = (c.attribute.push(of_set(&set)), c.mode.push(of_set(&set)));
+      let inclusion = c.predicate.push(Constructor::new(Box::new([set.clone(), set.clone()])));
+      env.g.reqs.set(Requirement::Empty, ConstrKind::Attr(empty));
+      env.g.reqs.set(Requirement::Element, ConstrKind::Mode(element));
+      env.g.reqs.set(Requirement::Inclusion, ConstrKind::Pred(inclusion));
+      let mut c0_ty = set.clone();
+      if non_empty {
+        let attrs = Attrs::Consistent(vec![Attr { nr: empty, pos: false, args: Box::new([]) }]);
+        c0_ty.attrs = (attrs.clone(), attrs)
+      }
+      let element_of = Type { args: vec![c0()], ..Type::new(TypeKind::Mode(element)) };
+      for ty in [c0_ty, set, element_of] {
+        env.lc.fixed_var.push(FixedVar { id: IdentId::NONE, ty, def: None });
+      }
+      env.checker().justify(fs.iter().collect()).is_ok()
+    };
+    let c1 = Term::Const(ConstId(1));
+    let element_of_c1 = Box::new(Type { args: vec![c1.clone()], ..Type::new(ModeId(2).into()) });
+    let scope = Formula::Is { term: Box::new(Term::Bound(BoundId(0))), ty: element_of_c1 };
+    let object = Box::new(Type::new(TypeKind::Mode(ModeId(0))));
+    let none = Formula::ForAll { id: IdentId::NONE, dom: object, scope: Box::new(scope.mk_neg()) };
+    // only there to give `c2` a class
+    let c2 = Term::Const(ConstId(2));
+    let c2_ne_c1 = Formula::Pred { nr: PredId(0), args: Box::new([c2, c1.clone()]) }.mk_neg();
+    let included = Formula::Pred { nr: PredId(2), args: Box::new([c0(), c1]) };
+    assert!(justify(true, &[c2_ne_c1.clone(), included.clone(), none.clone()]));
+    assert!(!justify(false, &[c2_ne_c1.clone(), included, none.clone()]));
+    assert!(!justify(true, &[c2_ne_c1, none]));
+  }
+
+  /// The predicate index of `Atoms` lists exactly the predicate atoms with that
+  /// predicate, through new and repeated inserts, and an atom keeps its first origin.
+  #[test]
+  fn atoms_index() {
+    let env = crate::replay::test_env();
+    let (g, lc) = (&env.g, &env.lc);
+    let pred = |nr, n| Formula::Pred { nr: PredId(nr), args: Box::new([c0(), num(n)]) };
+    let mut atoms = Atoms::default();
+    let (pos, neg) = (Polarity::Pos, Polarity::Neg);
+    let fs = [
+      (pred(0, 1), pos),
+      (pred(1, 1), neg),
+      (Formula::True, neg),
+      (pred(0, 2), neg),
+      (pred(1, 1), pos),
+      (pred(0, 1), neg),
+    ];
+    for (f, origin) in fs {
+      atoms.insert(g, lc, Cow::Owned(f), origin);
+      for nr in [PredId(0), PredId(1)] {
+        let scan = atoms.iter().filter(|f| matches!(f, Formula::Pred { nr: n, .. } if *n == nr));
+        assert!(atoms.preds(Some(nr)).eq(scan));
+      }
+    }
+    assert_eq!(atoms.len(), 4);
+    assert_eq!(atoms.preds(Some(PredId(0))).count(), 2);
+    assert_eq!(atoms.preds(None).count(), 0);
+    let origins = atoms.enum_iter().map(|(a, _)| atoms.origin(a)).collect::<Vec<_>>();
+    assert_eq!(origins, [pos, neg, neg, neg]);
+    let listing = atoms.fmt_with(lc).to_string();
+    assert_eq!(listing.lines().count(), 4);
+    assert!(listing.starts_with("  a0 (+): "), "{listing}");
+  }
+
+  fn flex_and(terms: [Term; 2]) -> Formula {
+    // f i = c0 for i from terms[0] to terms[1]
+    let fi = Term::Functor { nr: FuncId(0), args: Box::new([Term::Bound(BoundId(0))]) };
+    let scope = Formula::Pred { nr: PredId(0), args: Box::new([fi, c0()]) };
+    let nat = Box::new(Type::new(TypeKind::Mode(ModeId(0))));
+    Formula::FlexAnd { nat, le: PredId(1), terms: Box::new(terms), scope: Box::new(scope) }
+  }
+  fn c0() -> Term { Term::Const(ConstId(0)) }
+  fn num(n: u32) -> Term { Term::Numeral(n.into()) }
+
+  /// A flex-and between numerals expands to its instances, unless the range is too long.
+  #[test]
+  fn expand_flex_numerals_instances() {
+    let env = crate::replay::test_env();
+    let Formula::FlexAnd { terms, scope, .. } = flex_and([num(1), num(3)]) else { unreachable!() };
+    let mut conjs = vec![];
+    assert!(expand_flex_numerals(&env.g, &env.lc, &terms, &scope, &mut conjs));
+    let want = [1, 2, 3].map(|i| (*scope).visit_cloned(&mut Inst0(0, &num(i))));
+    assert!(conjs == want);
+    let Formula::FlexAnd { terms, .. } = flex_and([num(1), num(200)]) else { unreachable!() };
+    assert!(!expand_flex_numerals(&env.g, &env.lc, &terms, &scope, &mut vec![]));
+  }
+
+  /// Normalizing a negated flex-and between numerals gives one alternative for each index,
+  /// while one between constants stays an atom, together with its quantified expansion.
+  #[test]
+  fn normalize_flex_and() {
+    let env = crate::replay::test_env();
+    let mut atoms = Atoms::default();
+    let Ok(Dnf::Or(conjs)) = atoms.normalize(&env.g, &env.lc, flex_and([num(1), num(3)]), false)
+    else {
+      panic!("not a disjunction")
+    };
+    assert_eq!(conjs.len(), 3);
+    assert!(conjs.iter().all(|conj| conj.0.len() == 1 && conj.0.values().all(|&pos| !pos)));
+    assert!(atoms.iter().all(|f| matches!(f, Formula::Pred { .. })));
+
+    let mut atoms = Atoms::default();
+    let consts = [c0(), Term::Const(ConstId(1))];
+    let Ok(Dnf::Or(conjs)) = atoms.normalize(&env.g, &env.lc, flex_and(consts), true) else {
+      panic!("not a disjunction")
+    };
+    let [conj] = &*conjs else { panic!("not a conjunct") };
+    let kinds = conj.0.keys().map(|&a| &atoms[a]).collect::<Vec<_>>();
+    assert!(matches!(kinds[..], [Formula::FlexAnd { .. }, Formula::ForAll { .. }]));
+  }
+
+  /// Normalizing `(a & b) & c` gives the same atoms and DNF as `a & b & c`, whether it is
+  /// assumed or a goal.
+  #[test]
+  fn normalize_nested_and() {
+    let env = crate::replay::test_env();
+    let c = |n| Term::Const(ConstId(n));
+    let [a, b, c] = [(0, 1), (1, 2), (0, 2)]
+      .map(|(i, j)| Formula::Pred { nr: PredId(0), args: Box::new([c(i), c(j)]) });
+    let flat = Formula::And { args: vec![a.clone(), b.clone(), c.clone()] };
+    let nested = Formula::And { args: vec![Formula::And { args: vec![a, b] }, c] };
+    for pos in [true, false] {
+      let normalize = |f| {
+        let mut atoms = Atoms::default();
+        let dnf = atoms.normalize(&env.g, &env.lc, f, pos).unwrap();
+        (atoms.iter().cloned().collect::<Vec<_>>(), format!("{dnf:?}"))
+      };
+      let (flat_atoms, flat_dnf) = normalize(flat.clone());
+      let (nested_atoms, nested_dnf) = normalize(nested.clone());
+      assert_eq!(flat_atoms.len(), 3);
+      assert!(nested_atoms == flat_atoms);
+      assert_eq!(nested_dnf, flat_dnf);
+    }
+  }
+
+  type AtomConj = Conjunct<AtomId, bool>;
+
+  /// `Dnf::insert_and_absorb` as it was before the signatures, comparing every pair
+  fn absorb_unindexed(this: &mut Vec<AtomConj>, conj: AtomConj) -> bool {
+    for (i, conj1) in this.iter_mut().enumerate() {
+      if conj1.weaker_than(&conj) {
+        return false
+      }
+      if conj.weaker_than(conj1) {
+        this.retain_mut_from(i + 1, |conj2| !conj.weaker_than(conj2));
+        this[i] = conj;
+        return true
+      }
+    }
+    this.push(conj);
+    true
+  }
+
+  /// A conjunct of up to `len` literals about the atoms below `atoms`
+  fn random_conj(rng: &mut Shuffle, atoms: u64, len: u64) -> AtomConj {
+    let lits = (0..=rng.below(len)).map(|_| (AtomId(rng.below(atoms) as _), rng.below(2) == 0));
+    Conjunct(lits.collect())
+  }
+
+  /// Absorbing with the signatures keeps and drops the same conjuncts as comparing every
+  /// pair, and keeps the signatures in step, also when the atoms collide modulo 64.
+  #[test]
+  fn absorb_signatures() {
+    let mut rng = Shuffle::new(440);
+    for _ in 0..300 {
+      let (mut this, mut old, mut sigs) = (vec![], vec![], vec![]);
+      for _ in 0..rng.below(100) {
+        let conj = random_conj(&mut rng, 100, 4);
+        let added = Dnf::insert_and_absorb_sig(&mut this, &mut sigs, conj.clone()).unwrap();
+        assert_eq!(added, absorb_unindexed(&mut old, conj));
+        assert!(this.iter().map(|c| &c.0).eq(old.iter().map(|c| &c.0)));
+        assert!(this.iter().map(Conjunct::signature).eq(sigs.iter().copied()));
+      }
+    }
+  }
+
+  /// Times the absorption of 1000 random conjuncts with and without the signatures.
+  #[test]
+  #[ignore = "a benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+  fn absorb_benchmark() {
+    let mut rng = Shuffle::new(1000);
+    let conjs = (0..1000).map(|_| random_conj(&mut rng, 500, 6)).collect_vec();
+    let time = |absorb: &mut dyn FnMut(AtomConj)| {
+      let start = std::time::Instant::now();
+      conjs.iter().for_each(|conj| absorb(conj.clone()));
+      start.elapsed()
+    };
+    let (mut this, mut sigs, mut old) = (vec![], vec![], vec![]);
+    let new_time = time(&mut |conj| {
+      Dnf::insert_and_absorb_sig(&mut this, &mut sigs, conj).unwrap();
+    });
+    let old_time = time(&mut |conj| {
+      absorb_unindexed(&mut old, conj);
+    });
+    assert!(this.iter().map(|c| &c.0).eq(old.iter().map(|c| &c.0)));
+    println!("{} conjuncts: {new_time:?} with signatures, {old_time:?} without", this.len());
+  }
+}
@@ -0,0 +1,188 @@
+//! Differential testing against the original Mizar verifier, for `--compare-with-mizar`.
+//!
+//! The Pascal `verifier` is started on the article once its environment has been read, so
+//! that the two run side by side, and once we are done its `<article>.err` file is read and
+//! compared with our own errors. Errors are matched by line, allowing the columns to differ
+//! by up to [`COL_TOLERANCE`] since the two do not always agree on where an item starts.
+//! Our own `.err` file is written only after this, so it is not clobbered by the verifier.
+use crate::types::Position;
+use crate::{outln, MizPath};
+use std::io;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+
+/// How far apart the columns of two errors on the same line can be to be considered the same
+pub const COL_TOLERANCE: u32 = 2;
+
+pub struct Verifier {
+  cmd: String,
+  child: Child,
+  /// The error file the verifier writes
+  err: PathBuf,
+}
+
+/// Parses the contents of a `.err` file, the lines of which are `line col code`.
+/// Malformed lines are skipped.
+pub fn parse_err(text: &str) -> Vec<(Position, u32)> {
+  let mut out = vec![];
+  for line in text.lines() {
+    let mut it = line.split_whitespace().map(str::parse::<u32>);
+    if let (Some(Ok(line)), Some(Ok(col)), Some(Ok(code))) = (it.next(), it.next(), it.next()) {
+      out.push((Position { line, col }, code))
+    }
+  }
+  out
+}
+
+/// The result of matching two lists of errors
+#[derive(Default)]
+pub struct Diff {
+  pub agree: usize,
+  /// Errors with matching positions but different codes, as (position, ours, theirs)
+  pub code_differs: Vec<(Position, u32, u32)>,
+  /// Errors we report that the verifier does not, so we reject and they accept
+  pub only_ours: Vec<(Position, u32)>,
+  /// Errors the verifier reports that we do not, so we accept and they reject
+  pub only_theirs: Vec<(Position, u32)>,
+}
+
+impl Diff {
+  pub fn new(ours: &[(Position, u32)], theirs: &[(Position, u32)]) -> Diff {
+    let mut diff = Diff::default();
+    let mut unmatched = theirs.to_vec();
+    for &(pos, code) in ours {
+      let close = |&(p, _): &(Position, u32)| {
+        p.line == pos.line && p.col.abs_diff(pos.col) <= COL_TOLERANCE
+      };
+      // prefer an error with the same code, then the closest column
+      let best = (unmatched.iter().enumerate())
+        .filter(|(_, e)| close(e))
+        .min_by_key(|(_, &(p, c))| (c != code, p.col.abs_diff(pos.col)))
+        .map(|(i, _)| i);
+      match best.map(|i| unmatched.remove(i)) {
+        Some((_, c)) if c == code => diff.agree += 1,
+        Some((_, c)) => diff.code_differs.push((pos, code, c)),
+        None => diff.only_ours.push((pos, code)),
+      }
+    }
+    diff.only_theirs = unmatched;
+    diff
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.code_differs.is_empty() && self.only_ours.is_empty() && self.only_theirs.is_empty()
+  }
+}
+
+impl Verifier {
+  /// Starts `cmd -q -l <article>.miz` in the background.
+  pub fn spawn(cmd: &str, path: &MizPath) -> io::Result<Verifier> {
+    Self::spawn_at(cmd, path.to_path(true, false, "miz"), path.to_path(true, false, "err"))
+  }
+
+  fn spawn_at(cmd: &str, miz: PathBuf, err: PathBuf) -> io::Result<Verifier> {
+    // so that we don't read a stale error file if the verifier does not write one
+    let _ = std::fs::remove_file(&err);
+    let child = (Command::new(cmd).args(["-q", "-l"]).arg(miz))
+      .stdin(Stdio::null())
+      .stdout(Stdio::null())
+      .stderr(Stdio::null())
+      .spawn()?;
+    Ok(Verifier { cmd: cmd.to_owned(), child, err })
+  }
+
+  /// Waits for the verifier to finish, reads its `.err` file and prints how it differs
+  /// from `ours`. Returns false if they disagree.
+  pub fn finish(mut self, path: &MizPath, ours: &[(Position, u32)]) -> io::Result<bool> {
+    let status = self.child.wait()?;
+    let theirs = match std::fs::read_to_string(&self.err) {
+      Ok(text) => parse_err(&text),
+      Err(e) => {
+        eprintln!("{}: no error file from {} ({status}): {e}", path.art, self.cmd);
+        return Ok(false)
+      }
+    };
+    let diff = Diff::new(ours, &theirs);
+    outln!(
+      "{}: compared with mizar: {} agree, {} differ in code, {} only here, {} only there",
+      path.art,
+      diff.agree,
+      diff.code_differs.len(),
+      diff.only_ours.len(),
+      diff.only_theirs.len()
+    );
+    for (pos, ours, theirs) in &diff.code_differs {
+      outln!("  {}:{}: *{ours} here, *{theirs} in mizar", pos.line, pos.col)
+    }
+    for (pos, code) in &diff.only_ours {
+      outln!("  {}:{}: *{code} only here", pos.line, pos.col)
+    }
+    for (pos, code) in &diff.only_theirs {
+      outln!("  {}:{}: *{code} only there", pos.line, pos.col)
+    }
+    Ok(diff.is_empty())
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use std::os::unix::fs::PermissionsExt;
+
+  fn pos(line: u32, col: u32) -> Position { Position { line, col } }
+
+  /// `Position` equality ignores the position, so the errors are compared as tuples
+  fn tuples(errs: &[(Position, u32)]) -> Vec<(u32, u32, u32)> {
+    errs.iter().map(|&(p, code)| (p.line, p.col, code)).collect()
+  }
+
+  /// A stand-in for the verifier, which writes `errs` to the error file of the article
+  /// named by its last argument.
+  fn mock_verifier(dir: &std::path::Path, errs: &str) -> String {
+    let script = dir.join("verifier");
+    let body = format!(
+      "#!/bin/sh\nfor a; do miz=$a; done\nprintf '{errs}' > \"${{miz%.miz}}.err\"\n"
+    );
+    std::fs::write(&script, body).unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    script.to_str().unwrap().to_owned()
+  }
+
+  #[test]
+  fn mock_verifier_diff() {
+    let dir = std::env::temp_dir().join(format!("mizar-rs-compat-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (miz, err) = (dir.join("test.miz"), dir.join("test.err"));
+    let path = MizPath::new("TEST").unwrap();
+    let run = |cmd: &str, ours: &[(Position, u32)]| {
+      Verifier::spawn_at(cmd, miz.clone(), err.clone()).unwrap().finish(&path, ours).unwrap()
+    };
+    // a column off by one is the same error, and the stale error file is replaced
+    std::fs::write(&err, "9 9 4\n").unwrap();
+    assert!(run(&mock_verifier(&dir, "3 5 4\\n"), &[(pos(3, 6), 4)]));
+    assert_eq!(tuples(&parse_err(&std::fs::read_to_string(&err).unwrap())), [(3, 5, 4)]);
+    // we reject what they accept
+    assert!(!run(&mock_verifier(&dir, ""), &[(pos(3, 6), 4)]));
+    // they reject what we accept
+    assert!(!run(&mock_verifier(&dir, "3 5 4\\n7 1 100\\n"), &[(pos(3, 5), 4)]));
+    // the same position with another code
+    assert!(!run(&mock_verifier(&dir, "3 5 100\\n"), &[(pos(3, 5), 4)]));
+    // a verifier that writes no error file, where the stale one is not read either
+    std::fs::write(&err, "").unwrap();
+    assert!(!run("true", &[]));
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn diff_matching() {
+    let diff = Diff::new(
+      &[(pos(1, 1), 4), (pos(2, 10), 4), (pos(5, 1), 4)],
+      &[(pos(1, 3), 4), (pos(2, 13), 4), (pos(5, 2), 7), (pos(8, 1), 4)],
+    );
+    assert_eq!(diff.agree, 1);
+    let differs = diff.code_differs.iter().map(|&(p, a, b)| (p.line, p.col, a, b));
+    assert_eq!(differs.collect::<Vec<_>>(), [(5, 1, 4, 7)]);
+    assert_eq!(tuples(&diff.only_ours), [(2, 10, 4)]);
+    assert_eq!(tuples(&diff.only_theirs), [(2, 13, 4), (8, 1, 4)]);
+  }
+}
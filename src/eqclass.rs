@@ -0,0 +1,49 @@
+use crate::bignum::Complex;
+use crate::types::*;
+use enum_map::Enum;
+
+/// The data attached to an equivalence class that is shared between the equalizer
+/// and the unifier. The equalizer and unifier each wrap this with their own bookkeeping.
+#[derive(Clone, Default)]
+pub struct EqClass {
+  pub ty_class: Vec<Type>,
+  pub supercluster: Attrs,
+  /// The numeric value of the class, if it is known
+  pub number: Option<Complex>,
+}
+
+impl std::fmt::Debug for EqClass {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    if let Some(n) = &self.number {
+      write!(f, " = {n}")?
+    }
+    write!(f, ": {:?}{:?}", &self.supercluster, &self.ty_class)
+  }
+}
+
+#[derive(Copy, Clone, Debug, Enum)]
+pub enum ComplexTermKind {
+  Functor,
+  SchFunc,
+  PrivFunc,
+  Aggregate,
+  Selector,
+  Fraenkel,
+  Choice,
+}
+use ComplexTermKind as CTK;
+
+impl Term {
+  pub fn complex_kind(&self) -> Option<ComplexTermKind> {
+    match self {
+      Term::Functor { .. } => Some(CTK::Functor),
+      Term::SchFunc { .. } => Some(CTK::SchFunc),
+      Term::PrivFunc { .. } => Some(CTK::PrivFunc),
+      Term::Aggregate { .. } => Some(CTK::Aggregate),
+      Term::Selector { .. } => Some(CTK::Selector),
+      Term::Fraenkel { .. } => Some(CTK::Fraenkel),
+      Term::The { .. } => Some(CTK::Choice),
+      _ => None,
+    }
+  }
+}
@@ -1,28 +1,121 @@
 use self::polynomial::{Monomial, Polynomial};
 use crate::bignum::{Complex, Rational};
-use crate::checker::{Atoms, Checker, Conjunct, Dnf, OrUnsat, Unsat};
+use crate::checker::{Atoms, Checker, CheckerCtx, Conjunct, Dnf, OrUnsat, Polarity, Unsat};
+use crate::eqclass::EqClass;
 use crate::types::*;
+use crate::util::Shuffle;
 use crate::{
-  vprintln, CheckBound, CmpStyle, EqCtx, Equate, ExpandPrivFunc, Global, Inst, LocalContext,
-  OnVarMut, Visit, VisitMut, WithGlobalLocal,
+  peak, stat, vprintln, ClusterHits, CmpStyle, EqCtx, Equate, ExpandPrivFunc, Global, Inst,
+  InternConst, LocalContext, ShiftBound, Visit, VisitMut, WithGlobalLocal,
 };
 use enum_map::EnumMap;
 use itertools::Itertools;
 use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
 
 mod polynomial;
 
+#[cfg(test)]
+thread_local! {
+  /// A hook for the tests of `union_terms`: while it is set, the classes are merged without
+  /// first checking for opposite attributes, which leaves them to be found on insertion.
+  static LATE_CONTRADICTS: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// The signs known of a real number, for `Equalizer::propagate_signs`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Signs {
+  pos: bool,
+  neg: bool,
+  nonneg: bool,
+  nonpos: bool,
+}
+
+impl Signs {
+  /// The signs of `a * b` (if `mult`) or `a + b`, given those of `a` and `b`.
+  fn combine(mult: bool, a: Signs, b: Signs) -> Signs {
+    if mult {
+      Signs {
+        pos: a.pos && b.pos || a.neg && b.neg,
+        neg: a.pos && b.neg || a.neg && b.pos,
+        nonneg: a.nonneg && b.nonneg || a.nonpos && b.nonpos,
+        nonpos: a.nonneg && b.nonpos || a.nonpos && b.nonneg,
+      }
+    } else {
+      Signs {
+        pos: a.pos && b.nonneg || a.nonneg && b.pos,
+        neg: a.neg && b.nonpos || a.nonpos && b.neg,
+        nonneg: a.nonneg && b.nonneg,
+        nonpos: a.nonpos && b.nonpos,
+      }
+    }
+  }
+}
+
+/// The saved state of an equalizer, from `Equalizer::snapshot`.
+#[derive(Clone)]
+pub struct EqSnapshot {
+  marks: IdxVec<EqMarkId, (Term, EqTermId)>,
+  infers: IdxVec<InferId, Option<EqMarkId>>,
+  constrs: ConstrMaps,
+  terms: IdxVec<EqTermId, EqTerm>,
+  next_eq_class: EqClassId,
+  clash: bool,
+  numbers: BTreeMap<Complex, EqTermId>,
+  numbers_merged: bool,
+  bas: EnumMap<Polarity, Atoms>,
+  eqs: Equals,
+}
+
+/// The result of `Equalizer::explain_neq`.
+pub enum NeqExplanation {
+  /// The terms are in the same class, so they are equal
+  Equal,
+  /// The term at this position (0 or 1) does not occur in any class
+  NotRepresentable(usize),
+  /// The classes have different numeric values
+  Numbers(Complex, Complex),
+  /// No term of one class has the same head symbol as a term of the other
+  Heads,
+  /// These terms of the two classes have the same head symbol,
+  /// but their arguments at position `pos` are in different classes
+  Args { pos: usize, terms: Box<[Term; 2]> },
+  /// The classes share a head symbol with congruent arguments, but were not merged
+  Unknown,
+}
+
+impl std::fmt::Display for NeqExplanation {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      NeqExplanation::Equal => write!(f, "the terms are equal"),
+      NeqExplanation::NotRepresentable(i) => write!(f, "term {} does not occur", i + 1),
+      NeqExplanation::Numbers(n1, n2) => write!(f, "the values {n1} and {n2} differ"),
+      NeqExplanation::Heads => write!(f, "no terms of the classes have the same head"),
+      NeqExplanation::Args { pos, terms } =>
+        write!(f, "argument {} of {:?} and {:?} differs", pos + 1, terms[0], terms[1]),
+      NeqExplanation::Unknown => write!(f, "no difference found"),
+    }
+  }
+}
+
+#[derive(Clone)]
 pub struct EqTerm {
   pub id: EqClassId,
   /// Term is EqMark(mark)
   pub mark: EqMarkId,
   pub eq_class: Vec<EqMarkId>,
-  pub ty_class: Vec<Type>,
-  pub supercluster: Attrs,
-  pub number: Option<Complex>,
   pub eq_polys: BTreeSet<Polynomial<EqTermId>>,
+  pub class: EqClass,
+}
+
+impl std::ops::Deref for EqTerm {
+  type Target = EqClass;
+  fn deref(&self) -> &Self::Target { &self.class }
+}
+impl std::ops::DerefMut for EqTerm {
+  fn deref_mut(&mut self) -> &mut Self::Target { &mut self.class }
 }
 
 impl std::fmt::Debug for EqTerm {
@@ -32,14 +125,11 @@ impl std::fmt::Debug for EqTerm {
       .entries(self.eq_class.iter().map(|&m| Term::EqMark(m)))
       .entries(&self.eq_polys)
       .finish()?;
-    if let Some(n) = &self.number {
-      write!(f, " = {n}")?
-    }
-    write!(f, ": {:?}{:?}", &self.supercluster, &self.ty_class)
+    write!(f, "{:?}", self.class)
   }
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 struct ConstrMap<I>(BTreeMap<I, Vec<EqMarkId>>);
 
 impl<I: Idx> ConstrMap<I> {
@@ -71,7 +161,7 @@ struct AllowedClusters {
   fcl: Vec<(usize, Attrs)>,
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 struct ConstrMaps {
   functor: ConstrMap<FuncId>,
   aggregate: ConstrMap<AggrId>,
@@ -85,13 +175,31 @@ struct ConstrMaps {
 pub struct Equalizer<'a> {
   pub g: &'a Global,
   pub lc: &'a mut LocalContext,
-  reductions: &'a [Reduction],
+  ctx: CheckerCtx<'a>,
   infers: IdxVec<InferId, Option<EqMarkId>>,
   constrs: ConstrMaps,
   /// TrmS
   pub terms: IdxVec<EqTermId, EqTerm>,
   pub next_eq_class: EqClassId,
   clash: bool,
+  /// A term of the class with each number assigned so far (see `set_number`)
+  numbers: BTreeMap<Complex, EqTermId>,
+  /// Whether `init_numbers` has merged the classes of the pending equalities. Until then
+  /// the classes are being set up, and `set_number` only adds an equality to `eqs`.
+  numbers_merged: bool,
+  /// The premises that have been passed through `push_premise` but not yet `saturate`
+  bas: EnumMap<Polarity, Atoms>,
+  /// The positive equalities among the pending premises
+  eqs: Equals,
+  /// Set once `--max-marks` is reached. From then on the equalizer stops with `Unsat`
+  /// as soon as it can, so the checker must check this before trusting the result.
+  pub marks_exceeded: bool,
+  /// Like `marks_exceeded`, for `--memory-budget-mb`
+  pub memory_exceeded: bool,
+  /// When to give up, if `--equate-budget-ms` is set
+  deadline: Option<Instant>,
+  /// Like `marks_exceeded`, set once the deadline has passed
+  pub timed_out: bool,
 }
 impl WithGlobalLocal for Equalizer<'_> {
   fn global(&self) -> &Global { self.g }
@@ -122,6 +230,9 @@ impl Visit for CheckE<'_> {
   }
 }
 
+/// The largest number of atoms in a pair of comprehensions that `equiv_compr` will look at
+const MAX_COMPR_ATOMS: usize = 12;
+
 struct EqMarks;
 
 impl Equate for EqMarks {
@@ -185,6 +296,36 @@ impl Term {
 }
 
 impl Equalizer<'_> {
+  /// Whether `--max-marks`, `--memory-budget-mb` or `--equate-budget-ms` has been reached,
+  /// see `marks_exceeded`. The memory is estimated from the marks and classes only, which
+  /// is cheap enough to do for every new term; the checker checks the full [`Footprint`]
+  /// afterwards.
+  fn hit_limit(&mut self) -> bool {
+    if self.g.cfg.max_marks.is_some_and(|n| self.lc.marks.len() >= n) {
+      self.marks_exceeded = true
+    }
+    if let Some(mb) = self.g.cfg.memory_budget_mb {
+      let (marks, eq_classes) = (self.lc.marks.len(), self.terms.len());
+      let fp = Footprint { marks, eq_classes, ..Default::default() };
+      if fp.bytes() > mb << 20 {
+        self.memory_exceeded = true
+      }
+    }
+    self.out_of_time().is_err() || self.marks_exceeded || self.memory_exceeded
+  }
+
+  /// Stops the saturation passes with `Unsat` once `--equate-budget-ms` is used up.
+  /// As with `marks_exceeded`, the checker must not trust the result then.
+  fn out_of_time(&mut self) -> OrUnsat<()> {
+    if self.deadline.is_some_and(|d| Instant::now() > d) {
+      self.timed_out = true
+    }
+    if self.timed_out {
+      return Err(Unsat)
+    }
+    Ok(())
+  }
+
   /// YEqClass
   fn new_eq_class(&mut self, tm: &mut Term) -> (EqMarkId, EqTermId) {
     let id = self.next_eq_class.fresh();
@@ -193,10 +334,8 @@ impl Equalizer<'_> {
       id,
       mark: Default::default(),
       eq_class: vec![],
-      ty_class: vec![Type::ANY],
-      supercluster: Attrs::default(),
-      number: None,
       eq_polys: Default::default(),
+      class: EqClass { ty_class: vec![Type::ANY], ..Default::default() },
     });
     let m = self.lc.marks.push((std::mem::take(tm), et));
     *tm = Term::EqMark(m);
@@ -222,9 +361,7 @@ impl Equalizer<'_> {
         }
         return Ok(added)
       }
-      self.y(|y| y.visit_type(&mut new))?; // is this okay? we already visited it
       let Attrs::Consistent(attrs) = std::mem::take(&mut new.attrs).1 else { unreachable!() };
-      eq_term = &mut self.terms[et];
       for attr in attrs {
         eq_term.supercluster.try_insert(&self.g.constrs, self.lc, attr)?;
       }
@@ -232,6 +369,9 @@ impl Equalizer<'_> {
         if let Some(new2) = new.widening(self.g, self.lc) {
           eq_term.ty_class.push(std::mem::replace(&mut new, *new2));
           added = true;
+          // only the widened type is new, the ones in the class have already been visited
+          self.y(|y| y.visit_type(&mut new))?;
+          eq_term = &mut self.terms[et];
           continue
         }
       }
@@ -289,10 +429,9 @@ impl<'a> Equalizer<'a> {
   fn prep_binder(
     &mut self, tm: &mut Term, depth: u32, coll: fn(&mut ConstrMaps) -> &mut Vec<EqMarkId>,
   ) -> Option<Result<EqTermId, usize>> {
-    if CheckBound::get(0..depth, |cb| cb.visit_term(tm)) {
+    if !ShiftBound::term(tm, depth) {
       return None
     }
-    OnVarMut(|n| *n -= depth).visit_term(tm);
     let vec = coll(&mut self.constrs);
     match vec.binary_search_by(|&m| {
       self.lc.marks[m].0.cmp(Some(&self.g.constrs), Some(self.lc), tm, CmpStyle::Red)
@@ -349,6 +488,10 @@ impl VisitMut for Y<'_, '_> {
     if self.abort() {
       return
     }
+    if self.hit_limit() {
+      self.unsat = Err(Unsat);
+      return
+    }
     // vprintln!("y term <- {tm:?}");
     let et = match *tm {
       Term::Bound(_) | Term::EqClass(_) => return,
@@ -358,12 +501,15 @@ impl VisitMut for Y<'_, '_> {
         } else {
           let et = self.new_eq_class(tm).1;
           *self.eq.infers.get_mut_extending(nr) = Some(self.eq.terms[et].mark);
-          let ty = {
+          let (ty, number) = {
             let ic = self.eq.lc.infer_const.borrow();
-            self.eq.terms[et].number.clone_from(&ic[nr].number);
-            ic[nr].ty.visit_cloned(&mut ExpandPrivFunc(&self.eq.g.constrs, self.eq.lc))
+            let ty = ic[nr].ty.visit_cloned(&mut ExpandPrivFunc(&self.eq.g.constrs, self.eq.lc));
+            (ty, ic[nr].number.clone())
           };
           y_try!(self, self.insert_type(ty, et));
+          if let Some(n) = number {
+            y_try!(self, self.set_number(et, n))
+          }
           *tm = Term::EqMark(self.terms[et].mark);
         }
         return
@@ -388,9 +534,6 @@ impl VisitMut for Y<'_, '_> {
         let (m, et) = self.new_eq_class(tm);
         self.constrs.functor.insert(nr2, m);
         y_try!(self, self.insert_type(ty, et));
-        if self.g.reqs.zero_number() == Some(Term::adjusted_nr(nr2, &self.g.constrs)) {
-          self.terms[et].number = Some(Complex::ZERO);
-        }
         let constr = &self.g.constrs.functor[nr];
         if constr.properties.get(PropertyKind::Commutativity) {
           args1.swap(constr.properties.arg1 as usize, constr.properties.arg2 as usize);
@@ -400,6 +543,9 @@ impl VisitMut for Y<'_, '_> {
           self.terms[et].eq_class.push(m);
           self.constrs.functor.insert(nr3, m)
         }
+        if self.g.checker_reqs().zero_number() == Some(Term::adjusted_nr(nr2, &self.g.constrs)) {
+          y_try!(self, self.set_number(et, Complex::ZERO))
+        }
         *tm = Term::EqMark(self.terms[et].mark);
         return
       }
@@ -494,6 +640,9 @@ impl Equalizer<'_> {
 
   /// YYTerm(fTrm = term, fi = fi)
   fn yy_term(&mut self, mut term: Term, fi: EqTermId) -> OrUnsat<EqTermId> {
+    if self.hit_limit() {
+      return Err(Unsat)
+    }
     // vprintln!("yy term {term:?} <- {:?}", self.terms[fi]);
     macro_rules! func_like {
       ($k:ident: $nr:expr, $args:expr) => {{
@@ -509,17 +658,12 @@ impl Equalizer<'_> {
       }};
     }
     match term {
-      Term::Numeral(n) => {
+      Term::Numeral(ref n) => {
         let c = n.into();
-        for etm in self.terms.0.iter() {
-          if !etm.eq_class.is_empty() && etm.number.as_ref() == Some(&c) {
-            return Ok(self.lc.marks[etm.mark].1)
-          }
-        }
-        let et = self.lc.marks[self.terms[fi].mark].1;
-        if matches!(self.terms[et].number.replace(c.clone()), Some(c2) if c != c2) {
-          return Err(Unsat)
+        if let Some(&et) = self.numbers.get(&c) {
+          return Ok(self.lc.marks[self.terms[et].mark].1)
         }
+        self.set_number(fi, c)?;
         Ok(fi)
       }
       Term::Functor { nr, ref mut args } => {
@@ -540,11 +684,6 @@ impl Equalizer<'_> {
           None
         };
         let et = self.lc.marks[self.terms[fi].mark].1;
-        match self.g.reqs.rev.get(nr1) {
-          Some(Some(Requirement::ZeroNumber)) => self.terms[et].number = Some(Complex::ZERO),
-          Some(Some(Requirement::ImaginaryUnit)) => self.terms[et].number = Some(Complex::I),
-          _ => {}
-        }
         let m = self.lc.marks.push((Term::Functor { nr: nr1, args: args1.to_vec().into() }, fi));
         self.constrs.functor.insert(nr1, m);
         self.terms[et].eq_class.push(m);
@@ -554,7 +693,13 @@ impl Equalizer<'_> {
           self.constrs.functor.insert(nr2, m);
           self.terms[et].eq_class.push(m);
         }
-        Ok(fi)
+        match self.g.checker_reqs().rev(nr1) {
+          Some(Requirement::ZeroNumber) => self.set_number(et, Complex::ZERO)?,
+          Some(Requirement::ImaginaryUnit) => self.set_number(et, Complex::I)?,
+          _ => return Ok(fi),
+        }
+        // the class may have been merged with one that already had this number
+        Ok(self.lc.marks[self.terms[fi].mark].1)
       }
       Term::SchFunc { nr, ref mut args } => func_like!(sch_func: nr, args),
       Term::PrivFunc { nr, ref mut args, .. } => func_like!(priv_func: nr, args),
@@ -604,7 +749,7 @@ impl Equalizer<'_> {
   }
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 struct Equals(BTreeSet<(EqTermId, EqTermId)>);
 
 impl Equals {
@@ -633,15 +778,16 @@ impl Visit for HasInfer<'_> {
   fn abort(&self) -> bool { self.found }
   fn visit_term(&mut self, tm: &Term) {
     match *tm {
-      Term::Infer(n) => self.found |= self.infers.get(n).map_or(true, |i| i.is_none()),
+      Term::Infer(n) => self.found |= self.infers.get(n).is_none_or(|i| i.is_none()),
       _ => self.super_visit_term(tm),
     }
   }
 }
 
 impl Attr {
+  /// Whether this is `strict` for some structure, looking through redefinitions.
   fn is_strict(&self, ctx: &Constructors) -> bool {
-    self.pos && ctx.attribute[self.nr].properties.get(PropertyKind::Abstractness)
+    self.pos && ctx.attribute[self.adjusted_nr(ctx)].properties.get(PropertyKind::Abstractness)
   }
 }
 
@@ -697,7 +843,7 @@ impl Instantiate<'_> {
             z.mk_and_then(|| Ok(Dnf::single(Conjunct::single(v, self.terms[et].id)))).unwrap();
             z
           }
-          &Term::Numeral(n) => Dnf::mk_bool(self.terms[et].number == Some(n.into())),
+          Term::Numeral(n) => Dnf::mk_bool(self.terms[et].number == Some(n.into())),
           Term::Functor { nr: n1, args: args1 } => {
             let (n1, args1) = Term::adjust(*n1, args1, Some(&self.g.constrs));
             let mut res = Dnf::FALSE;
@@ -806,7 +952,7 @@ impl Instantiate<'_> {
 }
 
 fn is_empty_set(g: &Global, lc: &LocalContext, terms: &[EqMarkId]) -> bool {
-  let empty = g.reqs.empty_set().unwrap();
+  let Some(empty) = g.checker_reqs().empty_set() else { return false };
   terms.iter().any(|&m| matches!(lc.marks[m].0, Term::Functor { nr, .. } if nr == empty))
 }
 
@@ -820,20 +966,79 @@ impl Attrs {
   }
 }
 
+/// The sizes of the tables of an [`Equalizer`], sampled by the checker to report the peak
+/// usage in the stats and to enforce `--memory-budget-mb`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Footprint {
+  pub marks: usize,
+  pub eq_classes: usize,
+  pub ty_class: usize,
+  pub supercluster: usize,
+}
+
+impl Footprint {
+  /// A rough estimate of the memory used, which counts only the top level of the
+  /// marked terms and types.
+  pub fn bytes(&self) -> usize {
+    self.marks * std::mem::size_of::<(Term, EqTermId)>()
+      + self.eq_classes * std::mem::size_of::<EqTerm>()
+      + self.ty_class * std::mem::size_of::<Type>()
+      + self.supercluster * std::mem::size_of::<Attr>()
+  }
+
+  /// Raises each table size to that of `other`, if it is larger.
+  pub fn peak_with(&mut self, other: &Footprint) {
+    self.marks = self.marks.max(other.marks);
+    self.eq_classes = self.eq_classes.max(other.eq_classes);
+    self.ty_class = self.ty_class.max(other.ty_class);
+    self.supercluster = self.supercluster.max(other.supercluster);
+  }
+
+  pub fn record(&self) {
+    peak("equalizer marks", self.marks);
+    peak("equalizer classes", self.eq_classes);
+    peak("equalizer ty_class entries", self.ty_class);
+    peak("equalizer supercluster attrs", self.supercluster);
+  }
+}
+
 impl<'a> Equalizer<'a> {
+  pub fn footprint(&self) -> Footprint {
+    let mut fp = Footprint { marks: self.lc.marks.len(), ..Default::default() };
+    for etm in self.terms.0.iter().filter(|etm| !etm.eq_class.is_empty()) {
+      fp.eq_classes += 1;
+      fp.ty_class += etm.ty_class.len();
+      fp.supercluster += etm.supercluster.attrs().len();
+    }
+    fp
+  }
+
   pub fn new(ck: &'a mut Checker<'_>) -> Self {
+    ck.lc.marks.0.clear();
     Self {
       g: ck.g,
       lc: ck.lc,
-      reductions: ck.reductions,
+      ctx: ck.ctx,
       infers: Default::default(),
       constrs: Default::default(),
       terms: Default::default(),
       next_eq_class: Default::default(),
       clash: false,
+      numbers: Default::default(),
+      numbers_merged: false,
+      bas: Default::default(),
+      eqs: Default::default(),
+      marks_exceeded: false,
+      memory_exceeded: false,
+      deadline: ck.g.cfg.equate_budget_ms.map(|ms| Instant::now() + Duration::from_millis(ms)),
+      timed_out: false,
     }
   }
 
+  fn intern_const(&self) -> InternConst<'_> {
+    self.ctx.intern_const(self.g, self.lc)
+  }
+
   fn filter_allowed(&self, attrs: &Attrs) -> Attrs {
     match attrs {
       Attrs::Inconsistent => Attrs::Inconsistent,
@@ -846,21 +1051,21 @@ impl<'a> Equalizer<'a> {
   }
 
   fn add_symm(&self, pos: &Atoms, neg: &mut Atoms, prop: PropertyKind) {
-    for f in &pos.0 .0 {
+    for f in pos.iter() {
       if let Formula::Pred { nr, args } = f {
         let pred = &self.g.constrs.predicate[*nr];
         // Why are we searching for f in neg_bas here?
         if pred.properties.get(prop) && neg.find(self.g, self.lc, f).is_none() {
           let mut args = args.clone();
           args.swap(pred.properties.arg1 as usize, pred.properties.arg2 as usize);
-          neg.insert(self.g, self.lc, Cow::Owned(Formula::Pred { nr: *nr, args }));
+          neg.insert(self.g, self.lc, Cow::Owned(Formula::Pred { nr: *nr, args }), Polarity::Neg);
         }
       }
     }
   }
 
   fn check_refl(&self, atoms: &Atoms, prop: PropertyKind, ineqs: &mut Ineqs) -> OrUnsat<()> {
-    for f in &atoms.0 .0 {
+    for f in atoms.iter() {
       if let Formula::Pred { nr, args } = f {
         let pred = self.g.constrs.predicate[*nr].properties;
         if pred.get(prop) {
@@ -900,10 +1105,21 @@ impl<'a> Equalizer<'a> {
     //   self.terms[y].eq_class.iter().map(|&x| Term::EqMark(x)).collect_vec(),
     // );
     self.clash = true;
+    // equal terms cannot have opposite attributes
+    let (sc1, sc2) = (&self.terms[from].supercluster, &self.terms[to].supercluster);
+    #[cfg(test)]
+    let early = !LATE_CONTRADICTS.get();
+    #[cfg(not(test))]
+    let early = true;
+    if early && sc1.contradicts(&self.g.constrs, self.lc, sc2) {
+      return Err(Unsat)
+    }
     if let Some(n1) = self.terms[from].number.clone() {
       if matches!(self.terms[to].number.replace(n1.clone()), Some(n2) if n1 != n2) {
         return Err(Unsat)
       }
+      // `from` is no longer a class, so the number now belongs to `to`
+      self.numbers.insert(n1, to);
     }
     for &m in &self.terms[from].eq_class {
       let m = self.terms[self.lc.marks[m].1].mark;
@@ -972,7 +1188,7 @@ impl<'a> Equalizer<'a> {
           et.eq_class.iter().any(|&m| matches!(self.lc.marks[m].0, Term::Infer(n2) if n == n2))
         })
         .map(|et| et.mark),
-      Term::Numeral(nr) => {
+      Term::Numeral(ref nr) => {
         let c = nr.into();
         self.terms.0.iter().find(|et| et.number.as_ref() == Some(&c)).map(|et| et.mark)
       }
@@ -1031,7 +1247,7 @@ impl<'a> Equalizer<'a> {
         let et = self.lc.marks[m].1;
         // vprintln!("reducing: {et:?}'e{:#?}", self.terms[et].id);
         if !self.terms[et].eq_class.is_empty() {
-          for red in self.reductions {
+          for red in self.ctx.reductions {
             let inst = self
               .instantiate(&red.primary)
               .inst_term(&red.terms[0], &Term::EqMark(self.terms[et].mark));
@@ -1059,21 +1275,27 @@ impl<'a> Equalizer<'a> {
     Ok(())
   }
 
+  /// Gives the class of `et` the number `val`. All numbers are assigned through this, so that
+  /// a class that already has the same number is found in `numbers` and merged with it (in
+  /// `init_numbers`, if the classes are still being set up).
   fn set_number(&mut self, et: EqTermId, val: Complex) -> OrUnsat<()> {
+    let et = self.lc.marks[self.terms[et].mark].1;
     if let Some(n) = &self.terms[et].number {
       if val != *n {
         return Err(Unsat)
       }
-    } else {
-      for et2 in (0..self.terms.len()).map(EqTermId::from_usize) {
-        if !self.terms[et2].eq_class.is_empty() && self.terms[et2].number.as_ref() == Some(&val) {
-          vprintln!("[{et:?}] = [{et2:?}] = {val}");
-          self.union_terms(et, et2)?
-        }
+    } else if let Some(&et2) = self.numbers.get(&val) {
+      vprintln!("[{et:?}] = [{et2:?}] = {val}");
+      if self.numbers_merged {
+        self.union_terms(et, et2)?
+      } else {
+        self.eqs.insert(et, et2)
       }
+    } else {
       self.clash = true;
       // vprintln!("set_number[{et:?}] := {val}");
-      self.terms[et].number = Some(val);
+      self.terms[et].number = Some(val.clone());
+      self.numbers.insert(val, et);
       // for (et, etm) in self.terms.enum_iter() {
       //   vprintln!("state: {et:?}' {:#?}", etm);
       // }
@@ -1081,6 +1303,89 @@ impl<'a> Equalizer<'a> {
     Ok(())
   }
 
+  /// Whether two comprehension formulas are propositionally equivalent, treating atoms up to
+  /// the classes of the terms in them. This lets `{x where x is A : P & Q}` and
+  /// `{x where x is A : not not (Q & P)}` be identified, but nothing requiring reasoning
+  /// about the atoms themselves. Gives up on formulas with more than [`MAX_COMPR_ATOMS`] atoms.
+  fn equiv_compr(&self, f1: &Formula, f2: &Formula) -> bool {
+    fn atoms(f: &Formula) -> usize {
+      match f {
+        Formula::Neg { f } => atoms(f),
+        Formula::And { args } => args.iter().map(atoms).sum(),
+        _ => 1,
+      }
+    }
+    if atoms(f1) + atoms(f2) > MAX_COMPR_ATOMS {
+      return false
+    }
+    struct Canonical<'a> {
+      marks: &'a IdxVec<EqMarkId, (Term, EqTermId)>,
+      terms: &'a IdxVec<EqTermId, EqTerm>,
+    }
+    impl VisitMut for Canonical<'_> {
+      fn visit_term(&mut self, tm: &mut Term) {
+        match *tm {
+          Term::EqMark(m) => {
+            let et = self.marks[self.terms[self.marks[m].1].mark].1;
+            *tm = Term::EqMark(self.terms[et].mark)
+          }
+          _ => self.super_visit_term(tm),
+        }
+      }
+    }
+    let mut canon = Canonical { marks: &self.lc.marks, terms: &self.terms };
+    let (mut f1, mut f2) = (f1.clone(), f2.clone());
+    canon.visit_formula(&mut f1);
+    canon.visit_formula(&mut f2);
+    let mut atoms = Atoms::default();
+    let mut implies = |f1: &Formula, f2: &Formula| {
+      let f = Formula::mk_and_flat(vec![f1.clone(), f2.clone().mk_neg()]);
+      atoms.normalize(self.g, self.lc, f, true).is_ok_and(|dnf| dnf.is_false())
+    };
+    implies(&f1, &f2) && implies(&f2, &f1)
+  }
+
+  /// The converse of the rule that equal aggregates have equal fields: two objects of the
+  /// same strict structure, whose fields are all present and pairwise equal, are equal.
+  fn struct_extensionality(&self, to_union: &mut Vec<(EqTermId, EqTermId)>) {
+    let mut by_fields = BTreeMap::new();
+    for (et, etm) in self.terms.enum_iter() {
+      if etm.eq_class.is_empty() {
+        continue
+      }
+      let Some(s) = (etm.supercluster.attrs().iter())
+        .find(|attr| attr.is_strict(&self.g.constrs))
+        .map(|attr| self.g.constrs.attribute[attr.adjusted_nr(&self.g.constrs)].ty.struct_id())
+      else {
+        continue
+      };
+      let fields = &self.g.constrs.struct_mode[s].fields;
+      if fields.is_empty() || !etm.ty_class.iter().any(|ty| ty.kind == TypeKind::Struct(s)) {
+        continue
+      }
+      // The classes merged earlier in this round are only found through their marks
+      let class = |m: EqMarkId| self.lc.marks[self.terms[self.lc.marks[m].1].mark].1;
+      let key = fields.iter().map(|sel| {
+        let marks = self.constrs.selector.0.get(sel)?;
+        let of_et = |&m: &EqMarkId| {
+          let Term::Selector { args, .. } = &self.lc.marks[m].0 else { unreachable!() };
+          class(args.last().unwrap().mark().unwrap()) == et
+        };
+        marks.iter().find(|m| of_et(m)).map(|&m| class(m))
+      });
+      let Some(key) = key.collect::<Option<Vec<_>>>() else { continue };
+      match by_fields.entry((s, key)) {
+        std::collections::btree_map::Entry::Vacant(e) => {
+          e.insert(et);
+        }
+        std::collections::btree_map::Entry::Occupied(e) => {
+          stat("struct extensionality", false);
+          to_union.push((*e.get(), et))
+        }
+      }
+    }
+  }
+
   /// Identities(aArithmIncl = arith)
   fn identities(&mut self, arith: bool) -> OrUnsat<()> {
     let mut to_union = vec![];
@@ -1107,6 +1412,7 @@ impl<'a> Equalizer<'a> {
           }
         }
       }
+      self.struct_extensionality(&mut to_union);
       for (x, y) in to_union.drain(..) {
         self.union_terms(x, y)?;
       }
@@ -1175,7 +1481,7 @@ impl<'a> Equalizer<'a> {
             }
           };
         }
-        match self.g.reqs.rev.get(i).copied().flatten() {
+        match self.g.checker_reqs().rev(i) {
           Some(Requirement::Union) =>
             for &m in marks {
               let (Term::Functor { ref args, .. }, et) = self.lc.marks[m] else { unreachable!() };
@@ -1276,10 +1582,8 @@ impl<'a> Equalizer<'a> {
                   to_union.push((self.lc.marks[self.terms[et].mark].1, et1)),
                 (_, Some(x2)) if *x2 == Complex::ONE =>
                   to_union.push((self.lc.marks[self.terms[et].mark].1, et1)),
-                (Some(x1), Some(x2)) =>
-                  if *x2 != Complex::ZERO {
-                    to_number.push((self.lc.marks[self.terms[et].mark].1, x1.clone() / x2.clone()))
-                  },
+                (Some(x1), Some(x2)) if *x2 != Complex::ZERO =>
+                  to_number.push((self.lc.marks[self.terms[et].mark].1, x1.clone() / x2.clone())),
                 _ => {}
               }
             },
@@ -1339,7 +1643,18 @@ impl<'a> Equalizer<'a> {
                       .zip(&**args2)
                       .all(|(ty1, ty2)| EqMarks.eq(self.g, self.lc, &ty1.1, &ty2.1))
                     && EqMarks.eq(self.g, self.lc, sc1, sc2)
-                    && EqMarks.eq(self.g, self.lc, compr1, compr2)
+                    && (EqMarks.eq(self.g, self.lc, compr1, compr2) || {
+                      let equiv = self.equiv_compr(compr1, compr2);
+                      stat(
+                        if equiv {
+                          "fraenkel extensionality"
+                        } else {
+                          "fraenkel extensionality failed"
+                        },
+                        false,
+                      );
+                      equiv
+                    })
                   {
                     to_union.push((et1, et2))
                   },
@@ -1381,6 +1696,7 @@ impl<'a> Equalizer<'a> {
     // vprintln!("subst {from:?}' := {p:?}");
     // let from2 = self.lc.marks[self.terms[from].mark].1;
     let mut progress = false;
+    let mut to_number = vec![];
     for (v, etm) in self.terms.enum_iter_mut() {
       if v != from && !etm.eq_class.is_empty() {
         for mut p2 in std::mem::take(&mut etm.eq_polys) {
@@ -1395,7 +1711,7 @@ impl<'a> Equalizer<'a> {
                 if let Some(pending) = &mut pending {
                   pending.insert(from);
                 }
-                etm.number = Some(c)
+                to_number.push((v, c))
               }
             }
             progress = true;
@@ -1406,6 +1722,9 @@ impl<'a> Equalizer<'a> {
         }
       }
     }
+    for (v, c) in to_number {
+      self.set_number(v, c)?
+    }
     Ok(progress)
   }
 
@@ -1426,7 +1745,7 @@ impl<'a> Equalizer<'a> {
 
   /// EquatePolynomialValues
   fn equate_polynomial_values(&mut self, eqs: &mut Equals) -> OrUnsat<()> {
-    if self.g.reqs.complex().is_none() {
+    if self.g.checker_reqs().complex().is_none() {
       return Ok(())
     }
     let mut to_subst = vec![];
@@ -1599,13 +1918,13 @@ impl<'a> Equalizer<'a> {
   }
 
   fn nonempty_nonzero_of_ne(&mut self, et1: EqTermId, et2: EqTermId) -> OrUnsat<()> {
-    if let Some(empty) = self.g.reqs.empty() {
+    if let Some(empty) = self.g.checker_reqs().empty() {
       // a != b, a is empty => b is non empty
       self.insert_non_attr0(et1, et2, empty)?;
       // a != b, b is empty => a is non empty
       self.insert_non_attr0(et2, et1, empty)?;
     }
-    if let Some(zero) = self.g.reqs.zero() {
+    if let Some(zero) = self.g.checker_reqs().zero() {
       // a != b, a is zero => b is non zero
       self.insert_non_attr0(et1, et2, zero)?;
       // a != b, b is zero => a is non zero
@@ -1628,14 +1947,43 @@ impl<'a> Equalizer<'a> {
     Ok(())
   }
 
+  /// The type `ty2` of the class `et` strengthened with the attributes of its supercluster,
+  /// e.g. `finite set` for `set` once the supercluster has `finite`. Only the attributes
+  /// whose arguments are in classes that are still present are added. This is a variant
+  /// for the `Is` checks, and `ty_class` itself is not grown.
+  fn strengthened_type(&self, et: EqTermId, ty2: &Type) -> Type {
+    let mut ty2 = ty2.clone();
+    let present = |tm: &Term| {
+      tm.mark().is_none_or(|m| !self.terms[self.lc.marks[m].1].eq_class.is_empty())
+    };
+    for attr in self.terms[et].supercluster.attrs() {
+      if attr.args.iter().all(present) {
+        ty2.attrs.0.insert(Some(&self.g.constrs), self.lc, attr.clone());
+        ty2.attrs.1.insert(Some(&self.g.constrs), self.lc, attr.clone());
+      }
+    }
+    ty2
+  }
+
+  /// Whether `et is ty` follows from the types of the class, strengthened by
+  /// `strengthened_type`: the lower attributes of `ty` are among the attributes of one of
+  /// them, and the radices match.
+  fn has_type(&self, et: EqTermId, ty: &Type) -> bool {
+    self.terms[et].ty_class.iter().any(|ty2| {
+      let ty2 = self.strengthened_type(et, ty2);
+      ty2.decreasing_attrs(ty, |a1, a2| EqMarks.eq(self.g, self.lc, a1, a2))
+        && self.with_eq(|ctx| EqMarks.eq_radices(ctx, ty, &ty2))
+    })
+  }
+
   fn match_formulas(&self, neg: &Formula, pos_bas: &Atoms) -> OrUnsat<()> {
-    for pos in &pos_bas.0 .0 {
+    for pos in pos_bas.iter() {
       match (neg, pos) {
+        // compared up to redefinitions, dropping the superfluous arguments
+        (Formula::Attr { .. }, Formula::Attr { .. })
+          if EqMarks.eq(self.g, self.lc, neg, pos) =>
+          return Err(Unsat),
         (
-          Formula::Attr { nr: AttrId(n1), args: args1 },
-          Formula::Attr { nr: AttrId(n2), args: args2 },
-        )
-        | (
           Formula::SchPred { nr: SchPredId(n1), args: args1 },
           Formula::SchPred { nr: SchPredId(n2), args: args2 },
         )
@@ -1649,40 +1997,57 @@ impl<'a> Equalizer<'a> {
     Ok(())
   }
 
-  fn depends_on(&self, etm: &EqTerm, tgt: EqTermId) -> bool {
-    assert!(!self.terms[tgt].eq_class.is_empty());
-    !etm.eq_class.is_empty() && {
-      struct CheckEqTerm<'a> {
-        marks: &'a IdxVec<EqMarkId, (Term, EqTermId)>,
-        terms: &'a IdxVec<EqTermId, EqTerm>,
-        tgt: EqTermId,
-        found: bool,
-      }
-      impl Visit for CheckEqTerm<'_> {
-        fn abort(&self) -> bool { self.found }
-        fn visit_term(&mut self, tm: &Term) {
-          match *tm {
-            Term::EqClass(_) => self.found = true,
-            Term::EqMark(m) => {
-              let (ref tm, et) = self.marks[m];
-              if matches!(tm, Term::EqClass(_)) {
-                self.found |= self.marks[self.terms[et].mark].1 == self.tgt
-              } else {
-                self.super_visit_term(tm);
-              }
+  /// Adds `j` to the reverse dependency index `uses`, by recording it under every class
+  /// mentioned by its members, types and supercluster. Classes that contain a bare
+  /// `EqClass` term are put in `uses_all`, as they depend on every class.
+  fn add_uses(
+    &self, j: EqTermId, uses: &mut IdxVec<EqTermId, BTreeSet<EqTermId>>,
+    uses_all: &mut BTreeSet<EqTermId>,
+  ) {
+    struct CollectEqTerms<'a> {
+      marks: &'a IdxVec<EqMarkId, (Term, EqTermId)>,
+      terms: &'a IdxVec<EqTermId, EqTerm>,
+      found: BTreeSet<EqTermId>,
+      all: bool,
+    }
+    impl Visit for CollectEqTerms<'_> {
+      fn abort(&self) -> bool { self.all }
+      fn visit_term(&mut self, tm: &Term) {
+        match *tm {
+          Term::EqClass(_) => self.all = true,
+          Term::EqMark(m) => {
+            let (ref tm, et) = self.marks[m];
+            if matches!(tm, Term::EqClass(_)) {
+              self.found.insert(self.marks[self.terms[et].mark].1);
+            } else {
+              self.super_visit_term(tm);
             }
-            _ => self.super_visit_term(tm),
           }
+          _ => self.super_visit_term(tm),
         }
       }
+    }
 
-      let mut ck = CheckEqTerm { marks: &self.lc.marks, terms: &self.terms, tgt, found: false };
-      for &m in &etm.eq_class {
-        ck.visit_term(&Term::EqMark(m))
-      }
-      ck.visit_types(&etm.ty_class);
-      ck.visit_attrs(&etm.supercluster);
-      ck.found
+    let etm = &self.terms[j];
+    if etm.eq_class.is_empty() {
+      return
+    }
+    let mut ck = CollectEqTerms {
+      marks: &self.lc.marks,
+      terms: &self.terms,
+      found: Default::default(),
+      all: false,
+    };
+    for &m in &etm.eq_class {
+      ck.visit_term(&Term::EqMark(m))
+    }
+    ck.visit_types(&etm.ty_class);
+    ck.visit_attrs(&etm.supercluster);
+    if ck.all {
+      uses_all.insert(j);
+    }
+    for tgt in ck.found {
+      uses[tgt].insert(j);
     }
   }
 
@@ -1707,10 +2072,126 @@ impl<'a> Equalizer<'a> {
 
   pub fn run(
     &mut self, atoms: &Atoms, conj: &Conjunct<AtomId, bool>,
-  ) -> OrUnsat<EnumMap<bool, Atoms>> {
-    self.lc.marks.0.clear();
-    let mut eqs = Equals::default();
-    let mut bas = EnumMap::<bool, Atoms>::default();
+  ) -> OrUnsat<EnumMap<Polarity, Atoms>> {
+    self.push_premise(atoms, conj)?;
+    self.saturate()
+  }
+
+  /// Saves the state of the equalizer, so that `rollback` can return to it. This is used to
+  /// share the work of `push_premise` between conjuncts with a common subset of premises.
+  pub fn snapshot(&self) -> EqSnapshot {
+    EqSnapshot {
+      marks: self.lc.marks.clone(),
+      infers: self.infers.clone(),
+      constrs: self.constrs.clone(),
+      terms: self.terms.clone(),
+      next_eq_class: self.next_eq_class,
+      clash: self.clash,
+      numbers: self.numbers.clone(),
+      numbers_merged: self.numbers_merged,
+      bas: self.bas.clone(),
+      eqs: self.eqs.clone(),
+    }
+  }
+
+  pub fn rollback(&mut self, snap: EqSnapshot) {
+    self.lc.marks = snap.marks;
+    self.infers = snap.infers;
+    self.constrs = snap.constrs;
+    self.terms = snap.terms;
+    self.next_eq_class = snap.next_eq_class;
+    self.clash = snap.clash;
+    self.numbers = snap.numbers;
+    self.numbers_merged = snap.numbers_merged;
+    self.bas = snap.bas;
+    self.eqs = snap.eqs;
+  }
+
+  /// Runs the equalizer passes on the premises pushed so far, and returns
+  /// the negative and positive basis for the unifier.
+  pub fn saturate(&mut self) -> OrUnsat<EnumMap<Polarity, Atoms>> {
+    let (bas, mut eqs) = (std::mem::take(&mut self.bas), std::mem::take(&mut self.eqs));
+
+    // vprintln!("start");
+    // for (et, etm) in self.terms.enum_iter() {
+    //   vprintln!("state: {et:?}' {:#?}", etm);
+    // }
+    // vprintln!("eqs = {:?}", eqs.0);
+
+    let [mut neg_bas, mut pos_bas] = bas.into_array();
+    self.add_symm(&pos_bas, &mut neg_bas, PropertyKind::Asymmetry);
+    self.add_symm(&neg_bas, &mut pos_bas, PropertyKind::Connectedness);
+
+    self.check_pending_equals(&eqs)?;
+    let settings = self.init_eq_classes(&mut eqs)?;
+    self.init_special_classes(&mut eqs)?;
+    self.init_numbers(eqs, settings)?;
+    self.renumber_classes()?;
+    self.verify_contradictions(&pos_bas, &neg_bas)?;
+    self.saturate_pos(&pos_bas)?;
+    self.saturate_element(&pos_bas)?;
+    self.saturate_neg(&pos_bas, &neg_bas)?;
+    self.round_up_superclusters()?;
+    self.out_of_time()?;
+    self.pre_unification(&pos_bas, &mut neg_bas)?;
+    Ok(EnumMap::from_array([neg_bas, pos_bas]))
+  }
+
+  /// Explains why `t1` and `t2` are not equal in the state left by `run`.
+  /// The terms should be interned, but need not have been seen by the equalizer.
+  pub fn explain_neq(&self, t1: &Term, t2: &Term) -> NeqExplanation {
+    let Some(m1) = self.locate_term(&Conjunct::TRUE, t1) else {
+      return NeqExplanation::NotRepresentable(0)
+    };
+    let Some(m2) = self.locate_term(&Conjunct::TRUE, t2) else {
+      return NeqExplanation::NotRepresentable(1)
+    };
+    let (et1, et2) = (self.lc.marks[m1].1, self.lc.marks[m2].1);
+    if et1 == et2 {
+      return NeqExplanation::Equal
+    }
+    match (&self.terms[et1].number, &self.terms[et2].number) {
+      (Some(n1), Some(n2)) if n1 != n2 => return NeqExplanation::Numbers(n1.clone(), n2.clone()),
+      _ => {}
+    }
+    let mut same_head = false;
+    for &m1 in &self.terms[et1].eq_class {
+      for &m2 in &self.terms[et2].eq_class {
+        let (tm1, tm2) = (&self.lc.marks[m1].0, &self.lc.marks[m2].0);
+        let (args1, args2) = match (tm1, tm2) {
+          (Term::Functor { nr: n1, args: a1 }, Term::Functor { nr: n2, args: a2 }) if n1 == n2 =>
+            (a1, a2),
+          (Term::Selector { nr: n1, args: a1 }, Term::Selector { nr: n2, args: a2 })
+            if n1 == n2 =>
+            (a1, a2),
+          (Term::Aggregate { nr: n1, args: a1 }, Term::Aggregate { nr: n2, args: a2 })
+            if n1 == n2 =>
+            (a1, a2),
+          (Term::SchFunc { nr: n1, args: a1 }, Term::SchFunc { nr: n2, args: a2 }) if n1 == n2 =>
+            (a1, a2),
+          _ => continue,
+        };
+        same_head = true;
+        let class = |tm: &Term| tm.mark().map(|m| self.terms[self.lc.marks[m].1].id);
+        if let Some(pos) = args1.iter().zip(&**args2).position(|(a1, a2)| class(a1) != class(a2))
+        {
+          return NeqExplanation::Args { pos, terms: Box::new([tm1.clone(), tm2.clone()]) }
+        }
+      }
+    }
+    if same_head {
+      NeqExplanation::Unknown
+    } else {
+      NeqExplanation::Heads
+    }
+  }
+
+  /// The initial pass: y all the atoms of `conj`, adding the positive equalities to `eqs`
+  /// and the other atoms to the positive and negative basis. This can be called several
+  /// times before `saturate`, and then the premises are all considered together.
+  pub fn push_premise(&mut self, atoms: &Atoms, conj: &Conjunct<AtomId, bool>) -> OrUnsat<()> {
+    let mut eqs = std::mem::take(&mut self.eqs);
+    let mut bas = std::mem::take(&mut self.bas);
     // for (i, fv) in self.lc.fixed_var.enum_iter() {
     //   vprintln!("c{i:?} := {:#?}", fv);
     // }
@@ -1718,7 +2199,7 @@ impl<'a> Equalizer<'a> {
     //   vprintln!("?{i:?} := {:#?}", asgn);
     // }
     for pos in [true, false] {
-      for (i, f) in atoms.0.enum_iter() {
+      for (i, f) in atoms.enum_iter() {
         // vprintln!("y pass atom {f:?}");
         if conj.0.get(&i).copied() == Some(pos) {
           // vprintln!("{}: {f:?}", if pos { "assume" } else { "goal" });
@@ -1745,69 +2226,147 @@ impl<'a> Equalizer<'a> {
                 let m2 = self.y(|y| arg2.visit_cloned(y))?.mark().unwrap();
                 eqs.insert(self.lc.marks[m1].1, self.lc.marks[m2].1);
               } else {
-                bas[pos].0.push(self.y(|y| f.visit_cloned(y))?);
+                bas[pos.into()].push(&self.g.constrs, self.y(|y| f.visit_cloned(y))?, pos.into());
               }
             }
             _ => {
-              bas[pos].0.push(self.y(|y| f.visit_cloned(y))?);
+              bas[pos.into()].push(&self.g.constrs, self.y(|y| f.visit_cloned(y))?, pos.into());
             }
           }
         }
       }
     }
+    // the classes found equal by `set_number` in the y-pass
+    eqs.0.append(&mut self.eqs.0);
+    (self.bas, self.eqs) = (bas, eqs);
+    Ok(())
+  }
 
-    // vprintln!("start");
-    // for (et, etm) in self.terms.enum_iter() {
-    //   vprintln!("state: {et:?}' {:#?}", etm);
-    // }
-    // vprintln!("eqs = {:?}", eqs.0);
-
-    let [mut neg_bas, mut pos_bas] = bas.into_array();
-    self.add_symm(&pos_bas, &mut neg_bas, PropertyKind::Asymmetry);
-    self.add_symm(&neg_bas, &mut pos_bas, PropertyKind::Connectedness);
+  /// Refutes the premises early if an equality in `eqs` joins two classes with
+  /// contradictory attributes or different numbers, as these would only be found
+  /// by `union_terms` after the classes are initialized.
+  fn check_pending_equals(&self, eqs: &Equals) -> OrUnsat<()> {
+    let class = |et: EqTermId| &self.terms[self.lc.marks[self.terms[et].mark].1];
+    for &(et1, et2) in &eqs.0 {
+      let (etm1, etm2) = (class(et1), class(et2));
+      if etm1.supercluster.contradicts(&self.g.constrs, self.lc, &etm2.supercluster)
+        || matches!((&etm1.number, &etm2.number), (Some(n1), Some(n2)) if n1 != n2)
+      {
+        stat("early unsat from equality", false);
+        return Err(Unsat)
+      }
+    }
+    Ok(())
+  }
 
+  /// Expands the inference constants in the classes into their definitions and equal
+  /// constants, and the `set` constants into their definitions.
+  /// Returns the settings (`x = def(x)` equations) for SubstituteSettings.
+  fn init_eq_classes(&mut self, eqs: &mut Equals) -> OrUnsat<Equals> {
     let mut to_y_term = vec![];
     let mut to_yy_term = vec![];
+    let mut to_intern = vec![];
+    let mut fixed_seen = BTreeSet::new();
     let mut settings = Equals::default();
     let mut i = EqTermId::default();
+    let marks = self.lc.marks.len();
+    peak("equalizer marks before eq classes", marks);
     // This cannot be a for loop because the terms list grows due to y_term() and yy_term()
     while let Some(ets) = self.terms.get(i) {
       for &m in &ets.eq_class {
         if let Term::Infer(id) = self.lc.marks[m].0 {
           let asgn = &self.lc.infer_const.borrow()[id];
           for &z in &asgn.eq_const {
+            // Skip constants that are already in this class, to avoid redundant marks
+            // when many constants alias each other
+            let is_z = |&m: &EqMarkId| matches!(self.lc.marks[m].0, Term::Infer(z2) if z2 == z);
+            if ets.eq_class.iter().any(is_z) {
+              stat("eq_const shared", false);
+              continue
+            }
+            stat("eq_const", false);
             to_y_term.push((i, Term::Infer(z)));
           }
-          to_yy_term.push((i, asgn.def.visit_cloned(&mut ExpandPrivFunc(&self.g.constrs, self.lc))))
+          let def = asgn.def.visit_cloned(&mut ExpandPrivFunc(&self.g.constrs, self.lc));
+          to_yy_term.push((i, def));
+          // Interning usually records the definition of a `set` constant as an equal
+          // constant already, but not always, so we add it here as well.
+          // Definitions only refer to earlier constants, but we guard against cycles anyway.
+          if let Term::Const(c) = asgn.def {
+            if let Some((def, _)) = &self.lc.fixed_var[c].def {
+              if fixed_seen.insert(c) {
+                let def = (**def).visit_cloned(&mut ExpandPrivFunc(&self.g.constrs, self.lc));
+                to_intern.push((i, def))
+              }
+            }
+          }
+        }
+      }
+      for (i, mut tm) in to_intern.drain(..) {
+        tm.visit(&mut self.intern_const());
+        // The definition is already accounted for if its representative is in the class,
+        // or about to be added to it
+        let rep = match tm {
+          Term::Infer(z) => Some(Term::Infer(self.lc.eq_const_rep(z))),
+          _ => None,
+        };
+        let is_tm = |mark: &Term| *mark == tm || Some(mark) == rep.as_ref();
+        if !self.terms[i].eq_class.iter().any(|&m| is_tm(&self.lc.marks[m].0))
+          && !to_y_term.iter().any(|(j, mark)| *j == i && is_tm(mark))
+        {
+          stat("fixed_var def", false);
+          to_y_term.push((i, tm))
         }
       }
-      self.drain_pending(&mut to_y_term, &mut eqs)?;
+      self.drain_pending(&mut to_y_term, eqs)?;
       for (i, tm) in to_yy_term.drain(..) {
         settings.insert(i, self.yy_term(tm, i)?)
       }
       i.0 += 1;
     }
+    peak("equalizer marks from eq classes", self.lc.marks.len() - marks);
+    Ok(settings)
+  }
 
+  /// InitEmptyInEqClass, InitZeroInEqClass, InitStructuresInEqClass, and reductions
+  fn init_special_classes(&mut self, eqs: &mut Equals) -> OrUnsat<()> {
+    let mut to_y_term = vec![];
     // InitEmptyInEqClass
-    if let Some(empty_set) = self.g.reqs.empty_set() {
-      let empty = self.g.reqs.empty().unwrap();
+    let reqs = self.g.checker_reqs();
+    if let (Some(empty_set), Some(empty)) = (reqs.empty_set(), reqs.empty()) {
+      let element = reqs.element();
       for (i, ets) in self.terms.enum_iter() {
         assert!(!ets.eq_class.is_empty()); // TODO: is this true?
-        if !ets.eq_class.is_empty() && ets.supercluster.find0(&self.g.constrs, empty, true) {
+        if ets.eq_class.is_empty() {
+          continue
+        }
+        // An `Element of X` where `X` is empty is itself empty (SUBSET_1:def 1)
+        let empty_elem = || {
+          ets.ty_class.iter().any(|ty| {
+            let TypeKind::Mode(n) = ty.kind else { return false };
+            match Type::adjust(n, &ty.args, &self.g.constrs) {
+              (n, [x]) if Some(n) == element => {
+                let et = self.lc.marks[x.mark().unwrap()].1;
+                self.terms[et].supercluster.find0(&self.g.constrs, empty, true)
+              }
+              _ => false,
+            }
+          })
+        };
+        if ets.supercluster.find0(&self.g.constrs, empty, true) || empty_elem() {
           to_y_term.push((i, Term::Functor { nr: empty_set, args: Box::new([]) }))
         }
       }
-      self.drain_pending(&mut to_y_term, &mut eqs)?;
+      self.drain_pending(&mut to_y_term, eqs)?;
     }
-    if let Some(zero_number) = self.g.reqs.zero_number() {
-      let zero = self.g.reqs.zero().unwrap();
+    if let (Some(zero_number), Some(zero)) = (reqs.zero_number(), reqs.zero()) {
       for (i, ets) in self.terms.enum_iter() {
         assert!(!ets.eq_class.is_empty()); // TODO: is this true?
         if !ets.eq_class.is_empty() && ets.supercluster.find0(&self.g.constrs, zero, true) {
           to_y_term.push((i, Term::Functor { nr: zero_number, args: Box::new([]) }))
         }
       }
-      self.drain_pending(&mut to_y_term, &mut eqs)?;
+      self.drain_pending(&mut to_y_term, eqs)?;
     }
 
     // InitStructuresInEqClass
@@ -1823,16 +2382,24 @@ impl<'a> Equalizer<'a> {
         let mut strict_struct = None;
         for attr in ets.supercluster.try_attrs().unwrap() {
           if attr.is_strict(&self.g.constrs) {
-            let TypeKind::Struct(s) = self.g.constrs.attribute[attr.nr].ty.kind else { panic!() };
-            if matches!(strict_struct.replace(s), Some(old) if old != s) {
-              return Err(Unsat)
+            let nr = attr.adjusted_nr(&self.g.constrs);
+            let TypeKind::Struct(s) = self.g.constrs.attribute[nr].ty.kind else { panic!() };
+            if let Some((old, old_attr)) = strict_struct.replace((s, nr)) {
+              if old != s {
+                vprintln!("strict attributes clash: {:?} vs {:?}", old_attr, attr.nr);
+                return Err(Unsat)
+              }
             }
           }
         }
-        if let Some(s) = strict_struct {
+        if let Some((s, _)) = strict_struct {
           for ty in &ets.ty_class {
             if ty.kind == TypeKind::Struct(s) {
-              to_y_term.push((ei, Term::mk_aggr(self.g, self.lc, s, &Term::EqMark(ets.mark), ty)))
+              // If the aggregate can't be built, we just don't get the extra equation
+              let arg = Term::EqMark(ets.mark);
+              if let Some(tm) = Term::mk_aggr(self.g, self.lc, s, &arg, ty) {
+                to_y_term.push((ei, tm))
+              }
             }
           }
         }
@@ -1846,16 +2413,24 @@ impl<'a> Equalizer<'a> {
     // vprintln!("drain_pending -> {eqs:?}");
 
     self.process_reductions()?;
+    Ok(())
+  }
 
+  /// Propagates `complex` and merges the pending equalities,
+  /// then solves the polynomial and linear equations between the classes.
+  fn init_numbers(&mut self, mut eqs: Equals, settings: Equals) -> OrUnsat<()> {
+    // the classes found equal by `set_number` since `eqs` was taken
+    eqs.0.append(&mut std::mem::take(&mut self.eqs).0);
+    self.numbers_merged = true;
     // InitSuperClusterForComplex
-    if let Some(complex) = self.g.reqs.complex() {
+    if let Some(complex) = self.g.checker_reqs().complex() {
       let mut to_complex = vec![];
       for (et, etm) in self.terms.enum_iter() {
         for &m in &etm.eq_class {
           match self.lc.marks[m].0 {
             Term::Infer(_) if etm.number.is_some() => to_complex.push(et),
             Term::Functor { nr, ref args } =>
-              if let Some(Some(
+              if let Some(
                 Requirement::ImaginaryUnit
                 | Requirement::RealNeg
                 | Requirement::RealInv
@@ -1863,7 +2438,7 @@ impl<'a> Equalizer<'a> {
                 | Requirement::RealMult
                 | Requirement::RealDiff
                 | Requirement::RealDiv,
-              )) = self.g.reqs.rev.get(nr)
+              ) = self.g.checker_reqs().rev(nr)
               {
                 for arg1 in &**args {
                   let et1 = self.lc.marks[arg1.mark().unwrap()].1;
@@ -1883,7 +2458,7 @@ impl<'a> Equalizer<'a> {
 
     // UnionEqualsForNonComplex
     let eqs_orig = eqs.0.len();
-    if let Some(complex) = self.g.reqs.complex() {
+    if let Some(complex) = self.g.checker_reqs().complex() {
       let mut unsat = Ok(());
       eqs.0.retain(|&(x, y)| {
         let et1 = self.lc.marks[self.terms[x].mark].1;
@@ -1915,7 +2490,7 @@ impl<'a> Equalizer<'a> {
     }
 
     // InitPolynomialValues
-    if let Some(complex) = self.g.reqs.complex() {
+    if let Some(complex) = self.g.checker_reqs().complex() {
       let mut pending = BTreeSet::new();
       for et in (0..self.terms.len()).map(EqTermId::from_usize) {
         let etm = &mut self.terms[et];
@@ -1962,7 +2537,7 @@ impl<'a> Equalizer<'a> {
                     pending.insert(et);
                   }};
                 }
-                match self.g.reqs.rev.get(nr).copied().flatten() {
+                match self.g.checker_reqs().rev(nr) {
                   Some(Requirement::ImaginaryUnit) => {
                     assert!(etm.number.is_some());
                     etm.eq_polys.insert(Polynomial::single(Monomial::cnst(Complex::I)));
@@ -2047,7 +2622,10 @@ impl<'a> Equalizer<'a> {
         break
       }
     }
+    Ok(())
+  }
 
+  fn renumber_classes(&mut self) -> OrUnsat<()> {
     // vprintln!("before renumber");
     // for (et, etm) in self.terms.enum_iter() {
     //   vprintln!("state: {et:?}' {:#?}", etm);
@@ -2086,9 +2664,12 @@ impl<'a> Equalizer<'a> {
     // for (et, etm) in self.terms.enum_iter() {
     //   vprintln!("state: {et:?}' {:#?}", etm);
     // }
+    Ok(())
+  }
 
+  fn verify_contradictions(&mut self, pos_bas: &Atoms, neg_bas: &Atoms) -> OrUnsat<()> {
     // ContradictionVerify
-    for neg in &neg_bas.0 .0 {
+    for neg in neg_bas.iter() {
       match neg {
         Formula::Attr { nr, args } => self.check_neg_attr(*nr, args)?,
         Formula::Pred { nr, args } => {
@@ -2106,23 +2687,20 @@ impl<'a> Equalizer<'a> {
         Formula::Attr { .. }
         | Formula::SchPred { .. }
         | Formula::PrivPred { .. }
-        | Formula::Pred { .. } => {
-          if pos_bas.0 .0.iter().any(|pos| EqMarks.eq(self.g, self.lc, pos, neg)) {
-            return Err(Unsat)
-          }
-        }
-        Formula::Is { term, ty } => {
-          for ty2 in &self.terms[self.lc.marks[term.mark().unwrap()].1].ty_class {
-            if self.with_eq(|ctx| EqMarks.eq_radices(ctx, ty2, ty)) {
-              return Err(Unsat)
-            }
-          }
-        }
+        | Formula::Pred { .. }
+          if pos_bas.iter().any(|pos| EqMarks.eq(self.g, self.lc, pos, neg)) =>
+          return Err(Unsat),
+        // Checker::process_is usually splits the attributes off negative `Is` atoms into
+        // positive attribute alternatives, which clash with the supercluster directly.
+        // The attributes left, as for a struct type whose strictness is part of the type,
+        // are matched against the types of the class strengthened with the supercluster.
+        Formula::Is { term, ty } if self.has_type(self.lc.marks[term.mark().unwrap()].1, ty) =>
+          return Err(Unsat),
         _ => {}
       }
     }
 
-    for neg in &neg_bas.0 .0 {
+    for neg in neg_bas.iter() {
       if let Formula::Pred { nr, args } = neg {
         let props = self.g.constrs.predicate[*nr].properties;
         if props.get(PropertyKind::Reflexivity) {
@@ -2132,22 +2710,29 @@ impl<'a> Equalizer<'a> {
         }
       }
     }
+    Ok(())
+  }
 
+  /// Saturates the classes with the consequences of the positive basis
+  fn saturate_pos(&mut self, pos_bas: &Atoms) -> OrUnsat<()> {
+    let reqs = self.g.checker_reqs();
     loop {
+      self.out_of_time()?;
       let mut added = false;
       // vprintln!("start pos loop");
       // for (et, etm) in self.terms.enum_iter() {
       //   vprintln!("state: {et:?}' {:#?}", etm);
       // }
-      for pos in &pos_bas.0 .0 {
+      let preds = [reqs.less_or_equal(), reqs.belongs_to(), reqs.inclusion()];
+      for pos in preds.into_iter().flat_map(|nr| pos_bas.preds(nr)) {
         if let Formula::Pred { nr, args } = pos {
           let (nr, args) = Formula::adjust_pred(*nr, args, Some(&self.g.constrs));
-          if self.g.reqs.less_or_equal() == Some(nr) {
+          if reqs.less_or_equal() == Some(nr) {
             let [arg1, arg2] = args else { unreachable!() };
             let et1 = self.lc.marks[arg1.mark().unwrap()].1;
             let et2 = self.lc.marks[arg2.mark().unwrap()].1;
             if let (Some(positive), Some(negative)) =
-              (self.g.reqs.positive(), self.g.reqs.negative())
+              (reqs.positive(), reqs.negative())
             {
               // a <= b, a is positive => b is positive
               let pos1 = self.terms[et1].supercluster.find0(&self.g.constrs, positive, true);
@@ -2181,7 +2766,7 @@ impl<'a> Equalizer<'a> {
                   self.lc,
                   Attr::new0(positive, false),
                 )?;
-              if let Some(zero) = self.g.reqs.zero() {
+              if let Some(zero) = reqs.zero() {
                 // a <= b, a is non negative, b is non zero => b is positive
                 if nonneg1 && self.terms[et2].supercluster.find0(&self.g.constrs, zero, false) {
                   added |= self.terms[et2].supercluster.try_insert(
@@ -2205,11 +2790,11 @@ impl<'a> Equalizer<'a> {
                 return Err(Unsat)
               }
             }
-          } else if self.g.reqs.belongs_to() == Some(nr) {
+          } else if reqs.belongs_to() == Some(nr) {
             let [arg1, arg2] = args else { unreachable!() };
             let et1 = self.lc.marks[arg1.mark().unwrap()].1;
             let et2 = self.lc.marks[arg2.mark().unwrap()].1;
-            if let Some(empty) = self.g.reqs.empty() {
+            if let Some(empty) = reqs.empty() {
               // A in B => B is non empty
               added |= self.terms[et2].supercluster.try_insert(
                 &self.g.constrs,
@@ -2217,13 +2802,13 @@ impl<'a> Equalizer<'a> {
                 Attr::new0(empty, false),
               )?;
             }
-            if let Some(element) = self.g.reqs.element() {
+            if let Some(element) = reqs.element() {
               // A in B => A: Element of B
               let ty = Type { args: vec![arg2.clone()], ..Type::new(element.into()) };
               self.insert_type(ty, et1)?;
             }
-          } else if self.g.reqs.inclusion() == Some(nr) {
-            if let (Some(element), Some(pw)) = (self.g.reqs.element(), self.g.reqs.power_set()) {
+          } else if reqs.inclusion() == Some(nr) {
+            if let (Some(element), Some(pw)) = (reqs.element(), reqs.power_set()) {
               let [arg1, arg2] = args else { unreachable!() };
               // A c= B => A: Element of bool B
               let mut tm = Term::Functor { nr: pw, args: Box::new([arg2.clone()]) };
@@ -2234,32 +2819,81 @@ impl<'a> Equalizer<'a> {
           }
         }
       }
+      if let (Some(positive), Some(negative)) = (reqs.positive(), reqs.negative()) {
+        added |= self.propagate_signs(positive, negative)?
+      }
       if !added {
         break
       }
     }
+    Ok(())
+  }
+
+  /// Adds the signs of sums and products that follow from the signs of their arguments,
+  /// such as `a is negative, b is positive => a * b is negative`. Returns true if anything
+  /// was added.
+  fn propagate_signs(&mut self, positive: AttrId, negative: AttrId) -> OrUnsat<bool> {
+    let reqs = self.g.checker_reqs();
+    let sign = |this: &Self, arg: &Term| {
+      let sc = &this.terms[this.lc.marks[arg.mark().unwrap()].1].supercluster;
+      let (pos, neg) =
+        (sc.find0(&this.g.constrs, positive, true), sc.find0(&this.g.constrs, negative, true));
+      let nonneg = pos || sc.find0(&this.g.constrs, negative, false);
+      let nonpos = neg || sc.find0(&this.g.constrs, positive, false);
+      Signs { pos, neg, nonneg, nonpos }
+    };
+    let mut to_insert = vec![];
+    for (nr, mult) in [(reqs.real_mult(), true), (reqs.real_add(), false)] {
+      let Some(marks) = nr.and_then(|nr| self.constrs.functor.0.get(&nr)) else { continue };
+      for &m in marks {
+        let Term::Functor { ref args, .. } = self.lc.marks[m].0 else { unreachable!() };
+        let [arg1, arg2] = &**args else { continue };
+        let Signs { pos, neg, nonneg, nonpos } =
+          Signs::combine(mult, sign(self, arg1), sign(self, arg2));
+        let et = self.lc.marks[self.terms[self.lc.marks[m].1].mark].1;
+        let attrs = [
+          (pos, positive, true),
+          (neg, negative, true),
+          (nonneg, negative, false),
+          (nonpos, positive, false),
+        ];
+        for (_, nr, pos) in attrs.into_iter().filter(|p| p.0) {
+          to_insert.push((et, Attr::new0(nr, pos)))
+        }
+      }
+    }
+    let mut added = false;
+    for (et, attr) in to_insert {
+      added |= self.terms[et].supercluster.try_insert(&self.g.constrs, self.lc, attr)?
+    }
+    Ok(added)
+  }
 
+  /// Saturates the classes with `x in X` for `X: Element of bool Y` memberships
+  fn saturate_element(&mut self, pos_bas: &Atoms) -> OrUnsat<()> {
+    let reqs = self.g.checker_reqs();
     loop {
+      self.out_of_time()?;
       let mut added = false;
       // vprintln!("start element transitivity loop");
       // for (et, etm) in self.terms.enum_iter() {
       //   vprintln!("state: {et:?}' {:#?}", etm);
       // }
-      for pos2 in &pos_bas.0 .0 {
+      for pos2 in pos_bas.preds(reqs.belongs_to()) {
         if let Formula::Pred { nr, args } = pos2 {
           let (nr, args) = Formula::adjust_pred(*nr, args, Some(&self.g.constrs));
-          if self.g.reqs.belongs_to() == Some(nr) {
+          if reqs.belongs_to() == Some(nr) {
             let [arg1, arg2] = args else { unreachable!() };
             let et2 = self.lc.marks[arg2.mark().unwrap()].1;
             let mut to_push = vec![];
             for ty in &self.terms[et2].ty_class {
               if let TypeKind::Mode(n) = ty.kind {
                 let (n, args) = Type::adjust(n, &ty.args, &self.g.constrs);
-                if self.g.reqs.element() == Some(n) {
+                if reqs.element() == Some(n) {
                   let [arg3] = args else { unreachable!() };
                   for &m in &self.terms[self.lc.marks[arg3.mark().unwrap()].1].eq_class {
                     if let Term::Functor { nr, args } = &self.lc.marks[m].0 {
-                      if self.g.reqs.power_set() == Some(*nr) {
+                      if reqs.power_set() == Some(*nr) {
                         let [arg4] = &**args else { unreachable!() };
                         // a in b, b: Element of bool C => C is non empty, a: Element of C
                         to_push.push(arg4.mark().unwrap());
@@ -2269,7 +2903,7 @@ impl<'a> Equalizer<'a> {
                 }
               }
             }
-            if let Some(empty) = self.g.reqs.empty() {
+            if let Some(empty) = reqs.empty() {
               for &m in &to_push {
                 let et = self.lc.marks[m].1;
                 self.terms[et].supercluster.try_insert(
@@ -2279,7 +2913,7 @@ impl<'a> Equalizer<'a> {
                 )?;
               }
             }
-            if let Some(element) = self.g.reqs.element() {
+            if let Some(element) = reqs.element() {
               let et1 = self.lc.marks[arg1.mark().unwrap()].1;
               for &m in &to_push {
                 let ty = Type { args: vec![Term::EqMark(m)], ..Type::new(element.into()) };
@@ -2293,29 +2927,35 @@ impl<'a> Equalizer<'a> {
         break
       }
     }
+    Ok(())
+  }
 
+  /// Saturates the classes with the consequences of the negative basis
+  fn saturate_neg(&mut self, pos_bas: &Atoms, neg_bas: &Atoms) -> OrUnsat<()> {
+    let reqs = self.g.checker_reqs();
     loop {
+      self.out_of_time()?;
       let mut added = false;
       // vprintln!("start neg loop");
       // for (et, etm) in self.terms.enum_iter() {
       //   vprintln!("state: {et:?}' {:#?}", etm);
       // }
-      for neg in &neg_bas.0 .0 {
+      for neg in neg_bas.iter() {
         match neg {
           Formula::Attr { nr, args } => {
             self.check_neg_attr(*nr, args)?;
-            self.match_formulas(neg, &pos_bas)?
+            self.match_formulas(neg, pos_bas)?
           }
           Formula::SchPred { .. } | Formula::PrivPred { .. } =>
-            self.match_formulas(neg, &pos_bas)?,
+            self.match_formulas(neg, pos_bas)?,
           Formula::Pred { nr, args } => {
             let (nr, args) = Formula::adjust_pred(*nr, args, Some(&self.g.constrs));
-            if self.g.reqs.less_or_equal() == Some(nr) {
+            if reqs.less_or_equal() == Some(nr) {
               let [arg1, arg2] = args else { unreachable!() };
               let et1 = self.lc.marks[arg1.mark().unwrap()].1;
               let et2 = self.lc.marks[arg2.mark().unwrap()].1;
               if let (Some(positive), Some(negative)) =
-                (self.g.reqs.positive(), self.g.reqs.negative())
+                (reqs.positive(), reqs.negative())
               {
                 // b < a, a is non positive => b is negative
                 added |= self.terms[et1].supercluster.find0(&self.g.constrs, positive, false)
@@ -2337,8 +2977,8 @@ impl<'a> Equalizer<'a> {
                   return Err(Unsat)
                 }
               }
-            } else if self.g.reqs.belongs_to() == Some(nr) {
-              if let (Some(element), Some(empty)) = (self.g.reqs.element(), self.g.reqs.empty()) {
+            } else if reqs.belongs_to() == Some(nr) {
+              if let (Some(element), Some(empty)) = (reqs.element(), reqs.empty()) {
                 let [arg1, arg2] = args else { unreachable!() };
                 let et1 = self.lc.marks[arg1.mark().unwrap()].1;
                 let et2 = self.lc.marks[arg2.mark().unwrap()].1;
@@ -2353,8 +2993,8 @@ impl<'a> Equalizer<'a> {
                   }
                 }
               }
-            } else if self.g.reqs.inclusion() == Some(nr) {
-              if let (Some(element), Some(pw)) = (self.g.reqs.element(), self.g.reqs.power_set()) {
+            } else if reqs.inclusion() == Some(nr) {
+              if let (Some(element), Some(pw)) = (reqs.element(), reqs.power_set()) {
                 let [arg1, arg2] = args else { unreachable!() };
                 let et1 = self.lc.marks[arg1.mark().unwrap()].1;
                 let mut tm = Term::Functor { nr: pw, args: Box::new([arg2.clone()]) };
@@ -2369,20 +3009,14 @@ impl<'a> Equalizer<'a> {
                 }
               }
             }
-            for pos in &pos_bas.0 .0 {
+            for pos in pos_bas.iter() {
               if EqMarks.eq(self.g, self.lc, neg, pos) {
                 return Err(Unsat)
               }
             }
           }
-          Formula::Is { term, ty } => {
-            let et = self.lc.marks[term.mark().unwrap()].1;
-            if self.with_eq(|ctx| {
-              self.terms[et].ty_class.iter().any(|ty2| EqMarks.eq_radices(ctx, ty, ty2))
-            }) {
-              return Err(Unsat)
-            }
-          }
+          Formula::Is { term, ty } if self.has_type(self.lc.marks[term.mark().unwrap()].1, ty) =>
+            return Err(Unsat),
           _ => {}
         }
       }
@@ -2390,12 +3024,15 @@ impl<'a> Equalizer<'a> {
         break
       }
     }
+    Ok(())
+  }
 
+  fn round_up_superclusters(&mut self) -> OrUnsat<()> {
     let mut eq_stack: BTreeSet<EqTermId> =
       self.terms.enum_iter().filter(|p| !p.1.eq_class.is_empty()).map(|p| p.0).collect();
 
     // InitAllowedClusters
-    let allowed = AllowedClusters {
+    let mut allowed = AllowedClusters {
       ccl: (self.g.clusters.conditional.iter())
         .map(|cl| self.filter_allowed(&cl.consequent.1))
         .enumerate()
@@ -2407,6 +3044,23 @@ impl<'a> Equalizer<'a> {
         .filter(|attrs| !attrs.1.attrs().is_empty())
         .collect(),
     };
+    let record_hits = self.g.cfg.cluster_hits.is_some();
+    if record_hits {
+      ClusterHits::sort(&self.lc.cluster_hits.ccl, &mut allowed.ccl);
+      ClusterHits::sort(&self.lc.cluster_hits.fcl, &mut allowed.fcl);
+    } else if let Some(seed) = self.g.cfg.shuffle_seed {
+      // the result is a fixpoint, so it must not depend on the order of the clusters
+      let mut sh = Shuffle::new(seed);
+      sh.shuffle(&mut allowed.ccl);
+      sh.shuffle(&mut allowed.fcl);
+    }
+
+    // uses[i] is the set of classes that mention class i
+    let mut uses = IdxVec::from_default(self.terms.len());
+    let mut uses_all = BTreeSet::new();
+    for j in self.terms.enum_iter().map(|p| p.0) {
+      self.add_uses(j, &mut uses, &mut uses_all)
+    }
 
     while let Some(i) = eq_stack.pop_first() {
       // RoundUpSuperCluster
@@ -2416,6 +3070,7 @@ impl<'a> Equalizer<'a> {
       // vprintln!("round up superclusters {i:?}' {:#?}", self.terms[i]);
       let mut progress = false;
       loop {
+        self.out_of_time()?;
         let mut added = false;
         for &(j, ref attrs) in &allowed.ccl {
           let cl = &self.g.clusters.conditional.vec[j];
@@ -2423,7 +3078,13 @@ impl<'a> Equalizer<'a> {
           let inst = self.instantiate(&cl.primary);
           let mut r = inst.inst_type(&cl.ty, i);
           inst.and_inst_attrs(&cl.antecedent, i, &mut r);
-          added |= self.round_up_one_supercluster(i, attrs, &r)?;
+          if self.round_up_one_supercluster(i, attrs, &r)? {
+            if record_hits {
+              stat("round-up conditional cluster hit", false);
+              ClusterHits::hit(&mut self.lc.cluster_hits.ccl, j)
+            }
+            added = true
+          }
         }
         for &(j, ref attrs) in &allowed.fcl {
           let cl = &self.g.clusters.functor.vec[j];
@@ -2433,7 +3094,13 @@ impl<'a> Equalizer<'a> {
           if let Some(ty) = &cl.ty {
             r.mk_and_then(|| Ok(inst.inst_type(ty, i))).unwrap()
           }
-          added |= self.round_up_one_supercluster(i, attrs, &r)?;
+          if self.round_up_one_supercluster(i, attrs, &r)? {
+            if record_hits {
+              stat("round-up functor cluster hit", false);
+              ClusterHits::hit(&mut self.lc.cluster_hits.fcl, j)
+            }
+            added = true
+          }
         }
         if !added {
           break
@@ -2442,21 +3109,22 @@ impl<'a> Equalizer<'a> {
       }
 
       if progress {
-        for (j, etm) in self.terms.enum_iter() {
-          if self.depends_on(etm, i) {
-            eq_stack.insert(j);
-          }
-        }
+        // the supercluster of i may mention new classes
+        self.add_uses(i, &mut uses, &mut uses_all);
+        eq_stack.extend(uses[i].iter().chain(&uses_all));
       }
     }
     // vprintln!("after round up");
     // for (et, etm) in self.terms.enum_iter() {
     //   vprintln!("state: {et:?}' {:#?}", etm);
     // }
+    Ok(())
+  }
 
+  fn pre_unification(&mut self, pos_bas: &Atoms, neg_bas: &mut Atoms) -> OrUnsat<()> {
     // PreUnification
     let mut ineqs = Ineqs::default();
-    for f in &neg_bas.0 .0 {
+    for f in neg_bas.iter() {
       if let Formula::Pred { nr, args } = f {
         if self.g.reqs.equals_to() == Some(*nr) {
           let [arg1, arg2] = &**args else { unreachable!() };
@@ -2465,9 +3133,9 @@ impl<'a> Equalizer<'a> {
       }
     }
     ineqs.base = ineqs.ineqs.len();
-    self.check_refl(&pos_bas, PropertyKind::Irreflexivity, &mut ineqs)?;
-    self.check_refl(&neg_bas, PropertyKind::Reflexivity, &mut ineqs)?;
-    ineqs.process(self, &mut neg_bas)?;
+    self.check_refl(pos_bas, PropertyKind::Irreflexivity, &mut ineqs)?;
+    self.check_refl(neg_bas, PropertyKind::Reflexivity, &mut ineqs)?;
+    ineqs.process(self, neg_bas)?;
     for (etm1, etm2) in (self.terms.0.iter())
       .filter(|etm| !etm.eq_class.is_empty() && !etm.supercluster.attrs().is_empty())
       .tuple_combinations()
@@ -2476,7 +3144,7 @@ impl<'a> Equalizer<'a> {
         ineqs.push(etm1.mark, etm2.mark)
       }
     }
-    for f in &neg_bas.0 .0 {
+    for f in neg_bas.iter() {
       match f {
         Formula::Pred { nr, args } => {
           let (nr, args) = Formula::adjust_pred(*nr, args, Some(&self.g.constrs));
@@ -2489,7 +3157,7 @@ impl<'a> Equalizer<'a> {
             );
           }
           if self.g.reqs.equals_to() != Some(nr) {
-            for f2 in &pos_bas.0 .0 {
+            for f2 in pos_bas.preds(Some(nr)) {
               if let Formula::Pred { nr: nr2, args: args2 } = f2 {
                 let (nr2, args2) = Formula::adjust_pred(*nr2, args2, Some(&self.g.constrs));
                 if nr == nr2 {
@@ -2500,7 +3168,7 @@ impl<'a> Equalizer<'a> {
           }
         }
         Formula::SchPred { .. } | Formula::Attr { .. } | Formula::PrivPred { .. } => {
-          for f2 in &pos_bas.0 .0 {
+          for f2 in pos_bas.iter() {
             let (args1, args2) = match (f, f2) {
               (
                 Formula::SchPred { nr: n1, args: args1 },
@@ -2546,9 +3214,8 @@ impl<'a> Equalizer<'a> {
         _ => {}
       }
     }
-    ineqs.process(self, &mut neg_bas)?;
-
-    Ok(EnumMap::from_array([neg_bas, pos_bas]))
+    ineqs.process(self, neg_bas)?;
+    Ok(())
   }
 }
 
@@ -2630,11 +3297,18 @@ impl Ineqs {
   fn process(&mut self, eq: &mut Equalizer<'_>, neg_bas: &mut Atoms) -> OrUnsat<()> {
     while let Some(&(a, b)) = self.ineqs.get(self.processed) {
       eq.nonempty_nonzero_of_ne(eq.lc.marks[a].1, eq.lc.marks[b].1)?;
-      if self.processed >= self.base {
-        neg_bas.0.push(Formula::Pred {
-          nr: eq.g.reqs.equals_to().unwrap(),
-          args: Box::new([Term::EqMark(a), Term::EqMark(b)]),
-        });
+      // Without the equality requirement there is no atom to record the inequality with,
+      // but it is still used for the attributes and the other inequalities
+      let nr = eq.g.reqs.equals_to().filter(|_| self.processed >= self.base);
+      if let Some(nr) = nr {
+        // The same inequality can be found through different marks of the same classes
+        let args = Box::new([Term::EqMark(a), Term::EqMark(b)]);
+        let f = Formula::Pred { nr, args };
+        if neg_bas.preds(Some(nr)).any(|f2| EqMarks.eq(eq.g, eq.lc, f2, &f)) {
+          stat("duplicate inequality", false)
+        } else {
+          neg_bas.push(&eq.g.constrs, f, Polarity::Neg);
+        }
       }
       self.processed += 1;
       self.process_ineq(eq, a, b);
@@ -2642,3 +3316,791 @@ impl Ineqs {
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{CheckBound, FixedVar};
+
+  fn object() -> Type {
+    Type { kind: TypeKind::Mode(ModeId(0)), attrs: (Attrs::EMPTY, Attrs::EMPTY), args: vec![] }
+  }
+  fn c(n: u32) -> Term { Term::Const(ConstId(n)) }
+  fn f(tm: Term) -> Term { Term::Functor { nr: FuncId(0), args: Box::new([tm]) } }
+  fn equals(a: Term, b: Term) -> Formula { Formula::Pred { nr: PredId(0), args: Box::new([a, b]) } }
+
+  /// Pushes the conjunction of the literals `fs` to an equalizer in the environment of
+  /// `replay::test_env`, and passes it to `check` with the result of `push_premise`. The
+  /// literals are about constants of type `object`, one for each of `defs`, which are
+  /// their definitions as `set` constants.
+  fn with_premises(
+    defs: Vec<Option<Term>>, fs: Vec<Formula>,
+    check: impl FnOnce(&mut Equalizer<'_>, OrUnsat<()>),
+  ) {
+    let consts = defs.into_iter().map(|def| (object(), def)).collect();
+    with_premises_in(crate::replay::test_env(), consts, fs, check)
+  }
+
+  /// `with_premises` in `env`, with constants of the given types and definitions
+  fn with_premises_in(
+    mut env: crate::replay::Replay, consts: Vec<(Type, Option<Term>)>, fs: Vec<Formula>,
+    check: impl FnOnce(&mut Equalizer<'_>, OrUnsat<()>),
+  ) {
+    for (ty, def) in consts {
+      let def = def.map(|tm| (Box::new(tm), false));
+      env.lc.fixed_var.push(FixedVar { id: IdentId::NONE, ty, def });
+    }
+    let mut ck = env.checker();
+    let mut f = Formula::mk_and(fs);
+    f.visit(&mut ck.ctx.intern_const(ck.g, ck.lc));
+    let mut atoms = Atoms::default();
+    let Ok(Dnf::Or(conjs)) = atoms.normalize(ck.g, ck.lc, f, true) else { unreachable!() };
+    let [conj] = &*conjs else { panic!("not a conjunct") };
+    let mut eq = Equalizer::new(&mut ck);
+    let res = eq.push_premise(&atoms, conj);
+    check(&mut eq, res)
+  }
+
+  /// The y-pass gives classes to the closed subterms under a binder, which can be shared
+  /// with the terms outside of it, but not to the subterms with bound variables.
+  #[test]
+  fn no_classes_for_bound_subterms() {
+    // c0 = { f x where x is object : x = f c0 } and f c0 = c1
+    let x = Term::Bound(BoundId(0));
+    let fraenkel = Term::Fraenkel {
+      args: Box::new([(IdentId::NONE, object())]),
+      scope: Box::new(f(x.clone())),
+      compr: Box::new(equals(x, f(c(0)))),
+    };
+    with_premises(vec![None, None], vec![equals(c(0), fraenkel), equals(f(c(0)), c(1))], |eq, res| {
+      res.unwrap();
+      // c0, c1, f c0 and the Fraenkel term
+      assert_eq!(eq.terms.len(), 4);
+      let bound = CheckBound::get(0..1, |cb| {
+        let marks = eq.lc.marks.0.iter().filter(|m| !matches!(m.0, Term::Fraenkel { .. }));
+        marks.for_each(|m| cb.visit_term(&m.0))
+      });
+      assert!(!bound, "a class was created for a term with a bound variable");
+    })
+  }
+
+  /// In a chain of `set` constants, each defined as the previous one, every constant has
+  /// the first one as its representative, so the classes do not grow with the chain.
+  #[test]
+  fn aliased_constants() {
+    let marks = |n: u32| {
+      let defs = (0..n).map(|i| i.checked_sub(1).map(c)).collect();
+      let mut marks = 0;
+      with_premises(defs, vec![equals(c(n - 1), c(0)).mk_neg()], |eq, res| {
+        res.unwrap();
+        assert!(eq.saturate().is_err(), "c{} <> c0 was not refuted", n - 1);
+        marks = eq.lc.marks.len();
+      });
+      marks
+    };
+    assert_eq!(marks(100), marks(10));
+  }
+
+  fn small() -> Attr { Attr { nr: AttrId(0), pos: true, args: Box::new([]) } }
+  fn small_object() -> Box<Type> {
+    let attrs = Attrs::Consistent(vec![small()]);
+    Box::new(Type { attrs: (attrs.clone(), attrs), ..object() })
+  }
+  fn is_small(tm: Term) -> Formula { Formula::Attr { nr: AttrId(0), args: Box::new([tm]) } }
+
+  /// A negative `x is small object` atom contradicts `small` in the supercluster of `x`,
+  /// whether it comes from an atom about `x` itself or from a term equal to it, but not
+  /// the type `object` alone.
+  #[test]
+  fn supercluster_strengthens_types() {
+    let not_small_object = |tm| Formula::Is { term: Box::new(tm), ty: small_object() }.mk_neg();
+    let refuted = |consts: usize, fs: Vec<Formula>| {
+      let mut refuted = false;
+      with_premises(vec![None; consts], fs, |eq, res| {
+        refuted = res.is_err() || eq.saturate().is_err()
+      });
+      refuted
+    };
+    assert!(refuted(1, vec![is_small(c(0)), not_small_object(c(0))]));
+    assert!(refuted(2, vec![equals(c(0), c(1)), is_small(c(1)), not_small_object(c(0))]));
+    assert!(!refuted(1, vec![not_small_object(c(0))]));
+    assert!(!refuted(2, vec![is_small(c(1)), not_small_object(c(0))]));
+  }
+
+  /// Merging classes gives the same verdicts with and without checking for opposite
+  /// attributes first, on random literals about `small` and equalities of constants and
+  /// their images under `f`.
+  #[test]
+  fn early_contradicts() {
+    let mut rng = Shuffle::new(461);
+    let mut verdicts = [0; 2];
+    for _ in 0..500 {
+      let tm = |rng: &mut Shuffle| match rng.below(3) {
+        0 => f(c(rng.below(4) as u32)),
+        _ => c(rng.below(4) as u32),
+      };
+      // each atom at most once, so that the literals normalize to a single conjunct
+      let mut atoms: Vec<(Vec<Term>, bool)> = vec![];
+      for _ in 0..=rng.below(6) {
+        let atom = match rng.below(2) {
+          0 => vec![tm(&mut rng)],
+          _ => vec![tm(&mut rng), tm(&mut rng)],
+        };
+        let seen = atoms.iter().any(|(a, _)| *a == atom || a.iter().rev().eq(&atom));
+        if !(seen || atom.len() == 2 && atom[0] == atom[1]) {
+          atoms.push((atom, rng.below(3) == 0))
+        }
+      }
+      if atoms.is_empty() {
+        continue
+      }
+      let fs = (atoms.into_iter())
+        .map(|(mut atom, neg)| {
+          let lit = match atom.len() {
+            1 => is_small(atom.pop().unwrap()),
+            _ => equals(atom.remove(0), atom.remove(0)),
+          };
+          if neg { lit.mk_neg() } else { lit }
+        })
+        .collect_vec();
+      let refuted = |late| {
+        LATE_CONTRADICTS.set(late);
+        let mut refuted = false;
+        with_premises(vec![None; 4], fs.clone(), |eq, res| {
+          refuted = res.is_err() || eq.saturate().is_err()
+        });
+        LATE_CONTRADICTS.set(false);
+        refuted
+      };
+      let early = refuted(false);
+      assert_eq!(early, refuted(true), "different verdicts on {fs:?}");
+      verdicts[early as usize] += 1;
+    }
+    assert!(verdicts[0] > 50 && verdicts[1] > 50, "too few of each verdict: {verdicts:?}");
+  }
+
+  /// `replay::test_env` with the requirements `{}` (`FuncId(2)`), `empty` (`AttrId(1)`) and
+  /// `Element of X` (`ModeId(2)`)
+  fn boole_env() -> crate::replay::Replay {
+    let mut env = crate::replay::test_env();
+    let set = Type::new(TypeKind::Mode(ModeId(1)));
+    let c = &mut env.g.constrs;
+    fn ty_constr<I>(primary: Box<[Type]>, ty: &Type) -> TyConstructor<I> {
+      TyConstructor { c: Constructor::new(primary), ty: ty.clone() }
+    }
+    let empty_set = c.functor.push(ty_constr(Box::new([]), &set));
+    let empty = c.attribute.push(ty_constr(Box::new([set.clone()]), &set));
+    let element = c.mode.push(ty_constr(Box::new([set.clone()]), &set));
+    env.g.reqs.set(Requirement::EmptySet, ConstrKind::Func(empty_set));
+    *env.g.reqs.rev.get_mut_extending(empty_set) = Some(Requirement::EmptySet);
+    env.g.reqs.set(Requirement::Empty, ConstrKind::Attr(empty));
+    env.g.reqs.set(Requirement::Element, ConstrKind::Mode(element));
+    env
+  }
+
+  fn empty_set() -> Term { Term::Functor { nr: FuncId(2), args: Box::new([]) } }
+  fn is_empty(tm: Term) -> Formula { Formula::Attr { nr: AttrId(1), args: Box::new([tm]) } }
+  fn neq_empty(tm: Term) -> Formula { equals(tm, empty_set()).mk_neg() }
+
+  /// Whether the literals `fs` are refuted in `boole_env` with the requirements `disabled`
+  /// turned off. The constants are the set `c0`, `c1` of type `Element of c0` and the
+  /// object `c2`.
+  fn boole_refuted(disabled: &[Requirement], fs: Vec<Formula>) -> bool {
+    let mut env = boole_env();
+    env.g.cfg.disabled_requirements.extend(disabled);
+    let element_of = |tm| Type { kind: TypeKind::Mode(ModeId(2)), args: vec![tm], ..object() };
+    let set = Type::new(TypeKind::Mode(ModeId(1)));
+    let consts = vec![(set, None), (element_of(c(0)), None), (object(), None)];
+    let mut refuted = false;
+    with_premises_in(env, consts, fs, |eq, res| refuted = res.is_err() || eq.saturate().is_err());
+    refuted
+  }
+
+  /// A class with `empty` in its supercluster, or with the type `Element of X` for an empty
+  /// `X`, is the class of `{}`, while the other classes stay apart from it.
+  #[test]
+  fn special_classes_empty() {
+    let refuted = |fs| boole_refuted(&[], fs);
+    assert!(refuted(vec![is_empty(c(0)), neq_empty(c(0))]));
+    assert!(refuted(vec![is_empty(c(0)), neq_empty(c(1))]));
+    assert!(!refuted(vec![neq_empty(c(1))]));
+    assert!(!refuted(vec![is_empty(c(0)), neq_empty(c(2))]));
+    assert!(!refuted(vec![is_empty(c(0)), equals(c(2), c(1)).mk_neg()]));
+  }
+
+  /// With `Element` disabled, an `Element of X` for an empty `X` is no longer known to be
+  /// `{}`, while the reasoning about `empty` itself is unchanged, and disabling a
+  /// requirement that these do not use changes nothing.
+  #[test]
+  fn disabled_element() {
+    let fs = || [vec![is_empty(c(0)), neq_empty(c(0))], vec![is_empty(c(0)), neq_empty(c(1))]];
+    let refuted = |disabled| fs().map(|fs| boole_refuted(disabled, fs));
+    assert_eq!(refuted(&[]), [true, true]);
+    assert_eq!(refuted(&[Requirement::Element]), [true, false]);
+    assert_eq!(refuted(&[Requirement::EmptySet]), [false, false]);
+    assert_eq!(refuted(&[Requirement::PowerSet]), [true, true]);
+  }
+
+  /// Runs the phases of `saturate` that set up the classes on the state left by
+  /// `push_premise`, and returns the bases, negative first.
+  fn init_classes(eq: &mut Equalizer<'_>) -> OrUnsat<[Atoms; 2]> {
+    let (bas, mut eqs) = (std::mem::take(&mut eq.bas), std::mem::take(&mut eq.eqs));
+    eq.check_pending_equals(&eqs)?;
+    let settings = eq.init_eq_classes(&mut eqs)?;
+    eq.init_special_classes(&mut eqs)?;
+    eq.init_numbers(eqs, settings)?;
+    eq.renumber_classes()?;
+    Ok(bas.into_array())
+  }
+
+  /// `replay::test_env` with the requirement `0` (`FuncId(2)`)
+  fn zero_env() -> crate::replay::Replay {
+    let mut env = crate::replay::test_env();
+    let set = Type::new(TypeKind::Mode(ModeId(1)));
+    let constr = TyConstructor { c: Constructor::new(Box::new([])), ty: set };
+    let zero = env.g.constrs.functor.push(constr);
+    env.g.reqs.set(Requirement::ZeroNumber, ConstrKind::Func(zero));
+    *env.g.reqs.rev.get_mut_extending(zero) = Some(Requirement::ZeroNumber);
+    env
+  }
+  fn zero() -> Term { Term::Functor { nr: FuncId(2), args: Box::new([]) } }
+
+  /// `x = 0` and `y = 0` put `x` and `y` in one class, whether the `0` is a numeral or the
+  /// `ZeroNumber` functor, and `numbers` only refers to classes left after the unions.
+  #[test]
+  fn set_number_merges() {
+    let num0 = || Term::Numeral(0.into());
+    for (z1, z2) in [(num0(), num0()), (num0(), zero()), (zero(), num0())] {
+      let consts = vec![(object(), None); 2];
+      with_premises_in(zero_env(), consts, vec![equals(c(0), z1), equals(c(1), z2)], |eq, res| {
+        res.unwrap();
+        init_classes(eq).unwrap();
+        assert_eq!(class_of(eq, &c(0)), class_of(eq, &c(1)));
+        for (n, &et) in &eq.numbers {
+          assert!(!eq.terms[et].eq_class.is_empty(), "{n} refers to a merged class");
+          assert_eq!(eq.terms[et].number.as_ref(), Some(n));
+        }
+      })
+    }
+  }
+
+  /// With `ZeroNumber` disabled, the `0` functor is an ordinary term without a number,
+  /// so it no longer meets the numeral `0` in one class, whether it is equated to a
+  /// constant, first occurs in an atom or in the type `M of 0` that the mode `N` of the
+  /// constant `c2` widens to.
+  #[test]
+  fn disabled_zero_number() {
+    let m_of = |tm| Type { kind: TypeKind::Mode(ModeId(2)), args: vec![tm], ..object() };
+    let refuted = |disabled: bool, mut fs: Vec<Formula>| {
+      let mut env = zero_env();
+      let modes = &mut env.g.constrs.mode;
+      modes.push(TyConstructor { c: Constructor::new(Box::new([object()])), ty: object() });
+      let n = modes.push(TyConstructor { c: Constructor::new(Box::new([])), ty: m_of(zero()) });
+      if disabled {
+        env.g.cfg.disabled_requirements.insert(Requirement::ZeroNumber);
+      }
+      fs.extend([equals(c(1), Term::Numeral(0.into())), is_small(c(1))]);
+      let consts = vec![(object(), None), (object(), None), (Type::new(TypeKind::Mode(n)), None)];
+      let mut refuted = false;
+      with_premises_in(env, consts, fs, |eq, res| {
+        refuted = res.is_err() || eq.saturate().is_err()
+      });
+      refuted
+    };
+    let equated = vec![equals(c(0), zero()), is_small(c(0)).mk_neg()];
+    let in_atom = vec![is_small(zero()).mk_neg()];
+    let m_of_0 = Box::new(m_of(Term::Numeral(0.into())));
+    let in_type = vec![Formula::Is { term: Box::new(c(2)), ty: m_of_0 }.mk_neg()];
+    for fs in [equated, in_atom, in_type] {
+      assert!(refuted(false, fs.clone()));
+      assert!(!refuted(true, fs));
+    }
+  }
+
+  /// The class of `tm`, a constant or a term of the equalizer
+  fn class_of(eq: &Equalizer<'_>, tm: &Term) -> EqTermId {
+    let tm = match tm {
+      Term::Const(_) => tm.visit_cloned(&mut eq.intern_const()),
+      _ => tm.clone(),
+    };
+    let et = eq.lc.marks[eq.locate_term(&Conjunct::TRUE, &tm).unwrap()].1;
+    eq.lc.marks[eq.terms[et].mark].1
+  }
+
+  /// `check_pending_equals` refutes an equality between terms with clashing attributes
+  /// before the classes are set up, and lets the other equalities through.
+  #[test]
+  fn phase_check_pending_equals() {
+    let pending = |fs: Vec<Formula>| {
+      let mut res = None;
+      with_premises(vec![None; 2], fs, |eq, pushed| {
+        pushed.unwrap();
+        res = Some(eq.check_pending_equals(&eq.eqs))
+      });
+      res.unwrap()
+    };
+    let not_small = |tm| is_small(tm).mk_neg();
+    assert!(pending(vec![is_small(c(0)), not_small(c(1)), equals(c(0), c(1))]).is_err());
+    assert!(pending(vec![is_small(c(0)), not_small(c(1))]).is_ok());
+    assert!(pending(vec![is_small(c(0)), is_small(c(1)), equals(c(0), c(1))]).is_ok());
+  }
+
+  /// `x = y` with `x is empty` and `y` of type `non empty set` is refuted by the early pass,
+  /// with the attribute of `y` coming from its type rather than from an atom, and the pass
+  /// leaves `x = y` alone when `y` is only a `set`.
+  #[test]
+  fn pending_equals_attribute_conflict() {
+    let set = Type::new(TypeKind::Mode(ModeId(1)));
+    let non_empty = Attrs::Consistent(vec![Attr { nr: AttrId(1), pos: false, args: Box::new([]) }]);
+    let non_empty_set = Type { attrs: (non_empty.clone(), non_empty), ..set.clone() };
+    let pending = |ty: &Type| {
+      let consts = vec![(set.clone(), None), (ty.clone(), None)];
+      let fs = vec![is_empty(c(0)), equals(c(0), c(1))];
+      let mut res = None;
+      with_premises_in(boole_env(), consts, fs, |eq, pushed| {
+        pushed.unwrap();
+        let early = eq.check_pending_equals(&eq.eqs);
+        res = Some((early.is_err(), eq.saturate().is_err()))
+      });
+      res.unwrap()
+    };
+    assert_eq!(pending(&non_empty_set), (true, true));
+    assert_eq!(pending(&set), (false, false));
+  }
+
+  /// `init_numbers` joins the classes of the pending equalities, transitively, and
+  /// `renumber_classes` numbers the classes left from 0.
+  #[test]
+  fn phase_union_and_renumber() {
+    let fs = vec![equals(c(0), c(1)), equals(c(2), c(1)), equals(c(3), c(3))];
+    with_premises(vec![None; 4], fs, |eq, res| {
+      res.unwrap();
+      init_classes(eq).unwrap();
+      let classes = [c(0), c(1), c(2), c(3)].map(|tm| class_of(eq, &tm));
+      assert!(classes[0] == classes[1] && classes[1] == classes[2] && classes[2] != classes[3]);
+      let ids = eq.terms.0.iter().filter(|etm| !etm.eq_class.is_empty()).map(|etm| etm.id.0);
+      assert!(ids.eq(0..2));
+    })
+  }
+
+  /// `init_special_classes` adds the equality of a class with `empty` in its supercluster
+  /// and the class of `{}`, which is created for it.
+  #[test]
+  fn phase_init_special_classes() {
+    let consts = vec![(object(), None); 2];
+    with_premises_in(boole_env(), consts, vec![is_empty(c(0)), equals(c(1), c(1))], |eq, res| {
+      res.unwrap();
+      let mut eqs = std::mem::take(&mut eq.eqs);
+      eq.init_eq_classes(&mut eqs).unwrap();
+      let terms = eq.terms.len();
+      eq.init_special_classes(&mut eqs).unwrap();
+      assert_eq!(eq.terms.len(), terms + 1);
+      let empty_set = empty_set();
+      let pair = |tm1: &Term, tm2: &Term| {
+        let (et1, et2) = (class_of(eq, tm1), class_of(eq, tm2));
+        (et1.min(et2), et1.max(et2))
+      };
+      assert_eq!(eqs.0.iter().copied().collect_vec(), [pair(&c(0), &empty_set)]);
+    })
+  }
+
+  /// `verify_contradictions` refutes a negative atom that is a positive one up to the
+  /// classes of its arguments.
+  #[test]
+  fn phase_verify_contradictions() {
+    let belongs = |a, b| Formula::Pred { nr: PredId(1), args: Box::new([a, b]) };
+    let verified = |fs: Vec<Formula>| {
+      let mut res = None;
+      with_premises(vec![None; 3], fs, |eq, pushed| {
+        pushed.unwrap();
+        let [neg_bas, pos_bas] = init_classes(eq).unwrap();
+        res = Some(eq.verify_contradictions(&pos_bas, &neg_bas))
+      });
+      res.unwrap()
+    };
+    let (pos, neg) = (belongs(c(0), c(2)), belongs(c(1), c(2)).mk_neg());
+    assert!(verified(vec![pos.clone(), neg.clone(), equals(c(0), c(1))]).is_err());
+    assert!(verified(vec![pos, neg]).is_ok());
+  }
+
+  /// `explain_neq` after `saturate` tells apart equal terms, terms with different heads,
+  /// terms with a differing argument, different numbers and terms not in any class.
+  #[test]
+  fn explain_neq_cases() {
+    let mut env = crate::replay::test_env();
+    let ctor = TyConstructor { c: Constructor::new(Box::new([object(), object()])), ty: object() };
+    let h = env.g.constrs.functor.push(ctor);
+    let h = |a, b| Term::Functor { nr: h, args: Box::new([a, b]) };
+    let fs = vec![
+      equals(c(0), c(3)),
+      equals(c(4), Term::Numeral(1.into())),
+      equals(c(5), Term::Numeral(2.into())),
+      equals(h(c(0), c(2)), h(c(1), c(2))).mk_neg(),
+    ];
+    with_premises_in(env, vec![(object(), None); 6], fs, |eq, res| {
+      res.unwrap();
+      eq.saturate().unwrap();
+      let explain = |t1: Term, t2: Term| {
+        let [t1, t2] = [t1, t2].map(|tm| tm.visit_cloned(&mut eq.intern_const()));
+        eq.explain_neq(&t1, &t2)
+      };
+      assert!(matches!(explain(c(3), c(0)), NeqExplanation::Equal));
+      assert!(matches!(explain(c(0), c(1)), NeqExplanation::Heads));
+      let NeqExplanation::Args { pos: 0, terms } = explain(h(c(0), c(2)), h(c(1), c(2))) else {
+        panic!("no differing argument")
+      };
+      let [Term::Functor { args: args1, .. }, Term::Functor { args: args2, .. }] = &*terms else {
+        panic!("not functor terms")
+      };
+      assert!(args1[1] == args2[1] && args1[0] != args2[0]);
+      let NeqExplanation::Numbers(n1, n2) = explain(c(4), c(5)) else { panic!("no numbers") };
+      assert_eq!((n1.to_string(), n2.to_string()), ("1".into(), "2".into()));
+      assert!(matches!(explain(h(c(2), c(2)), c(0)), NeqExplanation::NotRepresentable(0)));
+      assert!(matches!(explain(c(0), h(c(2), c(2))), NeqExplanation::NotRepresentable(1)));
+    })
+  }
+
+  /// Each of `--max-marks`, `--memory-budget-mb` and `--equate-budget-ms` stops the y-pass
+  /// as soon as it is reached, with `Unsat` and the flag that tells the checker not to
+  /// trust it.
+  #[test]
+  fn limits() {
+    // c0 = c1 and c2 = c3, which need 8 marks: 2 for each of the 4 classes
+    let fs = vec![equals(c(0), c(1)), equals(c(2), c(3))];
+    let run = |set: fn(&mut crate::Config)| {
+      let mut env = crate::replay::test_env();
+      set(&mut env.g.cfg);
+      let mut out = None;
+      with_premises_in(env, vec![(object(), None); 4], fs.clone(), |eq, res| {
+        let flags = [res.is_err(), eq.marks_exceeded, eq.memory_exceeded, eq.timed_out];
+        out = Some((flags, eq.lc.marks.len()))
+      });
+      out.unwrap()
+    };
+    assert_eq!(run(|_| {}), ([false; 4], 8));
+    assert_eq!(run(|cfg| cfg.max_marks = Some(100)), ([false; 4], 8));
+    assert_eq!(run(|cfg| cfg.max_marks = Some(4)), ([true, true, false, false], 4));
+    assert_eq!(run(|cfg| cfg.memory_budget_mb = Some(0)), ([true, false, true, false], 2));
+    assert_eq!(run(|cfg| cfg.equate_budget_ms = Some(0)), ([true, false, false, true], 0));
+  }
+
+  /// `replay::test_env` with a structure of one field, and its selector and the type
+  /// `strict` of the structure
+  fn struct_env() -> (crate::replay::Replay, SelId, Type) {
+    let mut env = crate::replay::test_env();
+    let cs = &mut env.g.constrs;
+    let (s, sel, aggr) = (cs.struct_mode.peek(), cs.selector.peek(), cs.aggregate.peek());
+    let s_ty = Type::new(s.into());
+    let prim = Box::new([s_ty.clone()]);
+    cs.selector.push(TyConstructor { c: Constructor::new(prim.clone()), ty: object() });
+    let aggr_c = TyConstructor { c: Constructor::new(Box::new([object()])), ty: s_ty.clone() };
+    cs.aggregate.push(Aggregate { c: aggr_c, base: 0, fields: Box::new([sel]) });
+    let mut strict = Constructor::new(prim);
+    strict.properties.set(PropertyKind::Abstractness);
+    let strict = cs.attribute.push(TyConstructor { c: strict, ty: s_ty.clone() });
+    let (c2, parents, fields) = (Constructor::new(Box::new([])), Box::new([]), Box::new([sel]));
+    cs.struct_mode.push(StructMode { c: c2, parents, aggr, fields });
+    let attrs = Attrs::Consistent(vec![Attr { nr: strict, pos: true, args: Box::new([]) }]);
+    (env, sel, Type { attrs: (attrs.clone(), attrs), ..s_ty })
+  }
+
+  /// Two objects of a strict structure with one field are equal when their fields are,
+  /// also when the fields were merged earlier in the same round of `identities`.
+  #[test]
+  fn struct_extensionality() {
+    // c0 to c3 are strict structures and c4, c5 objects
+    let (_, sel, strict_s) = struct_env();
+    let consts = [vec![(strict_s, None); 4], vec![(object(), None); 2]].concat();
+    let field = |tm| Term::Selector { nr: sel, args: Box::new([tm]) };
+    let refuted = |fs| {
+      let mut refuted = false;
+      with_premises_in(struct_env().0, consts.clone(), fs, |eq, res| {
+        refuted = res.is_err() || eq.saturate().is_err()
+      });
+      refuted
+    };
+    let neq = |a, b| equals(a, b).mk_neg();
+    assert!(refuted(vec![equals(field(c(0)), c(4)), equals(field(c(1)), c(4)), neq(c(0), c(1))]));
+    assert!(!refuted(vec![equals(field(c(0)), c(4)), equals(field(c(1)), c(5)), neq(c(0), c(1))]));
+
+    // the fields c2 and c3 are merged in this round, without renumbering the classes
+    let fs = vec![equals(field(c(0)), c(2)), equals(field(c(1)), c(3))];
+    with_premises_in(struct_env().0, consts, fs, |eq, res| {
+      res.unwrap();
+      init_classes(eq).unwrap();
+      eq.union_terms(class_of(eq, &c(2)), class_of(eq, &c(3))).unwrap();
+      let mut to_union = vec![];
+      eq.struct_extensionality(&mut to_union);
+      let canon = |et: EqTermId| eq.lc.marks[eq.terms[et].mark].1;
+      let pairs = to_union.iter().map(|&(x, y)| (canon(x), canon(y))).collect_vec();
+      assert_eq!(pairs, [(class_of(eq, &c(0)), class_of(eq, &c(1)))]);
+    })
+  }
+
+  fn member(a: Term, b: Term) -> Formula { Formula::Pred { nr: PredId(1), args: Box::new([a, b]) } }
+  /// `not not f`, which `mk_neg` would simplify away
+  fn not_not(f: Formula) -> Formula {
+    Formula::Neg { f: Box::new(Formula::Neg { f: Box::new(f) }) }
+  }
+
+  /// `equiv_compr` identifies comprehensions up to the order of the conjuncts, double
+  /// negations and the classes of the terms in the atoms, but not comprehensions with
+  /// different atoms, and gives up on more than `MAX_COMPR_ATOMS` atoms.
+  #[test]
+  fn equiv_compr() {
+    let fs = vec![equals(c(0), c(1)), equals(c(2), c(3)).mk_neg()];
+    with_premises(vec![None; 4], fs, |eq, res| {
+      res.unwrap();
+      init_classes(eq).unwrap();
+      let m = |n| Term::EqMark(eq.terms[class_of(eq, &c(n))].mark);
+      let at = |i, j| member(m(i), m(j));
+      let and = |fs: Vec<Formula>| Formula::mk_and(fs);
+      let (p, q) = (at(0, 2), at(2, 3));
+      assert!(eq.equiv_compr(&and(vec![p.clone(), q.clone()]), &and(vec![q.clone(), p.clone()])));
+      let swapped = not_not(and(vec![q.clone(), p.clone()]));
+      assert!(eq.equiv_compr(&and(vec![p.clone(), q.clone()]), &swapped));
+      // c0 = c1
+      assert!(eq.equiv_compr(&at(0, 2), &at(1, 2)));
+      assert!(!eq.equiv_compr(&at(0, 2), &at(0, 3)));
+      assert!(!eq.equiv_compr(&and(vec![p.clone(), q.clone()]), &p));
+
+      // 6 atoms on each side are within the limit, but 7 are not
+      let atoms = [(0, 2), (0, 3), (2, 0), (2, 3), (3, 0), (3, 2), (2, 2)].map(|(i, j)| at(i, j));
+      let conj = |n: usize, rev: bool| {
+        let mut fs = atoms[..n].to_vec();
+        if rev {
+          fs.reverse()
+        }
+        and(fs)
+      };
+      assert_eq!(2 * 6, MAX_COMPR_ATOMS);
+      assert!(eq.equiv_compr(&conj(6, false), &conj(6, true)));
+      assert!(!eq.equiv_compr(&conj(7, false), &conj(7, true)));
+    })
+  }
+
+  /// Two Fraenkel terms whose comprehensions differ by the order of the conjuncts under a
+  /// double negation are the same set, while a weaker comprehension gives another set.
+  #[test]
+  fn fraenkel_extensionality() {
+    let x = || Term::Bound(BoundId(0));
+    let fraenkel = |compr| Term::Fraenkel {
+      args: Box::new([(IdentId::NONE, object())]),
+      scope: Box::new(x()),
+      compr: Box::new(compr),
+    };
+    let (p, q) = (member(x(), c(2)), member(c(3), x()));
+    let refuted = |compr2| {
+      let fs = vec![
+        equals(c(0), fraenkel(Formula::mk_and(vec![p.clone(), q.clone()]))),
+        equals(c(1), fraenkel(compr2)),
+        equals(c(0), c(1)).mk_neg(),
+      ];
+      let mut refuted = false;
+      with_premises(vec![None; 4], fs, |eq, res| refuted = res.is_err() || eq.saturate().is_err());
+      refuted
+    };
+    assert!(refuted(not_not(Formula::mk_and(vec![q.clone(), p.clone()]))));
+    assert!(!refuted(p.clone()));
+  }
+
+  /// An inequality found again through other marks of the same classes is only recorded
+  /// once in the negative basis.
+  #[test]
+  fn ineqs_dedup() {
+    with_premises(vec![None; 3], vec![equals(c(0), c(1)), equals(c(2), c(2))], |eq, res| {
+      res.unwrap();
+      init_classes(eq).unwrap();
+      // the marks of the terms of the constants, which c0 = c1 put in one class
+      let mark = |n| {
+        let tm = c(n).visit_cloned(&mut eq.intern_const());
+        eq.terms[eq.lc.marks.0.iter().find(|m| m.0 == tm).unwrap().1].mark
+      };
+      let [m0, m1, m2] = [0, 1, 2].map(mark);
+      let class = |m: EqMarkId| eq.lc.marks[m].1;
+      assert!(m0 != m1 && class(m0) == class(m1) && class(m0) != class(m2));
+      let mut ineqs = Ineqs::default();
+      ineqs.push(m0, m2);
+      ineqs.push(m1, m2);
+      assert_eq!(ineqs.ineqs.len(), 2);
+      let mut neg_bas = Atoms::default();
+      ineqs.process(eq, &mut neg_bas).unwrap();
+      assert_eq!(ineqs.processed, 2);
+      assert_eq!(neg_bas.len(), 1);
+    })
+  }
+
+  /// Without the equality requirement, `Ineqs::process` still goes through the inequalities
+  /// but records none of them as atoms, and `=` written with its predicate is an ordinary
+  /// predicate: `c0 = c1` no longer refutes `c0 is small` and `not c1 is small`, but the
+  /// symmetry of the predicate still refutes `c1 <> c0` with it.
+  #[test]
+  fn no_equality_requirement() {
+    let stripped = || {
+      let mut env = crate::replay::test_env();
+      env.g.reqs.fwd[Requirement::EqualsTo] = 0;
+      env
+    };
+    let mut fs = vec![is_small(c(0)), is_small(c(1)).mk_neg()];
+    with_premises_in(stripped(), vec![(object(), None); 2], fs.clone(), |eq, res| {
+      res.unwrap();
+      init_classes(eq).unwrap();
+      let mark = |n| {
+        let tm = c(n).visit_cloned(&mut eq.intern_const());
+        eq.terms[eq.lc.marks.0.iter().find(|m| m.0 == tm).unwrap().1].mark
+      };
+      let mut ineqs = Ineqs::default();
+      ineqs.push(mark(0), mark(1));
+      let mut neg_bas = Atoms::default();
+      ineqs.process(eq, &mut neg_bas).unwrap();
+      assert_eq!(ineqs.processed, 1);
+      assert_eq!(neg_bas.len(), 0);
+    });
+    let refuted = |env, fs| {
+      let mut refuted = false;
+      with_premises_in(env, vec![(object(), None); 2], fs, |eq, res| {
+        refuted = res.is_err() || eq.saturate().is_err()
+      });
+      refuted
+    };
+    fs.push(equals(c(0), c(1)));
+    assert!(refuted(crate::replay::test_env(), fs.clone()));
+    assert!(!refuted(stripped(), fs.clone()));
+    fs.push(equals(c(1), c(0)).mk_neg());
+    assert!(refuted(stripped(), fs));
+  }
+
+  /// The scan that `round_up_superclusters` did over every class before `add_uses`:
+  /// whether `etm` mentions the class `tgt`
+  fn depends_on(eq: &Equalizer<'_>, etm: &EqTerm, tgt: EqTermId) -> bool {
+    struct CheckEqTerm<'a> {
+      marks: &'a IdxVec<EqMarkId, (Term, EqTermId)>,
+      terms: &'a IdxVec<EqTermId, EqTerm>,
+      tgt: EqTermId,
+      found: bool,
+    }
+    impl Visit for CheckEqTerm<'_> {
+      fn abort(&self) -> bool { self.found }
+      fn visit_term(&mut self, tm: &Term) {
+        match *tm {
+          Term::EqClass(_) => self.found = true,
+          Term::EqMark(m) => {
+            let (ref tm, et) = self.marks[m];
+            if matches!(tm, Term::EqClass(_)) {
+              self.found |= self.marks[self.terms[et].mark].1 == self.tgt
+            } else {
+              self.super_visit_term(tm);
+            }
+          }
+          _ => self.super_visit_term(tm),
+        }
+      }
+    }
+    !etm.eq_class.is_empty() && {
+      let mut ck = CheckEqTerm { marks: &eq.lc.marks, terms: &eq.terms, tgt, found: false };
+      for &m in &etm.eq_class {
+        ck.visit_term(&Term::EqMark(m))
+      }
+      ck.visit_types(&etm.ty_class);
+      ck.visit_attrs(&etm.supercluster);
+      ck.found
+    }
+  }
+
+  /// The classes that `add_uses` records as mentioning each class are the ones found by
+  /// scanning every class with `depends_on`, both when the index is built and after a
+  /// class gains a type mentioning another class and is added again.
+  #[test]
+  fn uses_index() {
+    /// The number of uses, after checking them against the scan
+    fn check(
+      eq: &Equalizer<'_>, uses: &IdxVec<EqTermId, BTreeSet<EqTermId>>, all: &BTreeSet<EqTermId>,
+    ) -> usize {
+      let mut n = 0;
+      for i in (eq.terms.enum_iter()).filter(|p| !p.1.eq_class.is_empty()).map(|p| p.0) {
+        let old = eq.terms.enum_iter().filter(|p| depends_on(eq, p.1, i)).map(|p| p.0);
+        let new = uses[i].union(all).copied();
+        assert!(old.eq(new), "the uses of {i:?} differ");
+        n += uses[i].len()
+      }
+      n
+    }
+    let element_of = |tm| Type { kind: TypeKind::Mode(ModeId(2)), args: vec![tm], ..object() };
+    let set = Type::new(TypeKind::Mode(ModeId(1)));
+    let consts = vec![
+      (set.clone(), None),
+      (set, None),
+      (element_of(c(0)), None),
+      (element_of(c(1)), None),
+      (element_of(f(c(2))), None),
+      (object(), None),
+    ];
+    let fs = vec![
+      equals(c(2), f(c(3))),
+      equals(f(c(5)), c(1)),
+      neq_empty(c(0)),
+      is_small(c(4)),
+      equals(c(5), c(5)),
+    ];
+    with_premises_in(boole_env(), consts, fs, |eq, res| {
+      res.unwrap();
+      init_classes(eq).unwrap();
+      let mut uses = IdxVec::from_default(eq.terms.len());
+      let mut uses_all = BTreeSet::new();
+      for j in eq.terms.enum_iter().map(|p| p.0) {
+        eq.add_uses(j, &mut uses, &mut uses_all)
+      }
+      let before = check(eq, &uses, &uses_all);
+      assert!(before > 0);
+
+      let (et, arg) = (class_of(eq, &c(5)), class_of(eq, &c(4)));
+      let ty = element_of(Term::EqMark(eq.terms[arg].mark));
+      assert!(eq.insert_type(ty, et).unwrap());
+      eq.add_uses(et, &mut uses, &mut uses_all);
+      assert!(check(eq, &uses, &uses_all) > before);
+    })
+  }
+
+  /// The widenings of a type are visited before they are compared with the types already
+  /// in the class, so a widened type that is there already is not added again, and the
+  /// terms in its arguments do not get new classes.
+  #[test]
+  fn insert_type_widened() {
+    let mut env = boole_env();
+    let element_of = |tm| Type { kind: TypeKind::Mode(ModeId(2)), args: vec![tm], ..object() };
+    let set = Type::new(TypeKind::Mode(ModeId(1)));
+    // mode G of X -> Element of f X
+    let ty = element_of(f(Term::Locus(LocusId(0))));
+    let constr = TyConstructor { c: Constructor::new(Box::new([set.clone()])), ty };
+    let g = env.g.constrs.mode.push(constr);
+    let consts = vec![(set, None), (object(), None)];
+    with_premises_in(env, consts, vec![equals(c(0), c(1)).mk_neg()], |eq, res| {
+      res.unwrap();
+      init_classes(eq).unwrap();
+      let (et, arg) = (class_of(eq, &c(1)), class_of(eq, &c(0)));
+      let arg = Term::EqMark(eq.terms[arg].mark);
+      assert!(eq.insert_type(element_of(f(arg.clone())), et).unwrap());
+      let (marks, types) = (eq.lc.marks.len(), eq.terms[et].ty_class.len());
+      let g_of = Type { kind: TypeKind::Mode(g), args: vec![arg], ..object() };
+      assert!(eq.insert_type(g_of, et).unwrap());
+      assert_eq!(eq.lc.marks.len(), marks);
+      assert_eq!(eq.terms[et].ty_class.len(), types + 1);
+      let kinds = eq.terms[et].ty_class.iter().map(|ty| ty.kind);
+      assert_eq!(kinds.filter(|&k| k == TypeKind::Mode(ModeId(2))).count(), 1);
+    })
+  }
+
+  #[test]
+  fn sign_rules() {
+    const POS: Signs = Signs { pos: true, neg: false, nonneg: true, nonpos: false };
+    const NEG: Signs = Signs { pos: false, neg: true, nonneg: false, nonpos: true };
+    const NONNEG: Signs = Signs { pos: false, neg: false, nonneg: true, nonpos: false };
+    for (a, b, ab) in [(POS, POS, POS), (POS, NEG, NEG), (NEG, POS, NEG), (NEG, NEG, POS)] {
+      assert_eq!(Signs::combine(true, a, b), ab, "{a:?} * {b:?}");
+    }
+    assert_eq!(Signs::combine(true, NONNEG, NEG), Signs { nonpos: true, ..Default::default() });
+    assert_eq!(Signs::combine(false, POS, NONNEG), POS);
+    assert_eq!(Signs::combine(false, NONNEG, NONNEG), NONNEG);
+    assert_eq!(Signs::combine(false, POS, NEG), Signs::default());
+  }
+}
@@ -1,6 +1,12 @@
+#[cfg(feature = "parser")]
 use crate::parser::{try_to_line_col, ParseError};
-use crate::types::{Article, DirectiveKind, Formula, Position};
+use crate::types::{
+  Article, DefId, DirectiveKind, Formula, Position, PremiseId, SymbolDataKind,
+};
 use crate::{Global, LocalContext, MizPath};
+use itertools::Itertools;
+use std::cell::RefCell;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 
 #[derive(PartialEq, Eq)]
@@ -10,10 +16,13 @@ enum Severity {
   Warning,
 }
 
+#[cfg(feature = "parser")]
 impl ParseError {
   pub fn report(self, path: &Path) {
+    let mut at = Position::default();
     if let Some(pos) = self.pos() {
       if let Ok((line, col)) = try_to_line_col(path, pos) {
+        at = Position { line: line as u32 + 1, col: col as u32 + 1 };
         eprintln!("{}:{line}:{col}: error: {self}", path.to_string_lossy())
       } else {
         eprintln!("{}: index {pos}: error: {self}", path.to_string_lossy())
@@ -21,6 +30,7 @@ impl ParseError {
     } else {
       eprintln!("{}: error: {self}", path.to_string_lossy())
     }
+    ErrFile::push(at, None, || format!("{}: {self}", path.to_string_lossy()))
   }
 }
 
@@ -33,25 +43,444 @@ pub fn report_accom_warning(kind: DirectiveKind, path: PathBuf, art: Article, po
   );
 }
 
+/// Reports the sections of a prel file that were not understood and have been skipped.
+pub fn report_skipped_sections(path: &Path, names: &[String]) {
+  eprintln!(
+    "{}: warning: skipping unknown sections: {}",
+    path.to_string_lossy(),
+    names.iter().unique().map(|n| format!("<{n}>")).format(", ")
+  );
+}
+
+/// Reports a `constructors` directive for an article whose constructors were already
+/// brought in by the directives before it, for `--warn-directives`
+pub fn report_redundant_constructors(art: Article, pos: Position, dir: Article) {
+  eprintln!(
+    "{file}:{pos:?}: warning: constructors of {dir} are already included by earlier directives",
+    file = MizPath { art }.to_path(true, false, "miz").to_string_lossy(),
+  );
+}
+
+/// Reports the notations of `dir` that were dropped because they are for constructors of
+/// articles missing from the `constructors` directive, for `--warn-directives`
+pub fn report_dropped_notations(
+  art: Article, pos: Position, dir: Article, dropped: usize, missing: &[Article],
+) {
+  eprintln!(
+    "{file}:{pos:?}: warning: {dropped} notations of {dir} are not available, \
+    because the constructors directive is missing {missing}",
+    file = MizPath { art }.to_path(true, false, "miz").to_string_lossy(),
+    missing = missing.iter().format(", "),
+  );
+}
+
+/// Reports a `::$name=value` pragma that was ignored
+pub fn report_ignored_option(art: Article, pos: Position, name: &str, why: &str) {
+  eprintln!(
+    "{file}:{pos:?}: warning: ignoring pragma for {name}: {why}",
+    file = MizPath { art }.to_path(true, false, "miz").to_string_lossy(),
+  );
+}
+
+pub fn report_vacuous_inference(
+  art: Article, pos: Position, premises: &[(Formula, &[PremiseId])],
+) {
+  eprintln!(
+    "{file}:{pos:?}: warning: inference holds without its conclusion, \
+    because these premises are contradictory:",
+    file = MizPath { art }.to_path(true, false, "miz").to_string_lossy(),
+  );
+  for (f, cited) in premises {
+    eprintln!("  {f:?} (premise {})", cited.iter().map(|p| p.0).format(", "))
+  }
+}
+
+/// Warns about a conjunct that `--double-check` could not confirm, and dumps the problem
+/// (the literals, and the types and definitions of the constants they can mention) as a
+/// JSON line on standard error, for investigation.
+#[cfg(feature = "diagnostics")]
+pub fn report_unconfirmed(
+  art: Article, pos: Position, conjunct: usize, lc: &LocalContext, lits: &[Formula],
+) {
+  eprintln!(
+    "{file}:{pos:?}: warning: double check could not confirm conjunct {conjunct}:",
+    file = MizPath { art }.to_path(true, false, "miz").to_string_lossy(),
+  );
+  for f in lits {
+    eprintln!("  {f:?}")
+  }
+  let fixed = lc.fixed_var.enum_iter().map(|(c, v)| {
+    let def = v.def.as_ref().map(|(tm, _)| format!("{tm:?}"));
+    serde_json::json!({ "nr": c.0, "ty": format!("{:?}", v.ty), "def": def })
+  });
+  let infer = (lc.infer_const.borrow().enum_iter())
+    .map(|(n, a)| {
+      let (ty, def) = (format!("{:?}", a.ty), format!("{:?}", a.def));
+      let eq = a.eq_const.iter().map(|m| m.0).collect_vec();
+      serde_json::json!({ "nr": n.0, "ty": ty, "def": def, "eq": eq })
+    })
+    .collect_vec();
+  let out = serde_json::json!({
+    "unconfirmed": format!("{art}:{}:{}", pos.line, pos.col),
+    "conjunct": conjunct,
+    "literals": lits.iter().map(|f| format!("{f:?}")).collect_vec(),
+    "fixed": fixed.collect_vec(),
+    "infer": infer,
+  });
+  #[cfg(test)]
+  crate::audit::UNCONFIRMED.with_borrow_mut(|dumps| dumps.push(out.clone()));
+  let _ = crate::testing::JsonLines(io::stderr().lock()).write(&out);
+}
+
+/// A point in the processing of an article, reported by the panic hook
+/// installed by [`install_panic_hook`] if something goes wrong there.
+#[derive(Clone, Copy, Debug)]
+pub struct ArticlePos {
+  pub article: Article,
+  /// The index of the top level item, if known
+  pub item: Option<usize>,
+  pub pos: Position,
+}
+
+impl std::fmt::Display for ArticlePos {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}:{:?}", self.article, self.pos)?;
+    if let Some(item) = self.item {
+      write!(f, " (item {item})")?
+    }
+    Ok(())
+  }
+}
+
+thread_local! {
+  static LOCATION: RefCell<Vec<ArticlePos>> = const { RefCell::new(vec![]) };
+  static QUIET: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Pops the location pushed by [`ArticlePos::enter`] when dropped
+pub struct LocationGuard(());
+
+impl Drop for LocationGuard {
+  fn drop(&mut self) { LOCATION.with(|l| l.borrow_mut().pop()); }
+}
+
+impl ArticlePos {
+  /// Records that the current thread is working at this location, until the guard is dropped
+  #[must_use]
+  pub fn enter(self) -> LocationGuard {
+    LOCATION.with(|l| l.borrow_mut().push(self));
+    LocationGuard(())
+  }
+}
+
+/// Installs a panic hook which follows the usual panic message
+/// with the locations entered by the panicking thread, innermost first.
+pub fn install_panic_hook() {
+  let default = std::panic::take_hook();
+  std::panic::set_hook(Box::new(move |info| {
+    if QUIET.with(|q| q.get()) {
+      return
+    }
+    default(info);
+    LOCATION.with(|l| {
+      if let Ok(l) = l.try_borrow() {
+        l.iter().rev().for_each(|loc| eprintln!("  while processing {loc}"))
+      }
+    })
+  }))
+}
+
+/// Runs `f`, turning a panic into an error with the panic message, without printing
+/// it. This is for code that reports bad input by panicking, such as the parser.
+pub fn catch_quietly<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+  let old = QUIET.with(|q| q.replace(true));
+  let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+  QUIET.with(|q| q.set(old));
+  res.map_err(|payload| match payload.downcast::<String>() {
+    Ok(msg) => *msg,
+    Err(payload) => payload.downcast_ref::<&str>().map_or("unknown error", |s| s).to_owned(),
+  })
+}
+
+pub fn report_bad_explain_neq(art: Article, term: &str, why: &str) {
+  eprintln!(
+    "{file}: error: cannot parse --explain-neq term '{term}': {why}",
+    file = MizPath { art }.to_path(true, false, "miz").to_string_lossy(),
+  );
+}
+
+/// Reports a symbol of the article-local vocabulary file that `mml.vct` defines differently
+pub fn report_vocabulary_conflict(
+  path: &Path, voc: Article, token: &str, local: &SymbolDataKind<'_>, mml: &SymbolDataKind<'_>,
+) {
+  let (path, mml_vct) = (path.to_string_lossy(), crate::mml_vct_path());
+  match (local, mml) {
+    (SymbolDataKind::Func { prio }, SymbolDataKind::Func { prio: mml_prio }) => eprintln!(
+      "{path}: warning: vocabulary {voc}: '{token}' has priority {prio} here \
+       but {mml_prio} in {mml_vct}"
+    ),
+    _ => eprintln!(
+      "{path}: warning: vocabulary {voc}: '{token}' is {local:?} here but {mml:?} in {mml_vct}"
+    ),
+  }
+}
+
 #[derive(Debug)]
 pub enum MizError {
   UnexpectedPragma(String),
   IterEqualityNotAnEquality(Box<Formula>),
+  ThesisUnknown,
+  InconsistentDefiniens { art: Article, nr: DefId, msg: &'static str },
+  /// A formula for the checker contains `it`, a locus or a free variable,
+  /// which can only come from a malformed definition
+  LeakedTerm(&'static str),
+  /// A `thus` or `assume` step that does not match the thesis, with what the step says
+  /// and what the thesis expected
+  SkeletonMismatch { step: SkeletonStep, got: Box<Formula>, want: Box<Formula> },
+  /// A `the_<struct> of` term whose structure has no fields, or whose argument's type does
+  /// not widen to the structure
+  NoSubAggregate,
+  /// An inference the checker did not accept (it has already been reported)
+  NotAccepted(ArticlePos),
+  /// A file that could not be read or written
+  Io(io::Error),
+  /// A file that could not be parsed, either the article or one from `prel/`
+  #[cfg(feature = "parser")]
+  Parse(PathBuf, ParseError),
+}
+
+impl From<io::Error> for MizError {
+  fn from(e: io::Error) -> Self { MizError::Io(e) }
+}
+
+#[cfg(feature = "parser")]
+impl From<(PathBuf, ParseError)> for MizError {
+  fn from((path, e): (PathBuf, ParseError)) -> Self { MizError::Parse(path, e) }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum SkeletonStep {
+  Conclusion,
+  Assumption,
 }
 
 impl MizError {
+  /// The closest error code used by the original Mizar verifier, if any.
+  fn err_code(&self) -> Option<u32> {
+    match self {
+      MizError::UnexpectedPragma(_)
+      | MizError::IterEqualityNotAnEquality(_)
+      | MizError::ThesisUnknown
+      | MizError::InconsistentDefiniens { .. }
+      | MizError::LeakedTerm(_)
+      | MizError::NoSubAggregate
+      | MizError::Io(_) => None,
+      #[cfg(feature = "parser")]
+      MizError::Parse(..) => None,
+      MizError::NotAccepted(_) => Some(ErrFile::INFERENCE_NOT_ACCEPTED),
+      MizError::SkeletonMismatch { step: SkeletonStep::Conclusion, .. } => Some(51),
+      MizError::SkeletonMismatch { step: SkeletonStep::Assumption, .. } => Some(52),
+    }
+  }
+
+  /// The error message. Formulas are pretty printed if `lc` is provided.
+  fn message(&self, lc: Option<&LocalContext>) -> String {
+    match self {
+      MizError::UnexpectedPragma(pragma) => format!("unknown pragma '{pragma}'"),
+      MizError::IterEqualityNotAnEquality(f) => match lc {
+        Some(lc) => format!("not an equality: {}", lc.pp(f)),
+        None => format!("not an equality: {f:?}"),
+      },
+      MizError::ThesisUnknown => "'thesis' used outside of a proof".into(),
+      MizError::InconsistentDefiniens { art, nr, msg } =>
+        format!("inconsistent definiens {art}:def {nr:?}: {msg}"),
+      MizError::LeakedTerm(what) =>
+        format!("this inference mentions {what} outside of a definition, and cannot be checked"),
+      MizError::NoSubAggregate =>
+        "the structure has no fields, or the term does not have a type widening to it".into(),
+      MizError::NotAccepted(pos) => format!("{pos}: inference not accepted"),
+      MizError::Io(e) => format!("IO error: {e}"),
+      #[cfg(feature = "parser")]
+      MizError::Parse(path, e) => format!("{}: {e}", path.to_string_lossy()),
+      MizError::SkeletonMismatch { step, got, want } => {
+        let step = match step {
+          SkeletonStep::Conclusion => "conclusion",
+          SkeletonStep::Assumption => "assumption",
+        };
+        match lc {
+          Some(lc) => format!("invalid {step}: got {}, expected {}", lc.pp(got), lc.pp(want)),
+          None => format!("invalid {step}: got {got:?}, expected {want:?}"),
+        }
+      }
+    }
+  }
+
   pub fn report(self, art: Article, pos: Position, _g: &Global, lc: &LocalContext) -> bool {
     let severity = Severity::Error;
-    let msg = match &self {
-      MizError::UnexpectedPragma(pragma) => format!("unknown pragma '{pragma}'"),
-      MizError::IterEqualityNotAnEquality(f) => format!("not an equality: {}", lc.pp(f)),
-    };
+    let msg = self.message(Some(lc));
     let file = MizPath { art }.to_path(true, false, "miz");
     let sev = match severity {
       Severity::Error => "error",
       Severity::Warning => "warning",
     };
     eprintln!("{}:{pos:?}: {sev}: {msg}", file.to_string_lossy());
+    if severity == Severity::Error {
+      ErrFile::push(pos, self.err_code(), || msg);
+    }
     severity == Severity::Error
   }
 }
+
+impl std::fmt::Display for MizError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.message(None))
+  }
+}
+impl std::error::Error for MizError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      MizError::Io(e) => Some(e),
+      #[cfg(feature = "parser")]
+      MizError::Parse(_, e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+/// The line starts of a source buffer, for converting positions to other conventions.
+/// A [`Position`] counts lines from 1 and columns in bytes from 1, as in Mizar and the
+/// `.err` file; editors speaking LSP want both from 0, with columns in UTF-16 code units.
+pub struct LineIndex<'a> {
+  text: &'a [u8],
+  /// The byte offset of the start of every line
+  starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+  pub fn new(text: &'a [u8]) -> Self {
+    let starts = std::iter::once(0).chain(memchr::memchr_iter(b'\n', text).map(|i| i + 1));
+    Self { text, starts: starts.collect() }
+  }
+
+  /// The text of line `line` (counting from 1) before column `col`, clamped to the line
+  fn prefix(&self, Position { line, col }: Position) -> &'a [u8] {
+    let Some(&start) = self.starts.get((line as usize).wrapping_sub(1)) else { return &[] };
+    let end = self.starts.get(line as usize).map_or(self.text.len(), |&end| end - 1);
+    &self.text[start..(start + (col as usize).saturating_sub(1)).min(end)]
+  }
+
+  /// The 0-based line and UTF-16 column of `pos`, as used by LSP
+  pub fn utf16(&self, pos: Position) -> (u32, u32) {
+    let col = String::from_utf8_lossy(self.prefix(pos)).encode_utf16().count();
+    (pos.line.saturating_sub(1), col as u32)
+  }
+}
+
+/// Collects the errors of the article being processed on the current thread,
+/// to be written out in the classic `<article>.err` format (`line col code`, separated
+/// by tabs and sorted by position) used by tools built around the original Mizar verifier.
+#[derive(Default)]
+pub struct ErrFile(Vec<(Position, u32, Option<String>)>);
+
+thread_local! {
+  static ERR_FILE: RefCell<Option<ErrFile>> = const { RefCell::new(None) };
+}
+
+impl ErrFile {
+  /// "This inference is not accepted"
+  pub const INFERENCE_NOT_ACCEPTED: u32 = 4;
+  /// An inference given up because `--equate-budget-ms` or `--unify-budget-ms` ran out.
+  /// Mizar has no budgets, so this is one of the reserved codes.
+  pub const TIMED_OUT: u32 = 9998;
+  /// Reserved for errors which have no counterpart in Mizar.
+  /// Their messages are written to the `<article>.err.txt` file.
+  pub const OTHER: u32 = 9999;
+
+  /// Starts collecting errors for a new article.
+  pub fn start() { ERR_FILE.with(|e| *e.borrow_mut() = Some(ErrFile::default())) }
+
+  /// Records an error, if we are collecting errors. If `code` is `None`
+  /// then the error is recorded under [`ErrFile::OTHER`] with the message `msg`.
+  pub fn push(pos: Position, code: Option<u32>, msg: impl FnOnce() -> String) {
+    ERR_FILE.with(|e| {
+      if let Some(errs) = &mut *e.borrow_mut() {
+        match code {
+          Some(code) => errs.0.push((pos, code, None)),
+          None => errs.0.push((pos, Self::OTHER, Some(msg()))),
+        }
+      }
+    })
+  }
+
+  /// The errors recorded so far, as (position, code), sorted by position.
+  pub fn errors() -> Vec<(Position, u32)> {
+    let mut out = ERR_FILE.with(|e| {
+      e.borrow().as_ref().map_or(vec![], |errs| errs.0.iter().map(|e| (e.0, e.1)).collect())
+    });
+    out.sort_by_key(|e| (e.0.line, e.0.col));
+    out
+  }
+
+  /// Stops collecting errors, and if `write` is set, writes the `.err` file (and the
+  /// `.err.txt` file if there are any errors with no Mizar error code) next to the article
+  /// source.
+  pub fn finish(path: &MizPath, write: bool) -> io::Result<()> {
+    let Some(mut errs) = ERR_FILE.with(|e| e.borrow_mut().take()) else { return Ok(()) };
+    if !write {
+      return Ok(())
+    }
+    errs.0.sort_by_key(|e| (e.0.line, e.0.col));
+    let mut w = io::BufWriter::new(path.create(true, false, "err")?);
+    for (pos, code, _) in &errs.0 {
+      writeln!(w, "{}\t{}\t{code}", pos.line, pos.col)?
+    }
+    w.flush()?;
+    let txt = path.to_path(true, false, "err.txt");
+    if errs.0.iter().any(|e| e.2.is_some()) {
+      let mut w = io::BufWriter::new(path.create(true, false, "err.txt")?);
+      for (pos, _, msg) in &errs.0 {
+        if let Some(msg) = msg {
+          writeln!(w, "{}\t{}\t{msg}", pos.line, pos.col)?
+        }
+      }
+      w.flush()?;
+    } else if txt.exists() {
+      std::fs::remove_file(txt)?
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn messages_have_context() {
+    let article = Article::from_lower(b"xboole_0").unwrap();
+    let pos = ArticlePos { article, item: Some(3), pos: Position { line: 12, col: 5 } };
+    let msg = MizError::NotAccepted(pos).to_string();
+    assert_eq!(msg, "xboole_0:12:5 (item 3): inference not accepted");
+    #[cfg(feature = "parser")]
+    {
+      let e = MizError::Parse("prel/x/xboole_0.dco".into(), ParseError::BadInteger(7));
+      assert!(e.to_string().starts_with("prel/x/xboole_0.dco: "));
+    }
+    let e = MizError::from(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+    assert!(std::error::Error::source(&e).is_some());
+  }
+
+  #[test]
+  #[cfg(feature = "parser")]
+  fn parse_errors_go_to_the_err_file() {
+    let path = std::env::temp_dir().join(format!("mizar-rs-err-{}.dre", std::process::id()));
+    std::fs::write(&path, "<?xml version=\"1.0\"?>\n<Requirements>\n").unwrap();
+    ErrFile::start();
+    ParseError::BadInteger(25).report(&path);
+    ParseError::MissingFile.report(&path);
+    std::fs::remove_file(&path).unwrap();
+    let errs = ERR_FILE.with(|e| e.borrow_mut().take()).unwrap().0;
+    let errs = errs.into_iter().map(|(pos, code, msg)| (pos.line, pos.col, code, msg.is_some()));
+    assert_eq!(errs.collect_vec(), [(2, 4, ErrFile::OTHER, true), (0, 0, ErrFile::OTHER, true)]);
+  }
+}
@@ -1,7 +1,5 @@
-use crate::accom::SigBuilder;
 use crate::analyze::Analyzer;
 use crate::parser::{catch_missing, MaybeMut, PathResult};
-use crate::reader::DefiniensId;
 use crate::types::*;
 use crate::{Assignment, LocalContext, OnVarMut, VisitMut};
 use enum_map::EnumMap;
@@ -39,6 +37,52 @@ fn assert_eq_iter<T: Debug + PartialEq<U>, U: Debug>(
   }
 }
 
+/// The names by which the entries of a `.the` file are referred to, as `Th<n>` or `Def<n>`.
+/// Canceled theorems and definitions keep their numbers, as in the source article.
+pub fn theorem_names(thms: &[Theorem]) -> impl Iterator<Item = String> + '_ {
+  let (mut th, mut def) = (0, 0);
+  thms.iter().map(move |thm| match thm.kind {
+    TheoremKind::Thm | TheoremKind::CanceledThm => {
+      th += 1;
+      format!("Th{th}")
+    }
+    TheoremKind::Def(_) | TheoremKind::CanceledDef => {
+      def += 1;
+      format!("Def{def}")
+    }
+  })
+}
+
+/// Describes the first theorem on which `thms1` and the theorems `thms2` from the prel differ,
+/// by the name it is referred to by, since a shift in the numbering breaks references to
+/// all the theorems after it.
+fn theorem_mismatch(art: Article, thms1: &[Theorem], thms2: &[Theorem]) -> Option<String> {
+  let it1 = theorem_names(thms1).zip(thms1);
+  let it2 = theorem_names(thms2).zip(thms2);
+  for ((n1, thm1), (n2, thm2)) in it1.zip(it2) {
+    if thm1 != thm2 {
+      let line = thm1.pos.line;
+      return Some(format!(
+        "{art}: theorems first differ from the prel at {n1} (line {line}), {n2} there"
+      ))
+    }
+  }
+  if thms1.len() != thms2.len() {
+    return Some(format!("{art}: {} theorems here, but {} in the prel", thms1.len(), thms2.len()))
+  }
+  None
+}
+
+/// Describes the references to constructors that do not exist, which would make the
+/// exported `.<ext>` file unreadable by the articles that import it.
+fn dangling_constructors(art: Article, ext: &str, dangling: &[(&str, u32)]) -> Option<String> {
+  let [(kind, nr), rest @ ..] = dangling else { return None };
+  Some(format!(
+    "{art}: .{ext} refers to nonexistent constructor {kind} {nr}{}",
+    if rest.is_empty() { String::new() } else { format!(" (and {} more)", rest.len()) }
+  ))
+}
+
 struct ExportPrep<'a> {
   ctx: Option<&'a Constructors>,
   lc: &'a LocalContext,
@@ -113,16 +157,20 @@ struct MarkConstr<'a> {
   /// (Because this always contains an entry for the current article it may either
   /// be the same length or one longer than accum.)
   used: Vec<bool>,
+  /// The references to constructors past `base`, as (kind, number)
+  dangling: Vec<(&'static str, u32)>,
 }
 
 impl<'a> MarkConstr<'a> {
   fn new(sig: &'a SigBuilder, n: usize) -> Self {
-    Self { accum: &sig.sig.0, base: &sig.base, used: vec![false; n + 1] }
+    Self { accum: &sig.sig.0, base: &sig.base, used: vec![false; n + 1], dangling: vec![] }
   }
 
-  fn mark(&mut self, n: u32, key: impl Fn(&ConstructorsBase) -> u32) {
+  fn mark(&mut self, kind: &'static str, n: u32, key: impl Fn(&ConstructorsBase) -> u32) {
     if n < key(self.base) {
       self.used[self.accum[1..].partition_point(|(_, base)| key(base) <= n)] = true
+    } else {
+      self.dangling.push((kind, n))
     }
   }
 
@@ -171,13 +219,13 @@ impl<'a> MarkConstr<'a> {
 }
 
 impl VisitMut for MarkConstr<'_> {
-  fn visit_mode_id(&mut self, n: &mut ModeId) { self.mark(n.0, |b| b.mode) }
-  fn visit_struct_id(&mut self, n: &mut StructId) { self.mark(n.0, |b| b.struct_mode) }
-  fn visit_attr_id(&mut self, n: &mut AttrId) { self.mark(n.0, |b| b.attribute) }
-  fn visit_pred_id(&mut self, n: &mut PredId) { self.mark(n.0, |b| b.predicate) }
-  fn visit_func_id(&mut self, n: &mut FuncId) { self.mark(n.0, |b| b.functor) }
-  fn visit_sel_id(&mut self, n: &mut SelId) { self.mark(n.0, |b| b.selector) }
-  fn visit_aggr_id(&mut self, n: &mut AggrId) { self.mark(n.0, |b| b.aggregate) }
+  fn visit_mode_id(&mut self, n: &mut ModeId) { self.mark("mode", n.0, |b| b.mode) }
+  fn visit_struct_id(&mut self, n: &mut StructId) { self.mark("struct", n.0, |b| b.struct_mode) }
+  fn visit_attr_id(&mut self, n: &mut AttrId) { self.mark("attr", n.0, |b| b.attribute) }
+  fn visit_pred_id(&mut self, n: &mut PredId) { self.mark("pred", n.0, |b| b.predicate) }
+  fn visit_func_id(&mut self, n: &mut FuncId) { self.mark("func", n.0, |b| b.functor) }
+  fn visit_sel_id(&mut self, n: &mut SelId) { self.mark("sel", n.0, |b| b.selector) }
+  fn visit_aggr_id(&mut self, n: &mut AggrId) { self.mark("aggr", n.0, |b| b.aggregate) }
 }
 
 #[derive(Default, Debug)]
@@ -202,11 +250,16 @@ impl VisitMut for ApplyMarkConstr {
 }
 
 impl AccumConstructors {
+  /// Renumbers the constructors in `t`, the contents of a `.<ext>` file, to the articles
+  /// that it uses, and returns those articles.
   fn mark<T: for<'a> Visitable<MarkConstr<'a>> + Visitable<ApplyMarkConstr>>(
-    &mut self, t: &mut T, n: usize, arts: &[Article],
+    &mut self, t: &mut T, n: usize, arts: &[Article], (art, ext): (Article, &str),
   ) -> Vec<Article> {
     let mut marks = MarkConstr::new(&self.sig, n);
     t.visit(&mut marks);
+    if let Some(msg) = dangling_constructors(art, ext, &marks.dangling) {
+      eprintln!("{msg}")
+    }
     marks.closure(&mut self.constrs);
     marks.apply_with(|v| t.visit(v));
     marks.filtered(arts)
@@ -370,6 +423,9 @@ impl Analyzer<'_> {
         mark_formats(&vocs1, &mut dno1.vocs, &mut dno1.pats, |p| &mut p.fmt);
         let mut marks = MarkConstr::new(&aco.sig, arts1.len());
         dno1.pats.iter_mut().for_each(|p| p.visit(&mut marks));
+        if let Some(msg) = dangling_constructors(self.article, "dno", &marks.dangling) {
+          eprintln!("{msg}")
+        }
         marks.closure(&mut aco.constrs);
         marks.apply_with(|v| dno1.pats.iter_mut().for_each(|p| p.visit(v)));
         dno1.sig = marks.filtered(&arts2);
@@ -403,7 +459,7 @@ impl Analyzer<'_> {
       if nonempty {
         dcl1.cl = since1.to_owned();
         dcl1.cl.visit(ep);
-        dcl1.sig = aco.mark(&mut dcl1.cl, arts1.len(), &arts2);
+        dcl1.sig = aco.mark(&mut dcl1.cl, arts1.len(), &arts2, (self.article, "dcl"));
         if self.g.cfg.verify_export {
           ep.with_ctx(None, |ep| dcl2.cl.visit(ep));
           assert_eq!(dcl1.sig, dcl2.sig);
@@ -437,7 +493,7 @@ impl Analyzer<'_> {
       }
       if nonempty {
         def1.visit(ep);
-        sig1 = aco.mark(&mut def1, arts1.len(), &arts2);
+        sig1 = aco.mark(&mut def1, arts1.len(), &arts2, (self.article, "def"));
         if self.g.cfg.verify_export {
           ep.with_ctx(None, |ep| def2.visit(ep));
           assert_eq!(sig1, sig);
@@ -463,7 +519,7 @@ impl Analyzer<'_> {
 
     // validating .did
     {
-      let mut did1 = self.identify[self.export.identify_base as usize..].to_owned();
+      let mut did1 = self.session.identify[self.export.identify_base as usize..].to_owned();
       let nonempty = !did1.is_empty();
       let (mut sig1, mut sig, mut did2) = Default::default();
       if self.g.cfg.verify_export {
@@ -471,7 +527,7 @@ impl Analyzer<'_> {
       }
       if nonempty {
         did1.visit(ep);
-        sig1 = aco.mark(&mut did1, arts1.len(), &arts2);
+        sig1 = aco.mark(&mut did1, arts1.len(), &arts2, (self.article, "did"));
         if self.g.cfg.verify_export {
           ep.with_ctx(None, |ep| did2.visit(ep));
           assert_eq!(sig1, sig);
@@ -497,7 +553,7 @@ impl Analyzer<'_> {
 
     // validating .drd
     {
-      let mut drd1 = self.reductions[self.export.reductions_base as usize..].to_owned();
+      let mut drd1 = self.session.reductions[self.export.reductions_base as usize..].to_owned();
       let nonempty = !drd1.is_empty();
       let (mut sig1, mut sig, mut drd2) = Default::default();
       if self.g.cfg.verify_export {
@@ -505,7 +561,7 @@ impl Analyzer<'_> {
       }
       if nonempty {
         drd1.visit(ep);
-        sig1 = aco.mark(&mut drd1, arts1.len(), &arts2);
+        sig1 = aco.mark(&mut drd1, arts1.len(), &arts2, (self.article, "drd"));
         if self.g.cfg.verify_export {
           ep.with_ctx(None, |ep| drd2.visit(ep));
           assert_eq!(sig1, sig);
@@ -540,7 +596,7 @@ impl Analyzer<'_> {
       }
       if nonempty {
         dpr1.visit(ep);
-        sig1 = aco.mark(&mut dpr1, arts1.len(), &arts2);
+        sig1 = aco.mark(&mut dpr1, arts1.len(), &arts2, (self.article, "dpr"));
         if self.g.cfg.verify_export {
           ep.with_ctx(None, |ep| dpr2.visit(ep));
           assert_eq!(sig1, sig);
@@ -574,10 +630,13 @@ impl Analyzer<'_> {
       }
       if nonempty {
         thms1.thm.visit(ep);
-        thms1.sig = aco.mark(&mut thms1.thm, arts1.len(), &arts2);
+        thms1.sig = aco.mark(&mut thms1.thm, arts1.len(), &arts2, (self.article, "the"));
         if self.g.cfg.verify_export {
           ep.with_ctx(None, |ep| thms2.thm.visit(ep));
           assert_eq!(thms1.sig, thms2.sig);
+          if let Some(msg) = theorem_mismatch(self.article, &thms1.thm, &thms2.thm) {
+            eprintln!("{msg}")
+          }
           assert_eq_iter("theorems", thms1.thm.iter(), thms2.thm.iter());
         }
         if self.g.cfg.xml_export {
@@ -606,7 +665,7 @@ impl Analyzer<'_> {
         assert_eq_nonempty!(self, nonempty, self.path.read_sch(false, &mut schs2));
       }
       if nonempty {
-        schs1.sig = aco.mark(&mut schs1.sch, arts1.len(), &arts2);
+        schs1.sig = aco.mark(&mut schs1.sch, arts1.len(), &arts2, (self.article, "sch"));
         if self.g.cfg.verify_export {
           ep.with_ctx(None, |ep| schs2.sch.visit(ep));
           assert_eq!(schs1.sig, schs2.sig);
@@ -628,3 +687,78 @@ impl Analyzer<'_> {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn thm(line: u32, kind: TheoremKind) -> Theorem {
+    Theorem { pos: Position { line, col: 1 }, kind, stmt: Formula::True }
+  }
+
+  /// An article with `canceled;` items among its theorems and definitions, as in the MML.
+  fn canceled_fixture() -> Vec<Theorem> {
+    vec![
+      thm(3, TheoremKind::Thm),
+      thm(4, TheoremKind::CanceledThm),
+      thm(4, TheoremKind::CanceledThm),
+      thm(6, TheoremKind::Def(ConstrKind::Func(FuncId(0)))),
+      thm(7, TheoremKind::CanceledDef),
+      thm(9, TheoremKind::Def(ConstrKind::Pred(PredId(0)))),
+      thm(11, TheoremKind::Thm),
+    ]
+  }
+
+  #[test]
+  fn canceled_numbering() {
+    let names = theorem_names(&canceled_fixture()).collect_vec();
+    assert_eq!(names, ["Th1", "Th2", "Th3", "Def1", "Def2", "Def3", "Th4"]);
+  }
+
+  #[test]
+  fn canceled_mismatch() {
+    let art = Article::from_lower(b"cancel").unwrap();
+    let thms = canceled_fixture();
+    assert_eq!(theorem_mismatch(art, &thms, &thms), None);
+    // the prel is missing one of the canceled theorems, so Th3 here is Th4 there
+    let mut prel = thms.clone();
+    prel.remove(2);
+    let msg = theorem_mismatch(art, &thms, &prel).unwrap();
+    assert_eq!(msg, "cancel: theorems first differ from the prel at Th3 (line 4), Def1 there");
+    // a theorem canceled only in the prel keeps its name
+    let mut prel = thms.clone();
+    prel[6].kind = TheoremKind::CanceledThm;
+    let msg = theorem_mismatch(art, &thms, &prel).unwrap();
+    assert_eq!(msg, "cancel: theorems first differ from the prel at Th4 (line 11), Th4 there");
+    prel.pop();
+    let msg = theorem_mismatch(art, &thms, &prel).unwrap();
+    assert_eq!(msg, "cancel: 7 theorems here, but 6 in the prel");
+  }
+
+  /// A theorem mentioning a functor that the article does not export, as a private functor
+  /// would be, is caught when its constructors are renumbered for the `.the` file.
+  #[test]
+  fn private_functor() {
+    let art = Article::from_lower(b"private").unwrap();
+    let mut sig = SigBuilder::default();
+    let hidden = ConstructorsBase { mode: 2, predicate: 2, ..Default::default() };
+    let own = ConstructorsBase { functor: 2, ..Default::default() };
+    for (art, counts) in [(Article::HIDDEN, hidden), (art, own)] {
+      sig.sig.push((art, sig.base));
+      sig.base += counts;
+    }
+    let c0 = Term::Const(ConstId(0));
+    let f = |nr| Term::Functor { nr: FuncId(nr), args: Box::new([c0.clone()]) };
+    let eq = |tm| Formula::Pred { nr: PredId(0), args: Box::new([tm, c0.clone()]) };
+    let dangling = |mut stmt: Formula| {
+      let mut marks = MarkConstr::new(&sig, sig.sig.len());
+      stmt.visit(&mut marks);
+      dangling_constructors(art, "the", &marks.dangling)
+    };
+    assert_eq!(dangling(eq(f(1))), None);
+    let msg = "private: .the refers to nonexistent constructor func 2";
+    assert_eq!(dangling(eq(f(2))).as_deref(), Some(msg));
+    let msg = "private: .the refers to nonexistent constructor func 3 (and 1 more)";
+    assert_eq!(dangling(Formula::mk_and(vec![eq(f(3)), eq(f(2))])).as_deref(), Some(msg));
+  }
+}
@@ -1,5 +1,7 @@
 use crate::types::*;
-use crate::{LocalContext, MizPath};
+use crate::LocalContext;
+#[cfg(feature = "parser")]
+use crate::MizPath;
 use pretty::{Arena, DocAllocator, DocBuilder};
 use std::borrow::Cow;
 use std::cell::Cell;
@@ -82,6 +84,7 @@ impl Formatter {
     pats.iter().for_each(|pat| self.push(ctx, pat))
   }
 
+  #[cfg(feature = "parser")]
   pub fn init_symbols(&mut self, path: &MizPath, symbols: Option<Vec<(SymbolKind, String)>>) {
     self.idents.push("".into());
     if self.cfg.enable_formatter {
@@ -304,7 +307,7 @@ impl<'a> Pretty<'a> {
         .append(self.terms(vis.map(|v| &v[left as usize..]), args, vars, depth, lift).parens()),
     };
     let doc = doc.group();
-    return if prec && left + right != 0 { doc.parens() } else { doc }
+    if prec && left + right != 0 { doc.parens() } else { doc }
   }
 
   fn term(&self, prec: bool, tm: &Term, vars: &mut Vec<IdentId>, depth: u32, lift: u32) -> Doc<'a> {
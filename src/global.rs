@@ -4,6 +4,7 @@ use crate::types::*;
 use crate::{mizfiles, stat, Config};
 use enum_map::EnumMap;
 use itertools::EitherOrBoth;
+use serde_derive::{Deserialize, Serialize};
 use std::cell::RefCell;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
@@ -13,6 +14,12 @@ use std::ops::Range;
 use std::path::PathBuf;
 
 impl Global {
+  /// The requirements for the built-in reasoning of the checker,
+  /// without the ones disabled by `--disable-requirements`
+  pub fn checker_reqs(&self) -> CheckedRequirements<'_> {
+    self.reqs.checked(&self.cfg.disabled_requirements)
+  }
+
   /// TypReachable(fWider = wider, fNarrower = narrower)
   fn type_reachable(&self, wider: &Type, narrower: &Type) -> bool {
     // vprintln!("TypReachable {wider:?} -> {narrower:?}");
@@ -339,8 +346,8 @@ impl Term {
         | (Infer(InferId(n1)), Infer(InferId(n2)))
         | (FreeVar(FVarId(n1)), FreeVar(FVarId(n2)))
         | (EqClass(EqClassId(n1)), EqClass(EqClassId(n2)))
-        | (EqMark(EqMarkId(n1)), EqMark(EqMarkId(n2)))
-        | (Numeral(n1), Numeral(n2)) => n1.cmp(n2),
+        | (EqMark(EqMarkId(n1)), EqMark(EqMarkId(n2))) => n1.cmp(n2),
+        (Numeral(n1), Numeral(n2)) => n1.cmp(n2),
         (Functor { nr: n1, args: args1 }, Functor { nr: n2, args: args2 }) => match style {
           CmpStyle::Strict | CmpStyle::Attr | CmpStyle::Alt =>
             n1.cmp(n2).then_with(|| Term::cmp_list(ctx, lc, args1, args2, style)),
@@ -407,16 +414,21 @@ impl Term {
   }
 
   /// ReconAggregTrm
-  /// performs eta expansion of aggregates: `foo` ~> `(# foo.1 , foo.2 #)`
-  pub fn mk_aggr(g: &Global, lc: &LocalContext, s: StructId, arg: &Term, ty: &Type) -> Term {
-    assert!(!g.constrs.struct_mode[s].fields.is_empty());
+  /// performs eta expansion of aggregates: `foo` ~> `(# foo.1 , foo.2 #)`.
+  /// Returns `None` if the structure has no selectors or `ty` does not widen to `s`.
+  pub fn mk_aggr(
+    g: &Global, lc: &LocalContext, s: StructId, arg: &Term, ty: &Type,
+  ) -> Option<Term> {
+    if g.constrs.struct_mode[s].fields.is_empty() {
+      return None
+    }
     let nr = g.constrs.struct_mode[s].aggr;
-    let ty = &*Type::new(s.into()).widening_of(g, lc, ty).unwrap();
+    let ty = &*Type::new(s.into()).widening_of(g, lc, ty)?;
     let mut args = ty.args.clone();
     for &sel in &*g.constrs.aggregate[nr].fields {
       args.push(Self::mk_select(g, lc, sel, arg, ty));
     }
-    Term::Aggregate { nr, args: args.into() }
+    Some(Term::Aggregate { nr, args: args.into() })
   }
 }
 
@@ -578,8 +590,7 @@ impl Type {
       TypeKind::Mode(n) => {
         let n = Type::adjust(n, &self.args, &g.constrs).0;
         let mut w = CowBox::Borrowed(other);
-        loop {
-          let TypeKind::Mode(n2) = w.kind else { break };
+        while let TypeKind::Mode(n2) = w.kind {
           let true = n2 >= n else { break };
           if g.eq_radices(lc, self, &w) {
             return true
@@ -649,7 +660,7 @@ impl Formula {
     (nr, &args[c.superfluous as usize..])
   }
 
-  fn cmp(
+  pub fn cmp(
     &self, ctx: Option<&Constructors>, lc: Option<&LocalContext>, other: &Formula, style: CmpStyle,
   ) -> Ordering {
     // vprintln!("{self:?} <?> {other:?}");
@@ -777,8 +788,8 @@ pub trait Equate {
       (&Locus(n1), &Locus(n2)) if self.eq_locus_var(ctx, n1, n2) => true,
       (Bound(n1), Bound(n2)) => n1.0 + ctx.lift1 == n2.0 + ctx.lift2,
       (Const(ConstId(n1)), Const(ConstId(n2)))
-      | (FreeVar(FVarId(n1)), FreeVar(FVarId(n2)))
-      | (Numeral(n1), Numeral(n2)) => n1 == n2,
+      | (FreeVar(FVarId(n1)), FreeVar(FVarId(n2))) => n1 == n2,
+      (Numeral(n1), Numeral(n2)) => n1 == n2,
       (EqClass(EqClassId(n1)), EqClass(EqClassId(n2)))
       | (EqMark(EqMarkId(n1)), EqMark(EqMarkId(n2)))
       | (Infer(InferId(n1)), Infer(InferId(n2)))
@@ -1269,6 +1280,56 @@ impl Visit for CheckBound {
   }
 }
 
+/// Shifts the bound variables of a term down by `depth` in one pass, like
+/// `OnVarMut(|n| *n -= depth)`, unless the term has a bound variable below `depth` or a
+/// locus, as `CheckBound::get(0..depth, ..)` would find, in which case it is left unchanged.
+pub struct ShiftBound {
+  depth: u32,
+  /// The number of bound variables shifted so far
+  shifted: usize,
+  found: bool,
+}
+impl ShiftBound {
+  /// Returns whether `tm` was shifted.
+  pub fn term(tm: &mut Term, depth: u32) -> bool {
+    let mut sb = Self { depth, shifted: 0, found: false };
+    sb.visit_term(tm);
+    if sb.found && depth > 0 {
+      // The variables are visited in the same order, so this undoes exactly the shifts
+      // made before the variable below `depth` was found
+      struct Unshift(u32, usize);
+      impl VisitMut for Unshift {
+        fn abort(&self) -> bool { self.1 == 0 }
+        fn visit_term(&mut self, tm: &mut Term) {
+          self.super_visit_term(tm);
+          if let (Term::Bound(BoundId(nr)), false) = (tm, self.abort()) {
+            *nr += self.0;
+            self.1 -= 1
+          }
+        }
+      }
+      Unshift(depth, sb.shifted).visit_term(tm)
+    }
+    !sb.found
+  }
+}
+impl VisitMut for ShiftBound {
+  fn abort(&self) -> bool { self.found }
+  fn visit_term(&mut self, tm: &mut Term) {
+    self.super_visit_term(tm);
+    match tm {
+      _ if self.found => {}
+      Term::Bound(BoundId(nr)) if *nr < self.depth => self.found = true,
+      Term::Bound(BoundId(nr)) => {
+        *nr -= self.depth;
+        self.shifted += 1
+      }
+      Term::Locus(_) => self.found = true,
+      _ => {}
+    }
+  }
+}
+
 pub struct CheckLocus(pub bool);
 impl CheckLocus {
   pub fn get(f: impl FnOnce(&mut Self)) -> bool {
@@ -1287,6 +1348,29 @@ impl Visit for CheckLocus {
   }
 }
 
+/// Finds the terms that only make sense inside definitions (`it` and loci) or in the
+/// unifier (free variables), which must not appear in a formula sent to the checker.
+pub struct CheckLeaked(pub Option<&'static str>);
+impl CheckLeaked {
+  pub fn get(f: impl FnOnce(&mut Self)) -> Option<&'static str> {
+    let mut cb = Self(None);
+    f(&mut cb);
+    cb.0
+  }
+}
+impl Visit for CheckLeaked {
+  fn abort(&self) -> bool { self.0.is_some() }
+  fn visit_term(&mut self, tm: &Term) {
+    self.super_visit_term(tm);
+    match *tm {
+      Term::It => self.0 = Some("'it'"),
+      Term::Locus(_) => self.0 = Some("a locus"),
+      Term::FreeVar(_) => self.0 = Some("a free variable"),
+      _ => {}
+    }
+  }
+}
+
 struct OnFunc<F: FnMut(FuncId, &[Term])>(F);
 impl<F: FnMut(FuncId, &[Term])> Visit for OnFunc<F> {
   fn visit_term(&mut self, tm: &Term) {
@@ -1368,12 +1452,30 @@ impl Term {
   }
 
   /// RoundUpTrmType(fTrm = self)
+  ///
+  /// If `recursive` is set, then when checking whether a functor cluster applies,
+  /// the types of the arguments matched against the cluster loci are themselves
+  /// rounded up (see [`Subst::check_loci_types`]), rather than using the types
+  /// recorded in the term cache. This is needed in the equalizer, where the arguments
+  /// may have picked up extra attributes not yet reflected in the cache.
+  /// This plays the role of the global `ItIsChecker` flag in Mizar,
+  /// but it is passed down explicitly rather than stored in `Global`.
   pub fn round_up_type<'a>(
     &self, g: &Global, lc: &'a LocalContext, recursive: bool,
   ) -> CowBox<'a, Type> {
     let tm = self.skip_priv_func(Some(lc));
+    let ground = RoundUpCache::is_ground(tm);
+    if ground {
+      if let Some(ty) = lc.round_up_cache.borrow_mut().get(g, tm, recursive) {
+        return CowBox::Owned(Box::new(ty))
+      }
+    }
     let ty = Box::new(tm.get_type_uncached(g, lc));
-    tm.round_up_type_from(g, lc, CowBox::Owned(ty), recursive)
+    let ty = tm.round_up_type_from(g, lc, CowBox::Owned(ty), recursive);
+    if ground {
+      lc.round_up_cache.borrow_mut().insert(&g.constrs, tm.clone(), recursive, (*ty).clone())
+    }
+    ty
   }
 
   /// RoundUpTrmTypeWithType(lTyp = ty, fTrm = self)
@@ -1426,6 +1528,9 @@ impl Term {
   }
 
   /// GetTrmType(self = fTrm)
+  ///
+  /// `round_up` has the same meaning as in [`Term::round_up_type`], and is used
+  /// when a new term is inserted into the term cache.
   pub fn get_type(&self, g: &Global, lc: &LocalContext, round_up: bool) -> Type {
     // vprintln!("GetTrmType {self:?}");
     match self {
@@ -1474,6 +1579,77 @@ impl Term {
   }
 }
 
+/// An article-scoped cache of [`Term::round_up_type`] for ground terms (built only from
+/// functors, selectors, aggregates and numerals), whose types do not depend on the
+/// local context and so survive from one item to the next. Unlike `TermCollection`,
+/// it is not scoped; instead it is dropped whenever the article registers a new cluster.
+#[derive(Default)]
+pub struct RoundUpCache {
+  /// The number of (registered, functor, conditional) clusters when the cache was filled
+  clusters: (usize, usize, usize),
+  /// sorted by `recursive`, then by `Term::cmp` (CmpStyle::Alt)
+  terms: Vec<(bool, Term, Type)>,
+}
+
+impl RoundUpCache {
+  pub fn clear(&mut self) { self.terms.clear() }
+
+  fn check_ground(f: impl FnOnce(&mut CheckGround)) -> bool {
+    let mut cg = CheckGround(true);
+    f(&mut cg);
+    cg.0
+  }
+
+  fn is_ground(tm: &Term) -> bool {
+    matches!(tm, Term::Functor { .. } | Term::Selector { .. } | Term::Aggregate { .. })
+      && Self::check_ground(|cg| cg.visit_term(tm))
+  }
+
+  fn find(&self, ctx: &Constructors, tm: &Term, recursive: bool) -> Result<usize, usize> {
+    self.terms.binary_search_by(|a| {
+      a.0.cmp(&recursive).then_with(|| a.1.cmp(Some(ctx), None, tm, CmpStyle::Alt))
+    })
+  }
+
+  fn get(&mut self, g: &Global, tm: &Term, recursive: bool) -> Option<Type> {
+    let cl = &g.clusters;
+    let clusters = (cl.registered.len(), cl.functor.len(), cl.conditional.len());
+    if self.clusters != clusters {
+      self.clusters = clusters;
+      self.terms.clear();
+      return None
+    }
+    Some(self.terms[self.find(&g.constrs, tm, recursive).ok()?].2.clone())
+  }
+
+  fn insert(&mut self, ctx: &Constructors, tm: Term, recursive: bool, ty: Type) {
+    // The type may mention inference constants, which do not outlive the current item
+    if !Self::check_ground(|cg| cg.visit_type(&ty)) {
+      return
+    }
+    if let Err(i) = self.find(ctx, &tm, recursive) {
+      if self.terms.len() > crate::GC_THRESHOLD {
+        stat("gc", false);
+        self.terms.clear();
+        return
+      }
+      self.terms.insert(i, (recursive, tm, ty))
+    }
+  }
+}
+
+struct CheckGround(bool);
+impl Visit for CheckGround {
+  fn abort(&self) -> bool { !self.0 }
+  fn visit_term(&mut self, tm: &Term) {
+    match tm {
+      Term::Functor { .. } | Term::Selector { .. } | Term::Aggregate { .. } | Term::Numeral(_) =>
+        self.super_visit_term(tm),
+      _ => self.0 = false,
+    }
+  }
+}
+
 #[derive(Default)]
 pub struct TermCollection {
   scope: u32,
@@ -1956,7 +2132,7 @@ impl Attrs {
   }
 
   pub fn find0(&self, ctx: &Constructors, nr: AttrId, pos: bool) -> bool {
-    self.find0_abs(ctx, nr).map_or(false, |attr| attr.pos == pos)
+    self.find0_abs(ctx, nr).is_some_and(|attr| attr.pos == pos)
   }
 
   pub fn reinsert_all(
@@ -2025,6 +2201,9 @@ impl Attrs {
   }
 
   /// ContradictoryAttrs(aClu1 = self, aClu2 = other)
+  ///
+  /// Attributes are matched after adjusting redefinitions, of both the attribute and its
+  /// arguments, so an attribute contradicts a redefinition of it with the opposite polarity.
   pub fn contradicts(&self, ctx: &Constructors, lc: &LocalContext, other: &Self) -> bool {
     let (Self::Consistent(this), Self::Consistent(other)) = (self, other) else { return true };
     itertools::merge_join_by(this, other, |a, b| a.cmp_abs(Some(ctx), Some(lc), b, CmpStyle::Attr))
@@ -2058,7 +2237,7 @@ impl Attrs {
           }
           for (pos, map) in &*self.new_attr_nums {
             for (&adj_nr, &val) in map {
-              if self.old_attr_nums[pos].get(&adj_nr).map_or(true, |&v| v < val) {
+              if self.old_attr_nums[pos].get(&adj_nr).is_none_or(|&v| v < val) {
                 if let Some(set) = g.clusters.conditional.attr_clusters[pos].get(&adj_nr) {
                   for &nr in set {
                     let x = &mut self.cl_fire[nr as usize];
@@ -2220,6 +2399,84 @@ impl Definiens {
     }
     Some(subst)
   }
+
+  /// Checks that the definiens is well formed with respect to its constructor,
+  /// so that `matches` and the expansion functions can use it without panicking.
+  pub fn check_consistency(&self, ctx: &Constructors) -> Result<(), &'static str> {
+    fn visible<I>(c: Option<&Constructor<I>>) -> Option<usize> {
+      let c = c?;
+      Some(c.primary.len() - if c.redefines.is_some() { c.superfluous as usize } else { 0 })
+    }
+    let arity = match self.constr {
+      ConstrKind::Mode(n) => visible(ctx.mode.get(n).map(|c| &c.c)),
+      ConstrKind::Struct(n) => visible(ctx.struct_mode.get(n).map(|c| &c.c)),
+      ConstrKind::Attr(n) => visible(ctx.attribute.get(n).map(|c| &c.c)),
+      ConstrKind::Pred(n) => visible(ctx.predicate.get(n)),
+      ConstrKind::Func(n) => visible(ctx.functor.get(n).map(|c| &c.c)),
+      ConstrKind::Sel(n) => visible(ctx.selector.get(n).map(|c| &c.c)),
+      ConstrKind::Aggr(n) => visible(ctx.aggregate.get(n).map(|c| &c.c.c)),
+    };
+    let Some(mut arity) = arity else { return Err("unknown constructor") };
+    if let ConstrKind::Func(_) | ConstrKind::Mode(_) = self.constr {
+      arity += 1 // the `it` locus
+    }
+    if self.essential.len() != arity {
+      return Err("number of essential loci does not match the constructor")
+    }
+    let len = self.primary.len();
+    if self.essential.iter().any(|n| n.into_usize() >= len) {
+      return Err("essential locus out of range")
+    }
+    let mut cl = CheckLocusRange(len, false);
+    for (i, ty) in self.primary.iter().enumerate() {
+      cl.0 = i;
+      cl.visit_type(ty);
+    }
+    if cl.1 {
+      return Err("locus type refers to a later locus")
+    }
+    cl.0 = len;
+    cl.visit_formula(&self.assumptions);
+    match &self.value {
+      DefValue::Term(body) => {
+        let ConstrKind::Func(_) = self.constr else {
+          return Err("term definiens for a non-functor constructor")
+        };
+        for case in &*body.cases {
+          cl.visit_term(&case.case);
+          cl.visit_formula(&case.guard);
+        }
+        if let Some(ow) = &body.otherwise {
+          cl.visit_term(ow)
+        }
+      }
+      DefValue::Formula(body) => {
+        for case in &*body.cases {
+          cl.visit_formula(&case.case);
+          cl.visit_formula(&case.guard);
+        }
+        if let Some(ow) = &body.otherwise {
+          cl.visit_formula(ow)
+        }
+      }
+    }
+    if cl.1 {
+      return Err("locus out of range")
+    }
+    Ok(())
+  }
+}
+
+/// Checks that all loci are less than `self.0`.
+struct CheckLocusRange(usize, bool);
+impl Visit for CheckLocusRange {
+  fn abort(&self) -> bool { self.1 }
+  fn visit_term(&mut self, tm: &Term) {
+    self.super_visit_term(tm);
+    if let Term::Locus(n) = *tm {
+      self.1 |= n.into_usize() >= self.0
+    }
+  }
 }
 
 impl EqualsDef {
@@ -2296,7 +2553,7 @@ impl VisitMut for ExpandPrivFunc<'_> {
 impl Term {
   fn try_to_number(&self, g: &Global, lc: &LocalContext) -> Option<Complex> {
     match *self {
-      Term::Numeral(n) => Some(n.into()),
+      Term::Numeral(ref n) => Some(n.into()),
       Term::Functor { nr, ref args } => {
         let (nr, args) = Term::adjust(nr, args, Some(&g.constrs));
         macro_rules! op {
@@ -2471,7 +2728,9 @@ impl VisitMut for InternConst<'_> {
         }
         self.collect_infer_const(tm);
         let Term::Infer(nr) = *tm else { unreachable!() };
-        if let Some(n) = eq {
+        // Record the representative rather than `n` itself, so that a chain of `set`
+        // constants aliasing each other becomes a star around its first constant.
+        if let Some(n) = eq.map(|n| self.lc.eq_const_rep(n)).filter(|&n| n != nr) {
           self.lc.infer_const.borrow_mut()[nr].insert_eq_const(n)
         }
       }
@@ -2517,9 +2776,8 @@ impl VisitMut for InternConst<'_> {
         self.visit_term(scope);
         self.visit_formula(compr);
         self.pop_bound(args.len() as u32);
-        self.only_constants = !CheckBound::get(0..self.depth, |cb| cb.visit_term(tm));
+        self.only_constants = ShiftBound::term(tm, self.depth);
         if self.only_constants {
-          OnVarMut(|n| *n -= self.depth).visit_term(tm);
           self.collect_infer_const(tm)
         }
       }
@@ -2548,6 +2806,17 @@ pub struct ExpandConsts<'a> {
   depth: u32,
 }
 impl LocalContext {
+  /// The canonical representative of the inference constant `n`: the earliest constant
+  /// reached by following its equal constants to earlier ones. All the constants of an
+  /// alias chain have the first one as their representative.
+  pub fn eq_const_rep(&self, mut n: InferId) -> InferId {
+    let ic = self.infer_const.borrow();
+    while let Some(&m) = ic[n].eq_const.iter().filter(|&&m| m < n).min() {
+      n = m
+    }
+    n
+  }
+
   pub fn expand_consts(&self, ctx: &Constructors, f: impl FnOnce(&mut ExpandConsts<'_>)) {
     f(&mut ExpandConsts { ctx, lc: self, ic: &self.infer_const.borrow().vec, depth: 0 })
   }
@@ -2602,7 +2871,7 @@ impl VisitMut for Descope {
   }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FixedVar {
   pub id: IdentId,
   pub ty: Type,
@@ -2610,7 +2879,7 @@ pub struct FixedVar {
   pub def: Option<(Box<Term>, bool)>,
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Serialize, Deserialize)]
 pub struct Assignment {
   /// Must be Term::Functor
   pub def: Term,
@@ -2635,7 +2904,7 @@ impl<V: VisitMut> Visitable<V> for Assignment {
   }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct FuncDef {
   pub primary: Box<[Type]>,
   pub ty: Box<Type>,
@@ -2673,14 +2942,133 @@ pub struct LocalContext {
   pub priv_func: IdxVec<PrivFuncId, FuncDef>,
   /// gTermCollection
   pub term_cache: RefCell<TermCollection>,
+  pub round_up_cache: RefCell<RoundUpCache>,
   /// ItTyp
   pub it_type: Option<Box<Type>>,
   /// Not in mizar, used in equalizer for TrmInfo marks
   pub marks: IdxVec<EqMarkId, (Term, EqTermId)>,
+  pub cluster_hits: ClusterHits,
+  /// The largest tables of the equalizer since the last top level item,
+  /// for [`ProgressSink::on_item_footprint`](crate::analyze::ProgressSink)
+  pub footprint: crate::equate::Footprint,
   pub attr_sort_bug: bool,
 }
 
+/// The number of times each conditional and functor cluster extended an equivalence class
+/// in the round-up of the equalizer, indexed like `Clusters::conditional` and
+/// `Clusters::functor`. With `--cluster-hits`, the counts are kept from one run to the next
+/// in a file keyed by a hash of the environment, and the round-up tries the clusters with
+/// the most hits first. The round-up is a fixpoint, so this does not affect the result.
+#[derive(Default)]
+pub struct ClusterHits {
+  pub ccl: Vec<u32>,
+  pub fcl: Vec<u32>,
+}
+
+impl ClusterHits {
+  pub fn hit(counts: &mut Vec<u32>, i: usize) {
+    if counts.len() <= i {
+      counts.resize(i + 1, 0)
+    }
+    counts[i] += 1
+  }
+
+  /// Sorts `cls`, whose first components are cluster indices, by descending hit count.
+  /// The sort is stable, so the clusters with equal counts stay in registration order.
+  pub fn sort<T>(counts: &[u32], cls: &mut [(usize, T)]) {
+    cls.sort_by_key(|&(i, _)| std::cmp::Reverse(counts.get(i).copied().unwrap_or(0)))
+  }
+
+  pub fn path(dir: &str, clusters: &Clusters) -> PathBuf {
+    use std::hash::{Hash, Hasher};
+    let mut h = std::collections::hash_map::DefaultHasher::new();
+    format!("{:?}{:?}", clusters.conditional.vec, clusters.functor.vec.0).hash(&mut h);
+    format!("{dir}/{:016x}.hits", h.finish()).into()
+  }
+
+  /// Reads the counts saved by `save`. A missing or malformed file gives no counts.
+  pub fn load(path: &std::path::Path) -> Self {
+    let mut out = Self::default();
+    let Ok(text) = std::fs::read_to_string(path) else { return out };
+    for line in text.lines() {
+      let mut it = line.split(' ');
+      let (Some(kind), Some(Ok(i)), Some(Ok(n))) =
+        (it.next(), it.next().map(str::parse::<usize>), it.next().map(str::parse::<u32>))
+      else {
+        return Self::default()
+      };
+      let counts = match kind {
+        "c" => &mut out.ccl,
+        "f" => &mut out.fcl,
+        _ => return Self::default(),
+      };
+      Self::hit(counts, i);
+      counts[i] = n
+    }
+    out
+  }
+
+  pub fn save(&self, path: &std::path::Path) -> io::Result<()> {
+    use std::io::Write;
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    let mut w = io::BufWriter::new(File::create(path)?);
+    for (kind, counts) in [("c", &self.ccl), ("f", &self.fcl)] {
+      for (i, &n) in counts.iter().enumerate().filter(|p| *p.1 != 0) {
+        writeln!(w, "{kind} {i} {n}")?
+      }
+    }
+    w.flush()
+  }
+}
+
+/// The sizes of the growable tables of a [`LocalContext`], taken by
+/// [`LocalContext::snapshot`] so that a speculative run can be undone by
+/// [`LocalContext::rollback`].
+#[must_use]
+pub struct LcSnapshot {
+  bound_var: usize,
+  fixed_var: usize,
+  infer_const: usize,
+  priv_func: usize,
+  marks: usize,
+  term_cache_scope: u32,
+}
+
 impl LocalContext {
+  /// Opens a term cache scope and records the sizes of the tables.
+  /// Snapshots must be rolled back in the reverse order they were taken.
+  pub fn snapshot(&mut self) -> LcSnapshot {
+    let term_cache = self.term_cache.get_mut();
+    term_cache.open_scope();
+    LcSnapshot {
+      bound_var: self.bound_var.len(),
+      fixed_var: self.fixed_var.len(),
+      infer_const: self.infer_const.get_mut().len(),
+      priv_func: self.priv_func.len(),
+      marks: self.marks.len(),
+      term_cache_scope: term_cache.scope,
+    }
+  }
+
+  /// Removes everything that was added to the tables since the snapshot was taken.
+  pub fn rollback(&mut self, snap: LcSnapshot) {
+    let term_cache = self.term_cache.get_mut();
+    debug_assert_eq!(term_cache.scope, snap.term_cache_scope, "unbalanced term cache scopes");
+    term_cache.close_scope();
+    debug_assert!(
+      self.bound_var.len() >= snap.bound_var
+        && self.fixed_var.len() >= snap.fixed_var
+        && self.infer_const.get_mut().len() >= snap.infer_const
+        && self.priv_func.len() >= snap.priv_func,
+      "local context was truncated below a snapshot"
+    );
+    self.bound_var.0.truncate(snap.bound_var);
+    self.fixed_var.0.truncate(snap.fixed_var);
+    self.infer_const.get_mut().truncate(snap.infer_const);
+    self.priv_func.0.truncate(snap.priv_func);
+    self.marks.0.truncate(snap.marks);
+  }
+
   /// gTermCollection.FreeAll
   pub fn clear_term_cache(&self) { self.term_cache.borrow_mut().clear() }
 
@@ -2930,8 +3318,10 @@ pub struct MizPath {
 }
 
 impl MizPath {
+  /// The article named `s`, in any case, since articles are stored with lowercase
+  /// file names whatever case `mml.lar` uses.
   pub fn new(s: &str) -> Result<Self, ToArticleError> {
-    Ok(Self { art: Article::from_lower(s.as_bytes())? })
+    Ok(Self { art: Article::from_upper(s.as_bytes())? })
   }
 
   pub fn mml(&self) -> PathBuf {
@@ -2940,7 +3330,7 @@ impl MizPath {
   }
 
   fn prel(&self, new_prel: bool) -> PathBuf {
-    let s = self.art.as_str();
+    let s = self.art.prel_name();
     if new_prel {
       format!("{}/../prel/{}/{s}", mizfiles(), &s[..1]).into()
     } else {
@@ -2960,17 +3350,210 @@ impl MizPath {
     File::open(path)
   }
 
-  pub fn read_miz(&self) -> io::Result<Vec<u8>> {
+  pub fn read_miz(&self) -> Result<Vec<u8>, crate::error::MizError> {
     let path = self.to_path(true, false, "miz");
     // eprintln!("opening {}", path.to_str().unwrap());
-    std::fs::read(path)
+    Ok(std::fs::read(path)?)
   }
 
+  /// Creates the file `to_path(mml, new_prel, ext)`. Files in `prel/` can't be written
+  /// for long article names, because the Mizar tools read the article names in them
+  /// into 8 characters.
   #[allow(clippy::unwrap_used)]
   pub fn create(&self, mml: bool, new_prel: bool, ext: &str) -> io::Result<File> {
+    if !mml && self.art.is_long() {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+          "{}: can't write .{ext} to prel/, the name is longer than {MIZAR_ARTICLE_LEN} characters",
+          self.art
+        ),
+      ))
+    }
     let path = self.to_path(mml, new_prel, ext);
     std::fs::create_dir_all(path.parent().unwrap())?;
     // eprintln!("writing {}", path.to_str().unwrap());
     File::create(path)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn long_names_are_not_written_to_prel() {
+    let path = MizPath::new("graph_theory_basics").unwrap();
+    let err = path.create(false, false, "dfr").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(path.to_path(false, false, "dfr").ends_with(format!("g/{}.dfr", path.art.prel_name())));
+  }
+
+  #[test]
+  fn cluster_hits_order() {
+    let mut hits = ClusterHits::default();
+    for i in [3, 1, 3, 0, 3, 1] {
+      ClusterHits::hit(&mut hits.ccl, i)
+    }
+    ClusterHits::hit(&mut hits.fcl, 2);
+    let path = std::env::temp_dir().join(format!("mizar-rs-{}.hits", std::process::id()));
+    hits.save(&path).unwrap();
+    let loaded = ClusterHits::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!((loaded.ccl, loaded.fcl), (hits.ccl.clone(), hits.fcl.clone()));
+    let mut cls = (0..5).map(|i| (i, ())).collect::<Vec<_>>();
+    ClusterHits::sort(&hits.ccl, &mut cls);
+    assert_eq!(cls.iter().map(|p| p.0).collect::<Vec<_>>(), [3, 1, 0, 2, 4]);
+  }
+
+  /// A term of at most `size` nodes whose bound variables are below `bound`, with
+  /// Fraenkel terms binding more, and a locus now and then.
+  fn random_term(rng: &mut crate::util::Shuffle, bound: u32, size: &mut u32) -> Term {
+    *size = size.saturating_sub(1);
+    match rng.below(if *size == 0 { 3 } else { 6 }) {
+      0 | 1 if bound > 0 => Term::Bound(BoundId(rng.below(bound as u64) as u32)),
+      0 if rng.below(8) == 0 => Term::Locus(LocusId(0)),
+      0..=2 => Term::Const(ConstId(rng.below(3) as u32)),
+      3 | 4 => {
+        let args = (0..1 + rng.below(2)).map(|_| random_term(rng, bound, size)).collect();
+        Term::Functor { nr: FuncId(rng.below(2) as u32), args }
+      }
+      _ => {
+        let n = 1 + rng.below(2) as u32;
+        let args = (0..n)
+          .map(|i| {
+            let args = vec![random_term(rng, bound + i, size)];
+            (IdentId::NONE, Type { args, ..Type::new(ModeId(1).into()) })
+          })
+          .collect();
+        let scope = Box::new(random_term(rng, bound + n, size));
+        let args2 = [(); 2].map(|_| random_term(rng, bound + n, size));
+        let compr = Box::new(Formula::Pred { nr: PredId(1), args: Box::new(args2) });
+        Term::Fraenkel { args, scope, compr }
+      }
+    }
+  }
+
+  /// `ShiftBound` does what `CheckBound` and then `OnVarMut` do, on random terms with
+  /// binders, and leaves the terms it does not shift unchanged.
+  #[test]
+  fn shift_bound_random() {
+    let mut rng = crate::util::Shuffle::new(0x5eed);
+    let mut outcomes = [0; 2];
+    for _ in 0..2000 {
+      let (depth, mut size) = (rng.below(4) as u32, 1 + rng.below(30) as u32);
+      let tm = random_term(&mut rng, depth, &mut size);
+      let mut want = tm.clone();
+      let ok = !CheckBound::get(0..depth, |cb| cb.visit_term(&want));
+      if ok {
+        OnVarMut(|n| *n -= depth).visit_term(&mut want)
+      }
+      let mut got = tm.clone();
+      assert_eq!(ShiftBound::term(&mut got, depth), ok, "{tm:?} at depth {depth}");
+      assert_eq!(got, want, "{tm:?} at depth {depth}");
+      outcomes[ok as usize] += 1
+    }
+    assert!(outcomes.iter().all(|&n| n > 200), "{outcomes:?}");
+  }
+
+  /// `mk_aggr` builds `(# x.sel #)` for an argument of a structure with a selector, and
+  /// nothing for a structure without selectors or an argument that does not widen to it.
+  #[test]
+  fn mk_aggr_edge_cases() {
+    let mut env = crate::replay::test_env();
+    let c = &mut env.g.constrs;
+    let (s, empty) = (c.struct_mode.peek(), StructId(c.struct_mode.peek().0 + 1));
+    let (sel, aggr) = (c.selector.peek(), c.aggregate.peek());
+    let (s_ty, empty_ty) = (Type::new(s.into()), Type::new(empty.into()));
+    let prim = |ty: &Type| Box::new([ty.clone()]) as Box<[_]>;
+    c.selector.push(TyConstructor { c: Constructor::new(prim(&s_ty)), ty: Type::SET });
+    for (fields, ty) in [(vec![sel], &s_ty), (vec![], &empty_ty)] {
+      let c2 = TyConstructor { c: Constructor::new(Box::new([])), ty: ty.clone() };
+      let nr = c.aggregate.push(Aggregate { c: c2, base: 0, fields: fields.clone().into() });
+      let (c2, parents) = (Constructor::new(Box::new([])), Box::new([]));
+      c.struct_mode.push(StructMode { c: c2, parents, aggr: nr, fields: fields.into() });
+    }
+    let x = Term::Const(ConstId(0));
+    let x_sel = Term::Selector { nr: sel, args: Box::new([x.clone()]) };
+    let want = Term::Aggregate { nr: aggr, args: Box::new([x_sel]) };
+    assert_eq!(Term::mk_aggr(&env.g, &env.lc, s, &x, &s_ty), Some(want));
+    assert_eq!(Term::mk_aggr(&env.g, &env.lc, empty, &x, &empty_ty), None);
+    assert_eq!(Term::mk_aggr(&env.g, &env.lc, s, &x, &empty_ty), None);
+    assert_eq!(Term::mk_aggr(&env.g, &env.lc, s, &x, &Type::SET), None);
+  }
+
+  /// The definientia of `replay::test_env` are consistent with their constructors, and
+  /// each corruption of one is caught. The essential loci of a functor are its visible
+  /// arguments and `it`, and those of an attribute only its visible arguments, where the
+  /// superfluous arguments of a redefinition are not visible.
+  #[test]
+  fn definiens_consistency() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/blobs/blobenv.json");
+    let blob: serde_json::Value =
+      serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let dfs: Vec<Definiens> = serde_json::from_value(blob["expansions"].clone()).unwrap();
+    let mut constrs = crate::replay::test_env().g.constrs;
+    assert!(dfs.iter().all(|df| df.check_consistency(&constrs).is_ok()));
+    let [f, _, small] = &*dfs else { panic!("not the definientia of f, g and small") };
+    let corrupt = |df: &Definiens, g: &dyn Fn(&mut Definiens)| {
+      let mut df = df.clone();
+      g(&mut df);
+      df
+    };
+    let arity = Err("number of essential loci does not match the constructor");
+    let df = corrupt(f, &|df| df.essential = Box::new([LocusId(0)]));
+    assert_eq!(df.check_consistency(&constrs), arity);
+    let df = corrupt(small, &|df| df.essential = Box::new([LocusId(0), LocusId(0)]));
+    assert_eq!(df.check_consistency(&constrs), arity);
+    let df = corrupt(f, &|df| df.essential = Box::new([LocusId(0), LocusId(2)]));
+    assert_eq!(df.check_consistency(&constrs), Err("essential locus out of range"));
+    let df = corrupt(f, &|df| {
+      let otherwise = Some(Term::Locus(LocusId(2)));
+      df.value = DefValue::Term(DefBody { cases: Box::new([]), otherwise })
+    });
+    assert_eq!(df.check_consistency(&constrs), Err("locus out of range"));
+    let df = corrupt(f, &|df| df.c.constr = ConstrKind::Func(FuncId(7)));
+    assert_eq!(df.check_consistency(&constrs), Err("unknown constructor"));
+
+    // as a redefinition of `g` with `x` superfluous, `f x` has only `it` as essential locus
+    let c = &mut constrs.functor[FuncId(0)].c;
+    (c.redefines, c.superfluous) = (Some(FuncId(1)), 1);
+    assert_eq!(f.check_consistency(&constrs), arity);
+    let df = corrupt(f, &|df| df.essential = Box::new([LocusId(1)]));
+    assert_eq!(df.check_consistency(&constrs), Ok(()));
+  }
+
+  fn f(tm: Term) -> Term { Term::Functor { nr: FuncId(0), args: Box::new([tm]) } }
+  fn equals(a: Term, b: Term) -> Formula { Formula::Pred { nr: PredId(0), args: Box::new([a, b]) } }
+
+  /// Adds a constant `c` of type `object`, defined as `def`, and checks `f c = c` in the
+  /// environment of `replay::test_env`. Returns whether it was accepted, with the tables of
+  /// the local context afterwards.
+  fn check_constant(env: &mut crate::replay::Replay, def: Option<Term>) -> (bool, String) {
+    let ty = Type::new(TypeKind::Mode(ModeId(0)));
+    let def = def.map(|tm| (Box::new(tm), false));
+    let c = Term::Const(env.lc.fixed_var.push(FixedVar { id: IdentId::NONE, ty, def }));
+    let mut premise = equals(f(c.clone()), c).mk_neg();
+    let mut ck = env.checker();
+    premise.visit(&mut ck.ctx.intern_const(ck.g, ck.lc));
+    let ok = ck.justify(vec![&premise]).is_ok();
+    let lc = &env.lc;
+    let tables = (&lc.bound_var, &lc.fixed_var, &*lc.infer_const.borrow(), &lc.priv_func);
+    (ok, format!("{} {:?}", serde_json::to_string(&tables).unwrap(), lc.marks.0))
+  }
+
+  /// A speculative run rolled back to a snapshot leaves nothing behind, so the run after it
+  /// is the same as in a fresh local context.
+  #[test]
+  fn snapshot_rollback() {
+    let want = check_constant(&mut crate::replay::test_env(), None);
+    assert!(want.0);
+    let mut env = crate::replay::test_env();
+    let snap = env.lc.snapshot();
+    let start = check_constant(&mut env, None);
+    let speculative = check_constant(&mut env, Some(f(f(Term::Const(ConstId(0))))));
+    assert_ne!(start.1, speculative.1);
+    env.lc.rollback(snap);
+    assert_eq!(check_constant(&mut env, None), want);
+  }
+}
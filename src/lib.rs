@@ -0,0 +1,490 @@
+//! The Mizar verifier as a library. The core (the types, the checker, the equalizer and
+//! the unifier) is always built; the rest is behind cargo features. The `diagnostics`
+//! feature adds the debugging aids of the checker: the double check of `--double-check`
+//! (`audit`), the dumps of failed inferences and their replay (`replay`), the snapshots
+//! (`testing`) and the export of unsolved problems to TPTP (`tptp`). Without it the
+//! options for these are ignored. The other features each need the ones before it:
+//!
+//!   * `parser`: the parsers of `.miz` files and of the XML files in `prel/`
+//!   * `accom`: the accomodator, which builds the environment of an article from `prel/`,
+//!     and the writers of the XML files
+//!   * `exporter`: the analyzer, the reader driving it and the export of its results;
+//!     it also needs `diagnostics`
+//!   * `driver`: the `--server` mode, and the command line tool in `main.rs`
+use crate::format::FormatterConfig;
+use crate::types::*;
+use once_cell::sync::OnceCell;
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::AtomicBool;
+use std::sync::Mutex;
+
+#[cfg(feature = "accom")]
+pub mod accom;
+#[cfg(feature = "exporter")]
+pub mod analyze;
+#[cfg(feature = "parser")]
+pub mod ast;
+#[cfg(feature = "diagnostics")]
+pub mod audit;
+pub mod bignum;
+#[cfg(feature = "accom")]
+pub mod cache;
+pub mod checker;
+#[cfg(feature = "exporter")]
+pub mod compat;
+pub mod eqclass;
+pub mod equate;
+pub mod error;
+#[cfg(feature = "exporter")]
+pub mod export;
+pub mod format;
+pub mod global;
+#[cfg(feature = "parser")]
+pub mod parser;
+#[cfg(feature = "exporter")]
+pub mod query;
+#[cfg(feature = "exporter")]
+pub mod reader;
+#[cfg(any(test, feature = "diagnostics"))]
+pub mod replay;
+#[cfg(feature = "driver")]
+pub mod server;
+#[cfg(feature = "diagnostics")]
+pub mod testing;
+#[cfg(feature = "diagnostics")]
+pub mod tptp;
+pub mod types;
+pub mod unify;
+pub mod util;
+#[cfg(feature = "accom")]
+pub mod write;
+
+pub use global::*;
+
+thread_local! {
+  pub static JOB_ID: Cell<Option<usize>> = const { Cell::new(None) };
+  static NO_STATS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f` without recording stats or peaks, for work whose outcome is not reported
+/// as such, like the speculative runs of the checker.
+pub fn without_stats<R>(f: impl FnOnce() -> R) -> R {
+  let old = NO_STATS.replace(true);
+  let r = f();
+  NO_STATS.set(old);
+  r
+}
+
+#[allow(clippy::unwrap_used)]
+pub fn stat(s: &'static str, fail: bool) {
+  if NO_STATS.get() {
+    return
+  }
+  *STATS.lock().unwrap().get_or_insert_with(HashMap::new).entry(s).or_default() += 1;
+  if fail {
+    if let Some((failures, _)) = JOBS.get() {
+      JOB_ID.with(|job_id| {
+        if let Some(job_id) = job_id.get() {
+          failures[job_id].store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+      })
+    }
+  }
+}
+
+#[macro_export]
+macro_rules! vprintln {
+  ($($args:tt)*) => {
+    if $crate::verbose() {
+      eprintln!($($args)*)
+    }
+  };
+}
+
+/// Set in `--server` mode, where standard output only carries the responses.
+pub static STDOUT_RESERVED: AtomicBool = AtomicBool::new(false);
+pub fn stdout_reserved() -> bool { STDOUT_RESERVED.load(std::sync::atomic::Ordering::Relaxed) }
+
+/// Like `println!`, for the messages and reports of the verifier, which go to
+/// standard output except in `--server` mode, where they go to standard error.
+#[macro_export]
+macro_rules! outln {
+  ($($args:tt)*) => {
+    if $crate::stdout_reserved() {
+      eprintln!($($args)*)
+    } else {
+      println!($($args)*)
+    }
+  };
+}
+
+#[allow(unused)]
+#[macro_export]
+macro_rules! vdbg {
+  ($($args:tt)*) => {
+    if $crate::verbose() {
+      dbg!($($args)*)
+    } else {
+      ($($args)*)
+    }
+  };
+}
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+pub fn verbose() -> bool { DEBUG && VERBOSE.load(std::sync::atomic::Ordering::SeqCst) }
+pub fn set_verbose(b: bool) { VERBOSE.store(b, std::sync::atomic::Ordering::SeqCst) }
+
+static STATS: Mutex<Option<HashMap<&'static str, u32>>> = Mutex::new(None);
+static PEAKS: Mutex<Option<HashMap<&'static str, usize>>> = Mutex::new(None);
+
+/// Records a sample `n` of the quantity `s`, of which the stats report the largest.
+#[allow(clippy::unwrap_used)]
+pub fn peak(s: &'static str, n: usize) {
+  if NO_STATS.get() {
+    return
+  }
+  let mut g = PEAKS.lock().unwrap();
+  let e = g.get_or_insert_with(HashMap::new).entry(s).or_default();
+  *e = (*e).max(n)
+}
+
+pub static JOBS: OnceCell<(Vec<AtomicBool>, Vec<String>)> = OnceCell::new();
+
+pub fn print_stats_and_exit(has_errors: bool) {
+  #[allow(clippy::unwrap_used)]
+  let mut g = STATS.lock().unwrap();
+  let mut vec: Vec<_> = g.get_or_insert_with(HashMap::new).iter().collect();
+  vec.sort();
+  for (s, i) in vec {
+    println!("{s}: {i}");
+  }
+  #[allow(clippy::unwrap_used)]
+  let mut g = PEAKS.lock().unwrap();
+  let mut vec: Vec<_> = g.get_or_insert_with(HashMap::new).iter().collect();
+  vec.sort();
+  for (s, i) in vec {
+    println!("peak {s}: {i}");
+  }
+  if let Some((failures, jobs)) = JOBS.get() {
+    let mut first = true;
+    for (fail, job) in failures.iter().zip(jobs) {
+      if fail.load(std::sync::atomic::Ordering::SeqCst) {
+        if std::mem::take(&mut first) {
+          println!("failed articles:")
+        }
+        println!("  {job}")
+      }
+    }
+  }
+  std::process::exit(has_errors as i32)
+}
+
+macro_rules! mk_dump {
+  (struct $dump:ident {
+    $($id:ident,)*
+  }) => {
+    #[derive(Clone, Debug, Default)]
+    pub struct $dump {
+      $(pub $id: bool,)*
+    }
+    #[derive(Clone, Copy, Debug)]
+    #[cfg_attr(feature = "driver", derive(clap::ValueEnum))]
+    #[allow(non_camel_case_types)]
+    pub enum DumpKind { $($id,)* }
+    impl From<&Option<Vec<DumpKind>>> for $dump {
+      fn from(it: &Option<Vec<DumpKind>>) -> $dump {
+        match it {
+          None => Dump { $($id: false),* },
+          Some(it) if it.is_empty() => Dump { $($id: true),* },
+          Some(it) => {
+            let mut out = $dump::default();
+            for &k in it {
+              match k { $(DumpKind::$id => out.$id = true,)* }
+            }
+            out
+          }
+        }
+      }
+    }
+  }
+}
+mk_dump! {
+  struct Dump {
+    config,
+    constructors,
+    requirements,
+    notations,
+    clusters,
+    definitions,
+    libraries,
+    formatter,
+    environment,
+    directives,
+    directives_json,
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+  pub top_item_header: bool,
+  pub always_verbose_item: bool,
+  pub item_header: bool,
+  pub checker_inputs: bool,
+  pub checker_header: bool,
+  pub checker_conjuncts: bool,
+  pub checker_result: bool,
+  pub unify_header: bool,
+  pub unify_insts: bool,
+  pub unify_head_pruning: bool,
+  pub unify_shared_cache: bool,
+  pub check_definiens: bool,
+  pub corr_conds: bool,
+  pub minimize_failures: bool,
+  pub warn_vacuous: bool,
+  pub warn_directives: bool,
+  pub double_check: bool,
+  pub explain_neq: Option<(String, String)>,
+  pub failure_hints: bool,
+  pub disabled_requirements: BTreeSet<Requirement>,
+  pub export_unsolved_tptp: Option<String>,
+  pub dump_failures_dir: Option<String>,
+  pub snapshot: Option<String>,
+  pub shuffle_seed: Option<u64>,
+  pub compare_with_mizar: Option<String>,
+  pub verify_requirements: bool,
+  pub dump_filter: Option<String>,
+
+  pub dump: Dump,
+
+  pub accom_enabled: bool,
+  pub parser_enabled: bool,
+  pub nameck_enabled: bool,
+  pub analyzer_enabled: bool,
+  pub analyzer_full: bool,
+  pub checker_enabled: bool,
+  pub exporter_enabled: bool,
+  pub verify_export: bool,
+  pub xml_export: bool,
+  pub quick_export: bool,
+  pub analyze_only: bool,
+  pub xml_internals: bool,
+  pub xml_internals_self_test: bool,
+  pub json_parse: bool,
+  pub overwrite_prel: bool,
+  pub cache_prel: bool,
+  pub write_err_file: bool,
+  pub continue_on_error: bool,
+  pub incremental_equalizer: bool,
+  pub share_cluster_antecedents: bool,
+  pub retry_with_equals: bool,
+  pub cluster_hits: Option<String>,
+  pub vocabulary_dirs: Vec<String>,
+
+  // Unsound flags //
+  /// This flag enables checking of `P[a] & ... & P[b]` equality by checking
+  /// only the endpoints `P[a]` and `P[b]`. This is unsound, but needed to
+  /// check some proofs
+  pub legacy_flex_handling: bool,
+
+  /// Cluster lists in `Attrs` are supposed to be sorted, but Mizar fails
+  /// to re-sort after some operations that can change relative sort order,
+  /// notably instantiation. Unfortunately this is user-visible because of
+  /// implicit argument inference in ambiguous cases; afinsq_2 needs a bunch
+  /// of `qua`s and I think there are some cases which are just impossible
+  /// to specify this way. (This is not unsound.)
+  pub attr_sort_bug: bool,
+
+  pub panic_on_fail: bool,
+  pub first_verbose_line: Option<u32>,
+  pub max_term_depth: u32,
+  pub memory_budget_mb: Option<usize>,
+  pub max_marks: Option<usize>,
+  pub equate_budget_ms: Option<u64>,
+  pub unify_budget_ms: Option<u64>,
+  pub one_item: bool,
+  pub skip_to_verbose: bool,
+}
+
+/// The configuration of a plain run of one article: every pass that checks it, with the
+/// defaults of the command line for the other options, and no debugging output.
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      top_item_header: false,
+      always_verbose_item: false,
+      item_header: false,
+      checker_inputs: false,
+      checker_header: false,
+      checker_conjuncts: false,
+      checker_result: false,
+      unify_header: false,
+      unify_insts: false,
+      unify_head_pruning: true,
+      unify_shared_cache: true,
+      check_definiens: false,
+      corr_conds: false,
+      minimize_failures: false,
+      warn_vacuous: false,
+      warn_directives: false,
+      double_check: false,
+      explain_neq: None,
+      failure_hints: false,
+      disabled_requirements: BTreeSet::new(),
+      export_unsolved_tptp: None,
+      dump_failures_dir: None,
+      snapshot: None,
+      shuffle_seed: None,
+      compare_with_mizar: None,
+      verify_requirements: false,
+      dump_filter: None,
+      dump: Dump::default(),
+      accom_enabled: true,
+      parser_enabled: true,
+      nameck_enabled: true,
+      analyzer_enabled: true,
+      analyzer_full: true,
+      checker_enabled: true,
+      exporter_enabled: false,
+      verify_export: false,
+      xml_export: false,
+      quick_export: false,
+      analyze_only: false,
+      xml_internals: false,
+      xml_internals_self_test: false,
+      json_parse: false,
+      overwrite_prel: false,
+      cache_prel: false,
+      write_err_file: false,
+      continue_on_error: true,
+      incremental_equalizer: false,
+      share_cluster_antecedents: false,
+      retry_with_equals: false,
+      cluster_hits: None,
+      vocabulary_dirs: vec![],
+      legacy_flex_handling: true,
+      attr_sort_bug: true,
+      panic_on_fail: false,
+      first_verbose_line: None,
+      max_term_depth: 2000,
+      memory_budget_mb: None,
+      max_marks: None,
+      equate_budget_ms: None,
+      unify_budget_ms: None,
+      one_item: false,
+      skip_to_verbose: false,
+    }
+  }
+}
+
+impl Config {
+  /// Sets one of the options that a `::$name=value` pragma can override for an item.
+  /// Only the limits and diagnostics of the checker can be changed this way.
+  pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+    fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+      value.parse().map_err(|_| format!("invalid value '{value}'"))
+    }
+    match name {
+      "max_term_depth" => self.max_term_depth = parse(value)?,
+      "memory_budget_mb" => self.memory_budget_mb = Some(parse(value)?),
+      "max_marks" => self.max_marks = Some(parse(value)?),
+      "equate_budget_ms" => self.equate_budget_ms = Some(parse(value)?),
+      "unify_budget_ms" => self.unify_budget_ms = Some(parse(value)?),
+      "unify_head_pruning" => self.unify_head_pruning = parse(value)?,
+      "unify_shared_cache" => self.unify_shared_cache = parse(value)?,
+      "incremental_equalizer" => self.incremental_equalizer = parse(value)?,
+      "share_cluster_antecedents" => self.share_cluster_antecedents = parse(value)?,
+      "warn_vacuous" => self.warn_vacuous = parse(value)?,
+      "minimize_failures" => self.minimize_failures = parse(value)?,
+      "failure_hints" => self.failure_hints = parse(value)?,
+      "double_check" => self.double_check = parse(value)?,
+      _ => return Err(format!("unknown option '{name}'")),
+    }
+    Ok(())
+  }
+}
+
+pub const DEBUG: bool = cfg!(debug_assertions);
+const GC_THRESHOLD: usize = 5000;
+pub const READ_MAX_LINE_COUNT: bool = true;
+
+impl FormatterConfig {
+  pub const DEFAULT: Self = Self {
+    enable_formatter: true,
+    show_infer: false,
+    show_only_infer: false,
+    show_priv: false,
+    show_marks: true,
+    show_invisible: false,
+    show_orig: true,
+    show_var_names: true,
+    upper_clusters: false,
+    both_clusters: false,
+    negation_sugar: true,
+  };
+}
+
+pub fn mizfiles() -> &'static str {
+  static MIZFILES: OnceCell<Option<String>> = OnceCell::new();
+  MIZFILES.get_or_init(|| std::env::var("MIZFILES").ok()).as_deref().unwrap_or("miz/mizshare")
+}
+
+pub fn mml_vct_path() -> String { format!("{}/mml.vct", mizfiles()) }
+
+/// The core as an embedder without the `parser` feature sees it: an environment is built by
+/// hand through the public API and an inference is checked in it.
+#[cfg(all(test, not(feature = "parser")))]
+mod core_only {
+  use super::*;
+  use crate::checker::{Checker, CheckerSession};
+
+  /// The modes `object` and `set` and the predicate `=`, with constants `c0` and `c1`
+  fn env() -> (Global, LocalContext) {
+    let mut constrs = Constructors::default();
+    let object = Type::ANY;
+    let mode = |ty| TyConstructor { c: Constructor::new(Box::new([])), ty };
+    constrs.mode.push(mode(object.clone()));
+    constrs.mode.push(mode(object.clone()));
+    let mut eq = Constructor::new(Box::new([object.clone(), object.clone()]));
+    eq.properties.set(PropertyKind::Reflexivity);
+    eq.properties.set(PropertyKind::Symmetry);
+    eq.properties.arg2 = 1;
+    let equals = constrs.predicate.push(eq);
+    let mut reqs = RequirementIndexes::default();
+    reqs.set(Requirement::Any, ConstrKind::Mode(ModeId::ANY));
+    reqs.set(Requirement::SetMode, ConstrKind::Mode(ModeId::SET));
+    reqs.set(Requirement::EqualsTo, ConstrKind::Pred(equals));
+    let (clusters, numeral_type) = (Clusters::default(), Type::SET);
+    let g = Global { cfg: Config::default(), reqs, constrs, clusters, numeral_type };
+    let mut lc = LocalContext::default();
+    for _ in 0..2 {
+      lc.fixed_var.push(FixedVar { id: IdentId::NONE, ty: object.clone(), def: None });
+    }
+    (g, lc)
+  }
+
+  /// Checks the conjunction of `premises`, the first of which is the negated thesis
+  fn justify(premises: &[Formula]) -> bool {
+    let (g, mut lc) = env();
+    let session = CheckerSession::default();
+    let mut checker = Checker {
+      g: &g,
+      lc: &mut lc,
+      ctx: session.ctx(),
+      expand_cache: None,
+      article: Article::from_upper(b"TEST").unwrap(),
+      pos: Position { line: 1, col: 1 },
+      explain_neq: None,
+      reserved_consts: &[],
+    };
+    checker.justify(premises.iter().collect()).is_ok()
+  }
+
+  #[test]
+  fn check_by_hand() {
+    let c = |n| Term::Const(ConstId(n));
+    let equals = |a, b| Formula::Pred { nr: PredId(0), args: Box::new([a, b]) };
+    assert!(justify(&[equals(c(1), c(0)).mk_neg(), equals(c(0), c(1))]));
+    assert!(!justify(&[equals(c(1), c(0)).mk_neg()]));
+  }
+}
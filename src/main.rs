@@ -1,108 +1,18 @@
 // #![warn(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
-use crate::format::FormatterConfig;
-use crate::types::*;
-use clap::{ArgAction, CommandFactory, Parser, ValueEnum};
-use enum_map::EnumMap;
+use clap::{ArgAction, CommandFactory, Parser};
+use enum_map::Enum;
 use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 use itertools::Itertools;
+use mizar_rs::analyze::{ProgressSink, StderrProgress};
+use mizar_rs::reader::ArticleSource;
+use mizar_rs::types::*;
+use mizar_rs::*;
 use once_cell::sync::OnceCell;
-use std::cell::Cell;
-use std::cmp::Ordering;
-use std::collections::{BTreeMap, HashMap};
 use std::fmt::Display;
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
 use std::sync::atomic::AtomicBool;
 use std::sync::Mutex;
 
-mod accom;
-mod analyze;
-mod ast;
-mod bignum;
-mod cache;
-mod checker;
-mod equate;
-mod error;
-mod export;
-mod format;
-mod global;
-mod parser;
-mod reader;
-mod types;
-mod unify;
-mod util;
-mod write;
-
-pub use global::*;
-
-thread_local! {
-  static JOB_ID: Cell<Option<usize>> = const { Cell::new(None) };
-}
-
-#[allow(clippy::unwrap_used)]
-pub fn stat(s: &'static str, fail: bool) {
-  *STATS.lock().unwrap().get_or_insert_with(HashMap::new).entry(s).or_default() += 1;
-  if fail {
-    if let Some((failures, _)) = JOBS.get() {
-      JOB_ID.with(|job_id| {
-        if let Some(job_id) = job_id.get() {
-          failures[job_id].store(true, std::sync::atomic::Ordering::SeqCst);
-        }
-      })
-    }
-  }
-}
-
-#[macro_export]
-macro_rules! vprintln {
-  ($($args:tt)*) => {
-    if $crate::verbose() {
-      eprintln!($($args)*)
-    }
-  };
-}
-
-#[allow(unused)]
-#[macro_export]
-macro_rules! vdbg {
-  ($($args:tt)*) => {
-    if $crate::verbose() {
-      dbg!($($args)*)
-    } else {
-      ($($args)*)
-    }
-  };
-}
-
-static VERBOSE: AtomicBool = AtomicBool::new(false);
-pub fn verbose() -> bool { DEBUG && VERBOSE.load(std::sync::atomic::Ordering::SeqCst) }
-pub fn set_verbose(b: bool) { VERBOSE.store(b, std::sync::atomic::Ordering::SeqCst) }
-
-static STATS: Mutex<Option<HashMap<&'static str, u32>>> = Mutex::new(None);
-
-static JOBS: OnceCell<(Vec<AtomicBool>, Vec<String>)> = OnceCell::new();
-
-fn print_stats_and_exit(has_errors: bool) {
-  #[allow(clippy::unwrap_used)]
-  let mut g = STATS.lock().unwrap();
-  let mut vec: Vec<_> = g.get_or_insert_with(HashMap::new).iter().collect();
-  vec.sort();
-  for (s, i) in vec {
-    println!("{s}: {i}");
-  }
-  if let Some((failures, jobs)) = JOBS.get() {
-    let mut first = true;
-    for (fail, job) in failures.iter().zip(jobs) {
-      if fail.load(std::sync::atomic::Ordering::SeqCst) {
-        if std::mem::take(&mut first) {
-          println!("failed articles:")
-        }
-        println!("  {job}")
-      }
-    }
-  }
-  std::process::exit(has_errors as i32)
-}
-
 #[derive(Clone)]
 struct Progress {
   multi: MultiProgress,
@@ -181,6 +91,17 @@ struct Cli {
     require_equals = true, default_missing_value = "true", hide_possible_values = true)]
   one_file: bool,
 
+  /// Read the text of the selected article from standard input
+  /// instead of from its `.miz` file (implies `--one-file`)
+  #[arg(long)]
+  stdin: bool,
+
+  /// Verify articles on request: read JSON requests `{"id": .., "article": ..}` from
+  /// standard input, one per line, and answer each with its errors on standard output.
+  /// A request `{"id": .., "problem": <blob>}` checks a failure blob as `--replay` does
+  #[arg(long, conflicts_with = "stdin")]
+  server: bool,
+
   /// Index of the last file to process, if specified
   #[arg(long)]
   last_file: Option<usize>,
@@ -231,6 +152,15 @@ struct CliPasses {
   /// Produce exported statements to the `miz/prel/` directory (requires `-e`)
   #[arg(short = 'x', long)]
   xml_export: bool,
+  /// Check only the correctness conditions of definitions and registrations, skipping the
+  /// other proofs. The exported statements are well-formed, but theorems whose proofs were
+  /// skipped are not verified
+  #[arg(long)]
+  quick_export: bool,
+  /// Enables (only) the analyzer, skipping all proofs, and prints the statements of the
+  /// theorems and definitions as JSON, one per line with the name of the article
+  #[arg(long)]
+  analyze_only: bool,
   /// Produce XML files for internal data structures, in Mizar-compatible format
   #[arg(short = 'X', long)]
   xml_internals: bool,
@@ -263,6 +193,42 @@ struct CliOther {
   #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(false),
     require_equals = true, default_missing_value = "true", hide_possible_values = true)]
   overwrite_prel: bool,
+  /// Keep checking the rest of the article after a failed inference, as Mizar does.
+  /// If false, the checker is skipped after the first error
+  #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(true),
+    require_equals = true, default_missing_value = "true", hide_possible_values = true)]
+  continue_on_error: bool,
+  /// When the normal form of an inference has several conjuncts, pass their common
+  /// premises through the equalizer only once and reuse the result for each conjunct
+  #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(false),
+    require_equals = true, default_missing_value = "true", hide_possible_values = true)]
+  incremental_equalizer: bool,
+  /// Check the coherence of consecutive conditional clusters with the same type and
+  /// antecedent (and no references) as one inference, sharing the antecedent in the
+  /// equalizer. The clusters are checked one by one if it fails
+  #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(false),
+    require_equals = true, default_missing_value = "true", hide_possible_values = true)]
+  share_cluster_antecedents: bool,
+  /// Try a failed inference again, with the equals definitions of its functors added as
+  /// equations (up to two unfoldings). This can accept inferences that Mizar rejects
+  #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(false),
+    require_equals = true, default_missing_value = "true", hide_possible_values = true)]
+  retry_with_equals: bool,
+  /// Keep per-cluster hit counts of the round-up in this directory, one file per environment,
+  /// and try the clusters with the most hits first
+  #[arg(long, value_name = "DIR")]
+  cluster_hits: Option<String>,
+  /// Look for vocabularies in `<DIR>/mml.vct` before the one in `$MIZFILES`, in the order
+  /// given. A vocabulary in `<article>.vct` next to the article is looked for first of all
+  #[arg(long, value_name = "DIR")]
+  vocabulary_dir: Vec<String>,
+  /// Write errors to `<article>.err` next to the article, in the format used by Mizar.
+  /// Only failed inferences (4) and invalid conclusions and assumptions (51, 52) have their
+  /// Mizar codes; inferences that ran out of a budget are written as 9998, and other errors
+  /// as 9999, with their messages in `<article>.err.txt`
+  #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(false),
+    require_equals = true, default_missing_value = "true", hide_possible_values = true)]
+  write_err_file: bool,
   /// Always read cross-article theorems from `prel/` instead of from memory
   #[arg(long)]
   no_cache: bool,
@@ -272,10 +238,117 @@ struct CliOther {
   /// Don't show the fancy progress bar
   #[arg(long)]
   no_progress: bool,
+  /// Print a line to stderr as each top level item is completed
+  #[arg(long)]
+  item_progress: bool,
+  /// Reject inferences whose terms or formulas are nested more deeply than this,
+  /// instead of overflowing the stack
+  #[arg(long, default_value_t = 2000)]
+  max_term_depth: u32,
+  /// Give up on an inference when the tables of the equalizer are estimated to take
+  /// more than this many megabytes, instead of running out of memory
+  #[arg(long, value_name = "MB")]
+  memory_budget_mb: Option<usize>,
+  /// Give up on a conjunct when the equalizer has made this many marks (term occurrences),
+  /// which stops it early on inferences that would otherwise exhaust the memory
+  #[arg(long, value_name = "N")]
+  max_marks: Option<usize>,
+  /// Give up on a conjunct when the equalizer takes more than this many milliseconds on it
+  #[arg(long, value_name = "MS")]
+  equate_budget_ms: Option<u64>,
+  /// Stop looking for instances of the universal premises for a conjunct after this many
+  /// milliseconds in the unifier
+  #[arg(long, value_name = "MS")]
+  unify_budget_ms: Option<u64>,
 }
 
 #[derive(Debug, clap::Args)]
 struct CliDebug {
+  /// Check that definientia are consistent with their constructors
+  #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(DEBUG),
+    require_equals = true, default_missing_value = "true", hide_possible_values = true)]
+  check_definiens: bool,
+  /// Print the correctness conditions generated for each definition in verbose mode
+  #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(DEBUG),
+    require_equals = true, default_missing_value = "true", hide_possible_values = true)]
+  corr_conds: bool,
+  /// Re-verify successful inferences with a naive congruence closure procedure,
+  /// and warn about the ones it cannot confirm
+  #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(false),
+    require_equals = true, default_missing_value = "true", hide_possible_values = true)]
+  double_check: bool,
+  /// When an inference fails, explain why the checker does not consider these two terms
+  /// equal, if they are built from functors and variables in scope at that point.
+  /// The terms can only use the symbols of the environment
+  #[arg(long, num_args = 2, value_names = ["TERM1", "TERM2"])]
+  explain_neq: Option<Vec<String>>,
+  /// When an inference fails, print the final equivalence classes and the closest misses
+  /// of the unifier, as a sketch of a countermodel
+  #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(false),
+    require_equals = true, default_missing_value = "true", hide_possible_values = true)]
+  failure_hints: bool,
+  /// Turn off the built-in reasoning of the checker about these requirements
+  /// (for example `element,power-set`), to find out which one an inference depends on
+  #[arg(long, value_delimiter = ',', value_name = "REQ")]
+  disable_requirements: Vec<String>,
+  /// When an inference fails, write the conjunct that could not be refuted to a TPTP file
+  /// `<article>-<line>-<col>-<conjunct>.p` in this directory, for use with external provers
+  #[arg(long, value_name = "DIR")]
+  export_unsolved_tptp: Option<String>,
+  /// When an inference fails, write the state of the checker to a JSON file
+  /// `<article>-<line>-<col>.json` in this directory, which `--replay` reads
+  #[arg(long, value_name = "DIR")]
+  dump_failures_dir: Option<String>,
+  /// Check the inference of a file written by `--dump-failures-dir` again, without the
+  /// MML, then exit
+  #[arg(long, value_name = "BLOB")]
+  replay: Option<String>,
+  /// Compare the outcome of every inference with the snapshot `<DIR>/<article>.json`,
+  /// and print the differences. Set `MIZAR_BLESS=1` to write the snapshot instead
+  #[arg(long, value_name = "DIR")]
+  snapshot: Option<String>,
+  /// Permute the premises, the conjuncts and the equivalence classes of each inference,
+  /// and the clusters of the round-up, pseudo-randomly with this seed. Comparing `--snapshot`
+  /// runs with different seeds shows which inferences depend on the order these are
+  /// considered in
+  #[arg(long, value_name = "SEED")]
+  shuffle_seed: Option<u64>,
+  /// Compare two directories of snapshots written by `--snapshot` (say, before and after
+  /// a change) and print the inferences that changed, then exit
+  #[arg(long, num_args = 2, value_names = ["OLD", "NEW"])]
+  compare_snapshots: Option<Vec<String>>,
+  /// For `--compare-snapshots`, flag the inferences whose atoms or equivalence classes
+  /// grew by more than this factor
+  #[arg(long, default_value_t = 2.0)]
+  regression_factor: f64,
+  /// Also run the original Mizar verifier (`verifier`, or the given command) on each
+  /// article, and report the errors on which the two disagree
+  #[arg(long, value_name = "VERIFIER", num_args = 0..=1, require_equals = true,
+    default_missing_value = "verifier")]
+  compare_with_mizar: Option<String>,
+  /// Compare the requirements computed from the `requirements` directive with the
+  /// article's `.ere` file, if there is one, and report the requirements on which they differ
+  #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(false),
+    require_equals = true, default_missing_value = "true", hide_possible_values = true)]
+  verify_requirements: bool,
+  /// Only print the entries of `--dump=environment` that mention the given symbol
+  #[arg(long, value_name = "SYMBOL")]
+  dump_filter: Option<String>,
+  /// When an inference fails, search for a minimal subset of the premises and part of the
+  /// thesis that still fails, and print it as a JSON line
+  #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(false),
+    require_equals = true, default_missing_value = "true", hide_possible_values = true)]
+  minimize_failures: bool,
+  /// Warn about inferences that hold only because the premises other than the
+  /// conclusion are already contradictory, and print a minimal contradictory subset
+  #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(false),
+    require_equals = true, default_missing_value = "true", hide_possible_values = true)]
+  warn_vacuous: bool,
+  /// Warn about `constructors` directives that are already included by the ones before them,
+  /// and about notations that are dropped because their constructors are not included
+  #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(false),
+    require_equals = true, default_missing_value = "true", hide_possible_values = true)]
+  warn_directives: bool,
   /// Print a header at every top level item
   #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(false),
     require_equals = true, default_missing_value = "true", hide_possible_values = true)]
@@ -312,6 +385,16 @@ struct CliDebug {
   #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(DEBUG),
     require_equals = true, default_missing_value = "true", hide_possible_values = true)]
   unify_insts: bool,
+  /// Skip unifying a variable with a class when no type of the class has the head of the
+  /// variable's type. Turning this off should not change any results
+  #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(true),
+    require_equals = true, default_missing_value = "true", hide_possible_values = true)]
+  unify_head_pruning: bool,
+  /// Share the instantiations of the free variables of the universal premises between all
+  /// the subproblems of the unifier. Turning this off should not change any results
+  #[arg(long, num_args = 0..=1, action = ArgAction::Set, default_value = bool_to_str(true),
+    require_equals = true, default_missing_value = "true", hide_possible_values = true)]
+  unify_shared_cache: bool,
 }
 
 #[derive(Debug, clap::Args)]
@@ -335,136 +418,58 @@ struct CliUnsound {
 //   }
 // }
 
-macro_rules! mk_dump {
-  (struct $dump:ident {
-    $($id:ident,)*
-  }) => {
-    #[derive(Clone, Debug, Default)]
-    pub struct $dump {
-      $(pub $id: bool,)*
-    }
-    #[derive(Clone, Copy, Debug, ValueEnum)]
-    #[allow(non_camel_case_types)]
-    enum DumpKind { $($id,)* }
-    impl From<&Option<Vec<DumpKind>>> for $dump {
-      fn from(it: &Option<Vec<DumpKind>>) -> $dump {
-        match it {
-          None => Dump { $($id: false),* },
-          Some(it) if it.is_empty() => Dump { $($id: true),* },
-          Some(it) => {
-            let mut out = $dump::default();
-            for &k in it {
-              match k { $(DumpKind::$id => out.$id = true,)* }
-            }
-            out
-          }
-        }
-      }
-    }
-  }
-}
-mk_dump! {
-  struct Dump {
-    config,
-    constructors,
-    requirements,
-    notations,
-    clusters,
-    definitions,
-    libraries,
-    formatter,
-  }
-}
-
-#[derive(Clone, Debug)]
-pub struct Config {
-  pub top_item_header: bool,
-  pub always_verbose_item: bool,
-  pub item_header: bool,
-  pub checker_inputs: bool,
-  pub checker_header: bool,
-  pub checker_conjuncts: bool,
-  pub checker_result: bool,
-  pub unify_header: bool,
-  pub unify_insts: bool,
-
-  pub dump: Dump,
-
-  pub accom_enabled: bool,
-  pub parser_enabled: bool,
-  pub nameck_enabled: bool,
-  pub analyzer_enabled: bool,
-  pub analyzer_full: bool,
-  pub checker_enabled: bool,
-  pub exporter_enabled: bool,
-  pub verify_export: bool,
-  pub xml_export: bool,
-  pub xml_internals: bool,
-  pub xml_internals_self_test: bool,
-  pub json_parse: bool,
-  pub overwrite_prel: bool,
-  pub cache_prel: bool,
-
-  // Unsound flags //
-  /// This flag enables checking of `P[a] & ... & P[b]` equality by checking
-  /// only the endpoints `P[a]` and `P[b]`. This is unsound, but needed to
-  /// check some proofs
-  pub legacy_flex_handling: bool,
-
-  /// Cluster lists in `Attrs` are supposed to be sorted, but Mizar fails
-  /// to re-sort after some operations that can change relative sort order,
-  /// notably instantiation. Unfortunately this is user-visible because of
-  /// implicit argument inference in ambiguous cases; afinsq_2 needs a bunch
-  /// of `qua`s and I think there are some cases which are just impossible
-  /// to specify this way. (This is not unsound.)
-  pub attr_sort_bug: bool,
-
-  pub panic_on_fail: bool,
-  pub first_verbose_line: Option<u32>,
-  pub one_item: bool,
-  pub skip_to_verbose: bool,
-}
-
-const DEBUG: bool = cfg!(debug_assertions);
-const GC_THRESHOLD: usize = 5000;
-const READ_MAX_LINE_COUNT: bool = true;
-
-impl FormatterConfig {
-  const DEFAULT: Self = Self {
-    enable_formatter: true,
-    show_infer: false,
-    show_only_infer: false,
-    show_priv: false,
-    show_marks: true,
-    show_invisible: false,
-    show_orig: true,
-    show_var_names: true,
-    upper_clusters: false,
-    both_clusters: false,
-    negation_sugar: true,
-  };
-}
-
 fn conflict(msg: impl Display) -> ! {
   Cli::command().error(clap::error::ErrorKind::ArgumentConflict, msg).exit()
 }
 
-fn mizfiles() -> &'static str {
-  static MIZFILES: OnceCell<Option<String>> = OnceCell::new();
-  MIZFILES.get_or_init(|| std::env::var("MIZFILES").ok()).as_deref().unwrap_or("miz/mizshare")
-}
-
 fn mizbin() -> &'static str {
   static MIZBIN: OnceCell<Option<String>> = OnceCell::new();
   MIZBIN.get_or_init(|| std::env::var("MIZBIN").ok()).as_deref().unwrap_or("miz/mizbin")
 }
 
 fn mml_lar_path() -> String { format!("{}/mml.lar", mizfiles()) }
-fn mml_vct_path() -> String { format!("{}/mml.vct", mizfiles()) }
+
+/// Exits with an explanation of where the MML data file `path` should come from.
+fn missing_mml_file(path: &str, why: impl Display) -> ! {
+  eprintln!(
+    "error: cannot read {path}: {why}\n\
+    This file is part of the MML, which is looked for in {} (set MIZFILES to change that). \
+    Run ./download-mml.sh to download it into miz/, or link miz/ to a Mizar installation.",
+    mizfiles()
+  );
+  std::process::exit(1)
+}
+
+/// Reads one of the MML data files, or exits if it is not there.
+fn read_mml_file(path: &str) -> Vec<u8> {
+  std::fs::read(path).unwrap_or_else(|e| missing_mml_file(path, e))
+}
+
+fn parse_requirement(name: &str) -> Requirement {
+  let norm = |s: &str| s.replace(['-', '_'], "").to_ascii_lowercase();
+  (0..Requirement::LENGTH)
+    .map(Requirement::from_usize)
+    .find(|req| norm(&format!("{req:?}")) == norm(name))
+    .unwrap_or_else(|| {
+      let msg = format!("unknown requirement '{name}'");
+      Cli::command().error(clap::error::ErrorKind::InvalidValue, msg).exit()
+    })
+}
 
 fn main() {
   let cli = Cli::parse();
-  let enable = cli.passes.analyzer || cli.passes.checker || cli.passes.export;
+  error::install_panic_hook();
+  if let Some([old, new]) = cli.debug.compare_snapshots.as_deref() {
+    match testing::compare_snapshots(old, new, cli.debug.regression_factor) {
+      Ok(same) => std::process::exit(!same as i32),
+      Err(e) => {
+        println!("IO error comparing snapshots: {e}");
+        std::process::exit(1)
+      }
+    }
+  }
+  let enable =
+    cli.passes.analyzer || cli.passes.checker || cli.passes.export || cli.passes.analyze_only;
   let disable = cli.passes.no_analyzer || cli.passes.no_checker || cli.passes.no_export;
   if enable && disable {
     conflict("can't use positive and negative pass selectors together")
@@ -473,17 +478,30 @@ fn main() {
     accom_enabled: !cli.passes.no_accom,
     parser_enabled: !cli.passes.no_parser,
     nameck_enabled: !cli.passes.no_nameck,
-    analyzer_enabled: if enable { cli.passes.analyzer } else { !cli.passes.no_analyzer },
+    analyzer_enabled: if enable {
+      cli.passes.analyzer || cli.passes.analyze_only
+    } else {
+      !cli.passes.no_analyzer
+    },
     analyzer_full: Default::default(),
     checker_enabled: if enable { cli.passes.checker } else { !cli.passes.no_checker },
     exporter_enabled: if enable { cli.passes.export } else { !cli.passes.no_export },
     verify_export: cli.passes.verify_export,
     xml_export: cli.passes.xml_export,
+    quick_export: cli.passes.quick_export,
+    analyze_only: cli.passes.analyze_only,
     xml_internals: cli.passes.xml_internals,
     xml_internals_self_test: cli.passes.xml_internals_self_test,
     json_parse: cli.passes.json_parse,
     overwrite_prel: cli.other.overwrite_prel,
     cache_prel: Default::default(),
+    write_err_file: cli.other.write_err_file,
+    continue_on_error: cli.other.continue_on_error,
+    incremental_equalizer: cli.other.incremental_equalizer,
+    share_cluster_antecedents: cli.other.share_cluster_antecedents,
+    retry_with_equals: cli.other.retry_with_equals,
+    cluster_hits: cli.other.cluster_hits,
+    vocabulary_dirs: cli.other.vocabulary_dir,
 
     top_item_header: cli.debug.top_item_header,
     always_verbose_item: cli.debug.always_verbose_item,
@@ -494,6 +512,26 @@ fn main() {
     checker_result: cli.debug.checker_result,
     unify_header: cli.debug.unify_header,
     unify_insts: cli.debug.unify_insts,
+    unify_head_pruning: cli.debug.unify_head_pruning,
+    unify_shared_cache: cli.debug.unify_shared_cache,
+    check_definiens: cli.debug.check_definiens,
+    corr_conds: cli.debug.corr_conds,
+    minimize_failures: cli.debug.minimize_failures,
+    warn_vacuous: cli.debug.warn_vacuous,
+    warn_directives: cli.debug.warn_directives,
+    double_check: cli.debug.double_check,
+    explain_neq: cli.debug.explain_neq.and_then(|tms| tms.into_iter().collect_tuple()),
+    failure_hints: cli.debug.failure_hints,
+    disabled_requirements: (cli.debug.disable_requirements.iter())
+      .map(|name| parse_requirement(name))
+      .collect(),
+    export_unsolved_tptp: cli.debug.export_unsolved_tptp,
+    dump_failures_dir: cli.debug.dump_failures_dir,
+    snapshot: cli.debug.snapshot,
+    shuffle_seed: cli.debug.shuffle_seed,
+    compare_with_mizar: cli.debug.compare_with_mizar,
+    verify_requirements: cli.debug.verify_requirements,
+    dump_filter: cli.debug.dump_filter,
 
     dump: (&cli.dump).into(),
 
@@ -502,6 +540,11 @@ fn main() {
 
     panic_on_fail: cli.other.panic_on_fail,
     first_verbose_line: cli.first_verbose_line, // None,
+    max_term_depth: cli.other.max_term_depth,
+    memory_budget_mb: cli.other.memory_budget_mb,
+    max_marks: cli.other.max_marks,
+    equate_budget_ms: cli.other.equate_budget_ms,
+    unify_budget_ms: cli.other.unify_budget_ms,
     one_item: cli.one_item,
     skip_to_verbose: cli.skip_to_verbose,
   };
@@ -513,31 +556,57 @@ fn main() {
   // let path = MizPath(Article::from_bytes(b"TEST"), "../test/text/test".into());
   // path.with_reader(&cfg, |v| v.run_checker(&path));
   // print_stats_and_exit(cfg.parallelism);
-  cfg.analyzer_full = cfg.analyzer_enabled;
+  cfg.analyzer_full = cfg.analyzer_enabled && !cfg.analyze_only;
   cfg.accom_enabled |= cfg.parser_enabled; // parser needs accom
   cfg.nameck_enabled |= cfg.parser_enabled; // parser needs nameck
   cfg.analyzer_full |= cfg.checker_enabled; // checker needs analyzer_full (if analyzer is used)
-  cfg.cache_prel = !cli.one_file && !cli.other.no_cache;
+  let one_file = cli.one_file || cli.stdin || cli.server;
+  cfg.cache_prel = !one_file && !cli.other.no_cache;
   cfg.exporter_enabled &= cfg.xml_export || cfg.verify_export || cfg.cache_prel;
   cfg.analyzer_enabled |= cfg.exporter_enabled; // exporter needs (quick) analyzer
   if cfg.cache_prel && cli.dep_order && cfg.verify_export {
     conflict("VERIFY_EXPORT and DEP_ORDER + CACHE are incompatible")
   }
-  let one_file = cli.one_file;
+  if cli.stdin && !cfg.parser_enabled {
+    conflict("--stdin requires the parser")
+  }
+  let stdin_text = &if cli.stdin {
+    let mut buf = vec![];
+    io::stdin().read_to_end(&mut buf).unwrap_or_else(|e| {
+      println!("IO error reading stdin: {e}");
+      std::process::abort()
+    });
+    Some(buf)
+  } else {
+    None
+  };
 
-  let file = std::fs::read_to_string(mml_lar_path()).unwrap_or_else(|e| {
-    println!("IO error reading {}: {e}", mml_lar_path());
-    std::process::abort()
+  if let Some(blob) = &cli.debug.replay {
+    match replay::replay(&cfg, blob) {
+      Ok(accepted) => print_stats_and_exit(!accepted),
+      Err(e) => {
+        println!("error replaying {blob}: {e}");
+        std::process::exit(1)
+      }
+    }
+  }
+
+  let file = String::from_utf8(read_mml_file(&mml_lar_path())).unwrap_or_else(|e| {
+    missing_mml_file(&mml_lar_path(), format!("not valid UTF-8 ({})", e.utf8_error()))
   });
   let mml_vct = &if cfg.accom_enabled {
-    let mml_vct_path = mml_vct_path();
-    std::fs::read(&mml_vct_path).unwrap_or_else(|e| {
-      println!("IO error reading {mml_vct_path}: {e}");
-      std::process::abort()
-    })
+    read_mml_file(&mml_vct_path())
   } else {
     vec![]
   };
+  if cli.server {
+    STDOUT_RESERVED.store(true, std::sync::atomic::Ordering::Relaxed);
+    if let Err(e) = server::run(&cfg, mml_vct) {
+      eprintln!("IO error in server: {e}");
+      std::process::exit(1)
+    }
+    std::process::exit(0)
+  }
   let mut jobs = file.lines().enumerate().collect_vec();
   let first_file = match cli.file {
     None => FIRST_FILE,
@@ -615,6 +684,14 @@ fn main() {
               continue
             }
           };
+          if path.art.is_long() {
+            eprintln!(
+              "warning: {}:{}: article name {s} is longer than the {MIZAR_ARTICLE_LEN} \
+              characters the Mizar tools accept",
+              mml_lar_path(),
+              i + 1
+            )
+          }
           if let Some(thread) = &thread {
             thread.set_message(format!("{i:4}: {s}"));
             thread.set_length(1);
@@ -622,7 +699,11 @@ fn main() {
             thread.reset_elapsed();
           }
           let start = std::time::Instant::now();
-          let result = std::panic::catch_unwind(|| -> io::Result<bool> {
+          let src = match stdin_text {
+            Some(text) => ArticleSource::Memory(text),
+            None => ArticleSource::File,
+          };
+          let result = std::panic::catch_unwind(|| -> Result<bool, error::MizError> {
             if cli.orig_mizar {
               let mizbin = mizbin();
               if cfg.accom_enabled {
@@ -663,9 +744,14 @@ fn main() {
               }
               Ok(false)
             } else if cfg.parser_enabled || cfg.analyzer_enabled {
-              path.with_reader(cfg, thread.as_ref(), mml_vct, &mut |v, p| v.run_analyzer(&path, p))
+              let mut stderr = StderrProgress::default();
+              let sink: &mut dyn ProgressSink =
+                if cli.other.item_progress { &mut stderr } else { &mut () };
+              path.with_reader(cfg, thread.as_ref(), mml_vct, src, &mut |v, p| {
+                v.run_analyzer(&path, p, sink)
+              })
             } else if cfg.checker_enabled {
-              path.with_reader(cfg, thread.as_ref(), mml_vct, &mut |v, _| v.run_checker(&path))
+              path.with_reader(cfg, thread.as_ref(), mml_vct, src, &mut |v, _| v.run_checker(&path))
             } else {
               Ok(false)
             }
@@ -676,7 +762,7 @@ fn main() {
                 has_errors.store(true, std::sync::atomic::Ordering::Relaxed)
               },
             Ok(Err(err)) => {
-              println!("error: {i}: {s} IO error: {err}");
+              println!("error: {i}: {s} {err}");
               stat("panic", true);
               if cfg.panic_on_fail {
                 std::process::abort()
@@ -721,5 +807,8 @@ fn main() {
     drop(p.multi.clear());
   }
   // std::thread::sleep(std::time::Duration::from_secs(60 * 60));
+  if cfg.quick_export && cfg.checker_enabled {
+    println!("quick export: only correctness conditions were checked, theorems are unverified")
+  }
   print_stats_and_exit(*has_errors.get_mut());
 }
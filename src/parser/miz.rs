@@ -1,11 +1,11 @@
 use crate::ast::{SchRef, *};
+use crate::parser::OWriteJson;
 use crate::types::{
   Article, ArticleId, BlockKind, CorrCondKind, DefId, DirectiveKind, Directives, Format,
   FormatFunc, FormatId, FuncSymId, IdxVec, LeftBrkSymId, LocusId, ModeSymId, Position, PredSymId,
   PriorityKind, PropertyKind, RightBrkSymId, SchId, StructSymId, SymbolKind, Symbols, ThmId,
   MAX_ARTICLE_LEN,
 };
-use crate::write::OWriteJson;
 use crate::READ_MAX_LINE_COUNT;
 use enum_map::Enum;
 use indicatif::ProgressBar;
@@ -128,6 +128,8 @@ enum TokenKind {
   Pragma,
   Dollar(u32),
   Number(u32),
+  /// A numeral that does not fit in a `u32`; the digits are in the spelling
+  BigNumber,
   Ident,
   Symbol(SymbolKind),
   Keyword(Keyword),
@@ -292,7 +294,7 @@ impl<'a> Scanner<'a> {
               if let Ok(n) = str.parse() {
                 TokenKind::Number(n)
               } else {
-                TokenKind::Error
+                TokenKind::BigNumber
               }
             } else {
               TokenKind::Ident
@@ -453,6 +455,29 @@ impl<'a> Parser<'a> {
     }
   }
 
+  /// Parses a standalone term, such as one given on the command line,
+  /// using the symbols and formats currently loaded into this parser.
+  pub fn parse_standalone_term(&mut self, data: &[u8]) -> Result<Box<Term>, String> {
+    fn swap(p: &mut Parser<'_>, q: &mut Parser<'_>) {
+      std::mem::swap(&mut p.scan.tokens, &mut q.scan.tokens);
+      std::mem::swap(&mut p.articles, &mut q.articles);
+      std::mem::swap(&mut p.formats, &mut q.formats);
+      std::mem::swap(&mut p.max_mode_args, &mut q.max_mode_args);
+      std::mem::swap(&mut p.max_struct_args, &mut q.max_struct_args);
+      std::mem::swap(&mut p.max_pred_rhs, &mut q.max_pred_rhs);
+      std::mem::swap(&mut p.func_prio, &mut q.func_prio);
+      std::mem::swap(&mut p.format_lookup, &mut q.format_lookup);
+    }
+    let mut p = Parser::new(self.art, None, data, Default::default());
+    swap(self, &mut p);
+    let res = crate::error::catch_quietly(|| (p.parse_term(), p.scan.next().kind));
+    swap(self, &mut p);
+    match res? {
+      (tm, TokenKind::Eof) => Ok(tm),
+      _ => Err("expected end of term".into()),
+    }
+  }
+
   fn parse_article(tok: Token<'a>) -> Article {
     assert!(
       tok.spelling.len() <= MAX_ARTICLE_LEN,
@@ -562,7 +587,9 @@ impl<'a> Parser<'a> {
         } else {
           Box::new(Term::Var { pos: tok.pos, kind: None, spelling: tok.spelling.to_owned() })
         },
-      TokenKind::Number(value) => Box::new(Term::Numeral { pos: tok.pos, value }),
+      TokenKind::Number(value) => Box::new(Term::Numeral { pos: tok.pos, value: value.into() }),
+      TokenKind::BigNumber =>
+        Box::new(Term::Numeral { pos: tok.pos, value: tok.spelling.parse().unwrap() }),
       TokenKind::Keyword(Keyword::It) => Box::new(Term::It { pos: tok.pos }),
       TokenKind::Dollar(nr) => {
         let nr =
@@ -699,7 +726,7 @@ impl<'a> LongTermBuilder<'a> {
   fn valid(&self, p: &Parser<'a>, i: usize) -> bool {
     let sym = self.stack[i].sym.0;
     let left = if self.stack[i].to_right { self.stack[i].lhs.len() as u8 } else { 1 };
-    let right = if self.stack.get(i + 1).map_or(false, |elem| elem.to_right) {
+    let right = if self.stack.get(i + 1).is_some_and(|elem| elem.to_right) {
       1
     } else {
       self.stack.get(i + 1).map_or(&self.rhs, |elem| &elem.lhs).len() as u8
@@ -846,7 +873,7 @@ impl<'a> Parser<'a> {
         }
         TokenKind::Keyword(Keyword::Comma)
           if !lhs.is_empty()
-            && (*paren > 0 || max_out.as_mut().map_or(true, |n| *n > 1 && (*n -= 1, true).1)) =>
+            && (*paren > 0 || max_out.as_mut().is_none_or(|n| *n > 1 && (*n -= 1, true).1)) =>
         {
           self.scan.next();
           lhs.push(*self.parse_term());
@@ -1,6 +1,6 @@
 use crate::parser::article::ArticleParser;
 use crate::types::*;
-use crate::{CmpStyle, MizPath, OnVarMut, RequirementIndexes, VisitMut};
+use crate::{outln, CmpStyle, MizPath, OnVarMut, RequirementIndexes, VisitMut};
 use enum_map::Enum;
 use quick_xml::events::{BytesStart, Event};
 use std::borrow::Cow;
@@ -12,9 +12,11 @@ use std::result::Result as StdResult;
 use std::str::FromStr;
 
 mod article;
+mod json;
 mod miz;
 mod msm;
 
+pub use json::{OWriteJson, WriteJson};
 pub use miz::Parser as MizParser;
 pub use msm::MsmParser;
 
@@ -23,7 +25,9 @@ impl MizPath {
     let mut r = BufReader::new(self.open(true, false, "ere")?);
     let mut buf = String::new();
     let mut pos = r.read_line(&mut buf)?;
-    assert!(buf.trim_end() == "0");
+    if buf.trim_end() != "0" {
+      return Err(ParseError::BadInteger(0))
+    }
     buf.clear();
     for (_, val) in &mut idx.fwd {
       let line = r.read_line(&mut buf)?;
@@ -72,22 +76,7 @@ impl MizPath {
   }
 
   pub fn read_sgl(&self, arts: &mut Vec<Article>) -> PathResult<()> {
-    with_open(self.to_path(true, false, "sgl"), false, |file| {
-      let mut r = BufReader::new(file);
-      let mut buf = String::new();
-      let mut pos = r.read_line(&mut buf)?;
-      let n = buf.trim_end().parse().map_err(|_| ParseError::BadInteger(0))?;
-      for _ in 0..n {
-        buf.clear();
-        let line = r.read_line(&mut buf)?;
-        let art = Article::from_upper(buf.trim_end().as_bytes());
-        arts.push(art.map_err(|e| ParseError::ToArticle(e, pos))?);
-        pos += line;
-      }
-      // Note: this is not the end of the file (constructor data follows),
-      // but the remainder is never parsed by Mizar
-      Ok(())
-    })
+    read_sgl_from(self.to_path(true, false, "sgl"), arts)
   }
 
   // pub fn read_dct(file: File, syms: &mut Vec<Token>) -> io::Result<()> {
@@ -108,7 +97,7 @@ impl MizPath {
 
 impl Article {
   pub fn read_vct<'a>(self, buf: &'a [u8], voc: &mut Vocabulary<'a>) -> Result<bool> {
-    let mut pattern = [0; 9];
+    let mut pattern = [0; MAX_ARTICLE_LEN + 1];
     let n = self.as_bytes().len();
     pattern[..n].copy_from_slice(self.as_bytes());
     pattern[..n].make_ascii_uppercase();
@@ -116,7 +105,7 @@ impl Article {
     let pattern = &pattern[..=n];
     let mut pos = 0;
     while let Some(i) = memchr::memchr(b'#', &buf[pos..]) {
-      if i.checked_sub(1).map_or(true, |i| buf[pos + i] == b'\n')
+      if i.checked_sub(1).is_none_or(|i| buf[pos + i] == b'\n')
         && buf[pos + i + 1..].get(0..pattern.len()) == Some(pattern)
       {
         pos += i + 1 + pattern.len();
@@ -205,6 +194,8 @@ pub enum ParseError {
   BadInteger(usize),
   ToArticle(ToArticleError, usize),
   InvalidVocabLine(usize, String),
+  /// A constructor property refers to an argument the constructor does not have
+  BadPropertyArgs(usize, Properties, usize),
   MissingFile,
 }
 
@@ -240,11 +231,27 @@ impl std::fmt::Display for ParseError {
       ParseError::ExpectedEof(_) => write!(f, "expected EOF"),
       ParseError::BadInteger(_) => write!(f, "not an integer or out of range"),
       ParseError::InvalidVocabLine(_, line) => write!(f, "invalid vocabulary line '{line}'"),
+      ParseError::BadPropertyArgs(_, props, len) => write!(
+        f,
+        "properties {:?} use arguments {} and {}, but the constructor has {len}",
+        props.properties,
+        props.arg1 + 1,
+        props.arg2 + 1
+      ),
       ParseError::MissingFile => write!(f, "file not found"),
       ParseError::ToArticle(e, _) => e.fmt(f),
     }
   }
 }
+impl std::error::Error for ParseError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      ParseError::Xml(_, e) => Some(e),
+      ParseError::ToArticle(e, _) => Some(e),
+      _ => None,
+    }
+  }
+}
 
 impl ParseError {
   pub fn pos(&self) -> Option<usize> {
@@ -254,7 +261,8 @@ impl ParseError {
       | ParseError::ExpectedEof(pos)
       | ParseError::BadInteger(pos)
       | ParseError::ToArticle(_, pos)
-      | ParseError::InvalidVocabLine(pos, _) => Some(pos),
+      | ParseError::InvalidVocabLine(pos, _)
+      | ParseError::BadPropertyArgs(pos, ..) => Some(pos),
       ParseError::MissingFile => None,
     }
   }
@@ -272,6 +280,9 @@ impl FromStrPos for u32 {
 impl FromStrPos for usize {
   fn to_err(_: Self::Err, pos: usize) -> ParseError { ParseError::BadInteger(pos) }
 }
+impl FromStrPos for Numeral {
+  fn to_err(_: Self::Err, pos: usize) -> ParseError { ParseError::BadInteger(pos) }
+}
 
 pub fn catch_missing<T>(result: PathResult<T>) -> PathResult<Option<T>> {
   match result {
@@ -314,6 +325,7 @@ impl XmlReader {
       | ParseError::BadInteger(_)
       | ParseError::ToArticle(..)
       | ParseError::InvalidVocabLine(..)
+      | ParseError::BadPropertyArgs(..)
       | ParseError::MissingFile => {}
     }
   }
@@ -357,6 +369,24 @@ impl XmlReader {
     }
   }
 
+  /// Like `try_read_start`, for the repeated sections of a prel file. Elements other than
+  /// the expected ones are skipped with a warning, so that files written by newer versions
+  /// of Mizar, which add sections of their own, can still be read.
+  fn try_read_section(
+    &mut self, buf: &mut Vec<u8>, expecting: &[&str],
+  ) -> Result<Option<BytesStart<'static>>> {
+    loop {
+      let name = match self.read_event(buf)? {
+        Event::Start(e) if expecting.iter().any(|s| e.local_name().as_ref() == s.as_bytes()) =>
+          return Ok(Some(e.into_owned())),
+        Event::Start(e) => e.name().as_ref().to_vec(),
+        _ => return Ok(None),
+      };
+      SKIPPED_SECTIONS.with(|v| v.borrow_mut().push(String::from_utf8_lossy(&name).into()));
+      self.read_to_end(&name, buf)
+    }
+  }
+
   fn eof(&mut self, buf: &mut Vec<u8>) -> Result<()> {
     if let Event::Eof = self.read_event(buf)? {
       Ok(())
@@ -388,7 +418,7 @@ impl XmlReader {
     let _ = r.seek_relative(-1024);
     let mut out = vec![];
     r.read_to_end(&mut out).unwrap();
-    println!("{}", std::str::from_utf8(&out[..1024]).unwrap());
+    outln!("{}", std::str::from_utf8(&out[..1024]).unwrap());
   }
 
   fn get_pos(&mut self, e: &BytesStart<'_>) -> Result<Position> {
@@ -475,14 +505,68 @@ impl<'a> MizReader<'a> {
   }
 }
 
+thread_local! {
+  /// The sections skipped by `try_read_section` in the file currently being read,
+  /// reported by `with_open` once the file is done.
+  static SKIPPED_SECTIONS: std::cell::RefCell<Vec<String>> =
+    const { std::cell::RefCell::new(vec![]) };
+}
+
+/// The body of [`MizPath::read_sgl`], reading from an explicit path.
+pub(crate) fn read_sgl_from(path: PathBuf, arts: &mut Vec<Article>) -> PathResult<()> {
+  with_open(path, false, |file| {
+    let mut r = BufReader::new(file);
+    let mut buf = String::new();
+    let mut pos = r.read_line(&mut buf)?;
+    let n = buf.trim_end().parse().map_err(|_| ParseError::BadInteger(0))?;
+    for _ in 0..n {
+      buf.clear();
+      let line = r.read_line(&mut buf)?;
+      let art = Article::from_upper(buf.trim_end().as_bytes());
+      arts.push(art.map_err(|e| ParseError::ToArticle(e, pos))?);
+      pos += line;
+    }
+    // Note: this is not the end of the file (constructor data follows),
+    // but the remainder is never parsed by Mizar
+    Ok(())
+  })
+}
+
+/// The body of [`MizPath::read_aco`], reading from an explicit path.
+pub(crate) fn read_aco_from(path: PathBuf, aco: &mut AccumConstructors) -> PathResult<()> {
+  with_open(path, false, |file| {
+    MizReader::with(file, MaybeMut::None, false, |r, buf| {
+      r.read_pi(buf)?;
+      r.read_start(buf, Some("Constructors"))?;
+      r.read_start(buf, Some("SignatureWithCounts"))?;
+      while let Ok(e) = r.try_read_start(buf, Some("ConstrCounts"))? {
+        let art =
+          Article::from_upper(&e.try_get_attribute(b"name").unwrap().unwrap().value).unwrap();
+        let mut counts = Default::default();
+        r.parse_constr_counts_body(buf, &mut counts)?;
+        aco.sig.sig.push((art, std::mem::replace(&mut aco.sig.base, counts)));
+      }
+      r.parse_constructors_body(buf, Some(&mut aco.constrs))?;
+      r.eof(buf)?;
+      assert_eq!(aco.sig.sig.0[0].0, Article::HIDDEN);
+      Ok(())
+    })
+  })
+}
+
 fn with_open<T>(
   path: PathBuf, allow_empty: bool, f: impl FnOnce(File) -> Result<T>,
 ) -> PathResult<T> {
-  match File::open(&path) {
+  let result = match File::open(&path) {
     Err(e) if allow_empty && e.kind() == io::ErrorKind::NotFound =>
-      Err((path, ParseError::MissingFile)),
-    file => (|| f(file?))().map_err(|e| (path, e)),
-  }
+      return Err((path, ParseError::MissingFile)),
+    file => (|| f(file?))(),
+  };
+  let skipped = SKIPPED_SECTIONS.with(|v| std::mem::take(&mut *v.borrow_mut()));
+  if !skipped.is_empty() {
+    crate::error::report_skipped_sections(&path, &skipped)
+  }
+  result.map_err(|e| (path, e))
 }
 
 fn with_open0(path: PathBuf, f: impl FnOnce(File) -> Result<()>) -> PathResult<()> {
@@ -587,7 +671,7 @@ impl MizPath {
       MizReader::with(file, MaybeMut::None, false, |r, buf| {
         r.read_pi(buf)?;
         r.read_start(buf, Some("Notations"))?;
-        while let Ok(e) = r.try_read_start(buf, Some("Pattern"))? {
+        while let Some(e) = r.try_read_section(buf, &["Pattern"])? {
           let attrs = r.parse_pattern_attrs(&e)?;
           notas.push(r.parse_pattern_body(buf, attrs, |x| x)?)
         }
@@ -602,7 +686,7 @@ impl MizPath {
         r.read_start(buf, Some("Notations"))?;
         r.parse_signature(buf, &mut dno.sig)?;
         r.parse_vocabularies(buf, &mut dno.vocs)?;
-        while let Ok(e) = r.try_read_start(buf, Some("Pattern"))? {
+        while let Some(e) = r.try_read_section(buf, &["Pattern"])? {
           let attrs = r.parse_pattern_attrs(&e)?;
           let Elem::Format(fmt) = r.parse_elem(buf)? else { panic!("expected <Format>") };
           dno.pats.push(r.parse_pattern_body(buf, attrs, |_| fmt)?)
@@ -624,24 +708,7 @@ impl MizPath {
   }
 
   pub fn read_aco(&self, aco: &mut AccumConstructors) -> PathResult<()> {
-    with_open(self.to_path(true, false, "aco"), false, |file| {
-      MizReader::with(file, MaybeMut::None, false, |r, buf| {
-        r.read_pi(buf)?;
-        r.read_start(buf, Some("Constructors"))?;
-        r.read_start(buf, Some("SignatureWithCounts"))?;
-        while let Ok(e) = r.try_read_start(buf, Some("ConstrCounts"))? {
-          let art =
-            Article::from_upper(&e.try_get_attribute(b"name").unwrap().unwrap().value).unwrap();
-          let mut counts = Default::default();
-          r.parse_constr_counts_body(buf, &mut counts)?;
-          aco.sig.sig.push((art, std::mem::replace(&mut aco.sig.base, counts)));
-        }
-        r.parse_constructors_body(buf, Some(&mut aco.constrs))?;
-        r.eof(buf)?;
-        assert_eq!(aco.sig.sig.0[0].0, Article::HIDDEN);
-        Ok(())
-      })
-    })
+    read_aco_from(self.to_path(true, false, "aco"), aco)
   }
 
   pub fn read_dco_uncached(
@@ -651,8 +718,7 @@ impl MizPath {
       MizReader::with(file, MaybeMut::None, false, |r, buf| {
         r.read_start(buf, Some("Constructors"))?;
         r.parse_signature(buf, &mut dco.sig)?;
-        r.read_start(buf, Some("ConstrCounts"))?;
-        r.parse_constr_counts_body(buf, &mut dco.counts)?;
+        r.parse_dep_constr_counts(buf, &mut dco.counts)?;
         if read_constrs {
           r.parse_constructors_body(buf, Some(&mut dco.constrs))?;
           r.eof(buf)?;
@@ -667,7 +733,7 @@ impl MizPath {
       MizReader::with(file, MaybeMut::None, false, |r, buf| {
         r.read_start(buf, Some("Requirements"))?;
         r.parse_signature(buf, &mut dre.sig)?;
-        while let Ok(e) = r.try_read_start(buf, Some("Requirement"))? {
+        while let Some(e) = r.try_read_section(buf, &["Requirement"])? {
           let kind = r.parse_constr_kind(&e)?.unwrap();
           let nr: u32 = r.get_attr(&e.try_get_attribute(b"nr").unwrap().unwrap().value)?;
           dre.reqs.push(DepRequirement { req: Requirement::from_usize((nr - 1) as _), kind });
@@ -700,7 +766,7 @@ impl MizPath {
       MizReader::with(file, MaybeMut::None, false, |r, buf| {
         r.read_start(buf, Some("Registrations"))?;
         r.parse_signature(buf, &mut dcl.sig)?;
-        while let Event::Start(e) = r.read_event(buf)? {
+        while let Some(e) = r.try_read_section(buf, &["RCluster", "FCluster", "CCluster"])? {
           match r.parse_cluster_attrs(&e)? {
             (aid, ClusterKind::R) => dcl.cl.registered.push(r.parse_rcluster(buf, aid)?),
             (aid, ClusterKind::F) => dcl.cl.functor.push(r.parse_fcluster(buf, aid)?),
@@ -725,7 +791,7 @@ impl MizPath {
         if let Some(sig) = sig {
           r.parse_signature(buf, sig)?;
         }
-        while let Ok(e) = r.try_read_start(buf, Some("Definiens"))? {
+        while let Some(e) = r.try_read_section(buf, &["Definiens"])? {
           let attrs = r.parse_definiens_attrs(e)?;
           defs.push(r.parse_definiens_body(buf, attrs)?)
         }
@@ -747,7 +813,7 @@ impl MizPath {
         if let Some(sig) = sig {
           r.parse_signature(buf, sig)?;
         }
-        while let Ok(e) = r.try_read_start(buf, Some("Property"))? {
+        while let Some(e) = r.try_read_section(buf, &["Property"])? {
           let attrs = r.parse_property_attrs(&e)?;
           props.push(r.parse_property_body(buf, attrs)?)
         }
@@ -769,7 +835,7 @@ impl MizPath {
         if let Some(sig) = sig {
           r.parse_signature(buf, sig)?;
         }
-        while let Ok(e) = r.try_read_start(buf, Some("Identify"))? {
+        while let Some(e) = r.try_read_section(buf, &["Identify"])? {
           let attrs = r.parse_identify_attrs(&e)?;
           ids.push(r.parse_identify_body(buf, attrs)?);
         }
@@ -791,7 +857,7 @@ impl MizPath {
         if let Some(sig) = sig {
           r.parse_signature(buf, sig)?;
         }
-        while let Ok(e) = r.try_read_start(buf, Some("Reduction"))? {
+        while let Some(e) = r.try_read_section(buf, &["Reduction"])? {
           let attrs = r.parse_reduction_attrs(&e)?;
           reds.push(r.parse_reduction_body(buf, attrs)?);
         }
@@ -819,12 +885,12 @@ impl MizPath {
             }
           }
           match kind {
-            b'T' if refs.map_or(true, |refs| refs.thm.contains(&(lib_nr, ThmId(thm_nr)))) => {
+            b'T' if refs.is_none_or(|refs| refs.thm.contains(&(lib_nr, ThmId(thm_nr)))) => {
               let th = r.parse_formula(buf)?.unwrap();
               r.end_tag(buf)?;
               libs.thm.insert((lib_nr, ThmId(thm_nr)), th);
             }
-            b'D' if refs.map_or(true, |refs| refs.def.contains(&(lib_nr, DefId(thm_nr)))) => {
+            b'D' if refs.is_none_or(|refs| refs.def.contains(&(lib_nr, DefId(thm_nr)))) => {
               let th = r.parse_formula(buf)?.unwrap();
               r.end_tag(buf)?;
               libs.def.insert((lib_nr, DefId(thm_nr)), th);
@@ -843,7 +909,7 @@ impl MizPath {
       MizReader::with(file, MaybeMut::None, false, |r, buf| {
         r.read_start(buf, Some("Theorems"))?;
         r.parse_signature(buf, &mut thms.sig)?;
-        while let Ok(e) = r.try_read_start(buf, Some("Theorem"))? {
+        while let Some(e) = r.try_read_section(buf, &["Theorem"])? {
           let kind = e.try_get_attribute(b"kind").unwrap().unwrap().value[0];
           let constr_kind = r.parse_constr_kind(&e)?;
           let stmt = r.parse_formula(buf)?.unwrap();
@@ -883,7 +949,7 @@ impl MizPath {
               _ => {}
             }
           }
-          if refs.map_or(true, |refs| refs.sch.contains(&(lib_nr, sch_nr))) {
+          if refs.is_none_or(|refs| refs.sch.contains(&(lib_nr, sch_nr))) {
             let sch_funcs = r.parse_arg_types(buf)?;
             if let Some(thesis) = r.parse_formula(buf)? {
               let mut prems = vec![];
@@ -907,7 +973,7 @@ impl MizPath {
       MizReader::with(file, MaybeMut::None, false, |r, buf| {
         r.read_start(buf, Some("Schemes"))?;
         r.parse_signature(buf, &mut schs.sig)?;
-        while let Event::Start(e) = r.read_event(buf)? {
+        while let Some(e) = r.try_read_section(buf, &["Canceled", "Scheme"])? {
           match e.local_name().as_ref() {
             b"Canceled" => {
               r.end_tag(buf)?;
@@ -1231,6 +1297,29 @@ impl MizReader<'_> {
     Ok(Pattern { article, abs_nr, kind, fmt: map(fmt), primary, visible, pos })
   }
 
+  /// Reads the constructor counts of a `.dco` file. These are either a `<ConstrCounts>`
+  /// block, or in the newer layout a `<SignatureWithCounts>` block as in `.aco` files,
+  /// in which the counts of the article itself come last.
+  fn parse_dep_constr_counts(
+    &mut self, buf: &mut Vec<u8>, counts: &mut ConstructorsBase,
+  ) -> Result<()> {
+    let e = self.read_start(buf, None)?;
+    match e.local_name().as_ref() {
+      b"ConstrCounts" => self.parse_constr_counts_body(buf, counts),
+      b"SignatureWithCounts" => {
+        while self.try_read_start(buf, Some("ConstrCounts"))?.is_ok() {
+          *counts = Default::default();
+          self.parse_constr_counts_body(buf, counts)?
+        }
+        Ok(())
+      }
+      _ => {
+        let got = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
+        Err(ParseError::unexpected_elem(self.position(), "ConstrCounts", Some(got.into())))
+      }
+    }
+  }
+
   fn parse_constr_counts_body(
     &mut self, buf: &mut Vec<u8>, counts: &mut ConstructorsBase,
   ) -> Result<()> {
@@ -1286,6 +1375,13 @@ impl MizReader<'_> {
       Elem::ArgTypes(args) => (Default::default(), args),
       _ => panic!("expected <ArgTypes>"),
     };
+    // the checker indexes the arguments with these, so they must be in range
+    let len = primary.len();
+    if properties.uses_arg1() && properties.arg1 as usize >= len
+      || properties.uses_arg2() && properties.arg2 as usize >= len
+    {
+      return Err(ParseError::BadPropertyArgs(self.position(), properties, len))
+    }
     macro_rules! constructor {
       ($id:ident) => {{
         let redefines = redefines.checked_sub(1).map($id);
@@ -1575,7 +1671,13 @@ impl MizReader<'_> {
         b"Const" => Elem::Term(Term::Const(ConstId(parse_var!() - 1))),
         // b"InfConst" => Elem::Term(Term::Infer { nr: InferId(parse_var!() - 1) }),
         // b"FreeVar" => Elem::Term(Term::FreeVar { nr: parse_var!() - 1 }),
-        b"Num" => Elem::Term(Term::Numeral(parse_var!())),
+        b"Num" => Elem::Term(Term::Numeral({
+          let attr = e.attributes().next().unwrap()?;
+          assert!(attr.key.0 == b"nr");
+          let value = self.get_attr(&attr.value)?;
+          self.end_tag(buf)?;
+          value
+        })),
         b"Func" => {
           let (kind, nr) = self.get_basic_attrs(&e)?;
           let args = self.parse_term_list(buf)?;
@@ -1678,9 +1780,9 @@ impl MizReader<'_> {
           let Formula::ForAll { id: _, dom, scope } = self.parse_formula(buf)?.unwrap() else {
             panic!()
           };
-          let sc2 = scope.mk_neg();
-          let &[Formula::Pred { nr: le, .. }, _, ref rest @ ..] = sc2.conjuncts() else { panic!() };
-          let scope = Formula::mk_and(rest.to_owned());
+          let conjs = scope.mk_neg().into_conjuncts();
+          let [Formula::Pred { nr: le, .. }, _, rest @ ..] = &*conjs else { panic!() };
+          let (le, scope) = (*le, Formula::mk_and(rest.to_vec()));
           self.end_tag(buf)?;
           Elem::Formula(Formula::FlexAnd { nat: dom, le, terms, scope: Box::new(scope.mk_neg()) })
         }
@@ -1900,3 +2002,63 @@ impl TryFrom<Elem> for Formula {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn read_counts(name: &str, xml: &str) -> ConstructorsBase {
+    let path = std::env::temp_dir().join(format!("mizar-rs-{}-{name}.dco", std::process::id()));
+    std::fs::write(&path, xml).unwrap();
+    let mut counts = ConstructorsBase::default();
+    with_open(path.clone(), false, |file| {
+      MizReader::with(file, MaybeMut::None, false, |r, buf| {
+        r.read_start(buf, Some("Constructors"))?;
+        r.parse_dep_constr_counts(buf, &mut counts)?;
+        while let Some(e) = r.try_read_section(buf, &["Constructor"])? {
+          r.read_to_end(e.name().as_ref(), buf)
+        }
+        Ok(())
+      })
+    })
+    .unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert!(SKIPPED_SECTIONS.with(|v| v.borrow().is_empty()));
+    counts
+  }
+
+  /// Numerals that do not fit in a `u32` are parsed to their exact value, and the others
+  /// stay inline.
+  #[test]
+  fn big_numerals() {
+    let art = Article::from_lower(b"bignum").unwrap();
+    let mut p = MizParser::new(art, None, b"", Default::default());
+    let mut value = |s: &str| match *p.parse_standalone_term(s.as_bytes()).unwrap() {
+      crate::ast::Term::Numeral { value, .. } => value,
+      ref t => panic!("expected a numeral, got {t:?}"),
+    };
+    let big = "123456789012345678901234567890";
+    let Numeral::Large(n) = value(big) else { panic!("{big} is not large") };
+    assert_eq!(n.to_string(), big);
+    assert!(value("4294967295") == Numeral::Small(u32::MAX));
+    assert!(matches!(value("4294967296"), Numeral::Large(n) if *n == (1u64 << 32).into()));
+  }
+
+  #[test]
+  fn dco_counts_layouts_agree() {
+    let old = read_counts(
+      "old",
+      r#"<?xml version="1.0"?><Constructors><ConstrCounts>
+        <ConstrCount kind="M" nr="2"/><ConstrCount kind="K" nr="5"/>
+      </ConstrCounts><Extension/></Constructors>"#,
+    );
+    let new = read_counts(
+      "new",
+      r#"<?xml version="1.0"?><Constructors><SignatureWithCounts><ConstrCounts>
+        <ConstrCount kind="M" nr="2"/><ConstrCount kind="K" nr="5"/>
+      </ConstrCounts></SignatureWithCounts></Constructors>"#,
+    );
+    assert_eq!((old.mode, old.functor, old.predicate), (2, 5, 0));
+    assert_eq!(old, new);
+  }
+}
@@ -0,0 +1,178 @@
+//! Queries over the accommodated environment, for `--dump=environment`.
+//!
+//! This gives a flat view of what an article can see once its environment has been read:
+//! the notations with their symbols, the constructors with the symbols that refer to them,
+//! and the clusters. Constructors are named by kind and number as in the rest of the
+//! checker, and symbols are the spellings from the `.dcx` file, so they are only known when
+//! the formatter is enabled. The result is printed as one JSON object per line, optionally
+//! restricted to the entries mentioning a given symbol (`--dump-filter`).
+use crate::format::Formatter;
+use crate::types::*;
+use serde_derive::Serialize;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct NotationEntry {
+  pub kind: &'static str,
+  /// The constructor this notation is for, if it is not an expandable mode
+  pub nr: Option<u32>,
+  pub symbol: String,
+  pub args: usize,
+  pub visible: usize,
+  pub pos: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ConstructorEntry {
+  pub kind: &'static str,
+  pub nr: u32,
+  pub args: usize,
+  pub superfluous: u8,
+  pub redefines: Option<u32>,
+  /// The symbols of all notations for this constructor
+  pub symbols: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ClusterEntry {
+  pub kind: &'static str,
+  pub args: usize,
+  /// The attributes of the antecedent (for conditional clusters), as (number, positive)
+  pub antecedent: Vec<(u32, bool)>,
+  /// The attributes of the consequent, as (number, positive)
+  pub consequent: Vec<(u32, bool)>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "entry", rename_all = "lowercase")]
+pub enum Entry {
+  Notation(NotationEntry),
+  Constructor(ConstructorEntry),
+  Cluster(ClusterEntry),
+}
+
+impl Entry {
+  /// Whether this entry mentions the symbol `sym`
+  pub fn mentions(&self, sym: &str) -> bool {
+    match self {
+      Entry::Notation(n) => n.symbol == sym,
+      Entry::Constructor(c) => c.symbols.iter().any(|s| s == sym),
+      Entry::Cluster(_) => false,
+    }
+  }
+}
+
+fn pattern_kind(kind: &PatternKind) -> (&'static str, Option<u32>) {
+  match *kind {
+    PatternKind::Mode(nr) => ("mode", Some(nr.0)),
+    PatternKind::ExpandableMode { .. } => ("mode", None),
+    PatternKind::Struct(nr) => ("struct", Some(nr.0)),
+    PatternKind::Attr(nr) => ("attr", Some(nr.0)),
+    PatternKind::Pred(nr) => ("pred", Some(nr.0)),
+    PatternKind::Func(nr) => ("func", Some(nr.0)),
+    PatternKind::Sel(nr) => ("sel", Some(nr.0)),
+    PatternKind::Aggr(nr) => ("aggr", Some(nr.0)),
+    PatternKind::SubAggr(nr) => ("subaggr", Some(nr.0)),
+  }
+}
+
+/// The spelling of a format, or `?` for symbols we don't have the name of
+fn format_symbol(fmt: &Formatter, f: &Format) -> String {
+  let sym = |k: SymbolKind| fmt.symbols.get(&k).map_or("?", |s| s);
+  match *f {
+    Format::Aggr(f) => sym(f.sym.into()).to_owned(),
+    Format::SubAggr(s) => sym(s.into()).to_owned(),
+    Format::Struct(f) => sym(f.sym.into()).to_owned(),
+    Format::Mode(f) => sym(f.sym.into()).to_owned(),
+    Format::Sel(s) => sym(s.into()).to_owned(),
+    Format::Attr(f) => sym(f.sym.into()).to_owned(),
+    Format::Func(FormatFunc::Func { sym: s, .. }) => sym(s.into()).to_owned(),
+    Format::Func(FormatFunc::Bracket { lsym, rsym, .. }) =>
+      format!("{} {}", sym(lsym.into()), sym(rsym.into())),
+    Format::Pred(f) => sym(f.sym.into()).to_owned(),
+  }
+}
+
+pub fn notations<'a>(
+  fmt: &'a Formatter, pats: &'a [Pattern],
+) -> impl Iterator<Item = NotationEntry> + 'a {
+  pats.iter().map(|pat| {
+    let (kind, nr) = pattern_kind(&pat.kind);
+    NotationEntry {
+      kind,
+      nr,
+      // the formats are not loaded when only the checker runs
+      symbol: fmt.formats.get(pat.fmt).map_or_else(|| "?".into(), |f| format_symbol(fmt, f)),
+      args: pat.primary.len(),
+      visible: pat.visible.len(),
+      pos: pat.pos,
+    }
+  })
+}
+
+/// Lists the constructors, together with the symbols of the given notations for them.
+pub fn constructors(constrs: &Constructors, notations: &[NotationEntry]) -> Vec<ConstructorEntry> {
+  let mut out = vec![];
+  let mut push = |kind, nr: u32, args, superfluous, redefines| {
+    let symbols = (notations.iter())
+      .filter(|n| n.kind == kind && n.nr == Some(nr))
+      .map(|n| n.symbol.clone())
+      .collect();
+    out.push(ConstructorEntry { kind, nr, args, superfluous, redefines, symbols })
+  };
+  macro_rules! push_all {
+    ($($field:ident: $kind:literal,)*) => {$(
+      for (nr, c) in constrs.$field.enum_iter() {
+        push($kind, nr.0, c.primary.len(), c.superfluous, c.redefines.map(|r| r.0))
+      }
+    )*}
+  }
+  push_all! {
+    mode: "mode",
+    struct_mode: "struct",
+    attribute: "attr",
+    predicate: "pred",
+    functor: "func",
+    selector: "sel",
+    aggregate: "aggr",
+  }
+  out
+}
+
+fn attrs(attrs: &Attrs) -> Vec<(u32, bool)> {
+  match attrs {
+    Attrs::Inconsistent => vec![],
+    Attrs::Consistent(attrs) => attrs.iter().map(|a| (a.nr.0, a.pos)).collect(),
+  }
+}
+
+pub fn clusters(cl: &Clusters) -> impl Iterator<Item = ClusterEntry> + '_ {
+  let entry = |kind, c: &Cluster, antecedent| ClusterEntry {
+    kind,
+    args: c.primary.len(),
+    antecedent,
+    consequent: attrs(&c.consequent.1),
+  };
+  let registered = cl.registered.iter().map(move |c| entry("registered", c, vec![]));
+  let functor = cl.functor.vec.0.iter().map(move |c| entry("functor", c, vec![]));
+  let conditional =
+    cl.conditional.iter().map(move |c| entry("conditional", c, attrs(&c.antecedent)));
+  registered.chain(functor).chain(conditional)
+}
+
+/// Prints the environment as one JSON object per line, restricted to the entries
+/// mentioning `filter` if given.
+pub fn dump_environment(
+  fmt: &Formatter, constrs: &Constructors, pats: &[Pattern], cl: &Clusters, filter: Option<&str>,
+) {
+  let notations = notations(fmt, pats).collect::<Vec<_>>();
+  let constructors = constructors(constrs, &notations);
+  let entries = (notations.into_iter().map(Entry::Notation))
+    .chain(constructors.into_iter().map(Entry::Constructor))
+    .chain(clusters(cl).map(Entry::Cluster));
+  let mut w = crate::testing::JsonLines::stdout();
+  for e in entries {
+    if filter.is_none_or(|sym| e.mentions(sym)) {
+      w.write(&e).unwrap()
+    }
+  }
+}
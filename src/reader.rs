@@ -1,20 +1,21 @@
 use crate::accom::Accomodator;
-use crate::checker::Checker;
-use crate::error::MizError;
+use crate::checker::{Checker, CheckerSession};
+use crate::compat::Verifier;
+use crate::error::{ArticlePos, ErrFile, MizError};
 use crate::parser::MizParser;
+use crate::testing::Snapshot;
 use crate::types::*;
 use crate::*;
-use std::io;
+use enum_map::EnumMap;
+use indicatif::ProgressBar;
+use itertools::Itertools;
+use std::collections::{BTreeMap, HashSet};
 
 enum PendingDef {
   Constr(ConstrKind),
   Cluster(ClusterKind, usize),
 }
 
-mk_id! {
-  DefiniensId(u32),
-}
-
 pub struct Reader {
   pub g: Global,
   pub lc: LocalContext,
@@ -22,11 +23,25 @@ pub struct Reader {
   pub article: Article,
   treat_thm_as_axiom: bool,
   pub no_suppress_checker: bool,
+  /// The options set by `::$name=value` pragmas, which apply to the next item only
+  pub item_options: Vec<(String, String)>,
+  /// Whether we are justifying a correctness condition, which `--quick-export` still checks
+  pub in_corr_cond: bool,
+  /// The elaborated terms of `--explain-neq`, set by the analyzer around each inference
+  pub explain_neq: Option<Box<[Term; 2]>>,
+  /// The constants that stand for reserved variables, set by the analyzer around each
+  /// inference so that failures can show the reserved types
+  pub reserved_consts: Vec<ConstId>,
+  /// The positions of the coherence inferences that `--share-cluster-antecedents` has
+  /// accepted ahead of their clusters
+  pub shared_coherence: Vec<Position>,
   pub accom: Option<Box<Accomodator>>,
   /// gFormatsColl
   #[allow(clippy::box_collection)]
   pub formats: Box<HashMap<Format, FormatId>>,
   pub has_errors: bool,
+  /// The number of errors reported so far, to tell which items had errors
+  pub error_count: u32,
   pub formats_base: usize,
   /// Notat
   pub notations: EnumMap<PatternKindClass, ExtVec<Pattern>>,
@@ -34,16 +49,10 @@ pub struct Reader {
   pub definitions: IdxVec<DefiniensId, Definiens>,
   /// EqDefinientia
   pub equalities: Vec<Definiens>,
-  /// ExDefinientia
-  pub expansions: Vec<Definiens>,
   /// gPropertiesList
   pub properties: Vec<Property>,
-  /// gIdentifications
-  pub identify: Vec<IdentifyFunc>,
-  /// gReductions
-  pub reductions: Vec<Reduction>,
-  pub equals: BTreeMap<ConstrKind, Vec<EqualsDef>>,
-  pub func_ids: BTreeMap<ConstrKind, Vec<usize>>,
+  /// ExDefinientia, gIdentifications and gReductions, with the caches of the checker
+  pub session: CheckerSession,
   props: Vec<Formula>,
   labels: IdxVec<LabelId, Option<usize>>,
   pending_defs: Vec<PendingDef>,
@@ -56,17 +65,90 @@ impl WithGlobalLocal for Reader {
   fn local(&self) -> &LocalContext { &self.lc }
 }
 
+/// Where the text of the article comes from. The environment is always read
+/// from the MML directory.
+#[derive(Clone, Copy)]
+pub enum ArticleSource<'a> {
+  /// Read `<article>.miz` from the `mml/` directory
+  File,
+  /// The text of the article is already in memory
+  Memory(&'a [u8]),
+}
+
 impl MizPath {
+  /// Prints the directives for `--dump=directives` (or `directives-json`). If they were
+  /// parsed from the article and there is an `.evl` file from an earlier run, the changes
+  /// from it are printed as well.
+  fn dump_directives(&self, cfg: &Config, dirs: &Directives, parsed: bool) {
+    let mut old = Directives::default();
+    let diff = (parsed && self.read_evl(&mut old).is_ok()).then(|| old.difference(dirs));
+    if cfg.dump.directives_json {
+      outln!("{}", serde_json::json!({ "directives": dirs, "changed": diff }))
+    }
+    if cfg.dump.directives {
+      dirs.dump();
+      for d in diff.iter().flat_map(|d| &d.0) {
+        let list = |arts: &[Article]| arts.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let (kind, added, removed) = (d.kind.name(), list(&d.added), list(&d.removed));
+        outln!("{kind}: added {added:?}, removed {removed:?}, reordered {:?}", list(&d.reordered))
+      }
+    }
+  }
+
+  /// Compares the requirements given by the environment with the `.ere` file, if any,
+  /// printing the differences. Returns false if they differ.
+  fn verify_requirements(&self, reqs: &RequirementIndexes) -> bool {
+    if !self.to_path(true, false, "ere").exists() {
+      return true
+    }
+    let mut old = RequirementIndexes::default();
+    if let Err(e) = self.read_ere(&mut old) {
+      eprintln!("{}: failed to read the .ere file: {e}", self.art);
+      return false
+    }
+    let mut ok = true;
+    for (req, &val) in &reqs.fwd {
+      if val != old.fwd[req] {
+        ok = false;
+        let art = self.art;
+        outln!("{art}: requirement {req:?} is {val} in the environment, {} in .ere", old.fwd[req])
+      }
+    }
+    ok
+  }
+
   pub fn with_reader(
-    &self, cfg: &Config, progress: Option<&ProgressBar>, mml_vct: &[u8],
+    &self, cfg: &Config, progress: Option<&ProgressBar>, mml_vct: &[u8], src: ArticleSource<'_>,
     f: &mut dyn FnMut(&mut Reader, Option<&mut MizParser<'_>>),
-  ) -> io::Result<bool> {
+  ) -> Result<bool, MizError> {
+    if cfg.write_err_file || cfg.compare_with_mizar.is_some() {
+      ErrFile::start()
+    }
+    if cfg.snapshot.is_some() {
+      Snapshot::start()
+    }
     let mut accom = cfg.accom_enabled.then(Box::<Accomodator>::default);
+    if let Some(accom) = &mut accom {
+      accom.article = self.art;
+      accom.warn_directives = cfg.warn_directives
+    }
+    let local_vcts = accom.as_ref().map_or_else(Vec::new, |_| {
+      let dirs = cfg.vocabulary_dirs.iter().map(|dir| std::path::Path::new(dir).join("mml.vct"));
+      (std::iter::once(self.to_path(true, false, "vct")).chain(dirs))
+        .filter_map(|path| std::fs::read(&path).ok().map(|buf| (path, buf)))
+        .collect()
+    });
     let data;
     let mut parser = if cfg.parser_enabled {
-      data = self.read_miz().unwrap();
+      let text = match src {
+        ArticleSource::File => {
+          data = self.read_miz()?;
+          &data
+        }
+        ArticleSource::Memory(text) => text,
+      };
       let write_json = self.write_json(cfg.json_parse);
-      Some(Box::new(MizParser::new(self.art, progress, &data, write_json)))
+      Some(Box::new(MizParser::new(self.art, progress, text, write_json)))
     } else {
       None
     };
@@ -76,6 +158,9 @@ impl MizPath {
       } else {
         self.read_evl(&mut accom.dirs).unwrap();
       }
+      if cfg.dump.directives || cfg.dump.directives_json {
+        self.dump_directives(cfg, &accom.dirs, parser.is_some())
+      }
     }
 
     // MizPBlockObj.InitPrepData
@@ -95,10 +180,23 @@ impl MizPath {
     v.lc.formatter.dump = cfg.dump.formatter;
     let old = v.lc.start_stash();
     if let Some(accom) = &mut v.accom {
-      accom.accom_constructors(&mut v.g.constrs).unwrap();
-      accom.accom_requirements(&v.g.constrs, &mut v.g.reqs).unwrap();
+      accom.accom_constructors(&mut v.g.constrs)?;
+      accom.accom_requirements(&v.g.constrs, &mut v.g.reqs)?;
+      if cfg.verify_requirements && !self.verify_requirements(&v.g.reqs) {
+        stat("requirements mismatch", true)
+      }
       if cfg.xml_internals {
-        self.write_atr(&accom.sig, &v.g.constrs)
+        self.write_atr(&accom.sig, &v.g.constrs);
+        self.write_sgl(&accom.sig);
+        self.write_aco(&accom.sig, &v.g.constrs);
+        if cfg.xml_internals_self_test {
+          let (mut sgl, mut aco) = <(Vec<Article>, AccumConstructors)>::default();
+          self.read_sgl(&mut sgl).unwrap();
+          self.read_aco(&mut aco).unwrap();
+          assert!(sgl.iter().eq(accom.sig.sig.0.iter().map(|p| &p.0)), ".sgl round trip");
+          assert!(aco.sig.sig == accom.sig.sig, ".aco round trip");
+          assert!(aco.constrs.len() == v.g.constrs.len(), ".aco round trip");
+        }
       }
     } else {
       self.read_atr(&mut v.g.constrs).unwrap();
@@ -118,7 +216,7 @@ impl MizPath {
       let mut symbols = Default::default();
       let mut inf = parser.as_ref().map(|_| Default::default());
       let mut priority = vec![];
-      accom.accom_symbols(mml_vct, &mut symbols, &mut priority, inf.as_mut());
+      accom.accom_symbols(mml_vct, &local_vcts, &mut symbols, &mut priority, inf.as_mut());
       if cfg.checker_enabled || cfg.parser_enabled {
         accom.accom_articles()
       }
@@ -140,12 +238,12 @@ impl MizPath {
       if let Some(parser) = &mut parser {
         let mut fmts = Default::default();
         let mut fmt_map = Default::default();
-        accom.accom_notations(&mut fmt_map, Some(&mut fmts), &mut notations).unwrap();
+        accom.accom_notations(&mut fmt_map, Some(&mut fmts), &mut notations)?;
         fmts.formats.0.iter().for_each(|fmt| parser.read_format(fmt));
         v.formats.extend(fmts.formats.enum_iter().map(|(id, f)| (*f, id)));
         *v.lc.formatter.formats = fmts.formats;
       } else {
-        accom.accom_notations(&mut v.formats, None, &mut notations).unwrap();
+        accom.accom_notations(&mut v.formats, None, &mut notations)?;
       }
     } else {
       self.read_eno(&mut notations).unwrap();
@@ -175,7 +273,7 @@ impl MizPath {
       v.g.reqs.dump(&v.g.constrs)
     }
     if let Some(accom) = &mut v.accom {
-      accom.accom_clusters(&v.g.constrs, &mut v.g.clusters).unwrap();
+      accom.accom_clusters(&v.g.constrs, &mut v.g.clusters)?;
     } else {
       self.read_ecl(&v.g.constrs, &mut v.g.clusters).unwrap();
     }
@@ -183,6 +281,10 @@ impl MizPath {
     if cfg.dump.clusters {
       v.g.clusters.dump()
     }
+    if cfg.dump.environment {
+      let (fmt, filter) = (&v.lc.formatter, cfg.dump_filter.as_deref());
+      crate::query::dump_environment(fmt, &v.g.constrs, &notations, &v.g.clusters, filter)
+    }
 
     let mut attrs = Attrs::default();
     if let Some(zero) = v.g.reqs.zero() {
@@ -252,33 +354,59 @@ impl MizPath {
     if cfg.checker_enabled {
       if let Some(accom) = &mut v.accom {
         accom
-          .accom_definitions(&v.g.constrs, DirectiveKind::Expansions, &mut v.expansions)
+          .accom_definitions(&v.g.constrs, DirectiveKind::Expansions, &mut v.session.expansions)
           .unwrap();
       } else {
-        self.read_definitions(&v.g.constrs, false, "dfx", None, &mut v.expansions).unwrap();
+        let expansions = &mut v.session.expansions;
+        self.read_definitions(&v.g.constrs, false, "dfx", None, expansions).unwrap();
       }
       if cfg.dump.definitions {
-        for d in &v.expansions {
+        for d in &v.session.expansions {
           eprintln!("expansion: {d:?}");
         }
       }
     }
 
+    if cfg.check_definiens {
+      let mut seen = HashSet::new();
+      let dfs = v.definitions.0.iter().chain(&v.equalities).chain(&v.session.expansions);
+      let errs = (dfs.filter(|df| seen.insert((df.article, df.def_nr))))
+        .filter_map(|df| Some((df.article, df.def_nr, df.check_consistency(&v.g.constrs).err()?)))
+        .collect_vec();
+      for (art, nr, msg) in errs {
+        v.err(Position::default(), MizError::InconsistentDefiniens { art, nr, msg })
+      }
+    }
+
     // LoadPropertiesReg
     if let Some(accom) = &mut v.accom {
-      accom.accom_properties(&v.g.constrs, &mut v.properties).unwrap();
+      accom.accom_properties(&v.g.constrs, &mut v.properties)?;
     } else {
       self.read_properties(&v.g.constrs, false, "epr", None, &mut v.properties).unwrap();
     }
+    if cfg.dump.definitions {
+      for prop in &v.properties {
+        eprintln!("property: {prop:?}");
+      }
+    }
 
     // LoadIdentify, LoadReductions
     if cfg.checker_enabled || cfg.exporter_enabled {
       if let Some(accom) = &mut v.accom {
-        accom.accom_identify_regs(&v.g.constrs, &mut v.identify).unwrap();
-        accom.accom_reduction_regs(&v.g.constrs, &mut v.reductions).unwrap();
+        accom.accom_identify_regs(&v.g.constrs, &mut v.session.identify)?;
+        accom.accom_reduction_regs(&v.g.constrs, &mut v.session.reductions)?;
       } else {
-        self.read_identify_regs(&v.g.constrs, false, "eid", None, &mut v.identify).unwrap();
-        self.read_reduction_regs(&v.g.constrs, false, "erd", None, &mut v.reductions).unwrap();
+        let s = &mut v.session;
+        self.read_identify_regs(&v.g.constrs, false, "eid", None, &mut s.identify).unwrap();
+        self.read_reduction_regs(&v.g.constrs, false, "erd", None, &mut s.reductions).unwrap();
+      }
+      if cfg.dump.definitions {
+        for id in &v.session.identify {
+          eprintln!("identify: {id:?}");
+        }
+        for red in &v.session.reductions {
+          eprintln!("reduction: {red:?}");
+        }
       }
     }
 
@@ -286,22 +414,22 @@ impl MizPath {
     RoundUpTypes::with(&v.g, &mut v.lc, |rr| {
       v.definitions.visit(rr);
       v.equalities.visit(rr);
-      v.expansions.visit(rr);
+      v.session.expansions.visit(rr);
       v.properties.visit(rr);
-      v.identify.visit(rr);
-      v.reductions.visit(rr);
+      v.session.identify.visit(rr);
+      v.session.reductions.visit(rr);
     });
 
     for df in &v.equalities {
       if let Some(func) = df.equals_expansion() {
         let nr = func.pattern.0;
         if !func.expansion.has_func(&v.g.constrs, nr) {
-          v.equals.entry(df.constr).or_default().push(func);
+          v.session.equals.entry(df.constr).or_default().push(func);
         }
       }
     }
 
-    for id in &mut v.identify {
+    for id in &mut v.session.identify {
       for i in 0..id.primary.len() {
         v.lc.load_locus_tys(&id.primary);
         id.primary[i].round_up_with_self(&v.g, &v.lc, false);
@@ -309,10 +437,10 @@ impl MizPath {
       }
     }
 
-    for (i, id) in v.identify.iter().enumerate() {
+    for (i, id) in v.session.identify.iter().enumerate() {
       let Term::Functor { nr, .. } = id.lhs else { unreachable!() };
       let k = ConstrKind::Func(Term::adjusted_nr(nr, &v.g.constrs));
-      v.func_ids.entry(k).or_default().push(i);
+      v.session.func_ids.entry(k).or_default().push(i);
     }
 
     // CollectConstInEnvConstructors
@@ -334,19 +462,23 @@ impl MizPath {
     v.g.numeral_type = numeral_type;
     v.g.constrs = constrs;
     v.g.clusters = clusters;
+    let cluster_hits = cfg.cluster_hits.as_ref().map(|dir| ClusterHits::path(dir, &v.g.clusters));
+    if let Some(path) = &cluster_hits {
+      v.lc.cluster_hits = ClusterHits::load(path)
+    }
 
     // InLibraries
     if cfg.checker_enabled {
       if let Some(accom) = &mut v.accom {
-        accom.accom_theorems(cfg.xml_internals, &v.g.constrs, &mut v.def_map, &mut v.libs).unwrap();
+        accom.accom_theorems(cfg.xml_internals, &v.g.constrs, &mut v.def_map, &mut v.libs)?;
       } else {
         self.read_eth(&v.g.constrs, refs, &mut v.libs).unwrap();
       }
-      let cc = &mut InternConst::new(&v.g, &v.lc, &v.equals, &v.identify, &v.func_ids);
+      let cc = &mut v.session.ctx().intern_const(&v.g, &v.lc);
       v.libs.thm.values_mut().for_each(|f| f.visit(cc));
       v.libs.def.values_mut().for_each(|f| f.visit(cc));
       if let Some(accom) = &mut v.accom {
-        accom.accom_schemes(cfg.xml_internals, &v.g.constrs, &mut v.libs).unwrap();
+        accom.accom_schemes(cfg.xml_internals, &v.g.constrs, &mut v.libs)?;
       } else {
         self.read_esh(&v.g.constrs, refs, &mut v.libs).unwrap();
       }
@@ -371,9 +503,40 @@ impl MizPath {
       std::mem::take(&mut accom.articles_vec);
     }
 
-    f(&mut v, parser.as_deref_mut());
+    // The environment was rewritten in place above, so anything cached so far is stale
+    v.lc.round_up_cache.get_mut().clear();
+    // The verifier needs the environment written by the accomodator, so it can't start
+    // any earlier than this
+    let verifier = cfg.compare_with_mizar.as_deref().and_then(|cmd| {
+      (Verifier::spawn(cmd, self))
+        .map_err(|e| eprintln!("{}: failed to run {cmd}: {e}", self.art))
+        .ok()
+    });
+    if v.accom.as_ref().is_some_and(|accom| accom.has_errors) {
+      // an environment file was rejected (and reported), and the article would only run into
+      // the constructors and notations missing because of it
+      v.has_errors = true
+    } else {
+      f(&mut v, parser.as_deref_mut());
+    }
+    if let Some(path) = cluster_hits {
+      if let Err(e) = v.lc.cluster_hits.save(&path) {
+        eprintln!("failed to write {}: {e}", path.to_string_lossy())
+      }
+    }
 
     LocalContext::end_stash(old);
+    if let Some(verifier) = verifier {
+      if !verifier.finish(self, &ErrFile::errors())? {
+        stat("mizar mismatch", true)
+      }
+    }
+    ErrFile::finish(self, cfg.write_err_file)?;
+    if let Some(dir) = &cfg.snapshot {
+      if !Snapshot::finish(self, dir)? {
+        stat("snapshot mismatch", true)
+      }
+    }
     Ok(v.has_errors)
   }
 }
@@ -403,31 +566,35 @@ impl Reader {
       libs: Libraries::default(),
       article,
       treat_thm_as_axiom: matches!(article.as_str(), "tarski_0" | "tarski_a"),
-      has_errors: accom.as_deref().map_or(false, |acc| acc.has_errors),
+      has_errors: accom.as_deref().is_some_and(|acc| acc.has_errors),
+      error_count: 0,
       accom,
       formats: Default::default(),
       formats_base: 0,
       notations: Default::default(),
       definitions: Default::default(),
       equalities: Default::default(),
-      expansions: Default::default(),
       properties: Default::default(),
-      identify: Default::default(),
-      reductions: Default::default(),
-      equals: Default::default(),
-      func_ids: Default::default(),
+      session: Default::default(),
       props: Default::default(),
       labels: Default::default(),
       pending_defs: Default::default(),
       def_map: Default::default(),
       pos: Default::default(),
       no_suppress_checker: true,
+      item_options: vec![],
+      in_corr_cond: false,
+      explain_neq: None,
+      reserved_consts: vec![],
+      shared_coherence: vec![],
       progress,
     }
   }
 
   pub fn err(&mut self, pos: Position, msg: MizError) {
-    self.has_errors |= msg.report(self.article, pos, &self.g, &self.lc);
+    let err = msg.report(self.article, pos, &self.g, &self.lc);
+    self.has_errors |= err;
+    self.error_count += err as u32;
   }
 
   pub fn intern<'a, T: Clone + Visitable<InternConst<'a>>>(&'a self, t: &T) -> T {
@@ -437,7 +604,7 @@ impl Reader {
   }
 
   pub fn intern_const(&self) -> InternConst<'_> {
-    InternConst::new(&self.g, &self.lc, &self.equals, &self.identify, &self.func_ids)
+    self.session.ctx().intern_const(&self.g, &self.lc)
   }
 
   pub fn push_prop(&mut self, label: Option<LabelId>, prop: Formula) {
@@ -532,6 +699,7 @@ impl Reader {
 
   /// Prepare
   pub fn run_checker(&mut self, path: &MizPath) {
+    let mut idx = 0;
     let result = path.read_xml(|it| {
       assert!(matches!(
         it,
@@ -548,11 +716,15 @@ impl Reader {
       if self.g.cfg.top_item_header {
         eprintln!("item: {it:?}");
       }
+      let pos = it.pos().unwrap_or(self.pos);
+      let _loc = ArticlePos { article: path.art, item: Some(idx), pos }.enter();
       self.read_item(&it);
+      idx += 1;
     });
     if let Err((path, e)) = result {
       e.report(&path);
       self.has_errors = true;
+      self.error_count += 1;
     }
   }
 
@@ -685,7 +857,7 @@ impl Reader {
         }
         Registration::Reduction { kind, conds, corr } => {
           self.read_corr_conds(conds, corr);
-          self.reductions.push(kind.clone())
+          self.session.reductions.push(kind.clone())
         }
         Registration::Property { prop, just, .. } => self.read_just_prop(prop, just, false),
       },
@@ -755,12 +927,18 @@ impl Reader {
   }
 
   pub fn read_definiens(&mut self, df: &Definiens) {
+    if self.g.cfg.check_definiens {
+      if let Err(msg) = df.check_consistency(&self.g.constrs) {
+        let (art, nr) = (df.article, df.def_nr);
+        self.err(self.pos, MizError::InconsistentDefiniens { art, nr, msg })
+      }
+    }
     if self.g.cfg.analyzer_enabled {
       self.definitions.push(df.clone());
     }
     if self.g.cfg.checker_enabled {
       self.equalities.push(df.clone());
-      self.expansions.push(df.clone());
+      self.session.expansions.push(df.clone());
       if let Some(func) = df.equals_expansion() {
         let f = func.pattern.0;
         if !func.expansion.has_func(&self.g.constrs, f) {
@@ -782,7 +960,7 @@ impl Reader {
             }
             i += 1;
           }
-          self.equals.entry(df.constr).or_default().push(func);
+          self.session.equals.entry(df.constr).or_default().push(func);
         }
       }
     }
@@ -841,10 +1019,12 @@ impl Reader {
   }
 
   fn read_corr_conds(&mut self, conds: &[CorrCond], corr: &Option<Correctness>) {
+    let in_corr_cond = std::mem::replace(&mut self.in_corr_cond, true);
     conds.iter().for_each(|c| self.read_just_prop(&c.prop, &c.just, false));
     if let Some(c) = corr {
       self.read_just_prop(&c.prop, &c.just, false)
     }
+    self.in_corr_cond = in_corr_cond
   }
 
   pub fn push_constr(&mut self, id: ConstrKind) { self.pending_defs.push(PendingDef::Constr(id)) }
@@ -869,8 +1049,8 @@ impl Reader {
     }
     let Term::Functor { nr, .. } = id.lhs else { unreachable!() };
     let k = ConstrKind::Func(Term::adjusted_nr(nr, &self.g.constrs));
-    self.func_ids.entry(k).or_default().push(self.identify.len());
-    self.identify.push(id.clone());
+    self.session.func_ids.entry(k).or_default().push(self.session.identify.len());
+    self.session.identify.push(id.clone());
   }
 
   /// RoundUpFurther
@@ -885,15 +1065,15 @@ impl Reader {
         Term::Functor { args, .. }
         | Term::Selector { args, .. }
         | Term::Aggregate { args, .. }
-        | Term::SchFunc { args, .. } => {
-          if args.iter().any(|t| matches!(t, Term::Infer(i) if rounded_up.contains_key(i))) {
-            let mut ty = asgn.def.round_up_type(&self.g, &self.lc, false).to_owned();
-            ty.attrs.1.round_up_with(&self.g, &self.lc, &asgn.ty, false);
-            drop(ic);
-            ty.attrs.1.visit(&mut self.intern_const());
-            self.lc.infer_const.borrow_mut()[i].ty = *ty;
-            ic = self.lc.infer_const.borrow();
-          }
+        | Term::SchFunc { args, .. }
+          if args.iter().any(|t| matches!(t, Term::Infer(i) if rounded_up.contains_key(i))) =>
+        {
+          let mut ty = asgn.def.round_up_type(&self.g, &self.lc, false).to_owned();
+          ty.attrs.1.round_up_with(&self.g, &self.lc, &asgn.ty, false);
+          drop(ic);
+          ty.attrs.1.visit(&mut self.intern_const());
+          self.lc.infer_const.borrow_mut()[i].ty = *ty;
+          ic = self.lc.infer_const.borrow();
         }
         Term::Numeral(_) => {
           let mut attrs = asgn.ty.attrs.1.clone();
@@ -985,24 +1165,42 @@ impl Reader {
       stat("skipped by $V-", false);
       return
     }
+    if self.g.cfg.quick_export && !self.in_corr_cond {
+      stat("skipped by quick export", false);
+      return
+    }
+    if self.has_errors && !self.g.cfg.continue_on_error {
+      return
+    }
     self.set_pos(it.pos);
-    let refs = it.refs.iter().map(|r| match r.kind {
-      ReferenceKind::Priv(lab) => &self.props[self.labels[lab].unwrap()],
-      ReferenceKind::Thm(thm) => &self.libs.thm[&thm],
-      ReferenceKind::Def(def) => &self.libs.def[&def],
-    });
+    if let Some(i) = self.shared_coherence.iter().position(|&pos| pos == it.pos) {
+      self.shared_coherence.swap_remove(i);
+      return
+    }
+    let refs = || {
+      it.refs.iter().map(|r| match r.kind {
+        ReferenceKind::Priv(lab) => &self.props[self.labels[lab].unwrap()],
+        ReferenceKind::Thm(thm) => &self.libs.thm[&thm],
+        ReferenceKind::Def(def) => &self.libs.def[&def],
+      })
+    };
+    // These would hit an `unreachable!` in the checker
+    if let Some(what) = CheckLeaked::get(|cl| {
+      cl.visit_formula(thesis);
+      refs().for_each(|f| cl.visit_formula(f))
+    }) {
+      self.err(it.pos, MizError::LeakedTerm(what));
+      return
+    }
+    let refs = refs();
     let mut ck = Checker {
-      g: &mut self.g,
-      lc: &mut self.lc,
-      expansions: &self.expansions,
-      equals: &self.equals,
-      identify: &self.identify,
-      func_ids: &self.func_ids,
-      reductions: &self.reductions,
-      article: self.article,
-      pos: it.pos,
+      explain_neq: self.explain_neq.as_deref(),
+      reserved_consts: &self.reserved_consts,
+      ..self.session.checker(&self.g, &mut self.lc, self.article, it.pos)
     };
-    match it.kind {
+    // The conclusion is kept even if this fails, so that later steps do not
+    // cascade-fail, as in Mizar
+    let result = match it.kind {
       InferenceKind::By { linked } => {
         if !self.treat_thm_as_axiom || linked || !it.refs.is_empty() {
           // eprintln!("thesis: {thesis:?}");
@@ -1012,11 +1210,42 @@ impl Reader {
             premises.push(self.props.last().unwrap());
           }
           premises.extend(refs);
-          ck.justify(premises);
+          ck.justify(premises)
+        } else {
+          Ok(())
         }
       }
       InferenceKind::From { sch } =>
         ck.justify_scheme(&self.libs.sch[&sch], refs.collect(), thesis),
+    };
+    if result.is_err() {
+      self.has_errors = true;
+      self.error_count += 1
+    }
+  }
+
+  /// Checks `thesis`, the coherence shared by a group of conditional clusters whose own
+  /// coherence inferences are at `positions`, as the first of them. If it is accepted, the
+  /// inferences at `positions` are not checked again; otherwise nothing is reported, and
+  /// they are checked one by one.
+  pub fn read_shared_coherence(&mut self, thesis: &Formula, positions: &[Position]) {
+    if !self.g.cfg.checker_enabled || !self.no_suppress_checker {
+      return
+    }
+    if self.has_errors && !self.g.cfg.continue_on_error {
+      return
+    }
+    if CheckLeaked::get(|cl| cl.visit_formula(thesis)).is_some() {
+      return
+    }
+    self.set_pos(positions[0]);
+    let neg_thesis = thesis.clone().mk_neg();
+    let mut ck = Checker {
+      reserved_consts: &self.reserved_consts,
+      ..self.session.checker(&self.g, &mut self.lc, self.article, positions[0])
+    };
+    if ck.refute_shared(vec![&neg_thesis], positions) {
+      self.shared_coherence.extend_from_slice(positions)
     }
   }
 
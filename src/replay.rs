@@ -0,0 +1,212 @@
+//! Failure blobs, for reproducing a failing inference without the MML. With
+//! `--dump-failures-dir`, each inference the checker does not accept is written as JSON to
+//! `<dir>/<article>-<line>-<col>.json`, with its premises and all the tables of the
+//! environment and the local context that the checker reads. `--replay=<blob>` reads it
+//! back and runs the checker on it again, under the flags of the replaying run (the flags
+//! of the original run are not saved).
+use crate::checker::{Checker, CheckerCtx};
+use crate::types::*;
+use crate::{Assignment, Config, FixedVar, FuncDef, Global, LocalContext};
+use serde_derive::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io;
+
+/// The version of the blob format. A blob of another version is refused by the replay.
+pub const VERSION: u32 = 1;
+
+/// A failure blob as it is written, borrowing from the checker. The maps keyed by
+/// `ConstrKind` are written as lists of pairs, since JSON keys must be strings.
+#[derive(Serialize)]
+struct BlobRef<'a> {
+  version: u32,
+  article: Article,
+  pos: Position,
+  premises: &'a [&'a Formula],
+  reqs: &'a RequirementIndexes,
+  constrs: &'a Constructors,
+  clusters: &'a Clusters,
+  numeral_type: &'a Type,
+  locus_ty: &'a IdxVec<LocusId, Type>,
+  bound_var: &'a IdxVec<BoundId, (IdentId, Type)>,
+  fixed_var: &'a IdxVec<ConstId, FixedVar>,
+  infer_const: &'a SortedIdxVec<InferId, Assignment>,
+  sch_func_ty: &'a IdxVec<SchFuncId, Type>,
+  priv_func: &'a IdxVec<PrivFuncId, FuncDef>,
+  it_type: &'a Option<Box<Type>>,
+  expansions: &'a [Definiens],
+  equals: Vec<(ConstrKind, &'a [EqualsDef])>,
+  identify: &'a [IdentifyFunc],
+  func_ids: Vec<(ConstrKind, &'a [usize])>,
+  reductions: &'a [Reduction],
+  reserved_consts: &'a [ConstId],
+}
+
+/// A failure blob as it is read back, with the same fields as [`BlobRef`]
+#[derive(Deserialize)]
+struct Blob {
+  article: Article,
+  pos: Position,
+  premises: Vec<Formula>,
+  reqs: RequirementIndexes,
+  constrs: Constructors,
+  clusters: Clusters,
+  numeral_type: Type,
+  locus_ty: IdxVec<LocusId, Type>,
+  bound_var: IdxVec<BoundId, (IdentId, Type)>,
+  fixed_var: IdxVec<ConstId, FixedVar>,
+  infer_const: SortedIdxVec<InferId, Assignment>,
+  sch_func_ty: IdxVec<SchFuncId, Type>,
+  priv_func: IdxVec<PrivFuncId, FuncDef>,
+  it_type: Option<Box<Type>>,
+  expansions: Vec<Definiens>,
+  equals: Vec<(ConstrKind, Vec<EqualsDef>)>,
+  identify: Vec<IdentifyFunc>,
+  func_ids: Vec<(ConstrKind, Vec<usize>)>,
+  reductions: Vec<Reduction>,
+  reserved_consts: Vec<ConstId>,
+}
+
+/// Writes the failure blob of the inference `ck` is at, whose premises are `premises`.
+pub fn dump(ck: &Checker<'_>, dir: &str, premises: &[&Formula]) {
+  let infer_const = ck.lc.infer_const.borrow();
+  let blob = BlobRef {
+    version: VERSION,
+    article: ck.article,
+    pos: ck.pos,
+    premises,
+    reqs: &ck.g.reqs,
+    constrs: &ck.g.constrs,
+    clusters: &ck.g.clusters,
+    numeral_type: &ck.g.numeral_type,
+    locus_ty: &ck.lc.locus_ty,
+    bound_var: &ck.lc.bound_var,
+    fixed_var: &ck.lc.fixed_var,
+    infer_const: &infer_const,
+    sch_func_ty: &ck.lc.sch_func_ty,
+    priv_func: &ck.lc.priv_func,
+    it_type: &ck.lc.it_type,
+    expansions: ck.ctx.expansions,
+    equals: ck.ctx.equals.iter().map(|(&k, v)| (k, &**v)).collect(),
+    identify: ck.ctx.identify,
+    func_ids: ck.ctx.func_ids.iter().map(|(&k, v)| (k, &**v)).collect(),
+    reductions: ck.ctx.reductions,
+    reserved_consts: ck.reserved_consts,
+  };
+  let path = format!("{dir}/{}-{}-{}.json", ck.article, ck.pos.line, ck.pos.col);
+  let res = std::fs::create_dir_all(dir).and_then(|()| {
+    let mut w = io::BufWriter::new(std::fs::File::create(&path)?);
+    serde_json::to_writer(&mut w, &blob)?;
+    io::Write::flush(&mut w)
+  });
+  if let Err(e) = res {
+    eprintln!("failed to write {path}: {e}")
+  }
+}
+
+/// A failure blob as it is read back, with the environment to check its inference in. The
+/// unit tests also use it as a small environment of their own, with their own premises.
+pub struct Replay {
+  pub g: Global,
+  pub lc: LocalContext,
+  pub article: Article,
+  pub pos: Position,
+  pub premises: Vec<Formula>,
+  expansions: Vec<Definiens>,
+  equals: BTreeMap<ConstrKind, Vec<EqualsDef>>,
+  identify: Vec<IdentifyFunc>,
+  func_ids: BTreeMap<ConstrKind, Vec<usize>>,
+  reductions: Vec<Reduction>,
+  reserved_consts: Vec<ConstId>,
+}
+
+impl Replay {
+  /// Reads the failure blob at `path`, for checking under the flags of `cfg`. A blob of
+  /// another version is an error.
+  pub fn load(cfg: &Config, path: &str) -> io::Result<Replay> {
+    #[derive(Deserialize)]
+    struct Header {
+      version: u32,
+    }
+    let text = std::fs::read(path)?;
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, format!("{path}: {e}"));
+    let Header { version } = serde_json::from_slice(&text).map_err(|e| invalid(e.to_string()))?;
+    if version != VERSION {
+      return Err(invalid(format!("blob version {version}, but this build reads {VERSION}")))
+    }
+    let blob: Blob = serde_json::from_slice(&text).map_err(|e| invalid(e.to_string()))?;
+    let g = Global {
+      cfg: cfg.clone(),
+      reqs: blob.reqs,
+      constrs: blob.constrs,
+      clusters: blob.clusters,
+      numeral_type: blob.numeral_type,
+    };
+    let lc = LocalContext {
+      locus_ty: blob.locus_ty,
+      bound_var: blob.bound_var,
+      fixed_var: blob.fixed_var,
+      infer_const: RefCell::new(blob.infer_const),
+      sch_func_ty: blob.sch_func_ty,
+      priv_func: blob.priv_func,
+      it_type: blob.it_type,
+      attr_sort_bug: g.cfg.attr_sort_bug,
+      ..Default::default()
+    };
+    Ok(Replay {
+      g,
+      lc,
+      article: blob.article,
+      pos: blob.pos,
+      premises: blob.premises,
+      expansions: blob.expansions,
+      equals: blob.equals.into_iter().collect(),
+      identify: blob.identify,
+      func_ids: blob.func_ids.into_iter().collect(),
+      reductions: blob.reductions,
+      reserved_consts: blob.reserved_consts,
+    })
+  }
+
+  /// The checker at the position of the blob, in its environment
+  pub fn checker(&mut self) -> Checker<'_> {
+    Checker {
+      g: &self.g,
+      lc: &mut self.lc,
+      ctx: CheckerCtx {
+        expansions: &self.expansions,
+        equals: &self.equals,
+        identify: &self.identify,
+        func_ids: &self.func_ids,
+        reductions: &self.reductions,
+      },
+      article: self.article,
+      pos: self.pos,
+      expand_cache: None,
+      explain_neq: None,
+      reserved_consts: &self.reserved_consts,
+    }
+  }
+}
+
+/// Runs the checker on the failure blob at `path`, with the flags of `cfg`. Returns whether
+/// the inference was accepted; the failure, if any, is reported as in the original run.
+/// The inference is checked whatever `--skip-to-verbose` and the verbose line say.
+pub fn replay(cfg: &Config, path: &str) -> io::Result<bool> {
+  let mut cfg = cfg.clone();
+  (cfg.skip_to_verbose, cfg.first_verbose_line) = (false, None);
+  let mut replay = Replay::load(&cfg, path)?;
+  let premises = std::mem::take(&mut replay.premises);
+  Ok(replay.checker().justify(premises.iter().collect()).is_ok())
+}
+
+/// The environment of `tests/blobs/blobenv.json`, for the unit tests: the modes `object`
+/// (`ModeId(0)`) and `set`, the predicates `=` (`PredId(0)`) and `in`, the functors `f x`
+/// (`FuncId(0)`, equal to `x`) and `x g y` (equal to `y`), and the attribute `small`. The
+/// blob is the failure of `tests/blobs/blobenv.miz`, and has to be dumped again with
+/// `--dump-failures-dir` when `VERSION` changes.
+#[cfg(test)]
+pub(crate) fn test_env() -> Replay {
+  let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/blobs/blobenv.json");
+  Replay::load(&Config::default(), path).unwrap()
+}
@@ -0,0 +1,125 @@
+//! A long-running mode for driving the verifier from other programs, for `--server`.
+//!
+//! Requests are read from standard input, one JSON object per line, and each is answered
+//! by one JSON object on standard output with the same `id`:
+//!
+//! ```text
+//! {"id": 1, "article": "xboole_0"}
+//! {"id": 1, "ok": false, "errors": [[12, 5, 4]]}
+//! ```
+//!
+//! The article is read from its `.miz` file unless the request also has its `text`.
+//! The errors are those of the article's `.err` file, as `[line, col, code]`, and `ok` is
+//! whether there are none. As in the `.err` file, lines and columns count from 1 and
+//! columns are in bytes; with `"utf16": true` in the request they are given as in LSP
+//! instead, counting from 0 with columns in UTF-16 code units. A request that could not be
+//! processed at all is answered with `{"id": .., "error": "message"}`.
+//!
+//! A request with a `problem` instead of an `article` checks the failure blob of
+//! `--dump-failures-dir` at that path again, as `--replay` does, and is answered with
+//! `{"id": .., "ok": bool}`, whether the inference is now accepted.
+use crate::compat::parse_err;
+use crate::error::LineIndex;
+use crate::reader::ArticleSource;
+use crate::{Config, MizPath};
+use serde_derive::Deserialize;
+use serde_json::json;
+use std::io::{self, BufRead, Write};
+use std::panic::AssertUnwindSafe;
+
+#[derive(Deserialize)]
+struct Request {
+  #[serde(default)]
+  id: serde_json::Value,
+  #[serde(default)]
+  article: Option<String>,
+  #[serde(default)]
+  problem: Option<String>,
+  text: Option<String>,
+  #[serde(default)]
+  utf16: bool,
+}
+
+fn check_problem(cfg: &Config, blob: &str) -> Result<serde_json::Value, String> {
+  match std::panic::catch_unwind(AssertUnwindSafe(|| crate::replay::replay(cfg, blob))) {
+    Ok(Ok(ok)) => Ok(json!({ "ok": ok })),
+    Ok(Err(e)) => Err(format!("error replaying {blob}: {e}")),
+    Err(_) => Err("panicked".into()),
+  }
+}
+
+fn verify(cfg: &Config, mml_vct: &[u8], req: &Request) -> Result<serde_json::Value, String> {
+  let path = match (&req.article, &req.problem) {
+    (Some(art), None) => MizPath::new(art).map_err(|e| e.to_string())?,
+    (None, Some(blob)) => return check_problem(cfg, blob),
+    _ => return Err("a request has either an article or a problem".into()),
+  };
+  let src = match &req.text {
+    Some(_) if !cfg.parser_enabled => return Err("article text requires the parser".into()),
+    Some(text) => ArticleSource::Memory(text.as_bytes()),
+    None => ArticleSource::File,
+  };
+  let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+    path.with_reader(cfg, None, mml_vct, src, &mut |v, p| {
+      if cfg.parser_enabled || cfg.analyzer_enabled {
+        v.run_analyzer(&path, p, &mut ())
+      } else if cfg.checker_enabled {
+        v.run_checker(&path)
+      }
+    })
+  }));
+  match result {
+    Ok(Ok(_)) => {}
+    Ok(Err(e)) => return Err(e.to_string()),
+    Err(_) => return Err("panicked".into()),
+  }
+  let err = path.to_path(true, false, "err");
+  let text = std::fs::read_to_string(&err).map_err(|e| format!("{}: {e}", err.display()))?;
+  let errors = parse_err(&text);
+  let ok = errors.is_empty();
+  let errors = if req.utf16 {
+    let src = match &req.text {
+      Some(text) => text.clone().into_bytes(),
+      None => {
+        let miz = path.to_path(true, false, "miz");
+        std::fs::read(&miz).map_err(|e| format!("{}: {e}", miz.display()))?
+      }
+    };
+    let index = LineIndex::new(&src);
+    (errors.into_iter())
+      .map(|(pos, code)| {
+        let (line, col) = index.utf16(pos);
+        [line, col, code]
+      })
+      .collect::<Vec<_>>()
+  } else {
+    errors.into_iter().map(|(pos, code)| [pos.line, pos.col, code]).collect()
+  };
+  Ok(json!({ "ok": ok, "errors": errors }))
+}
+
+/// Answers requests from standard input until it is closed.
+pub fn run(cfg: &Config, mml_vct: &[u8]) -> io::Result<()> {
+  // the results are read back from the error file
+  let cfg = &Config { write_err_file: true, ..cfg.clone() };
+  let mut out = io::stdout().lock();
+  for line in io::stdin().lock().lines() {
+    let line = line?;
+    if line.trim().is_empty() {
+      continue
+    }
+    let resp = match serde_json::from_str::<Request>(&line) {
+      Err(e) => json!({ "id": null, "error": format!("bad request: {e}") }),
+      Ok(req) => match verify(cfg, mml_vct, &req) {
+        Ok(mut resp) => {
+          resp["id"] = req.id;
+          resp
+        }
+        Err(e) => json!({ "id": req.id, "error": e }),
+      },
+    };
+    writeln!(out, "{resp}")?;
+    out.flush()?
+  }
+  Ok(())
+}
@@ -0,0 +1,259 @@
+//! Snapshot testing of checker decisions, for `--snapshot DIR`.
+//!
+//! For every inference of an article this records whether the checker accepted it,
+//! together with the number of atoms and equivalence classes it took, and compares the
+//! result with the checked-in snapshot `DIR/<article>.json` (one item per line).
+//! Mismatches are printed as a compact per-item diff. If the environment variable
+//! `MIZAR_BLESS` is set, or there is no snapshot yet, the snapshot is written instead.
+//!
+//! Two snapshot directories can also be compared with each other (`--compare-snapshots`),
+//! which is a way to check a change for performance regressions: the atom and class
+//! counts are deterministic, unlike timings, so they are what is compared.
+use crate::{outln, MizPath};
+use serde_derive::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+/// The writer of the JSON reports, which have one JSON value per line: the snapshots,
+/// and the reports printed by `--analyze-only`, `--minimize-failures` and `--dump=environment`.
+pub struct JsonLines<W: Write>(pub W);
+
+impl JsonLines<Box<dyn Write>> {
+  /// A report printed on standard output, or standard error in `--server` mode.
+  pub fn stdout() -> Self {
+    if crate::stdout_reserved() {
+      JsonLines(Box::new(io::stderr().lock()))
+    } else {
+      JsonLines(Box::new(io::stdout().lock()))
+    }
+  }
+}
+
+impl<W: Write> JsonLines<W> {
+  pub fn write(&mut self, value: &impl serde::Serialize) -> io::Result<()> {
+    serde_json::to_writer(&mut self.0, value)?;
+    self.0.write_all(b"\n")
+  }
+
+  pub fn finish(mut self) -> io::Result<()> { self.0.flush() }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Outcome {
+  pub line: u32,
+  pub col: u32,
+  pub ok: bool,
+  /// Whether the inference failed because a time budget ran out
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub timed_out: bool,
+  pub atoms: usize,
+  pub eq_classes: usize,
+}
+
+impl std::fmt::Display for Outcome {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let Outcome { line, col, ok, timed_out, atoms, eq_classes } = *self;
+    let ok = if ok { "ok" } else if timed_out { "TIMEOUT" } else { "FAIL" };
+    write!(f, "{line}:{col} {ok}, {atoms} atoms, {eq_classes} classes")
+  }
+}
+
+#[derive(Default)]
+pub struct Snapshot(Vec<Outcome>);
+
+thread_local! {
+  static SNAPSHOT: RefCell<Option<Snapshot>> = const { RefCell::new(None) };
+}
+
+impl Snapshot {
+  /// Starts recording outcomes for a new article.
+  pub fn start() { SNAPSHOT.with(|s| *s.borrow_mut() = Some(Snapshot::default())) }
+
+  /// Records the outcome of an inference, if we are recording.
+  pub fn push(outcome: Outcome) {
+    SNAPSHOT.with(|s| {
+      if let Some(s) = &mut *s.borrow_mut() {
+        s.0.push(outcome)
+      }
+    })
+  }
+
+  fn read(path: &std::path::Path) -> io::Result<Vec<Outcome>> {
+    let mut out = vec![];
+    for line in io::BufReader::new(std::fs::File::open(path)?).lines() {
+      out.push(serde_json::from_str(&line?)?)
+    }
+    Ok(out)
+  }
+
+  /// Stops recording, and compares the outcomes with the snapshot in `dir`
+  /// (or writes it, when blessing). Returns false if they differ, or if there is
+  /// no snapshot to compare with.
+  pub fn finish(path: &MizPath, dir: &str) -> io::Result<bool> {
+    let Some(Snapshot(mut new)) = SNAPSHOT.with(|s| s.borrow_mut().take()) else { return Ok(true) };
+    new.sort_by_key(|o| (o.line, o.col));
+    let file = std::path::Path::new(dir).join(format!("{}.json", path.art));
+    if std::env::var_os("MIZAR_BLESS").is_some() {
+      std::fs::create_dir_all(dir)?;
+      let mut w = JsonLines(io::BufWriter::new(std::fs::File::create(&file)?));
+      for o in &new {
+        w.write(o)?
+      }
+      w.finish()?;
+      return Ok(true)
+    }
+    if !file.exists() {
+      outln!("no snapshot for {} ({}), set MIZAR_BLESS=1 to write it", path.art, file.display());
+      return Ok(false)
+    }
+    let old = Self::read(&file)?;
+    if old == new {
+      return Ok(true)
+    }
+    outln!("snapshot mismatch for {} ({}):", path.art, file.to_string_lossy());
+    let (mut old, mut new) = (old.into_iter().peekable(), new.into_iter().peekable());
+    loop {
+      match (old.peek(), new.peek()) {
+        (None, None) => break,
+        (Some(a), Some(b)) if a == b => {
+          old.next();
+          new.next();
+        }
+        (Some(a), Some(b)) if (a.line, a.col) == (b.line, b.col) => {
+          outln!("  - {a}\n  + {b}");
+          old.next();
+          new.next();
+        }
+        (Some(a), b) if b.is_none_or(|b| (a.line, a.col) < (b.line, b.col)) => {
+          outln!("  - {a}");
+          old.next();
+        }
+        (_, Some(b)) => {
+          outln!("  + {b}");
+          new.next();
+        }
+        (_, None) => unreachable!(),
+      }
+    }
+    Ok(false)
+  }
+}
+
+/// Reads all the snapshots in `dir`, by article name.
+fn read_dir(dir: &str) -> io::Result<BTreeMap<String, Vec<Outcome>>> {
+  let mut out = BTreeMap::new();
+  for entry in std::fs::read_dir(dir)? {
+    let path = entry?.path();
+    if path.extension().is_some_and(|ext| ext == "json") {
+      if let Some(art) = path.file_stem().and_then(|s| s.to_str()) {
+        out.insert(art.to_owned(), Snapshot::read(&path)?);
+      }
+    }
+  }
+  Ok(out)
+}
+
+/// Keys the outcomes of a snapshot by position, numbering the inferences at the same
+/// position (such as the conjuncts of a `by`) in the order they were recorded.
+fn by_position(outcomes: Vec<Outcome>) -> BTreeMap<(u32, u32, usize), Outcome> {
+  let mut seen = BTreeMap::<_, usize>::new();
+  let mut out = BTreeMap::new();
+  for o in outcomes {
+    let i = seen.entry((o.line, o.col)).or_default();
+    out.insert((o.line, o.col, *i), o);
+    *i += 1;
+  }
+  out
+}
+
+/// Compares the snapshots in `old` with those in `new`, matching inferences by article
+/// and position. It prints the inferences that were added, removed or changed whether
+/// they are accepted, and those whose atoms or classes grew by more than `factor`,
+/// followed by the totals of both runs. Returns false if any inference went from
+/// accepted to rejected or regressed in this way.
+pub fn compare_snapshots(old: &str, new: &str, factor: f64) -> io::Result<bool> {
+  #[derive(Default)]
+  struct Totals {
+    items: usize,
+    failures: usize,
+    atoms: usize,
+    eq_classes: usize,
+  }
+  impl Totals {
+    fn add(&mut self, o: &Outcome) {
+      self.items += 1;
+      self.failures += !o.ok as usize;
+      self.atoms += o.atoms;
+      self.eq_classes += o.eq_classes;
+    }
+  }
+  impl std::fmt::Display for Totals {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      let Totals { items, failures, atoms, eq_classes } = self;
+      write!(f, "{items} inferences, {failures} failures, {atoms} atoms, {eq_classes} classes")
+    }
+  }
+  let grew = |a: usize, b: usize| b as f64 > a.max(1) as f64 * factor;
+
+  let (old, mut new) = (read_dir(old)?, read_dir(new)?);
+  let (mut old_total, mut new_total) = (Totals::default(), Totals::default());
+  let mut ok = true;
+  for (art, old) in old {
+    let Some(new) = new.remove(&art) else {
+      println!("{art}: only in the old snapshots");
+      continue
+    };
+    let mut new = by_position(new);
+    for (key, a) in by_position(old) {
+      old_total.add(&a);
+      let Some(b) = new.remove(&key) else {
+        println!("{art}: removed {a}");
+        continue
+      };
+      new_total.add(&b);
+      if a.ok && !b.ok || grew(a.atoms, b.atoms) || grew(a.eq_classes, b.eq_classes) {
+        ok = false;
+        println!("{art}: regression:\n  - {a}\n  + {b}")
+      } else if !a.ok && b.ok {
+        println!("{art}: now accepted:\n  - {a}\n  + {b}")
+      }
+    }
+    for b in new.into_values() {
+      new_total.add(&b);
+      println!("{art}: added {b}")
+    }
+  }
+  for art in new.keys() {
+    println!("{art}: only in the new snapshots");
+  }
+  println!("old: {old_total}\nnew: {new_total}");
+  Ok(ok)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn same_position_outcomes() {
+    let o = |ok, atoms| Outcome { line: 3, col: 7, ok, timed_out: false, atoms, eq_classes: 1 };
+    let keys = by_position(vec![o(true, 1), o(true, 2), o(false, 3)]);
+    assert_eq!(keys.keys().map(|k| k.2).collect::<Vec<_>>(), [0, 1, 2]);
+
+    let dir = std::env::temp_dir().join(format!("mizar-rs-snapshots-{}", std::process::id()));
+    let write = |name: &str, outcomes: &[Outcome]| {
+      std::fs::create_dir_all(dir.join(name)).unwrap();
+      let file = std::fs::File::create(dir.join(name).join("test.json")).unwrap();
+      let mut w = JsonLines(file);
+      outcomes.iter().for_each(|o| w.write(o).unwrap());
+      dir.join(name).to_str().unwrap().to_owned()
+    };
+    let old = write("old", &[o(true, 1), o(true, 2)]);
+    let same = write("same", &[o(true, 1), o(true, 2)]);
+    let new = write("new", &[o(true, 1), o(false, 2)]);
+    assert!(compare_snapshots(&old, &same, 2.).unwrap());
+    assert!(!compare_snapshots(&old, &new, 2.).unwrap());
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,237 @@
+//! Export of unrefuted conjuncts in TPTP FOF syntax, for `--export-unsolved-tptp`.
+//!
+//! This is a hook for throwing the obligations the checker gives up on at an external
+//! prover; nothing is read back. The translation is direct: every literal of the conjunct
+//! becomes an axiom and the conjecture is `$false`. Symbols are named by constructor kind
+//! and number (`f3` is functor 3, `p5` is predicate 5, `v2` is attribute 2, `m1` is mode 1,
+//! `l4` is structure 4, and so on), types become guard predicates applied to the term,
+//! and Fraenkel and choice terms are approximated by fresh functions with defining axioms.
+use crate::checker::{Atoms, Conjunct};
+use crate::types::*;
+use crate::{Global, LocalContext};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+struct Tptp<'a> {
+  g: &'a Global,
+  lc: &'a LocalContext,
+  /// Auxiliary axioms: types and definitions of constants, and fresh function definitions
+  axioms: Vec<String>,
+  consts: BTreeSet<ConstId>,
+  infers: BTreeSet<InferId>,
+  fresh: usize,
+}
+
+fn var(i: u32) -> String { format!("B{i}") }
+
+fn app(head: &str, args: impl IntoIterator<Item = String>) -> String {
+  let args = args.into_iter().collect::<Vec<_>>();
+  if args.is_empty() {
+    head.to_owned()
+  } else {
+    format!("{head}({})", args.join(","))
+  }
+}
+
+impl<'a> Tptp<'a> {
+  fn terms(&mut self, depth: u32, args: &[Term]) -> Vec<String> {
+    args.iter().map(|tm| self.term(depth, tm)).collect()
+  }
+
+  /// A fresh function of the bound variables in scope, used for terms with no first-order
+  /// counterpart. Returns the application and the variables it is applied to.
+  fn fresh(&mut self, prefix: &str, depth: u32) -> (String, Vec<String>) {
+    self.fresh += 1;
+    let vars = (0..depth).map(var).collect::<Vec<_>>();
+    (app(&format!("{prefix}{}", self.fresh), vars.clone()), vars)
+  }
+
+  fn close(vars: &[String], body: String) -> String {
+    if vars.is_empty() {
+      body
+    } else {
+      format!("![{}]: ({body})", vars.join(","))
+    }
+  }
+
+  fn term(&mut self, depth: u32, tm: &Term) -> String {
+    match tm {
+      Term::Numeral(n) => format!("n{n}"),
+      Term::Bound(BoundId(i)) => var(*i),
+      &Term::Const(nr) => {
+        if self.consts.insert(nr) {
+          let c = format!("c{}", nr.0);
+          let v = &self.lc.fixed_var[nr];
+          let guard = self.guard(0, &c, &v.ty);
+          self.axioms.push(guard);
+          if let Some((def, _)) = &v.def {
+            let def = self.term(0, def);
+            self.axioms.push(format!("{c} = {def}"))
+          }
+        }
+        format!("c{}", nr.0)
+      }
+      &Term::Infer(nr) => {
+        if self.infers.insert(nr) {
+          let def = self.lc.infer_const.borrow()[nr].def.clone();
+          let def = self.term(0, &def);
+          self.axioms.push(format!("i{} = {def}", nr.0))
+        }
+        format!("i{}", nr.0)
+      }
+      Term::Functor { nr, args } => {
+        let (nr, args) = Term::adjust(*nr, args, Some(&self.g.constrs));
+        let args = self.terms(depth, args);
+        app(&format!("f{}", nr.0), args)
+      }
+      Term::Selector { nr, args } => {
+        let args = self.terms(depth, args);
+        app(&format!("s{}", nr.0), args)
+      }
+      Term::Aggregate { nr, args } => {
+        let args = self.terms(depth, args);
+        app(&format!("g{}", nr.0), args)
+      }
+      Term::SchFunc { nr, args } => {
+        let args = self.terms(depth, args);
+        app(&format!("sf{}", nr.0), args)
+      }
+      Term::PrivFunc { value, .. } => self.term(depth, value),
+      Term::Qua { value, .. } => self.term(depth, value),
+      Term::The { ty } => {
+        let (the, vars) = self.fresh("the", depth);
+        let guard = self.guard(depth, &the, ty);
+        self.axioms.push(Self::close(&vars, guard));
+        the
+      }
+      Term::Fraenkel { args, scope, compr } => {
+        let (fr, vars) = self.fresh("fr", depth);
+        let x = var(depth + args.len() as u32);
+        let mut body = vec![];
+        for (i, (_, ty)) in args.iter().enumerate() {
+          body.push(self.guard(depth + i as u32, &var(depth + i as u32), ty))
+        }
+        let inner = depth + args.len() as u32;
+        body.push(format!("{x} = {}", self.term(inner, scope)));
+        body.push(self.formula(inner, compr));
+        let ex = (depth..inner).map(var).collect::<Vec<_>>();
+        let mut rhs = format!("({})", body.join(" & "));
+        if !ex.is_empty() {
+          rhs = format!("?[{}]: {rhs}", ex.join(","))
+        }
+        let in_ = match self.g.reqs.belongs_to() {
+          Some(nr) => format!("p{}({x},{fr})", nr.0),
+          None => format!("in({x},{fr})"),
+        };
+        let mut all = vars;
+        all.push(x);
+        self.axioms.push(Self::close(&all, format!("{in_} <=> {rhs}")));
+        fr
+      }
+      Term::Locus(_)
+      | Term::EqClass(_)
+      | Term::EqMark(_)
+      | Term::FreeVar(_)
+      | Term::It => self.fresh("u", depth).0,
+    }
+  }
+
+  /// The guard formula asserting that `tm` has type `ty`
+  fn guard(&mut self, depth: u32, tm: &str, ty: &Type) -> String {
+    let mut conjs = vec![];
+    let args = self.terms(depth, &ty.args).into_iter().chain([tm.into()]);
+    match ty.kind {
+      TypeKind::Mode(ModeId::ANY) | TypeKind::Mode(ModeId::SET) => {}
+      TypeKind::Mode(nr) => conjs.push(app(&format!("m{}", nr.0), args)),
+      TypeKind::Struct(nr) => conjs.push(app(&format!("l{}", nr.0), args)),
+    }
+    match &ty.attrs.1 {
+      Attrs::Inconsistent => return "$false".into(),
+      Attrs::Consistent(attrs) =>
+        for attr in attrs {
+          let (nr, args) = attr.adjust(Some(&self.g.constrs));
+          let args = self.terms(depth, args);
+          let lit = app(&format!("v{}", nr.0), args.into_iter().chain([tm.into()]));
+          conjs.push(if attr.pos { lit } else { format!("~{lit}") })
+        },
+    }
+    match conjs.len() {
+      0 => "$true".into(),
+      1 => conjs.pop().unwrap(),
+      _ => format!("({})", conjs.join(" & ")),
+    }
+  }
+
+  fn formula(&mut self, depth: u32, f: &Formula) -> String {
+    match f {
+      Formula::SchPred { nr, args } => {
+        let args = self.terms(depth, args);
+        app(&format!("sp{}", nr.0), args)
+      }
+      Formula::Pred { nr, args } => {
+        let (nr, args) = Formula::adjust_pred(*nr, args, Some(&self.g.constrs));
+        let args = self.terms(depth, args);
+        match &*args {
+          [x, y] if self.g.reqs.equals_to() == Some(nr) => format!("{x} = {y}"),
+          _ => app(&format!("p{}", nr.0), args),
+        }
+      }
+      Formula::Attr { nr, args } => {
+        let (nr, args) = Formula::adjust_attr(*nr, args, Some(&self.g.constrs));
+        let args = self.terms(depth, args);
+        app(&format!("v{}", nr.0), args)
+      }
+      Formula::PrivPred { value, .. } => self.formula(depth, value),
+      Formula::Is { term, ty } => {
+        let term = self.term(depth, term);
+        self.guard(depth, &term, ty)
+      }
+      Formula::Neg { f } => format!("~({})", self.formula(depth, f)),
+      Formula::And { args } => {
+        let args = args.iter().map(|f| self.formula(depth, f)).collect::<Vec<_>>();
+        format!("({})", args.join(" & "))
+      }
+      Formula::ForAll { dom, scope, .. } => {
+        let x = var(depth);
+        let guard = self.guard(depth, &x, dom);
+        format!("![{x}]: ({guard} => {})", self.formula(depth + 1, scope))
+      }
+      Formula::LegacyFlexAnd { expansion, .. } => self.formula(depth, expansion),
+      Formula::FlexAnd { .. } => {
+        self.fresh += 1;
+        format!("flex{}", self.fresh)
+      }
+      Formula::True => "$true".into(),
+    }
+  }
+}
+
+/// Renders the conjunct `conj` of `atoms` as a TPTP problem, whose axioms are
+/// contradictory exactly when the conjunct is refutable.
+pub fn export(
+  g: &Global, lc: &LocalContext, atoms: &Atoms, conj: &Conjunct<AtomId, bool>, header: &str,
+) -> String {
+  let mut t = Tptp {
+    g,
+    lc,
+    axioms: vec![],
+    consts: Default::default(),
+    infers: Default::default(),
+    fresh: 0,
+  };
+  let lits = (conj.0.iter())
+    .map(|(&a, &pos)| {
+      let f = t.formula(0, &atoms[a]);
+      if pos { f } else { format!("~({f})") }
+    })
+    .collect::<Vec<_>>();
+  let mut out = format!("% {header}\n");
+  for (i, ax) in t.axioms.iter().enumerate() {
+    writeln!(out, "fof(aux{i}, axiom, {ax}).").unwrap();
+  }
+  for (i, lit) in lits.iter().enumerate() {
+    writeln!(out, "fof(lit{i}, axiom, {lit}).").unwrap();
+  }
+  writeln!(out, "fof(goal, conjecture, $false).").unwrap();
+  out
+}
@@ -1,9 +1,8 @@
-use crate::accom::SigBuilder;
-use crate::VisitMut;
+use crate::{outln, VisitMut};
 use enum_map::{Enum, EnumMap};
 use paste::paste;
-use serde::Serialize;
-use serde_derive::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_derive::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::marker::PhantomData;
@@ -86,6 +85,13 @@ impl<I, T: Serialize> Serialize for IdxVec<I, T> {
   }
 }
 
+impl<'de, I, T: Deserialize<'de>> Deserialize<'de> for IdxVec<I, T> {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where D: serde::Deserializer<'de> {
+    Vec::deserialize(deserializer).map(Self::from)
+  }
+}
+
 impl<I, T> IdxVec<I, T> {
   /// Construct a new empty [`IdxVec`].
   #[must_use]
@@ -212,7 +218,8 @@ impl<I: Idx, T> Index<Range<I>> for IdxVec<I, T> {
 macro_rules! mk_id {
   ($($id:ident($ty:ty) $(+ Visit($visit:ident))?,)*) => {
     $(
-      #[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, serde_derive::Serialize)]
+      #[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+      #[derive(serde_derive::Serialize, serde_derive::Deserialize)]
       pub struct $id(pub $ty);
       impl $crate::Idx for $id {
         fn from_usize(n: usize) -> Self { Self(n as $ty) }
@@ -227,6 +234,7 @@ macro_rules! mk_id {
         type Err = std::num::ParseIntError;
         fn from_str(s: &str) -> Result<Self, Self::Err> { <$ty>::from_str(s).map($id) }
       }
+      #[cfg(feature = "parser")]
       impl $crate::parser::FromStrPos for $id {
         fn to_err(_: Self::Err, pos: usize) -> $crate::parser::ParseError {
           $crate::parser::ParseError::BadInteger(pos)
@@ -240,7 +248,7 @@ macro_rules! mk_id {
   };
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SortedIdxVec<I, T> {
   pub vec: IdxVec<I, T>,
   pub sorted: Vec<I>,
@@ -333,6 +341,7 @@ impl<T> ExtVec<T> {
   pub fn push_ext(&mut self, t: T) { self.vec.push(t) }
   pub fn up(&mut self) { self.limit = self.vec.len() }
   pub fn len(&self) -> usize { self.limit }
+  pub fn is_empty(&self) -> bool { self.limit == 0 }
   pub fn ext_len(&self) -> usize { self.vec.len() }
 }
 
@@ -362,6 +371,9 @@ mk_id! {
   ThmId(u32),
   SchId(u32),
   AtomId(u32),
+  PremiseId(u32),
+  SigId(u32),
+  DefiniensId(u32),
 }
 impl ArticleId {
   pub const SELF: ArticleId = ArticleId(0);
@@ -403,6 +415,7 @@ impl ArticleId {
 ///   reference earlier theorems, maybe to justify why they can now be taken as axioms,
 ///   but some references like JGRAPH_6 also use ARITHM so this isn't very convincing.
 ///
+#[derive(Serialize, Deserialize)]
 pub struct RequirementIndexes {
   pub fwd: EnumMap<Requirement, u32>,
   pub rev: IdxVec<FuncId, Option<Requirement>>,
@@ -444,7 +457,7 @@ macro_rules! mk_requirements {
   (@is_func FuncId) => { true };
   (@is_func $_:tt) => { false };
   ($($(#[$attr:meta])* $id:ident: $ty:tt,)*) => {
-    #[derive(Copy, Clone, Debug, Enum)]
+    #[derive(Copy, Clone, Debug, Enum, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
     pub enum Requirement {
       $($(#[$attr])* $id,)*
     }
@@ -455,6 +468,10 @@ macro_rules! mk_requirements {
           pub fn [<$id:snake>](&self) -> Option<$ty> { self.get_raw(Requirement::$id).map($ty) }
         )*
       }
+      pub fn checked<'a>(&'a self, disabled: &'a BTreeSet<Requirement>) -> CheckedRequirements<'a> {
+        CheckedRequirements { reqs: self, disabled }
+      }
+
       pub fn on_func_ids(mut f: impl FnMut(Requirement)) {
         $(if mk_requirements!(@is_func $ty) { f(Requirement::$id) })*
       }
@@ -472,6 +489,31 @@ macro_rules! mk_requirements {
         }
       }
     }
+    impl CheckedRequirements<'_> {
+      paste! {
+        $(
+          pub fn [<$id:snake>](&self) -> Option<$ty> {
+            if self.disabled.contains(&Requirement::$id) { None } else { self.reqs.[<$id:snake>]() }
+          }
+        )*
+      }
+    }
+  }
+}
+
+/// The requirements as seen by the built-in reasoning of the checker. This is like
+/// [`RequirementIndexes`], except that the requirements disabled with
+/// `--disable-requirements` are missing. This only makes the checker weaker, so it is sound.
+#[derive(Clone, Copy)]
+pub struct CheckedRequirements<'a> {
+  reqs: &'a RequirementIndexes,
+  disabled: &'a BTreeSet<Requirement>,
+}
+
+impl CheckedRequirements<'_> {
+  /// The requirement defined by functor `nr`, if it is not disabled
+  pub fn rev(&self, nr: FuncId) -> Option<Requirement> {
+    self.reqs.rev.get(nr).copied().flatten().filter(|req| !self.disabled.contains(req))
   }
 }
 
@@ -569,8 +611,15 @@ impl RequirementIndexes {
 
   pub fn get_raw(&self, req: Requirement) -> Option<u32> { self.fwd[req].checked_sub(1) }
 
+  /// The formula `t1 = t2`. Panics if the environment lacks the equality requirement,
+  /// which the analyzer needs anyway; the checker uses `try_mk_eq` instead.
   pub fn mk_eq(&self, t1: Term, t2: Term) -> Formula {
-    Formula::Pred { nr: self.equals_to().unwrap(), args: Box::new([t1, t2]) }
+    self.try_mk_eq(t1, t2).expect("the equality requirement is missing")
+  }
+
+  /// The formula `t1 = t2`, if the environment has the equality requirement.
+  pub fn try_mk_eq(&self, t1: Term, t2: Term) -> Option<Formula> {
+    Some(Formula::Pred { nr: self.equals_to()?, args: Box::new([t1, t2]) })
   }
 }
 
@@ -618,14 +667,91 @@ impl<V, A: Visitable<V>, B: Visitable<V>> Visitable<V> for (A, B) {
   }
 }
 
+/// The value of a numeral. Numerals that fit in a `u32` are stored inline,
+/// so this is only allocated for large literals.
+/// Invariant: `Large` is only used for values that do not fit in a `u32`.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Numeral {
+  Small(u32),
+  Large(Box<num_bigint::BigUint>),
+}
+
+impl Default for Numeral {
+  fn default() -> Self { Self::Small(0) }
+}
+
+impl From<u32> for Numeral {
+  fn from(n: u32) -> Self { Self::Small(n) }
+}
+
+impl From<num_bigint::BigUint> for Numeral {
+  fn from(n: num_bigint::BigUint) -> Self {
+    match n.try_into() {
+      Ok(n) => Self::Small(n),
+      Err(n) => Self::Large(Box::new(n.into_original())),
+    }
+  }
+}
+
+impl std::str::FromStr for Numeral {
+  type Err = ();
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.parse() {
+      Ok(n) => Ok(Self::Small(n)),
+      Err(_) => Ok(s.parse::<num_bigint::BigUint>().map_err(|_| ())?.into()),
+    }
+  }
+}
+
+impl std::fmt::Display for Numeral {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Numeral::Small(n) => n.fmt(f),
+      Numeral::Large(n) => n.fmt(f),
+    }
+  }
+}
+
+impl std::fmt::Debug for Numeral {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    std::fmt::Display::fmt(self, f)
+  }
+}
+
+impl Serialize for Numeral {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where S: serde::Serializer {
+    match self {
+      Numeral::Small(n) => n.serialize(serializer),
+      Numeral::Large(n) => serializer.collect_str(n),
+    }
+  }
+}
+
+impl<'de> Deserialize<'de> for Numeral {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where D: serde::Deserializer<'de> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+      Small(u32),
+      Large(String),
+    }
+    match Repr::deserialize(deserializer)? {
+      Repr::Small(n) => Ok(Numeral::Small(n)),
+      Repr::Large(s) => s.parse().map_err(|()| serde::de::Error::custom("bad numeral")),
+    }
+  }
+}
+
 /// This type alias is used to indicate that the term might have a Qua at the top level.
 pub type TermQua = Term;
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Term {
   /// Invariant: nr != 0. Zero is not a numeral (!),
   /// it is a `Functor` using Requirement::ZeroNumber
-  Numeral(u32),
+  Numeral(Numeral),
   /// Locus numbers are shifted from mizar to start at 0
   Locus(LocusId),
   /// Bound var numbers are shifted from mizar to start at 0
@@ -681,7 +807,7 @@ pub enum Term {
 }
 
 impl Default for Term {
-  fn default() -> Self { Self::Numeral(0) }
+  fn default() -> Self { Self::Numeral(Numeral::Small(0)) }
 }
 
 impl Term {
@@ -708,6 +834,7 @@ impl Term {
       _ => self,
     }
   }
+  #[allow(clippy::needless_lifetimes)]
   pub fn unqua<'a>(self: &'a TermQua) -> &'a Term {
     match self {
       Term::Qua { value, .. } => value,
@@ -739,12 +866,12 @@ impl Term {
       Term::The { .. } => 216,
       Term::Fraenkel { .. } => 232,
       Term::Qua { .. } => 213,
-      Term::It { .. } => 234,
+      Term::It => 234,
     }
   }
 }
 
-#[derive(Clone, Default, PartialEq, Eq)]
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Type {
   /// The kind of type (either Mode or Struct), and the id
   pub kind: TypeKind,
@@ -775,7 +902,7 @@ impl<V: VisitMut> Visitable<V> for Type {
   fn visit(&mut self, v: &mut V) { v.visit_type(self) }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum TypeKind {
   Struct(StructId),
   Mode(ModeId),
@@ -811,7 +938,7 @@ impl TypeKind {
   }
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Formula {
   SchPred {
     nr: SchPredId,
@@ -864,13 +991,10 @@ pub enum Formula {
     expansion: Box<Formula>,
   },
   /// ikFrmVerum
+  #[default]
   True,
 }
 
-impl Default for Formula {
-  fn default() -> Self { Self::True }
-}
-
 impl<V: VisitMut> Visitable<V> for Formula {
   fn visit(&mut self, v: &mut V) { v.visit_formula(self) }
 }
@@ -917,12 +1041,16 @@ impl Formula {
   #[inline]
   pub fn forall0(dom: Type, scope: Self) -> Self { Self::forall(IdentId::NONE, dom, scope) }
 
-  pub fn conjuncts(&self) -> &[Formula] {
-    match self {
-      Formula::True => &[],
-      Formula::And { args } => args,
-      f => std::slice::from_ref(f),
-    }
+  /// The conjuncts of this formula, looking through nested conjunctions
+  pub fn conjuncts(&self) -> impl Iterator<Item = &Formula> {
+    let mut stack = vec![self];
+    std::iter::from_fn(move || loop {
+      match stack.pop()? {
+        Formula::True => {}
+        Formula::And { args } => stack.extend(args.iter().rev()),
+        f => return Some(f),
+      }
+    })
   }
 
   pub fn into_conjuncts(self) -> Vec<Formula> {
@@ -942,6 +1070,15 @@ impl Formula {
     }
   }
 
+  /// Like `append_conjuncts_to`, but also flattens conjunctions nested in the args
+  pub fn append_conjuncts_flat_to(self, vec: &mut Vec<Formula>) {
+    match self {
+      Formula::True => {}
+      Formula::And { args } => args.into_iter().for_each(|f| f.append_conjuncts_flat_to(vec)),
+      f => vec.push(f),
+    }
+  }
+
   // Precondition: the args are not And expressions
   pub fn mk_and(args: Vec<Formula>) -> Formula {
     match args.len() {
@@ -951,6 +1088,11 @@ impl Formula {
     }
   }
 
+  /// Like `mk_and`, but the args can be conjunctions themselves
+  pub fn mk_and_flat(args: Vec<Formula>) -> Formula {
+    Self::mk_and_with(|conjs| args.into_iter().for_each(|f| f.append_conjuncts_flat_to(conjs)))
+  }
+
   #[inline]
   pub fn mk_and_with(f: impl FnOnce(&mut Vec<Formula>)) -> Formula {
     let mut args = vec![];
@@ -986,7 +1128,7 @@ impl Formula {
   }
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Attrs {
   Inconsistent,
   Consistent(Vec<Attr>),
@@ -1010,7 +1152,7 @@ impl<V: VisitMut> Visitable<V> for Attrs {
   fn visit(&mut self, v: &mut V) { v.visit_attrs(self) }
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Attr {
   pub nr: AttrId,
   pub pos: bool,
@@ -1025,9 +1167,22 @@ impl<V: VisitMut> Visitable<V> for Attr {
   fn visit(&mut self, v: &mut V) { self.args.visit(v) }
 }
 
-pub const MAX_ARTICLE_LEN: usize = 8;
+/// The longest article name we accept. This is more than the Mizar tools accept
+/// ([`MIZAR_ARTICLE_LEN`]), so that newer libraries with longer names can be read,
+/// but such articles can't be used with the original verifier.
+pub const MAX_ARTICLE_LEN: usize = 32;
+/// The longest article name the Mizar tools accept
+pub const MIZAR_ARTICLE_LEN: usize = 8;
+
+/// An article name. Names of up to [`MIZAR_ARTICLE_LEN`] characters are stored inline,
+/// padded with NULs. Longer names are interned, and stored as [`Article::LONG`] followed
+/// by their index in `LONG_NAMES`, so that `Article` stays 8 bytes.
 #[derive(Hash, Copy, Clone, Default, PartialEq, Eq)]
-pub struct Article([u8; MAX_ARTICLE_LEN]);
+pub struct Article([u8; MIZAR_ARTICLE_LEN]);
+
+/// The names of the long articles, by index. They are never freed, which is fine since
+/// there are only as many as there are articles in the library.
+static LONG_NAMES: std::sync::Mutex<Vec<&'static [u8]>> = std::sync::Mutex::new(vec![]);
 
 impl std::fmt::Debug for Article {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -1046,6 +1201,14 @@ impl serde::Serialize for Article {
   }
 }
 
+impl<'de> serde::Deserialize<'de> for Article {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where D: serde::Deserializer<'de> {
+    let s = String::deserialize(deserializer)?;
+    Article::from_lower(s.as_bytes()).map_err(serde::de::Error::custom)
+  }
+}
+
 #[derive(Debug)]
 pub enum ToArticleError {
   TooLong,
@@ -1060,41 +1223,68 @@ impl std::fmt::Display for ToArticleError {
     }
   }
 }
+impl std::error::Error for ToArticleError {}
 
 impl Article {
   pub const HIDDEN: Article = Article(*b"hidden\0\0");
+  /// The first byte of an interned long name, which can't start an ASCII name
+  const LONG: u8 = 0xff;
+
   pub fn from_lower(s: &[u8]) -> Result<Article, ToArticleError> {
     if s.len() > MAX_ARTICLE_LEN {
       return Err(ToArticleError::TooLong)
     }
-    let mut arr = [0; MAX_ARTICLE_LEN];
-    arr[..s.len()].copy_from_slice(s);
-    if !arr.iter().all(u8::is_ascii) {
+    if !s.is_ascii() {
       return Err(ToArticleError::NotAscii)
     }
+    let mut arr = [0; MIZAR_ARTICLE_LEN];
+    if s.len() <= MIZAR_ARTICLE_LEN {
+      arr[..s.len()].copy_from_slice(s);
+    } else {
+      #[allow(clippy::unwrap_used)]
+      let mut names = LONG_NAMES.lock().unwrap();
+      let i = names.iter().position(|&name| name == s).unwrap_or_else(|| {
+        names.push(Box::leak(s.into()));
+        names.len() - 1
+      });
+      arr[0] = Self::LONG;
+      arr[1..5].copy_from_slice(&(i as u32).to_le_bytes());
+    }
     Ok(Article(arr))
   }
   pub fn from_upper(s: &[u8]) -> Result<Article, ToArticleError> {
-    if s.len() > MAX_ARTICLE_LEN {
-      return Err(ToArticleError::TooLong)
-    }
-    let mut arr = [0; MAX_ARTICLE_LEN];
-    arr[..s.len()].copy_from_slice(s);
-    if !arr.iter().all(u8::is_ascii) {
-      return Err(ToArticleError::NotAscii)
+    Self::from_lower(&s.to_ascii_lowercase())
+  }
+  pub fn as_bytes(&self) -> &[u8] {
+    if self.0[0] == Self::LONG {
+      let i = u32::from_le_bytes([self.0[1], self.0[2], self.0[3], self.0[4]]);
+      #[allow(clippy::unwrap_used)]
+      return LONG_NAMES.lock().unwrap()[i as usize]
     }
-    std::str::from_utf8_mut(&mut arr[..s.len()])
-      .map_err(|_| ToArticleError::NotAscii)?
-      .make_ascii_lowercase();
-    Ok(Article(arr))
+    &self.0[..self.0.iter().position(|&x| x == 0).unwrap_or(MIZAR_ARTICLE_LEN)]
   }
-  pub fn as_bytes(&self) -> &[u8] { &self.0[..self.0.iter().position(|&x| x == 0).unwrap_or(8)] }
   pub fn as_str(&self) -> &str { std::str::from_utf8(self.as_bytes()).unwrap() }
+
+  /// Whether the name is too long for the original Mizar tools
+  pub fn is_long(&self) -> bool { self.0[0] == Self::LONG }
+
+  /// The name of the article's files in `prel/`. This is the name itself, except for long
+  /// names, which are shortened to their first two characters and a 24 bit FNV-1a hash
+  /// of the rest, so that the files have names the Mizar tools can list.
+  pub fn prel_name(&self) -> std::borrow::Cow<'_, str> {
+    if !self.is_long() {
+      return self.as_str().into()
+    }
+    let (head, tail) = self.as_bytes().split_at(2);
+    let hash = (tail.iter()).fold(0x811c_9dc5_u32, |h, &c| (h ^ c as u32).wrapping_mul(0x0100_0193));
+    format!("{}{:06x}", std::str::from_utf8(head).unwrap(), (hash >> 24) ^ (hash & 0xff_ffff))
+      .into()
+  }
 }
 
 macro_rules! mk_property_kind {
   (enum $ty:ident { $($(#[$attr:meta])* $id:ident = $upper:literal | $lower:literal,)* }) => {
-    #[derive(Copy, Clone, Debug, Enum, PartialEq, Eq, Serialize)]
+    #[derive(Copy, Clone, Debug, Enum, PartialEq, Eq, Serialize, Deserialize)]
     pub enum $ty {
       $($(#[$attr])* $id,)*
     }
@@ -1166,7 +1356,7 @@ impl PropertyKind {
   }
 }
 
-#[derive(Copy, Clone, Default, PartialEq, Eq)]
+#[derive(Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PropertySet(u16);
 
 impl PropertySet {
@@ -1204,7 +1394,7 @@ impl PropertySet {
   pub fn set(&mut self, prop: PropertyKind) { self.0 |= 1 << prop as u16 }
 }
 
-#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Properties {
   pub properties: PropertySet,
   pub arg1: u8,
@@ -1240,7 +1430,7 @@ impl Properties {
   }
 }
 
-#[derive(Clone, Default, Debug, PartialEq, Eq)]
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Constructor<I> {
   // pub article: Article,
   // /// number of constructor in article
@@ -1262,7 +1452,7 @@ impl<V: VisitMut, I: Visitable<V>> Visitable<V> for Constructor<I> {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TyConstructor<I> {
   pub c: Constructor<I>,
   pub ty: Type,
@@ -1281,7 +1471,7 @@ impl<V: VisitMut, I: Visitable<V>> Visitable<V> for TyConstructor<I> {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StructMode {
   pub c: Constructor<StructId>,
   /// These are guaranteed to be struct types
@@ -1309,7 +1499,7 @@ impl<V: VisitMut> Visitable<V> for StructMode {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Aggregate {
   pub c: TyConstructor<AggrId>,
   pub base: u8,
@@ -1335,7 +1525,7 @@ macro_rules! impl_constructors {
   (struct Constructors {
     $($(#[$attr:meta])* $variant:ident($field:ident): IdxVec<$id:ty, $ty:ty> = $lit:expr,)*
   }) => {
-    #[derive(Clone, Debug, Default, PartialEq, Eq)]
+    #[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
     pub struct Constructors { $($(#[$attr])* pub $field: IdxVec<$id, $ty>),* }
 
     impl<V: VisitMut> Visitable<V> for Constructors {
@@ -1412,7 +1602,7 @@ macro_rules! impl_constructors {
       }
     }
 
-    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
     pub enum ConstrKind { $($variant($id),)* }
     impl<V: VisitMut> Visitable<V> for ConstrKind {
       fn visit(&mut self, v: &mut V) { match self { $(Self::$variant(c) => c.visit(v)),* } }
@@ -1443,7 +1633,7 @@ impl_constructors! {
   }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Clusters {
   pub registered: Vec<RegisteredCluster>,
   /// sorted by |a, b| FunctorCluster::cmp_term(&a.term, ctx, &b.term)
@@ -1537,7 +1727,7 @@ impl ClustersRaw {
   }
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Cluster {
   /// nPrimaryList
   pub primary: Box<[Type]>,
@@ -1566,7 +1756,7 @@ impl std::fmt::Debug for Cluster {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RegisteredCluster {
   pub cl: Cluster,
   pub ty: Box<Type>,
@@ -1588,7 +1778,7 @@ impl<V: VisitMut> Visitable<V> for RegisteredCluster {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ConditionalCluster {
   pub cl: Cluster,
   pub ty: Box<Type>,
@@ -1611,7 +1801,7 @@ impl<V: VisitMut> Visitable<V> for ConditionalCluster {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct FunctorCluster {
   pub cl: Cluster,
   pub ty: Option<Box<Type>>,
@@ -1650,7 +1840,7 @@ impl FunctorCluster {
   }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct ConditionalClusters {
   pub vec: Vec<ConditionalCluster>,
   pub attr_clusters: EnumMap<bool, BTreeMap<AttrId, BTreeSet<u32>>>,
@@ -1683,7 +1873,7 @@ impl ConditionalClusters {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ConstrDef {
   pub def_nr: DefId,
   pub article: Article,
@@ -1697,7 +1887,7 @@ impl<V: VisitMut> Visitable<V> for ConstrDef {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DefCase<T> {
   pub case: T,
   pub guard: Formula,
@@ -1709,7 +1899,7 @@ impl<V: VisitMut, T: Visitable<V>> Visitable<V> for DefCase<T> {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DefBody<T> {
   /// nPartialDefinientia
   pub cases: Box<[DefCase<T>]>,
@@ -1722,7 +1912,7 @@ impl<V: VisitMut, T: Visitable<V>> Visitable<V> for DefBody<T> {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DefValue {
   Term(DefBody<Term>),
   Formula(DefBody<Formula>),
@@ -1745,7 +1935,7 @@ impl DefValue {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Definiens {
   pub c: ConstrDef,
   // pub lab_id: Option<LabelId>,
@@ -1769,7 +1959,7 @@ impl<V: VisitMut> Visitable<V> for Definiens {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Property {
   // pub article: Article,
   // pub abs_nr: u32,
@@ -1781,7 +1971,7 @@ impl<V: VisitMut> Visitable<V> for Property {
   fn visit(&mut self, v: &mut V) { v.with_locus_tys(&mut self.primary, |v| self.ty.visit(v)) }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct IdentifyFunc {
   // pub article: Article,
   // pub abs_nr: u32,
@@ -1800,7 +1990,7 @@ impl<V: VisitMut> Visitable<V> for IdentifyFunc {
   }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Reduction {
   // pub article: Article,
   // pub abs_nr: u32,
@@ -1813,7 +2003,7 @@ impl<V: VisitMut> Visitable<V> for Reduction {
   }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct EqualsDef {
   pub primary: Box<[Type]>,
   pub expansion: Term,
@@ -1892,6 +2082,14 @@ impl serde::Serialize for Position {
   }
 }
 
+impl<'de> serde::Deserialize<'de> for Position {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where D: serde::Deserializer<'de> {
+    let (line, col) = Deserialize::deserialize(deserializer)?;
+    Ok(Position { line, col })
+  }
+}
+
 #[derive(Clone, Debug)]
 pub enum SchemeDecl {
   Func { args: Box<[Type]>, ty: Type },
@@ -2682,6 +2880,20 @@ pub struct DepNotation {
   pub pats: Vec<Pattern<Format>>,
 }
 
+/// The articles whose constructors are in the environment, in order, each with the
+/// offsets of its constructors
+#[derive(Debug, Default)]
+pub struct SigBuilder {
+  pub sig: IdxVec<SigId, (Article, ConstructorsBase)>,
+  pub base: ConstructorsBase,
+}
+
+impl SigBuilder {
+  pub fn hi(&self, id: SigId) -> &ConstructorsBase {
+    self.sig.get(SigId(id.0 + 1)).map_or(&self.base, |(_, base)| base)
+  }
+}
+
 #[derive(Debug, Default)]
 pub struct AccumConstructors {
   pub sig: SigBuilder,
@@ -2702,12 +2914,14 @@ pub struct DepClusters {
   pub cl: ClustersRaw,
 }
 
+#[allow(unused)]
 #[derive(Clone, Default, PartialEq, Eq)]
 pub struct DepIdentify {
   pub sig: Vec<Article>,
   pub defs: Vec<Definiens>,
 }
 
+#[allow(unused)]
 #[derive(Clone, Default, PartialEq, Eq)]
 pub struct DepReductions {
   pub sig: Vec<Article>,
@@ -2795,9 +3009,111 @@ impl DirectiveKind {
   }
 }
 
+impl Serialize for DirectiveKind {
+  fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(self.name())
+  }
+}
+
 #[derive(Debug, Default)]
 pub struct Directives(pub EnumMap<DirectiveKind, Vec<(Position, Article)>>);
 
+/// The elements of `new` that are not in a longest common subsequence of `old` and `new`.
+fn moved(old: &[Article], new: &[Article]) -> Vec<Article> {
+  // lcs[i][j] is the length of a longest common subsequence of old[i..] and new[j..]
+  let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+  for i in (0..old.len()).rev() {
+    for j in (0..new.len()).rev() {
+      lcs[i][j] =
+        if old[i] == new[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) }
+    }
+  }
+  let (mut i, mut j, mut out) = (0, 0, vec![]);
+  while j < new.len() {
+    if i < old.len() && old[i] == new[j] {
+      (i, j) = (i + 1, j + 1)
+    } else if i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1] {
+      i += 1
+    } else {
+      out.push(new[j]);
+      j += 1
+    }
+  }
+  out
+}
+
+/// How the articles of one directive differ between two environments
+#[derive(Debug, Serialize)]
+pub struct DirectiveKindDiff {
+  pub kind: DirectiveKind,
+  pub added: Vec<Article>,
+  pub removed: Vec<Article>,
+  /// The articles in both that moved relative to the others, in the new order. These are
+  /// the ones not in a longest common subsequence of the two orders
+  pub reordered: Vec<Article>,
+}
+
+/// The directives that differ between two environments, see [`Directives::difference`]
+#[derive(Debug, Default, Serialize)]
+pub struct DirectiveDiff(pub Vec<DirectiveKindDiff>);
+
+impl Directives {
+  pub fn iter_kind(&self, kind: DirectiveKind) -> impl Iterator<Item = Article> + '_ {
+    self.0[kind].iter().map(|&(_, art)| art)
+  }
+
+  pub fn contains(&self, kind: DirectiveKind, art: Article) -> bool {
+    self.iter_kind(kind).any(|a| a == art)
+  }
+
+  /// The changes from `self` to `other`, per directive. Positions are ignored, but the
+  /// order is not since it matters for accommodation.
+  pub fn difference(&self, other: &Directives) -> DirectiveDiff {
+    let mut out = DirectiveDiff::default();
+    for (kind, _) in &self.0 {
+      let added = other.iter_kind(kind).filter(|&a| !self.contains(kind, a)).collect::<Vec<_>>();
+      let removed = self.iter_kind(kind).filter(|&a| !other.contains(kind, a)).collect::<Vec<_>>();
+      let old = self.iter_kind(kind).filter(|&a| other.contains(kind, a)).collect::<Vec<_>>();
+      let new = other.iter_kind(kind).filter(|&a| self.contains(kind, a)).collect::<Vec<_>>();
+      let reordered = moved(&old, &new);
+      if !added.is_empty() || !removed.is_empty() || !reordered.is_empty() {
+        out.0.push(DirectiveKindDiff { kind, added, removed, reordered })
+      }
+    }
+    out
+  }
+
+  /// Prints the directives as a table, one article per line.
+  pub fn dump(&self) {
+    for (kind, arts) in &self.0 {
+      for (pos, art) in arts {
+        outln!("{:<14} {:>4}:{:<3} {art}", kind.name(), pos.line, pos.col)
+      }
+    }
+  }
+}
+
+/// Serializes as a map from the (non-empty) directives to their `{"pos", "art"}` entries,
+/// in the same shape as the `env` section of `--json-parse`.
+impl Serialize for Directives {
+  fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeMap;
+    #[derive(Serialize)]
+    struct Entry {
+      pos: Position,
+      art: Article,
+    }
+    let mut map = s.serialize_map(None)?;
+    for (kind, arts) in &self.0 {
+      if !arts.is_empty() {
+        let arts = arts.iter().map(|&(pos, art)| Entry { pos, art }).collect::<Vec<_>>();
+        map.serialize_entry(&kind, &arts)?
+      }
+    }
+    map.end()
+  }
+}
+
 #[derive(Clone, Debug)]
 pub struct DepRequirement {
   pub req: Requirement,
@@ -2811,6 +3127,7 @@ pub struct DepRequirements {
 }
 
 pub const DEFAULT_PRIO: u32 = 64;
+#[derive(Debug, PartialEq)]
 pub enum SymbolDataKind<'a> {
   Struct,
   LeftBrk,
@@ -2859,3 +3176,69 @@ pub struct Vocabulary<'a> {
   pub base: SymbolsBase,
   pub symbols: Vec<SymbolData<'a>>,
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn long_article_names() {
+    assert_eq!(std::mem::size_of::<Article>(), MIZAR_ARTICLE_LEN);
+    let ups = Article::from_lower(b"ups").unwrap();
+    assert_eq!((ups, ups.as_str(), ups.is_long()), (Article(*b"ups\0\0\0\0\0"), "ups", false));
+    let long = Article::from_upper(b"Graph_Theory_Basics").unwrap();
+    assert_eq!((long.as_str(), long.is_long()), ("graph_theory_basics", true));
+    assert_eq!(long, Article::from_lower(b"graph_theory_basics").unwrap());
+    assert_ne!(long, Article::from_lower(b"graph_theory_basicz").unwrap());
+    assert!(ups.as_bytes() > long.as_bytes() && long.as_bytes() > b"graph".as_slice());
+    assert!(matches!(Article::from_lower(&[b'a'; 33]), Err(ToArticleError::TooLong)));
+
+    let name = long.prel_name();
+    assert_eq!((name.len(), &name[..2]), (MIZAR_ARTICLE_LEN, "gr"));
+    assert_eq!(name, Article::from_lower(b"graph_theory_basics").unwrap().prel_name());
+    assert_eq!(ups.prel_name(), "ups");
+  }
+
+  #[test]
+  fn directive_difference() {
+    let [a, b, c, d] = [b"a", b"b", b"c", b"d"].map(|s| Article::from_lower(s).unwrap());
+    let dirs = |kind, arts: &[Article]| {
+      let mut dirs = Directives::default();
+      dirs.0[kind] = arts.iter().map(|&art| (Position::default(), art)).collect();
+      dirs
+    };
+    let old = dirs(DirectiveKind::Notations, &[a, b, c]);
+    assert!(old.difference(&old).0.is_empty());
+    let diff = old.difference(&dirs(DirectiveKind::Notations, &[b, c, a]));
+    let [DirectiveKindDiff { kind: DirectiveKind::Notations, added, removed, reordered }] = &*diff.0
+    else {
+      panic!("{diff:?}")
+    };
+    assert_eq!((&**added, &**removed, &**reordered), (&[][..], &[][..], &[a][..]));
+
+    let diff = old.difference(&dirs(DirectiveKind::Notations, &[d, c, a]));
+    let [DirectiveKindDiff { added, removed, reordered, .. }] = &*diff.0 else { panic!() };
+    assert_eq!((&**added, &**removed, reordered.len()), (&[d][..], &[b][..], 1));
+
+    let diff = old.difference(&dirs(DirectiveKind::Theorems, &[a]));
+    let kinds = diff.0.iter().map(|d| (d.kind, d.added.len(), d.removed.len()));
+    assert_eq!(
+      kinds.collect::<Vec<_>>(),
+      [(DirectiveKind::Notations, 0, 3), (DirectiveKind::Theorems, 1, 0)]
+    );
+  }
+
+  #[test]
+  fn flatten_conjunctions() {
+    let [a, b, c] = [0, 1, 2].map(|n| Formula::Pred { nr: PredId(n), args: Box::new([]) });
+    let flat = Formula::And { args: vec![a.clone(), b.clone(), c.clone()] };
+    let nested = Formula::And { args: vec![Formula::And { args: vec![a, b] }, c] };
+    assert!(nested.conjuncts().eq(flat.conjuncts()));
+    assert!(Formula::mk_and_flat(vec![nested.clone(), Formula::True]) == flat);
+    // the thesis machinery relies on into_conjuncts only taking off the outer conjunction
+    assert_eq!(nested.clone().into_conjuncts().len(), 2);
+    let mut conjs = vec![];
+    nested.append_conjuncts_flat_to(&mut conjs);
+    assert!(Formula::mk_and(conjs) == flat);
+  }
+}
@@ -1,23 +1,34 @@
-use crate::bignum::{Complex, Rational};
-use crate::checker::{Atoms, Dnf, Open, OrUnsat, Overflow, Unsat};
+use crate::bignum::Rational;
+use crate::checker::{Atoms, Dnf, Open, OrUnsat, Overflow, Polarity, Unsat};
+use crate::eqclass::{ComplexTermKind, EqClass};
 use crate::equate::Equalizer;
 use crate::types::*;
+use crate::util::Shuffle;
 use crate::{
-  vprintln, CheckLocus, EqCtx, Equate, ExpandPrivFunc, Global, LocalContext, Visit, VisitMut,
+  stat, vprintln, CheckLocus, EqCtx, Equate, ExpandPrivFunc, Global, LocalContext, Visit, VisitMut,
   WithGlobalLocal,
 };
-use enum_map::{Enum, EnumMap};
+use enum_map::EnumMap;
 use itertools::Itertools;
 use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
 
 const ENABLE_UNIFIER: bool = true;
 
 #[derive(Default)]
 struct EqTerm {
-  ty_class: Vec<Type>,
-  supercluster: Attrs,
   terms: EnumMap<ComplexTermKind, Vec<EqMarkId>>,
-  number: Option<Complex>,
+  class: EqClass,
+  /// The sorted heads of the types in `ty_class`, see [`radix_head`]
+  heads: Vec<TypeKind>,
+}
+
+impl std::ops::Deref for EqTerm {
+  type Target = EqClass;
+  fn deref(&self) -> &Self::Target { &self.class }
+}
+impl std::ops::DerefMut for EqTerm {
+  fn deref_mut(&mut self) -> &mut Self::Target { &mut self.class }
 }
 
 impl std::fmt::Debug for EqTerm {
@@ -29,10 +40,7 @@ impl std::fmt::Debug for EqTerm {
         f.debug_list().entries(self.terms.values().flatten()).finish()
       }
     })?;
-    if let Some(n) = &self.number {
-      write!(f, " = {n}")?
-    }
-    write!(f, ": {:?}{:?}", &self.supercluster, &self.ty_class)
+    write!(f, "{:?}", self.class)
   }
 }
 
@@ -41,45 +49,84 @@ pub struct Unifier<'a> {
   lc: &'a mut LocalContext,
   infer: HashMap<InferId, EqClassId>,
   eq_class: IdxVec<EqClassId, EqTerm>,
-  bas: &'a EnumMap<bool, Atoms>,
+  /// The order in which the unifier considers the equivalence classes
+  order: Vec<EqClassId>,
+  bas: &'a EnumMap<Polarity, Atoms>,
+  /// The closest miss for each universal premise, if `--failure-hints` is on
+  near_misses: Option<Vec<NearMiss>>,
+  /// When to give up on the remaining subproblems, if `--unify-budget-ms` is set
+  deadline: Option<Instant>,
+  /// Whether a subproblem was skipped because the budget was used up
+  pub timed_out: bool,
 }
 
-#[derive(Copy, Clone, Debug, Enum)]
-enum ComplexTermKind {
-  Functor,
-  SchFunc,
-  PrivFunc,
-  Aggregate,
-  Selector,
-  Fraenkel,
-  Choice,
+/// The clause of a universal premise that the unifier came closest to refuting.
+pub struct NearMiss {
+  /// The premise, with its quantified variables opened as free variables
+  pub premise: Formula,
+  /// The atoms of the clause that could be instantiated
+  pub atoms: Vec<Formula>,
+  /// The atom that could not be instantiated, or whose instantiation
+  /// is incompatible with those of the `atoms` that precede it
+  pub blocker: Formula,
+  /// True if the blocker had no instantiation at all
+  pub no_inst: bool,
 }
-use ComplexTermKind as CTK;
 
-impl Term {
-  fn complex_kind(&self) -> Option<ComplexTermKind> {
-    match self {
-      Term::Functor { .. } => Some(CTK::Functor),
-      Term::SchFunc { .. } => Some(CTK::SchFunc),
-      Term::PrivFunc { .. } => Some(CTK::PrivFunc),
-      Term::Aggregate { .. } => Some(CTK::Aggregate),
-      Term::Selector { .. } => Some(CTK::Selector),
-      Term::Fraenkel { .. } => Some(CTK::Fraenkel),
-      Term::The { .. } => Some(CTK::Choice),
-      _ => None,
+/// A sketch of a countermodel for a conjunct that the checker could not refute:
+/// the final equivalence classes, and how close the unifier got with each universal premise.
+pub struct FailureHint {
+  pub conjunct: Vec<Formula>,
+  pub classes: Vec<(EqClassId, Vec<Term>, Attrs, Vec<Type>)>,
+  pub near_misses: Vec<NearMiss>,
+}
+
+impl std::fmt::Display for FailureHint {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "surviving conjunct:")?;
+    for atom in &self.conjunct {
+      write!(f, "\n  {atom:?}")?
+    }
+    write!(f, "\nclasses:")?;
+    for (ec, terms, attrs, tys) in &self.classes {
+      write!(f, "\n  e{ec:?} = {terms:?}: {attrs:?}{tys:?}")?
     }
+    for nm in &self.near_misses {
+      write!(f, "\nnear miss: {:?}", nm.premise)?;
+      for atom in &nm.atoms {
+        write!(f, "\n  instantiated: {atom:?}")?
+      }
+      let why = if nm.no_inst { "no instantiation" } else { "incompatible instantiation" };
+      write!(f, "\n  blocked by: {:?} ({why})", nm.blocker)?
+    }
+    Ok(())
+  }
+}
+
+use ComplexTermKind as CTK;
+
+/// The head of a type as compared by `unify_radix_type`: its mode after adjusting
+/// redefinitions, or its structure
+fn radix_head(g: &Global, ty: &Type) -> TypeKind {
+  match ty.kind {
+    TypeKind::Mode(n) => TypeKind::Mode(Type::adjust(n, &ty.args, &g.constrs).0),
+    kind => kind,
   }
 }
 
 impl<'a> Unifier<'a> {
   /// InitUnifier
-  pub fn new(eq: Equalizer<'a>, bas: &'a EnumMap<bool, Atoms>) -> Self {
+  pub fn new(eq: Equalizer<'a>, bas: &'a EnumMap<Polarity, Atoms>) -> Self {
     let mut u = Self {
       g: eq.g,
       lc: eq.lc,
       infer: Default::default(),
       eq_class: IdxVec::from_default(eq.next_eq_class.into_usize()),
+      order: (0..eq.next_eq_class.into_usize()).map(EqClassId::from_usize).collect(),
       bas,
+      near_misses: eq.g.cfg.failure_hints.then(Vec::new),
+      deadline: eq.g.cfg.unify_budget_ms.map(|ms| Instant::now() + Duration::from_millis(ms)),
+      timed_out: false,
     };
     for etm in eq.terms.0 {
       let ec = &mut u.eq_class[etm.id];
@@ -95,9 +142,8 @@ impl<'a> Unifier<'a> {
             },
           }
         }
-        ec.number = etm.number;
-        ec.ty_class = etm.ty_class;
-        ec.supercluster = etm.supercluster;
+        ec.class = etm.class;
+        ec.heads = ec.ty_class.iter().map(|ty| radix_head(u.g, ty)).sorted().dedup().collect();
       }
     }
     if eq.g.cfg.unify_header && crate::verbose() {
@@ -108,31 +154,69 @@ impl<'a> Unifier<'a> {
         vprintln!("{:?} = e{j:#?}", Term::Infer(*i));
       }
       for (pos, ats) in u.bas.iter() {
-        for f in &ats.0 .0 {
-          vprintln!("{}: {f:?}", if pos { "assume" } else { "goal" });
+        for f in ats.iter() {
+          vprintln!("{}: {f:?}", if pos.is_pos() { "assume" } else { "goal" });
         }
       }
     }
     u
   }
 
+  /// Collects the state of the unifier after it failed to refute `conjunct`.
+  pub fn failure_hint(&mut self, conjunct: Vec<Formula>) -> FailureHint {
+    let mut classes = vec![];
+    for (ec, etm) in self.eq_class.enum_iter() {
+      let infers = self.infer.iter().filter(|p| *p.1 == ec).map(|p| *p.0).sorted();
+      let mut terms = infers.map(Term::Infer).collect_vec();
+      terms.extend(etm.terms.values().flatten().map(|&m| self.lc.marks[m].0.clone()));
+      if !terms.is_empty() {
+        classes.push((ec, terms, etm.supercluster.clone(), etm.ty_class.clone()))
+      }
+    }
+    let near_misses = self.near_misses.take().unwrap_or_default();
+    FailureHint { conjunct, classes, near_misses }
+  }
+
+  /// Standardizes `f` and opens its quantifiers, pushing the types of the new free variables
+  /// onto `fvars`.
+  fn open_premise(&mut self, f: &Formula, fvars: &mut IdxVec<FVarId, Type>) -> Formula {
+    let mut f = f.visit_cloned(&mut Standardize { g: self.g, lc: self.lc });
+    OpenAsFreeVar(fvars).open_quantifiers(&mut f, false);
+    f
+  }
+
   /// Verify: Attempts to prove f |- false
-  fn falsify(&mut self, mut f: Formula) -> Result<OrUnsat<()>, Overflow> {
-    Standardize { g: self.g, lc: self.lc }.visit_formula(&mut f);
+  fn falsify(&mut self, f: Formula) -> Result<OrUnsat<()>, Overflow> {
+    // Suppose f = ∀ xs, F(xs).
+    // First, introduce metavariables ("free vars") to obtain a formula F(?v)
+    let mut fvars = IdxVec::default();
+    let f = self.open_premise(&f, &mut fvars);
+    self.falsify_opened(f, &fvars, &mut Default::default())
+  }
+
+  /// The body of [`Self::falsify`], for a premise that has already been opened
+  /// with the free variables `fvars`.
+  fn falsify_opened(
+    &mut self, f: Formula, fvars: &IdxVec<FVarId, Type>, cache: &mut FVarCache,
+  ) -> Result<OrUnsat<()>, Overflow> {
+    self.check_deadline()?;
     if self.g.cfg.unify_header {
       eprintln!("falsify: {f:?}");
     }
-    let mut fvars = IdxVec::default();
-    // Suppose f = ∀ xs, F(xs).
-    // First, introduce metavariables ("free vars") to obtain a formula F(?v)
-    OpenAsFreeVar(&mut fvars).open_quantifiers(&mut f, false);
+    let premise = self.near_misses.is_some().then(|| f.clone());
+    let mut best: Option<NearMiss> = None;
+    let mut note = |nm: NearMiss| {
+      if best.as_ref().is_none_or(|best| best.atoms.len() < nm.atoms.len()) {
+        best = Some(nm)
+      }
+    };
     if self.g.cfg.unify_header {
       for (i, ty) in fvars.enum_iter() {
         vprintln!("v{i:?}: {ty:?}")
       }
     }
     let bas = self.bas;
-    let mut u = self.unify(&fvars);
+    let mut u = self.unify(fvars, cache);
 
     // want to show: ∃ ?v. |- !F(?v)
     // Normalize !F(?v) into DNF: ∃ ?v. |- C_1(?v) \/ ... \/ C_n(?v)
@@ -140,7 +224,7 @@ impl<'a> Unifier<'a> {
     let mut atoms = Atoms::default();
     let Dnf::Or(clauses) = atoms.normalize(u.g, u.lc, f, false)? else { return Ok(Err(Unsat)) };
     if u.g.cfg.unify_header {
-      vprintln!("atoms: {atoms:#?}");
+      vprintln!("atoms:\n{}", atoms.fmt_with(u.lc));
     }
 
     // For the remainder we prove each clause separately.
@@ -158,47 +242,81 @@ impl<'a> Unifier<'a> {
       // so it suffices to check that P(?v) is not identically false to ensure ∃ ?v. P(?v).
 
       let mut dnfs = vec![];
+      // The atoms with a trivial instantiation, and those of the `dnfs`, for `--failure-hints`
+      let (mut trivial, mut nontrivial) = (vec![], vec![]);
       // C(?v) is a conjunction A_1(?v) /\ ... /\ A_n(?v);
       // for each A_i(?v) we will construct P_i(?v) and AND them together
       for (a, val) in clause.0 {
+        let lit = || atoms[a].clone().maybe_neg(val);
         // Negate the conclusion to !A_i(?v) |- false to match the usual polarity,
         // and get an instantiation P_i(?v) such that P_i(?v), !A_i(?v) |- false.
-        match u.compute_inst(bas, &atoms.0[a], !val)? {
+        match u.compute_inst(bas, &atoms[a], (!val).into())? {
           // A_i(?v) is true without our help
-          Dnf::True => {}
+          Dnf::True =>
+            if premise.is_some() {
+              trivial.push(lit())
+            },
           // We failed to construct an instantiation,
           // the strongest P_i(?v) we could come up with is 'false'
-          Dnf::Or(dnf) if dnf.is_empty() => continue 'next,
+          Dnf::Or(dnf) if dnf.is_empty() => {
+            if let Some(premise) = &premise {
+              trivial.append(&mut nontrivial);
+              let (premise, blocker) = (premise.clone(), lit());
+              note(NearMiss { premise, atoms: trivial, blocker, no_inst: true })
+            }
+            continue 'next
+          }
           // Otherwise we push P_i(?v) on insts (we delay the join operation
           // in case we can get one of the other two cases on some atoms)
-          Dnf::Or(dnf) => dnfs.push(dnf),
+          Dnf::Or(dnf) => {
+            if premise.is_some() {
+              nontrivial.push(lit())
+            }
+            dnfs.push(dnf)
+          }
         }
       }
+      let copy = premise.is_some().then(|| dnfs.clone());
       // Unless /\_i P_i(?v) is the empty disjunction (false), it is satisfiable and we are done
       if !Dnf::and_many(dnfs)?.is_false() {
         return Ok(Err(Unsat))
       }
+      if let (Some(premise), Some(dnfs)) = (&premise, copy) {
+        // Find the first atom whose instantiation is incompatible with the previous ones
+        let mut acc = Dnf::True;
+        for (dnf, blocker) in dnfs.into_iter().zip(nontrivial) {
+          acc.mk_and(Dnf::Or(dnf))?;
+          if acc.is_false() {
+            let premise = premise.clone();
+            note(NearMiss { premise, atoms: trivial, blocker, no_inst: false });
+            break
+          }
+          trivial.push(blocker)
+        }
+      }
     }
     // falsification failed
+    if let (Some(nms), Some(best)) = (&mut self.near_misses, best) {
+      nms.push(best)
+    }
     Ok(Ok(()))
   }
 
-  /// Unifiable: Attempts to prove f_1, ..., f_n |- false
-  fn resolution(&mut self, fs: &[&Formula]) -> Result<OrUnsat<()>, Overflow> {
+  /// Unifiable: Attempts to prove f_1, ..., f_n |- false,
+  /// where the f_i have already been opened with the free variables `fvars`.
+  fn resolution(
+    &mut self, fs: &[&Formula], fvars: &IdxVec<FVarId, Type>, cache: &mut FVarCache,
+  ) -> Result<OrUnsat<()>, Overflow> {
+    self.check_deadline()?;
     // CreateClauses
     let mut all_clauses = Dnf::FALSE;
     let mut atoms = Atoms::default();
-    let mut fvars = IdxVec::default();
     // vprintln!("resolution: {fs:#?}");
     // We want to show |- !f_1 \/ ... \/ !f_n
     // Suppose f_i = ∀ xs, F_i(xs). Then !F_i(?v_i) implies !f_i,
     // so it suffices to show ∃ ?v_1 ... ?v_n. |- !F_1(?v_1) \/ ... \/ !F_n(?v_n)
-    for f in fs {
-      all_clauses.mk_or_else(|| {
-        let mut f = f.visit_cloned(&mut Standardize { g: self.g, lc: self.lc });
-        OpenAsFreeVar(&mut fvars).open_quantifiers(&mut f, false);
-        atoms.normalize(self.g, self.lc, f, false)
-      })?;
+    for &f in fs {
+      all_clauses.mk_or_else(|| atoms.normalize(self.g, self.lc, f.clone(), false))?;
     }
     // We normalized !F_1(?v_1) \/ ... \/ !F_n(?v_n) into DNF, as ∃ ?v. |- \/_i C_i(?v)
     // vprintln!("all_clauses = {all_clauses:#?}");
@@ -210,10 +328,10 @@ impl<'a> Unifier<'a> {
     }
 
     // CollectComplementaryLiterals
-    // vprintln!("atoms: {:#?}", atoms.0);
+    // vprintln!("atoms:\n{}", atoms.fmt_with(u.0.lc));
     let mut complementary = vec![];
     let bas = self.bas;
-    let mut u = UnifyWithConst(self.unify(&fvars));
+    let mut u = UnifyWithConst(self.unify(fvars, cache));
     // Each C_i is of the form /\_j A_ij, so we will look for "resolvents":
     // Suppose C and D are clauses such that C = C' /\ a and D = D' /\ !a;
     // then C \/ D = (C' /\ a) \/ (D' /\ !a) = if a { C' } else { D' }
@@ -225,8 +343,8 @@ impl<'a> Unifier<'a> {
     for (cl1, cl2) in all_clauses.iter().tuple_combinations() {
       for (&a1, &val1) in &cl1.0 {
         for (&a2, &val2) in &cl2.0 {
-          if val1 != val2 && Similar.eq(u.0.g, u.0.lc, &atoms.0[a1], &atoms.0[a2]) {
-            if let Dnf::Or(dnf) = u.unify_basic_formula(&atoms.0[a1], &atoms.0[a2])? {
+          if val1 != val2 && Similar.eq(u.0.g, u.0.lc, &atoms[a1], &atoms[a2]) {
+            if let Dnf::Or(dnf) = u.unify_basic_formula(&atoms[a1], &atoms[a2])? {
               if !dnf.is_empty() {
                 // vprintln!("found resolvable clauses {cl1:?} <a{a1:?}!=a{a2:?}> {cl2:?} = {dnf:#?}");
                 complementary.push(([(cl1, a1), (cl2, a2)], dnf));
@@ -254,7 +372,7 @@ impl<'a> Unifier<'a> {
       for (cl, a1) in cls {
         for (&a2, &val) in &cl.0 {
           if a2 != a1 {
-            match u.0.compute_inst(bas, &atoms.0[a2], !val)? {
+            match u.0.compute_inst(bas, &atoms[a2], (!val).into())? {
               Dnf::True => {}
               Dnf::Or(dnf) if dnf.is_empty() => continue 'next,
               Dnf::Or(dnf) => dnfs.push(dnf),
@@ -270,17 +388,43 @@ impl<'a> Unifier<'a> {
     Ok(Ok(()))
   }
 
+  /// Gives up on a subproblem, as though it overflowed, once the budget is used up.
+  fn check_deadline(&mut self) -> Result<(), Overflow> {
+    match self.deadline {
+      Some(deadline) if Instant::now() > deadline => {
+        self.timed_out = true;
+        Err(Overflow)
+      }
+      _ => Ok(()),
+    }
+  }
+
+  /// Permutes the order in which the equivalence classes are considered.
+  pub fn shuffle_classes(&mut self, sh: &mut Shuffle) { sh.shuffle(&mut self.order) }
+
   /// Unification
   pub fn run(&mut self) -> OrUnsat<()> {
-    let univ =
-      self.bas[true].0 .0.iter().filter(|f| matches!(f, Formula::ForAll { .. })).collect_vec();
+    let univ = (self.bas[Polarity::Pos].iter())
+      .filter(|f| matches!(f, Formula::ForAll { .. }))
+      .collect_vec();
+    // Each premise is opened once, with free variables numbered apart from those of the
+    // other premises, so that falsify and resolution can share the opened forms
+    // and the instantiations of each free variable to each class
+    let mut fvars = IdxVec::default();
+    let univ = univ.into_iter().map(|f| self.open_premise(f, &mut fvars)).collect_vec();
+    let mut cache = FVarCache::default();
+    // with --unify-shared-cache=false, every subproblem starts from an empty cache
+    let share = self.g.cfg.unify_shared_cache;
     let mut overflow = false;
     let mut set_overflow = |Overflow| {
       overflow = true;
       Ok(())
     };
-    for &f in &univ {
-      match self.falsify(f.clone()) {
+    for f in &univ {
+      if !share {
+        cache.clear()
+      }
+      match self.falsify_opened(f.clone(), &fvars, &mut cache) {
         Ok(or_unsat) => or_unsat?,
         Err(o) => {
           set_overflow(o)?;
@@ -290,26 +434,33 @@ impl<'a> Unifier<'a> {
     }
     if ENABLE_UNIFIER {
       for f in &univ {
-        self.resolution(&[f]).unwrap_or_else(&mut set_overflow)?;
+        if !share {
+          cache.clear()
+        }
+        self.resolution(&[f], &fvars, &mut cache).unwrap_or_else(&mut set_overflow)?;
       }
       for (f1, f2) in univ.iter().tuple_combinations() {
-        self.resolution(&[f1, f2]).unwrap_or_else(&mut set_overflow)?;
+        if !share {
+          cache.clear()
+        }
+        self.resolution(&[f1, f2], &fvars, &mut cache).unwrap_or_else(&mut set_overflow)?;
       }
     }
 
     let mut fraenkel_fmlas = vec![];
     for (pos, bas) in self.bas.iter() {
-      for f in &bas.0 .0 {
+      for f in bas.iter() {
         if let Formula::Pred { nr, args } = f {
           let (nr, args) = Formula::adjust_pred(*nr, args, Some(&self.g.constrs));
-          if self.g.reqs.belongs_to() == Some(nr) {
+          if self.g.checker_reqs().belongs_to() == Some(nr) {
             if let Some(ec) = args[1].unmark(self.lc).class() {
               for &m in &self.eq_class[ec].terms[CTK::Fraenkel] {
                 if let Term::Fraenkel { args: tys, scope, compr } = &self.lc.marks[m].0 {
                   let (tys, scope, compr) = (tys.clone(), (**scope).clone(), (**compr).clone());
-                  let mut fm = args[0].clone().not_in_fraenkel(tys, scope, compr, &self.g.reqs);
+                  let fm = args[0].clone().not_in_fraenkel(tys, scope, compr, &self.g.reqs);
+                  let Some(mut fm) = fm else { continue };
                   fm.distribute_quantifiers(&self.g.constrs, self.lc, 0);
-                  fraenkel_fmlas.push(fm.maybe_neg(!pos))
+                  fraenkel_fmlas.push(fm.maybe_neg(!pos.is_pos()))
                 }
               }
             }
@@ -338,17 +489,18 @@ impl Open for OpenAsFreeVar<'_> {
 impl Term {
   /// Given a fraenkel term `{ F(xs) where xs : P(xs) }` and a main term `self`,
   /// constructs the formula equivalent to `¬(self ∈ { F(xs) where xs : P(xs) })`,
-  /// that is: `¬ ∃ xs, self = F(xs) /\ P(xs)`
+  /// that is: `¬ ∃ xs, self = F(xs) /\ P(xs)`,
+  /// or `None` if the environment has no equality.
   fn not_in_fraenkel(
     self, args: Box<[(IdentId, Type)]>, scope: Term, compr: Formula, reqs: &RequirementIndexes,
-  ) -> Formula {
-    let mut conjs = vec![reqs.mk_eq(self, scope)];
+  ) -> Option<Formula> {
+    let mut conjs = vec![reqs.try_mk_eq(self, scope)?];
     compr.append_conjuncts_to(&mut conjs);
     let mut f = Formula::Neg { f: Box::new(Formula::And { args: conjs }) };
     for (id, ty) in args.into_vec().into_iter().rev() {
       f = Formula::forall(id, ty, f)
     }
-    f
+    Some(f)
   }
 }
 
@@ -431,13 +583,19 @@ impl Term {
   }
 }
 
+/// The instantiations found for each free variable against each equivalence class.
+/// These only depend on the type of the free variable, so they can be shared by all
+/// subproblems using the same free variables.
+type FVarCache = BTreeMap<(FVarId, EqClassId), Dnf<FVarId, EqClassId>>;
+
 struct Unify<'a> {
   g: &'a Global,
   lc: &'a LocalContext,
   infer: &'a HashMap<InferId, EqClassId>,
   eq_class: &'a IdxVec<EqClassId, EqTerm>,
+  order: &'a [EqClassId],
   fvars: &'a IdxVec<FVarId, Type>,
-  cache: BTreeMap<(FVarId, EqClassId), Dnf<FVarId, EqClassId>>,
+  cache: &'a mut FVarCache,
   base: u32,
   depth: u32,
 }
@@ -447,29 +605,39 @@ impl WithGlobalLocal for Unify<'_> {
 }
 
 impl Unifier<'_> {
-  fn unify<'a>(&'a mut self, fvars: &'a IdxVec<FVarId, Type>) -> Unify<'a> {
+  fn unify<'a>(
+    &'a mut self, fvars: &'a IdxVec<FVarId, Type>, cache: &'a mut FVarCache,
+  ) -> Unify<'a> {
     Unify {
       g: self.g,
       lc: self.lc,
       infer: &self.infer,
       eq_class: &self.eq_class,
+      order: &self.order,
       fvars,
-      cache: Default::default(),
+      cache,
       base: 0,
       depth: 0,
     }
   }
 }
 
-impl Unify<'_> {
+impl<'a> Unify<'a> {
+  /// The equivalence classes, in the order given by `--shuffle-seed`.
+  fn classes(&self) -> impl Iterator<Item = (EqClassId, &'a EqTerm)> + 'a {
+    let eq_class = self.eq_class;
+    self.order.iter().map(move |&ec| (ec, &eq_class[ec]))
+  }
+
   /// Constructs an instantiation P(?v) such that
   /// * pos = true: COMPInstAsTrue - P(?v) /\ F(?v) |- false
   /// * pos = false: COMPInstAsFalse - P(?v) /\ !F(?v) |- false
   fn compute_inst(
-    &mut self, bas: &EnumMap<bool, Atoms>, f: &Formula, pos: bool,
+    &mut self, bas: &EnumMap<Polarity, Atoms>, f: &Formula, pos: Polarity,
   ) -> Result<Dnf<FVarId, EqClassId>, Overflow> {
+    let reqs = self.g.checker_reqs();
     if self.g.cfg.unify_insts {
-      vprintln!("compute_inst {pos}: {f:?}");
+      vprintln!("compute_inst {pos:?}: {f:?}");
     }
     let mut inst = Dnf::FALSE;
     let mut skip = false;
@@ -478,8 +646,12 @@ impl Unify<'_> {
       Formula::True | Formula::Neg { .. } | Formula::And { .. } => unreachable!(),
       Formula::Pred { nr, ref args } => {
         let props = self.g.constrs.predicate[nr].properties;
-        if props.get(if pos { PropertyKind::Irreflexivity } else { PropertyKind::Reflexivity }) {
-          for (ec, _) in self.eq_class.enum_iter() {
+        let prop = match pos {
+          Polarity::Pos => PropertyKind::Irreflexivity,
+          Polarity::Neg => PropertyKind::Reflexivity,
+        };
+        if props.get(prop) {
+          for (ec, _) in self.classes() {
             let t = Term::EqClass(ec);
             let mut inst1 = self.unify_term(&args[props.arg1 as usize], &t)?;
             if !inst1.is_false() {
@@ -489,20 +661,20 @@ impl Unify<'_> {
           }
         }
         let (nr, args) = Formula::adjust_pred(nr, args, Some(&self.g.constrs));
-        if self.g.reqs.belongs_to() == Some(nr) {
+        if reqs.belongs_to() == Some(nr) {
           let [arg1, arg2] = args else { unreachable!() };
-          if let Some(empty) = self.g.reqs.empty() {
-            for (ec, etm) in self.eq_class.enum_iter() {
-              if etm.supercluster.find0(&self.g.constrs, empty, pos) {
+          if let Some(empty) = reqs.empty() {
+            for (ec, etm) in self.classes() {
+              if etm.supercluster.find0(&self.g.constrs, empty, pos.is_pos()) {
                 let mut inst1 = self.unify_term(arg2, &Term::EqClass(ec))?;
                 if !inst1.is_false() {
-                  if pos {
+                  if pos.is_pos() {
                     // x in A, A is empty |- false
                     inst.mk_or(inst1)?;
-                  } else if let Some(element) = self.g.reqs.element() {
+                  } else if let Some(element) = reqs.element() {
                     let ty = Type { args: vec![Term::EqClass(ec)], ..Type::new(element.into()) };
                     let mut inst2 = Dnf::FALSE;
-                    for (ec2, etm2) in self.eq_class.enum_iter() {
+                    for (ec2, etm2) in self.classes() {
                       if etm2.ty_class.iter().any(|ty2| self.eq_radices(ty2, &ty)) {
                         // !(x in A), A is not empty, x is Element of A |- false
                         inst2.mk_or_else(|| self.unify_term(arg1, &Term::EqClass(ec2)))?;
@@ -515,12 +687,12 @@ impl Unify<'_> {
               }
             }
           }
-          if pos {
-            for f in &bas[false].0 .0 {
+          if pos.is_pos() {
+            for f in bas[Polarity::Neg].iter() {
               if let Formula::Is { term, ty } = f {
                 if let TypeKind::Mode(n) = ty.kind {
                   let (n, args) = Type::adjust(n, &ty.args, &self.g.constrs);
-                  if self.g.reqs.element() == Some(n) {
+                  if reqs.element() == Some(n) {
                     let mut inst1 = self.unify_term(arg1, term)?;
                     if !inst1.is_false() {
                       inst1.mk_and(self.unify_term(arg2, &args[0])?)?;
@@ -531,15 +703,15 @@ impl Unify<'_> {
               }
             }
           }
-        } else if self.g.reqs.inclusion() == Some(nr) {
-          if let Some(power) = self.g.reqs.power_set() {
+        } else if reqs.inclusion() == Some(nr) {
+          if let Some(power) = reqs.power_set() {
             let [arg1, arg2] = args else { unreachable!() };
-            if pos {
-              for f in &bas[false].0 .0 {
+            if pos.is_pos() {
+              for f in bas[Polarity::Neg].iter() {
                 if let Formula::Is { term, ty } = f {
                   if let TypeKind::Mode(n) = ty.kind {
                     let (n, args) = Type::adjust(n, &ty.args, &self.g.constrs);
-                    if self.g.reqs.element() == Some(n) {
+                    if reqs.element() == Some(n) {
                       let mut inst1 = self.unify_term(arg1, term)?;
                       if !inst1.is_false() {
                         if let Term::EqClass(ec) = *arg2 {
@@ -561,8 +733,8 @@ impl Unify<'_> {
                   }
                 }
               }
-            } else if let Some(element) = self.g.reqs.element() {
-              for ec1 in (0..self.eq_class.len()).map(EqClassId::from_usize) {
+            } else if let Some(element) = reqs.element() {
+              for ec1 in self.classes().map(|(ec, _)| ec) {
                 let mut inst1 = self.unify_term(arg2, &Term::EqClass(ec1))?;
                 if !inst1.is_false() {
                   let mut inst2 = Dnf::FALSE;
@@ -571,7 +743,7 @@ impl Unify<'_> {
                     args: Box::new([Term::EqClass(ec1)]),
                   }) {
                     let ty = Type { args: vec![Term::EqClass(ec2)], ..Type::new(element.into()) };
-                    for (ec2, etm2) in self.eq_class.enum_iter() {
+                    for (ec2, etm2) in self.classes() {
                       if etm2.ty_class.iter().any(|ty2| self.eq_radices(ty2, &ty)) {
                         inst2.mk_or_else(|| self.unify_term(arg1, &Term::EqClass(ec2)))?;
                       }
@@ -584,24 +756,26 @@ impl Unify<'_> {
             }
           }
           // FIXME: the original control flow seems very haphazard/inconsistent here
-          if !pos {
+          if pos == Polarity::Neg {
             skip = true
           }
-        } else if self.g.reqs.less_or_equal() == Some(nr) {
+        } else if reqs.less_or_equal() == Some(nr) {
           let [arg1, arg2] = args else { unreachable!() };
-          if !pos {
-            for f2 in &bas[true].0 .0 {
+          if pos == Polarity::Neg {
+            for f2 in bas[Polarity::Pos].iter() {
               inst.mk_or_else(|| self.unify_formula(f, f2))?;
             }
           }
-          for (ec1, etm1) in self.eq_class.enum_iter() {
+          for (ec1, etm1) in self.classes() {
             if let Some(n1) = &etm1.number {
               let mut inst1 = self.unify_term(arg1, &Term::EqClass(ec1))?;
               if !inst1.is_false() {
                 let mut inst2 = Dnf::FALSE;
-                for (ec2, etm2) in self.eq_class.enum_iter() {
+                for (ec2, etm2) in self.classes() {
                   if let Some(n2) = &etm2.number {
-                    if n1.im == Rational::ZERO && n2.im == Rational::ZERO && (n1.re <= n2.re) != pos
+                    if n1.im == Rational::ZERO
+                      && n2.im == Rational::ZERO
+                      && (n1.re <= n2.re) != pos.is_pos()
                     {
                       inst2.mk_or(self.unify_term(arg2, &Term::EqClass(ec2))?)?;
                     }
@@ -612,16 +786,16 @@ impl Unify<'_> {
               }
             }
           }
-          if let (Some(positive), Some(negative)) = (self.g.reqs.positive(), self.g.reqs.negative())
+          if let (Some(positive), Some(negative)) = (reqs.positive(), reqs.negative())
           {
-            for (ec1, etm1) in self.eq_class.enum_iter() {
+            for (ec1, etm1) in self.classes() {
               let mut inst1 = self.unify_term(arg1, &Term::EqClass(ec1))?;
               if !inst1.is_false() {
                 let mut inst2 = Dnf::FALSE;
-                if pos {
+                if pos.is_pos() {
                   let pos1 = etm1.supercluster.find0(&self.g.constrs, positive, true);
                   let nonneg1 = etm1.supercluster.find0(&self.g.constrs, negative, false);
-                  for (ec2, etm2) in self.eq_class.enum_iter() {
+                  for (ec2, etm2) in self.classes() {
                     if pos1 && etm2.supercluster.find0(&self.g.constrs, positive, false)
                       || nonneg1 && etm2.supercluster.find0(&self.g.constrs, negative, true)
                     {
@@ -630,7 +804,7 @@ impl Unify<'_> {
                   }
                 } else {
                   let nonpos1 = etm1.supercluster.find0(&self.g.constrs, positive, false);
-                  for (ec2, etm2) in self.eq_class.enum_iter() {
+                  for (ec2, etm2) in self.classes() {
                     if nonpos1 && etm2.supercluster.find0(&self.g.constrs, negative, false) {
                       inst2.mk_or_else(|| self.unify_term(arg2, &Term::EqClass(ec2)))?;
                     }
@@ -641,18 +815,18 @@ impl Unify<'_> {
               }
             }
           }
-          if !pos {
+          if pos == Polarity::Neg {
             skip = true
           }
-        } else if self.g.reqs.equals_to() == Some(nr) {
-          if pos {
+        } else if reqs.equals_to() == Some(nr) {
+          if pos.is_pos() {
             let [arg1, arg2] = args else { unreachable!() };
-            for (ec1, etm1) in self.eq_class.enum_iter() {
+            for (ec1, etm1) in self.classes() {
               if let Some(n1) = &etm1.number {
                 let mut inst1 = self.unify_term(arg1, &Term::EqClass(ec1))?;
                 if !inst1.is_false() {
                   let mut inst2 = Dnf::FALSE;
-                  for (ec2, etm2) in self.eq_class.enum_iter() {
+                  for (ec2, etm2) in self.classes() {
                     if ec1 != ec2 {
                       if let Some(n2) = &etm2.number {
                         assert!(n1 != n2);
@@ -675,7 +849,7 @@ impl Unify<'_> {
         let (arg0, args) = args.split_last().unwrap();
         if let Some(ec) = arg0.unmark(self.lc).class() {
           for attr in self.eq_class[ec].supercluster.attrs() {
-            if attr.nr == nr && attr.pos != pos {
+            if attr.nr == nr && attr.pos != pos.is_pos() {
               inst.mk_or_else(|| self.unify_terms(args, &attr.args))?;
             }
           }
@@ -683,8 +857,10 @@ impl Unify<'_> {
         skip = true
       }
       Formula::Is { ref term, ref ty } => {
-        if pos {
-          for f2 in &bas[!pos].0 .0 {
+        if pos.is_pos() {
+          // The widenings of `ty`, computed as needed and shared by all the basis formulas
+          let mut chain = vec![CowBox::Borrowed(&**ty)];
+          for f2 in bas[pos.flip()].iter() {
             if let Formula::Is { term: term2, ty: ty2 } = f2 {
               let mut inst1 = self.unify_term(term, term2)?;
               if !inst1.is_false() {
@@ -694,13 +870,16 @@ impl Unify<'_> {
                     if let TypeKind::Mode(_) = ty.kind {
                       let n2 = Type::adjust(n2, &ty2.args, &self.g.constrs).0;
                       assert!(n2 != ModeId::ANY);
-                      let mut pty = CowBox::Borrowed(&**ty);
-                      while let TypeKind::Mode(pn) = pty.kind {
+                      for i in 0.. {
+                        if i == chain.len() {
+                          let next = chain[i - 1].widening(self.g, self.lc).unwrap();
+                          chain.push(CowBox::Owned(next))
+                        }
+                        let TypeKind::Mode(pn) = chain[i].kind else { break };
                         if pn < n2 {
                           break
                         }
-                        inst2.mk_or_else(|| self.unify_radix_type(&pty, ty2))?;
-                        pty = CowBox::Owned(pty.widening(self.g, self.lc).unwrap());
+                        inst2.mk_or_else(|| self.unify_radix_type(&chain[i], ty2))?;
                       }
                     },
                   TypeKind::Struct(_) =>
@@ -713,7 +892,7 @@ impl Unify<'_> {
               }
             }
           }
-          for (ec, etm) in self.eq_class.enum_iter() {
+          for (ec, etm) in self.classes() {
             let mut inst1 = self.unify_term(term, &Term::EqClass(ec))?;
             if !inst1.is_false() {
               let mut inst2 = Dnf::FALSE;
@@ -727,20 +906,21 @@ impl Unify<'_> {
             }
           }
         } else {
-          for (ec, etm) in self.eq_class.enum_iter() {
+          for (ec, etm) in self.classes() {
             let mut inst1 = self.unify_term(term, &Term::EqClass(ec))?;
             if !inst1.is_false() {
               inst1.mk_and(self.unify_eq_class_types(etm, ty)?)?;
               inst.mk_or(inst1)?;
             }
           }
+          self.or_inst_element_via_inclusion(bas, term, ty, &mut inst)?;
         }
         skip = true
       }
       _ => {}
     }
     if !skip {
-      for f2 in &bas[!pos].0 .0 {
+      for f2 in bas[pos.flip()].iter() {
         inst.mk_or_else(|| self.unify_formula(f, f2))?;
       }
     }
@@ -750,6 +930,47 @@ impl Unify<'_> {
     Ok(inst)
   }
 
+  /// For a goal `t is Element of Y` without attributes, instantiates `t` to the classes
+  /// typed `Element of X` for each positive `X c= Y'` with `Y'` unifiable with `Y`:
+  /// `t: Element of X, X is non empty, X c= Y |- t is Element of Y`.
+  /// (When `X` is empty, `Element of X` only says that `t` is empty.)
+  fn or_inst_element_via_inclusion(
+    &mut self, bas: &EnumMap<Polarity, Atoms>, term: &Term, ty: &Type,
+    inst: &mut Dnf<FVarId, EqClassId>,
+  ) -> Result<(), Overflow> {
+    let reqs = self.g.checker_reqs();
+    let (Some(element), Some(empty)) = (reqs.element(), reqs.empty()) else { return Ok(()) };
+    let TypeKind::Mode(n) = ty.kind else { return Ok(()) };
+    let (n, args) = Type::adjust(n, &ty.args, &self.g.constrs);
+    if n != element || !ty.attrs.0.attrs().is_empty() {
+      return Ok(())
+    }
+    let [y] = args else { unreachable!() };
+    for f in bas[Polarity::Pos].preds(reqs.inclusion()) {
+      let Formula::Pred { nr, args } = f else { unreachable!() };
+      let [x, y2] = Formula::adjust_pred(*nr, args, Some(&self.g.constrs)).1 else {
+        unreachable!()
+      };
+      let Some(ec) = x.unmark(self.lc).class() else { continue };
+      if !self.eq_class[ec].supercluster.find0(&self.g.constrs, empty, false) {
+        continue
+      }
+      let mut inst1 = self.unify_term(y, y2)?;
+      if !inst1.is_false() {
+        let ty = Type { args: vec![Term::EqClass(ec)], ..Type::new(element.into()) };
+        let mut inst2 = Dnf::FALSE;
+        for (ec2, etm2) in self.classes() {
+          if etm2.ty_class.iter().any(|ty2| self.eq_radices(ty2, &ty)) {
+            inst2.mk_or_else(|| self.unify_term(term, &Term::EqClass(ec2)))?;
+          }
+        }
+        inst1.mk_and(inst2)?;
+        inst.mk_or(inst1)?
+      }
+    }
+    Ok(())
+  }
+
   fn get_eq_class(&self, tm: &Term) -> Option<EqClassId> {
     self.equate_class().get(self.g, self.lc, tm)
   }
@@ -759,6 +980,14 @@ impl Unify<'_> {
     &mut self, ec: &EqTerm, ty: &Type,
   ) -> Result<Dnf<FVarId, EqClassId>, Overflow> {
     // vprintln!("unify_eq_class_types {ec:?} as {ty:?}");
+    if self.g.cfg.unify_head_pruning {
+      // no type of the class can unify with ty in unify_radix_type
+      if ec.heads.binary_search(&radix_head(self.g, ty)).is_err() {
+        stat("eq class types pruned", false);
+        return Ok(Dnf::FALSE)
+      }
+      stat("eq class types not pruned", false)
+    }
     let mut inst = Dnf::FALSE;
     for ty2 in &ec.ty_class {
       inst.mk_or_else(|| self.unify_radix_type(ty, ty2))?
@@ -907,6 +1136,7 @@ impl Unify<'_> {
       Term::FreeVar(n) =>
         if let Some(ec) = self.get_eq_class(t2) {
           if let Some(inst) = self.cache.get(&(n, ec)) {
+            stat("unify fvar cache hit", false);
             inst.clone()
           } else {
             let mut inst = self.unify_eq_class_types(&self.eq_class[ec], &self.fvars[n].clone())?;
@@ -962,8 +1192,12 @@ impl Unify<'_> {
         inst
       }
       Term::EqClass(n) => Dnf::mk_bool(self.get_eq_class(t2) == Some(n)),
-      Term::Numeral(_) | Term::Const(_) | Term::Infer(_) =>
-        Dnf::mk_bool(Some(self.get_eq_class(t1).unwrap()) == self.get_eq_class(t2)),
+      // A constant with no class (for example, an inference constant that only occurs
+      // under a binder) is not equal to anything in the equalizer
+      Term::Numeral(_) | Term::Const(_) | Term::Infer(_) => match self.get_eq_class(t1) {
+        Some(ec) => Dnf::mk_bool(Some(ec) == self.get_eq_class(t2)),
+        None => Dnf::FALSE,
+      },
       Term::EqMark(m) => self.unify_term(&self.lc.marks[m].0, t2)?,
       Term::Locus(_) | Term::Qua { .. } | Term::It => unreachable!(),
     };
@@ -1100,7 +1334,7 @@ impl UnifyWithConst<'_> {
   /// InstCollection.UnifyTrmsWithConsts
   fn unify_term(&mut self, t1: &Term, t2: &Term) -> Result<Dnf<FVarId, EqClassId>, Overflow> {
     let mut inst = Dnf::FALSE;
-    for (ec, _) in self.0.eq_class.enum_iter() {
+    for (ec, _) in self.0.classes() {
       let t = Term::EqClass(ec);
       let mut inst1 = self.0.unify_term(t1, &t)?;
       if !inst1.is_false() {
@@ -1258,11 +1492,19 @@ impl EquateClass<'_> {
     }
     match *tm {
       Term::EqClass(ec) => Some(ec),
-      Term::Numeral(n) => {
+      Term::Numeral(ref n) => {
         let c = Some(n.into());
         self.eq_class.enum_iter().find(|p| p.1.number == c).map(|p| p.0)
       }
-      Term::Infer(n) => self.infer.get(&n).copied(),
+      Term::Infer(n) => self.infer.get(&n).copied().or_else(|| {
+        // not y'd directly, so look for it through its equal constants and definition
+        let (eq_const, def) = {
+          let asgn = &lc.infer_const.borrow()[n];
+          (asgn.eq_const.clone(), asgn.def.clone())
+        };
+        (eq_const.into_iter().find_map(|z| self.infer.get(&z).copied()))
+          .or_else(|| self.get(g, lc, &def))
+      }),
       Term::Functor { nr, ref args } => {
         let ecs = args.iter().map(|t| self.get(g, lc, t)).collect::<Option<Vec<_>>>()?;
         for (ec, etm) in self.eq_class.enum_iter() {
@@ -97,3 +97,29 @@ impl<T> RetainMutFrom<T> for Vec<T> {
     drop(g);
   }
 }
+
+/// A small deterministic random number generator (SplitMix64), for `--shuffle-seed`.
+/// The same seed always gives the same permutations, on every platform.
+pub struct Shuffle(u64);
+
+impl Shuffle {
+  pub fn new(seed: u64) -> Self { Self(seed) }
+
+  fn next(&mut self) -> u64 {
+    self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = self.0;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+  }
+
+  /// A number in `0..n`, for `n > 0`.
+  pub fn below(&mut self, n: u64) -> u64 { self.next() % n }
+
+  /// Permutes `v` uniformly at random (Fisher-Yates).
+  pub fn shuffle<T>(&mut self, v: &mut [T]) {
+    for i in (1..v.len()).rev() {
+      v.swap(i, (self.next() % (i as u64 + 1)) as usize)
+    }
+  }
+}
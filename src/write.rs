@@ -1,15 +1,9 @@
-use crate::accom::SigBuilder;
 use crate::ast::CaseKind;
-use crate::reader::DefiniensId;
 use crate::types::{self, *};
 use crate::{Global, LocalContext, MizPath};
 use enum_map::{Enum, EnumMap};
 use quick_xml::events::attributes::Attribute;
 use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, Event};
-use serde::ser::Serializer;
-use serde::Serialize;
-use serde_json::ser::{CompactFormatter as JsonFormatter, Formatter};
-use serde_json::Serializer as JsonSerializer;
 use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
@@ -27,12 +21,17 @@ struct MizWriter {
   shift: Vec<u32>,
 }
 
+impl MizWriter {
+  fn new(file: File) -> Self {
+    let mut w = quick_xml::Writer::new_with_indent(BufWriter::new(file), b' ', INDENT);
+    w.write_event(Event::Decl(BytesDecl::new("1.0", None, None))).unwrap();
+    MizWriter { w, pending: None, depth: 0, lift: 0, two_clusters: false, shift: vec![] }
+  }
+}
+
 impl MizPath {
   fn create_xml(&self, mml: bool, new_prel: bool, ext: &str) -> io::Result<MizWriter> {
-    let w = BufWriter::new(self.create(mml, new_prel, ext)?);
-    let mut w = quick_xml::Writer::new_with_indent(w, b' ', INDENT);
-    w.write_event(Event::Decl(BytesDecl::new("1.0", None, None))).unwrap();
-    Ok(MizWriter { w, pending: None, depth: 0, lift: 0, two_clusters: false, shift: vec![] })
+    Ok(MizWriter::new(self.create(mml, new_prel, ext)?))
   }
 
   pub fn write_dfr(&self, new_prel: bool, vocs: &Vocabularies, formats: &[Format]) {
@@ -291,28 +290,23 @@ impl MizPath {
     let mut w = self.create_xml(true, false, "atr").unwrap();
     w.xsl();
     w.with0("Constructors", |w| {
-      #[derive(Clone, Copy)]
-      struct S<'a>(&'a SigBuilder);
-      impl ForeachConstructor for S<'_> {
-        fn foreach<I: Idx, T>(
-          self, arr: &IdxVec<I, T>, base: impl Fn(&ConstructorsBase) -> u32,
-          mut body: impl FnMut(&[u8], u32, u32, &T),
-        ) {
-          for (i, &(art, ref lo)) in self.0.sig.enum_iter() {
-            let art = art.as_str().to_ascii_uppercase();
-            let art = art.as_bytes();
-            let lo = base(lo);
-            for j in lo..base(self.0.hi(i)) {
-              body(art, j - lo, j, &arr.0[j as usize])
-            }
-          }
-        }
-      }
-      w.write_constructors(constrs, S(sig))
+      w.write_constructors(constrs, SigConstructors(sig))
     });
     w.finish()
   }
 
+  /// Writes the `.sgl` file, the list of articles in the constructor signature. Mizar
+  /// follows this with constructor data, but never reads it back, so we leave it out.
+  pub fn write_sgl(&self, sig: &SigBuilder) {
+    write_sgl_to(self.create(true, false, "sgl").unwrap(), sig)
+  }
+
+  /// Writes the `.aco` file, the accumulated constructors of the environment together with
+  /// the (cumulative) constructor counts after each article of the signature.
+  pub fn write_aco(&self, sig: &SigBuilder, constrs: &Constructors) {
+    write_aco_to(self.create(true, false, "aco").unwrap(), sig, constrs)
+  }
+
   pub fn write_dfs(&self, def: &[Definiens]) {
     if def.is_empty() {
       match std::fs::remove_file(self.to_path(true, false, "dfs")) {
@@ -331,6 +325,41 @@ impl MizPath {
   pub fn write_esh(self) -> WriteEsh { WriteEsh(MizWriterPart::Empty(self)) }
 }
 
+/// The body of [`MizPath::write_sgl`], writing to an already created file.
+pub(crate) fn write_sgl_to(file: File, sig: &SigBuilder) {
+  let mut w = BufWriter::new(file);
+  writeln!(w, "{}", sig.sig.len()).unwrap();
+  for (art, _) in &sig.sig.0 {
+    writeln!(w, "{}", art.as_str().to_ascii_uppercase()).unwrap();
+  }
+  w.flush().unwrap()
+}
+
+/// The body of [`MizPath::write_aco`], writing to an already created file.
+pub(crate) fn write_aco_to(file: File, sig: &SigBuilder, constrs: &Constructors) {
+  let mut w = MizWriter::new(file);
+  w.xsl();
+  w.with0("Constructors", |w| {
+    w.with0("SignatureWithCounts", |w| {
+      for (i, &(art, _)) in sig.sig.enum_iter() {
+        let hi = sig.hi(i);
+        let name = art.as_str().to_ascii_uppercase();
+        w.with("ConstrCounts", |e| e.attr_str(b"name", name), |w| {
+          w.write_constr_count(b'M', hi.mode);
+          w.write_constr_count(b'L', hi.struct_mode);
+          w.write_constr_count(b'V', hi.attribute);
+          w.write_constr_count(b'R', hi.predicate);
+          w.write_constr_count(b'K', hi.functor);
+          w.write_constr_count(b'U', hi.selector);
+          w.write_constr_count(b'G', hi.aggregate);
+        })
+      }
+    });
+    w.write_constructors(constrs, SigConstructors(sig))
+  });
+  w.finish()
+}
+
 enum MizWriterPart {
   Empty(MizPath),
   Ready(MizWriter),
@@ -866,7 +895,7 @@ impl MizWriter {
 
   fn write_term(&mut self, lc: Option<&LocalContext>, tm: &Term) {
     match tm {
-      Term::Numeral(n) => self.with_attr("Num", |w| w.attr_str(b"nr", *n)),
+      Term::Numeral(n) => self.with_attr("Num", |w| w.attr_str(b"nr", n)),
       Term::Locus(n) => self.with_attr("LocusVar", |w| w.attr_str(b"nr", n.0 + 1)),
       Term::Bound(n) => {
         let n = n.0 + self.lift;
@@ -1001,6 +1030,25 @@ trait ForeachConstructor: Copy {
   );
 }
 
+/// Numbers the constructors by the articles of a signature, as in `.atr` and `.aco`
+#[derive(Clone, Copy)]
+struct SigConstructors<'a>(&'a SigBuilder);
+impl ForeachConstructor for SigConstructors<'_> {
+  fn foreach<I: Idx, T>(
+    self, arr: &IdxVec<I, T>, base: impl Fn(&ConstructorsBase) -> u32,
+    mut body: impl FnMut(&[u8], u32, u32, &T),
+  ) {
+    for (i, &(art, ref lo)) in self.0.sig.enum_iter() {
+      let art = art.as_str().to_ascii_uppercase();
+      let art = art.as_bytes();
+      let lo = base(lo);
+      for j in lo..base(self.0.hi(i)) {
+        body(art, j - lo, j, &arr.0[j as usize])
+      }
+    }
+  }
+}
+
 pub struct OWriteXml(Option<Box<WriteXml>>);
 
 impl MizPath {
@@ -1901,92 +1949,3 @@ mk_write_constructor! {
   fn write_aggr_constructor(AggrId, Aggregate);
   fn write_sel_constructor(SelId, TyConstructor<SelId>);
 }
-
-pub struct OWriteJson(Option<Box<WriteJson>>);
-
-const MZP_VERSION: u32 = 0;
-
-pub enum JsonState {
-  Start,
-  Main(bool),
-}
-pub struct WriteJson {
-  w: BufWriter<File>,
-  state: JsonState,
-}
-
-impl MizPath {
-  pub fn write_json(&self, write: bool) -> OWriteJson {
-    OWriteJson(write.then(|| {
-      let mut w = BufWriter::new(self.create(true, false, "mzp.json").unwrap());
-      writeln!(w, "{{\"version\":{},", MZP_VERSION).unwrap();
-      Box::new(WriteJson { w, state: JsonState::Start })
-    }))
-  }
-}
-
-impl OWriteJson {
-  #[inline]
-  pub fn on<R: Default>(&mut self, f: impl FnOnce(&mut WriteJson) -> R) -> R {
-    if let Some(w) = &mut self.0 {
-      f(w)
-    } else {
-      R::default()
-    }
-  }
-
-  pub fn finish(&mut self) {
-    if let Some(mut w) = self.0.take() {
-      assert!(matches!(w.state, JsonState::Main(_)));
-      w.w.write_all(b"]}\n").unwrap();
-      w.w.get_mut().flush().unwrap()
-    }
-  }
-}
-
-impl WriteJson {
-  pub fn write_env(&mut self, dirs: &Directives) {
-    assert!(matches!(self.state, JsonState::Start));
-    JsonFormatter.begin_object_key(&mut self.w, true).unwrap();
-    for (kind, arts) in &dirs.0 {
-      if !arts.is_empty() {
-        JsonSerializer::new(&mut self.w).serialize_str(kind.name()).unwrap();
-        JsonFormatter.end_object_key(&mut self.w).unwrap();
-        JsonFormatter.begin_object_value(&mut self.w).unwrap();
-        JsonFormatter.begin_array(&mut self.w).unwrap();
-        let mut first = true;
-        for (pos, art) in arts {
-          JsonFormatter.begin_array_value(&mut self.w, std::mem::take(&mut first)).unwrap();
-          write!(self.w, "{{\"pos\":[{},{}],\"art\":\"{}\"}}", pos.line, pos.col, art).unwrap();
-          JsonFormatter.end_array_value(&mut self.w).unwrap();
-        }
-        JsonFormatter.end_object_value(&mut self.w).unwrap();
-        JsonFormatter.end_array(&mut self.w).unwrap();
-        JsonFormatter.begin_object_key(&mut self.w, false).unwrap();
-        self.w.write_all(b"\n").unwrap();
-      }
-    }
-  }
-
-  pub fn write_articles(&mut self, dirs: &IdxVec<ArticleId, Option<Article>>) {
-    assert!(matches!(self.state, JsonState::Start));
-    JsonFormatter.begin_object_key(&mut self.w, true).unwrap();
-    self.w.write_all(b"\"articles\":").unwrap();
-    dirs.0.serialize(&mut JsonSerializer::new(&mut self.w)).unwrap();
-    JsonFormatter.begin_object_key(&mut self.w, false).unwrap();
-    self.w.write_all(b"\n").unwrap();
-  }
-
-  pub fn start_main(&mut self) {
-    assert!(matches!(self.state, JsonState::Start));
-    self.w.write_all(b"\"body\":[\n\n").unwrap();
-    self.state = JsonState::Main(true);
-  }
-  pub fn write_item(&mut self, item: &crate::ast::Item) {
-    let JsonState::Main(first) = &mut self.state else { unreachable!() };
-    JsonFormatter.begin_array_value(&mut self.w, std::mem::take(first)).unwrap();
-    item.serialize(&mut JsonSerializer::new(&mut self.w)).unwrap();
-    JsonFormatter.end_array_value(&mut self.w).unwrap();
-    self.w.write_all(b"\n\n").unwrap();
-  }
-}
@@ -0,0 +1,909 @@
+//! Runs the checker on small articles in the environment of `tests/common`, for the
+//! debugging options and the behaviors that need a whole article to show.
+mod common;
+
+use common::Env;
+
+/// The speculative runs of `--warn-vacuous` find the contradictory premises without
+/// exporting the problems they fail on, or counting them as failures.
+#[test]
+fn vacuity_probe_writes_nothing() {
+  let env = Env::new("vacuity");
+  env.article("vacuous", &["Of"], "environ
+ vocabularies VACUOUS;
+begin
+reserve x, y for object, X for set;
+definition
+  let x be object;
+  func f x -> object equals x;
+  coherence;
+end;
+theorem x in X & not x in X implies f x = y
+proof
+  assume that A1: x in X and A2: not x in X;
+  thus f x = y by A1, A2;
+end;
+");
+  let tptp = env.path("tptp");
+  let export = format!("--export-unsolved-tptp={}", tptp.display());
+  let out = env.run("vacuous", &["--warn-vacuous", &export]);
+  let stdout = String::from_utf8_lossy(&out.stdout);
+  let stderr = String::from_utf8_lossy(&out.stderr);
+  assert!(out.status.success(), "{stdout}");
+  let warnings = stderr.matches("warning: inference holds without its conclusion").count();
+  assert_eq!(warnings, 1, "{stderr}");
+  assert!(!stdout.contains("failure"), "{stdout}");
+  assert!(!tptp.exists(), "the vacuity probe exported a problem");
+}
+
+/// A statement cited twice is checked once, and the contradictory premises reported by
+/// `--warn-vacuous` name both of its citations (the negated thesis is premise 0).
+#[test]
+fn vacuous_cites_duplicates() {
+  let env = Env::new("vacuous-dup");
+  env.article("vacdup", &[], "environ
+begin
+reserve x, y for object, X for set;
+theorem x in X & not x in X implies x = y
+proof
+  assume that A1: x in X and A2: not x in X;
+  thus x = y by A1, A2, A1;
+end;
+");
+  let out = env.run("vacdup", &["--warn-vacuous"]);
+  let stderr = String::from_utf8_lossy(&out.stderr);
+  assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stdout));
+  let warning = "vacdup.miz:7:14: warning: inference holds without its conclusion, \
+    because these premises are contradictory:\n  x in[1] X (premise 1, 3)\n  \
+    ¬(x in[1] X) (premise 2)\n";
+  assert!(stderr.contains(warning), "{stderr}");
+}
+
+/// `--minimize-failures` drops the premises that the failure does not need, and the
+/// part of the thesis that does not fail, without exporting or counting its own runs.
+#[test]
+fn minimize_failure() {
+  let env = Env::new("minimize");
+  env.article("min", &["Of"], "environ
+ vocabularies MIN;
+begin
+reserve x, y, z for object, X for set;
+definition
+  let x be object;
+  func f x -> object equals x;
+  coherence;
+end;
+theorem x = y & y in X implies f x = x & x = z
+proof
+  assume that A1: x = y and A2: y in X;
+  thus f x = x & x = z by A1, A2;
+end;
+");
+  let tptp = env.path("tptp");
+  let export = format!("--export-unsolved-tptp={}", tptp.display());
+  let stdout = env.stdout("min", &["--minimize-failures", &export]);
+  let line = stdout.lines().find(|line| line.starts_with("{\"minimized\"")).expect(&stdout);
+  let min: serde_json::Value = serde_json::from_str(line).unwrap();
+  assert_eq!(min["of"], 3);
+  assert_eq!(min["premises"], serde_json::json!(["¬(x =[0] z)"]));
+  // only the negated thesis is left
+  assert_eq!(min["premise_ids"], serde_json::json!([0]));
+  assert!(stdout.contains("failure: 1\n"), "{stdout}");
+  let files = std::fs::read_dir(&tptp).unwrap().map(|e| e.unwrap().file_name()).collect::<Vec<_>>();
+  assert_eq!(files, ["min-13-24-1.p"]);
+}
+
+/// `--retry-with-equals` accepts an inference that needs one more unfolding of an equals
+/// definition than Mizar does, counts it as rescued and does not export it.
+#[test]
+fn retry_with_equals() {
+  let env = Env::new("retry");
+  env.article("retry", &["Oa", "Ob", "Oc", "Od"], "environ
+ vocabularies RETRY;
+begin
+reserve x for object;
+definition
+  let x be object;
+  func a x -> object equals x;
+  coherence;
+end;
+definition
+  let x be object;
+  func b x -> object equals a x;
+  coherence;
+end;
+definition
+  let x be object;
+  func c x -> object equals b x;
+  coherence;
+end;
+definition
+  let x be object;
+  func d x -> object equals c x;
+  coherence;
+end;
+theorem d x = x;
+");
+  let stdout = env.stdout("retry", &[]);
+  assert!(stdout.contains("failure: 1\n"), "{stdout}");
+  let tptp = env.path("tptp");
+  let export = format!("--export-unsolved-tptp={}", tptp.display());
+  let stdout = env.stdout("retry", &["--retry-with-equals", &export]);
+  assert!(!stdout.contains("failure"), "{stdout}");
+  assert!(stdout.contains("rescued by retry with equals: 1\n"), "{stdout}");
+  assert!(!tptp.exists(), "a rescued inference was exported");
+}
+
+/// In a diffuse block, `thesis` is the thesis of the enclosing proof, and the outcome of
+/// the steps depends on it. Outside of a proof it is an error, which neither proves
+/// nor refutes anything.
+#[test]
+fn thesis_in_diffuse_block() {
+  let env = Env::new("thesis");
+  env.article("thesis", &[], "environ
+begin
+reserve x, y for object;
+theorem x = y implies y = x
+proof
+  assume A1: x = y;
+  A2: now
+    thus thesis by A1;
+  end;
+  thus thesis by A2;
+end;
+now
+  assume not thesis;
+  thus x = y;
+end;
+");
+  let out = env.run("thesis", &[]);
+  let stdout = String::from_utf8_lossy(&out.stdout);
+  let stderr = String::from_utf8_lossy(&out.stderr);
+  assert!(!stderr.contains("panicked"), "{stderr}");
+  assert_eq!(stderr.matches("'thesis' used outside of a proof").count(), 1, "{stderr}");
+  assert!(stdout.contains("failure: 1\n"), "{stdout}");
+  assert!(stdout.contains("failed to justify thesis:14:13"), "{stdout}");
+}
+
+/// `--dump-failures-dir` writes the state of the checker at a failing inference, and
+/// `--replay` checks it again without the environment, with the same outcome. A replay
+/// that fails writes the same blob again.
+#[test]
+fn dump_and_replay_failure() {
+  let env = Env::new("replay");
+  env.article("replay", &["Of"], "environ
+ vocabularies REPLAY;
+begin
+reserve x, y for object;
+definition
+  let x be object;
+  func f x -> object equals x;
+  coherence;
+end;
+theorem f x = x;
+theorem f x = y;
+");
+  let dir = env.path("blobs");
+  let stdout = env.stdout("replay", &[&format!("--dump-failures-dir={}", dir.display())]);
+  assert!(stdout.contains("failed to justify replay:11:16.0"), "{stdout}");
+  let files = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name()).collect::<Vec<_>>();
+  assert_eq!(files, ["replay-11-16.json"]);
+  let blob = dir.join("replay-11-16.json");
+  let replay = |blob: &std::path::Path, args: &[&str]| {
+    let mut cmd = std::process::Command::new(env!("CARGO_BIN_EXE_mizar-rs"));
+    cmd.current_dir(std::env::temp_dir()).env("MIZFILES", "/nonexistent");
+    cmd.arg(format!("--replay={}", blob.display())).arg("--panic-on-fail=false");
+    cmd.args(args).output().unwrap()
+  };
+  let dir2 = env.path("blobs2");
+  let out = replay(&blob, &[&format!("--dump-failures-dir={}", dir2.display())]);
+  let stdout = String::from_utf8_lossy(&out.stdout);
+  assert!(!out.status.success());
+  assert!(stdout.contains("failed to justify replay:11:16.0"), "{stdout}");
+  assert!(stdout.contains("failure: 1\n"), "{stdout}");
+  let text = std::fs::read_to_string(&blob).unwrap();
+  assert_eq!(std::fs::read_to_string(dir2.join("replay-11-16.json")).unwrap(), text);
+
+  let old = env.path("old.json");
+  std::fs::write(&old, text.replacen("{\"version\":1,", "{\"version\":0,", 1)).unwrap();
+  let out = replay(&old, &[]);
+  let stdout = String::from_utf8_lossy(&out.stdout);
+  assert!(!out.status.success());
+  assert!(stdout.contains("blob version 0, but this build reads 1"), "{stdout}");
+}
+
+/// The expansions of the closed premises are cached from one inference to the next, and
+/// dropped when the article registers a cluster, and the inferences after it see it.
+#[test]
+fn session_sees_new_cluster() {
+  let env = Env::new("session");
+  env.article("session", &["Of", "Vsmall"], "environ
+ vocabularies SESSION;
+begin
+reserve x for object;
+definition
+  let x be object;
+  func f x -> object equals x;
+  coherence;
+end;
+definition
+  let x be object;
+  attr x is small means x <> x;
+end;
+theorem T: f x = x;
+theorem f x is small by T;
+theorem f x is small by T;
+registration
+  let x be object;
+  cluster f x -> small;
+  coherence;
+end;
+theorem f x is small by T;
+");
+  let stdout = env.stdout("session", &[]);
+  let failed = stdout.lines().filter(|l| l.starts_with("failed to justify")).collect::<Vec<_>>();
+  let want = ["session:15:22.0", "session:16:22.0", "session:20:12.0"];
+  assert_eq!(failed, want.map(|pos| format!("failed to justify {pos}")), "{stdout}");
+  // the second inference hits the cache for both premises, the last one does not
+  assert!(stdout.contains("expansion cache hit: 2\n"), "{stdout}");
+}
+
+/// `per cases` must justify the disjunction of the case hypotheses, each of which is
+/// assumed in its case, also in nested cases and without a justification. Cases that do
+/// not follow from the premises are rejected, as are the steps that rely on them.
+#[test]
+fn per_cases_obligation() {
+  let env = Env::new("cases");
+  env.article("cases", &[], "environ
+begin
+reserve x, y, z for object;
+theorem x = y or x = z implies y = x or z = x
+proof
+  assume A1: x = y or x = z;
+  per cases by A1;
+  suppose x = y;
+    hence thesis;
+  end;
+  suppose x = z;
+    hence thesis;
+  end;
+end;
+theorem x = y or x = z implies y = x or z = x
+proof
+  assume A1: x = y or x = z;
+  per cases by A1;
+  suppose A2: x = y;
+    per cases;
+    suppose y = z;
+      hence thesis by A2;
+    end;
+    suppose y <> z;
+      thus thesis by A2;
+    end;
+  end;
+  suppose x = z;
+    hence thesis;
+  end;
+end;
+theorem x = y or x = z implies y = x or z = x
+proof
+  assume A1: x = y or x = z;
+  per cases by A1;
+  suppose x <> y;
+    thus thesis;
+  end;
+  suppose x <> z;
+    thus thesis;
+  end;
+end;
+");
+  let stdout = env.stdout("cases", &[]);
+  let failed = stdout.lines().filter(|l| l.starts_with("failed to justify")).collect::<Vec<_>>();
+  let want = ["cases:37:16.0", "cases:40:16.0", "cases:35:13.0"];
+  assert_eq!(failed, want.map(|pos| format!("failed to justify {pos}")), "{stdout}");
+}
+
+/// Private predicates are replaced by their bodies before normalization, so that the
+/// equalizer and the unifier see the same formula: a step that follows from the body
+/// alone is accepted, as is one that only needs the predicate to be equal to itself.
+#[test]
+fn private_predicates_expanded() {
+  let env = Env::new("privpred");
+  env.article("privpred", &[], "environ
+begin
+reserve x, y, z for object;
+defpred P[object, object] means $1 = $2;
+defpred Q[object] means for z being object holds z = $1;
+now
+  x = y implies P[x, y];
+  P[x, y] implies y = x;
+  (for z holds P[z, x]) implies y = x;
+  Q[x] implies Q[x];
+  Q[x] implies x = y;
+  P[x, y] implies P[x, z];
+end;
+");
+  let stdout = env.stdout("privpred", &[]);
+  let failed = stdout.lines().filter(|l| l.starts_with("failed to justify")).collect::<Vec<_>>();
+  assert_eq!(failed, ["failed to justify privpred:12:26.0"], "{stdout}");
+  assert!(stdout.contains("success: 5\n"), "{stdout}");
+}
+
+/// The conditions of `consider` can be cited by label or by `then` in the later steps,
+/// but as in Mizar they are not premises of the steps that do not cite them.
+#[test]
+fn consider_conditions_cited() {
+  let env = Env::new("consider");
+  env.article("consider", &[], "environ
+begin
+reserve x, y for object;
+theorem (ex y st y = x) implies x = x
+proof
+  assume ex y st y = x;
+  then consider y such that A1: y = x;
+  A2: x = y by A1;
+  y = x by A1;
+  then x = y;
+  thus thesis by A2;
+end;
+theorem (ex y st y = x) implies ex y st x = y
+proof
+  assume A1: ex y st y = x;
+  consider y such that A2: y = x by A1;
+  x = y;
+  thus thesis by A2;
+end;
+");
+  let stdout = env.stdout("consider", &[]);
+  let failed = stdout.lines().filter(|l| l.starts_with("failed to justify")).collect::<Vec<_>>();
+  assert_eq!(failed, ["failed to justify consider:17:8.0"], "{stdout}");
+}
+
+/// The obligation of `reconsider` is the whole target type, attributes included, with the
+/// cited premises, and the new constant has that type. An attribute that does not follow
+/// from the references is rejected.
+#[test]
+fn reconsider_checks_attributes() {
+  let env = Env::new("reconsider");
+  env.article("reconsid", &["V-near"], "environ
+ vocabularies RECONSID;
+begin
+reserve x, y for object;
+definition
+  let y, x be object;
+  attr x is y-near means :Def1: x = y;
+end;
+registration
+  let y be object;
+  cluster y-near for object;
+  existence
+  proof
+    take y;
+    thus thesis;
+  end;
+end;
+theorem x = y implies x = x
+proof
+  assume A1: x = y;
+  reconsider z = x as y-near object by A1, Def1;
+  z is y-near;
+  reconsider w = x as y-near object by Def1;
+  thus thesis;
+end;
+");
+  let stdout = env.stdout("reconsid", &[]);
+  let failed = stdout.lines().filter(|l| l.starts_with("failed to justify")).collect::<Vec<_>>();
+  assert_eq!(failed, ["failed to justify reconsid:23:37.0"], "{stdout}");
+}
+
+/// `qua` only selects a notation, and is gone by the time the checker sees the formula,
+/// also under a quantifier that the unifier instantiates. The instance must still have
+/// the type of the bound variable.
+#[test]
+fn qua_in_premise() {
+  let env = Env::new("qua");
+  env.article("qua", &[], "environ
+begin
+reserve x for object, X, Y for set;
+now
+  assume A1: for X being set holds X qua object in Y;
+  X in Y by A1;
+  X qua object in Y by A1;
+  x in Y by A1;
+end;
+");
+  let out = env.run("qua", &[]);
+  let stdout = String::from_utf8_lossy(&out.stdout);
+  let stderr = String::from_utf8_lossy(&out.stderr);
+  assert!(!stderr.contains("panicked"), "{stderr}");
+  let failed = stdout.lines().filter(|l| l.starts_with("failed to justify")).collect::<Vec<_>>();
+  assert_eq!(failed, ["failed to justify qua:8:10.0"], "{stdout}");
+}
+
+/// The type class of a term of a structure type also has the types of its ancestors, so a
+/// conditional cluster registered on the parent structure rounds up a term of the child.
+#[test]
+fn struct_ancestor_round_up() {
+  let env = Env::new("structs");
+  env.article("structs", &["GBase", "GChild", "Ucar", "Ucar2", "Vbig", "Vwide"], "environ
+ vocabularies STRUCTS;
+begin
+definition
+  struct Base (# car -> set #);
+end;
+definition
+  struct (Base) Child (# car, car2 -> set #);
+end;
+definition
+  let S be Base;
+  attr S is big means ex x being object st x in the car of S;
+end;
+definition
+  let S be Base;
+  attr S is wide means ex x being object st x in the car of S & x <> x;
+end;
+:: the coherence fails, but the cluster is registered
+registration
+  cluster big -> wide for Base;
+  coherence;
+end;
+reserve B for Base, C for Child;
+theorem B is big implies B is wide;
+theorem C is big implies C is wide;
+theorem C is big;
+");
+  let stdout = env.stdout("structs", &[]);
+  let failed = stdout.lines().filter(|l| l.starts_with("failed to justify")).collect::<Vec<_>>();
+  let want = ["structs:21:12.0", "structs:26:17.0"];
+  assert_eq!(failed, want.map(|pos| format!("failed to justify {pos}")), "{stdout}");
+}
+
+/// The formulas in the reports show each constructor as its symbol with its number, for
+/// the functors, predicates, attributes and modes.
+#[test]
+fn reports_show_symbol_names() {
+  let env = Env::new("names");
+  env.article("names", &["Of", "Vsmall"], "environ
+ vocabularies NAMES;
+begin
+reserve x, y for object;
+definition
+  let x be object;
+  func f x -> object means it = x;
+  existence;
+  uniqueness;
+end;
+definition
+  let x be object;
+  attr x is small means x <> x;
+end;
+theorem f x = y;
+theorem f x is small;
+");
+  let stdout = env.stdout("names", &["--minimize-failures"]);
+  let premises = (stdout.lines())
+    .filter(|line| line.starts_with("{\"minimized\""))
+    .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["premises"].clone())
+    .collect::<Vec<_>>();
+  let want = [
+    serde_json::json!(["∃ x: object[0], y: object[0] st ¬((f[0] x) =[0] y)"]),
+    serde_json::json!(["∃ x: object[0] st ¬(f[0] x is small[0])"]),
+  ];
+  assert_eq!(premises, want, "{stdout}");
+}
+
+/// The coherence of a functor cluster is checked with the types of its loci, so the
+/// registration in `tests/articles/funcclus.miz` whose coherence needs the type of a locus
+/// is accepted, and the one whose coherence does not hold is not.
+#[test]
+fn functor_cluster_coherence() {
+  let env = Env::new("funcclus");
+  let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/articles/funcclus.miz");
+  env.article("funcclus", &["Og", "V-near"], &std::fs::read_to_string(path).unwrap());
+  let stdout = env.stdout("funcclus", &[]);
+  let failed = stdout.lines().filter(|l| l.starts_with("failed to justify")).collect::<Vec<_>>();
+  assert_eq!(failed, ["failed to justify funcclus:43:13.0"], "{stdout}");
+}
+
+/// The reductions registered in an imported article are read from its `.drd` file, so a
+/// step that needs one is accepted when the article is in the `registrations` directive,
+/// and only then.
+#[test]
+fn imported_reduction() {
+  let env = Env::new("reduction");
+  env.article("reda", &["Of"], "environ
+ vocabularies REDA;
+begin
+definition
+  let x be object;
+  func f x -> object equals x;
+  coherence;
+end;
+registration
+  let x be object;
+  reduce f x to x;
+  reducibility;
+end;
+");
+  env.export("reda");
+  let theorem = "begin
+reserve x for object, X for set;
+theorem x in X implies f x in X;
+";
+  let environ = " vocabularies REDA;\n notations REDA;\n constructors REDA;\n";
+  env.article("redb", &[], &format!("environ\n{environ} registrations REDA;\n{theorem}"));
+  env.article("redc", &[], &format!("environ\n{environ}{theorem}"));
+  let stdout = env.stdout("redb", &[]);
+  assert!(stdout.contains("success: 1\n") && !stdout.contains("failure"), "{stdout}");
+  let stdout = env.stdout("redc", &[]);
+  assert!(stdout.contains("failed to justify redc:7:32.0"), "{stdout}");
+}
+
+/// `--check-definiens` finds nothing wrong with the definientia of the fixture articles,
+/// and reports the definiens of an imported article whose essential loci were corrupted
+/// in `prel/`.
+#[test]
+fn definiens_consistency() {
+  let env = common::fixtures("definiens");
+  for &(art, _) in common::FIXTURES {
+    let out = env.run(art, &["--check-definiens"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(!stdout.contains("inconsistent definiens"), "{art}: {stdout}");
+    assert!(!stderr.contains("inconsistent definiens"), "{art}: {stderr}");
+  }
+  env.article("defa", &["Of"], "environ
+ vocabularies DEFA;
+begin
+definition
+  let x be object;
+  func f x -> object equals x;
+  coherence;
+end;
+");
+  env.export("defa");
+  let def = env.path("prel/d/defa.def");
+  let text = std::fs::read_to_string(&def).unwrap();
+  std::fs::write(&def, text.replacen("<Int x=\"2\"/>\n", "", 1)).unwrap();
+  env.article("defb", &[], "environ
+ vocabularies DEFA;
+ notations DEFA;
+ constructors DEFA;
+ definitions DEFA;
+begin
+reserve x for object;
+theorem f x = x;
+");
+  let out = env.run("defb", &["--check-definiens"]);
+  let stderr = String::from_utf8_lossy(&out.stderr);
+  assert!(!stderr.contains("panicked"), "{stderr}");
+  let want = "error: inconsistent definiens defa:def 0: number of essential loci does not match";
+  assert_eq!(stderr.matches(want).count(), 1, "{stderr}");
+}
+
+/// With `--stdin` the article is read from standard input, in the environment of the
+/// article of that name, and the stats are the same as when it is read from its file.
+#[test]
+fn stdin_matches_file() {
+  use std::io::Write;
+  let env = common::fixtures("stdin");
+  let stats = |stdout: &[u8]| {
+    let stdout = String::from_utf8_lossy(stdout);
+    let stats = stdout.lines().filter(|l| !l.contains(" in ") && l.contains(": "));
+    stats.map(|l| l.to_owned()).collect::<Vec<_>>()
+  };
+  for &(art, _) in common::FIXTURES {
+    let from_file = stats(&env.run(art, &[]).stdout);
+    assert!(from_file.iter().any(|l| l.starts_with("success: ")), "{from_file:?}");
+    let text = std::fs::read(env.path(&format!("mml/{art}.miz"))).unwrap();
+    let mut child = (env.command(art, &["--stdin"]))
+      .stdin(std::process::Stdio::piped())
+      .stdout(std::process::Stdio::piped())
+      .stderr(std::process::Stdio::piped())
+      .spawn()
+      .unwrap();
+    child.stdin.take().unwrap().write_all(&text).unwrap();
+    let from_stdin = stats(&child.wait_with_output().unwrap().stdout);
+    assert_eq!(from_stdin, from_file, "{art}");
+  }
+}
+
+/// A step that fails still adds its conclusion, so the steps that use it are accepted and
+/// the article reports exactly one error; with `--continue-on-error=false` only the
+/// inferences before it are checked.
+#[test]
+fn one_error_per_failed_step() {
+  let env = Env::new("continue");
+  env.article("cont", &["Of"], "environ
+ vocabularies CONT;
+begin
+reserve x, y for object, X for set;
+definition
+  let x be object;
+  func f x -> object equals x;
+  coherence;
+end;
+theorem x in X implies f x = y & y in X
+proof
+  assume A1: x in X;
+  A2: x = y;
+  A3: f x = y by A2;
+  thus f x = y by A3;
+  thus y in X by A1, A2;
+end;
+");
+  let out = env.run("cont", &["--write-err-file"]);
+  let stdout = String::from_utf8_lossy(&out.stdout);
+  assert!(stdout.contains("failure: 1\n") && stdout.contains("success: 4\n"), "{stdout}");
+  let errs = std::fs::read_to_string(env.path("mml/cont.err")).unwrap();
+  assert_eq!(errs.lines().collect::<Vec<_>>(), ["13\t12\t4"], "{errs}");
+  let stdout = env.stdout("cont", &["--continue-on-error=false"]);
+  assert!(stdout.contains("failure: 1\n") && stdout.contains("success: 1\n"), "{stdout}");
+}
+
+/// A 30-digit numeral is checked by its exact value: it equals itself and differs from its
+/// successor and from its value wrapped to 32 bits.
+#[test]
+fn big_numeral() {
+  let env = Env::new("big-numeral");
+  env.article("bignum", &[], "environ
+begin
+reserve x for object;
+theorem 123456789012345678901234567890 = 123456789012345678901234567890;
+theorem x = 123456789012345678901234567890 implies x <> 123456789012345678901234567891;
+theorem x = 123456789012345678901234567890 implies x <> 1312754386;
+theorem x = 123456789012345678901234567890 implies x = 123456789012345678901234567891;
+");
+  let stdout = env.stdout("bignum", &[]);
+  assert!(stdout.contains("success: 3\n") && stdout.contains("failure: 1\n"), "{stdout}");
+  assert!(stdout.contains("failed to justify bignum:7:"), "{stdout}");
+}
+
+/// A panic in the checker is followed by the locations being processed, which name the
+/// item and the inference it happened at.
+#[test]
+fn panic_names_item() {
+  let env = Env::new("panic-location");
+  env.article("panicky", &[], "environ
+begin
+reserve x, y for object;
+theorem x = x;
+theorem x = y;
+");
+  // `Env::command` turns `--panic-on-fail` off
+  let mut cmd = std::process::Command::new(env!("CARGO_BIN_EXE_mizar-rs"));
+  cmd.current_dir(&env.dir).env("MIZFILES", &env.dir);
+  cmd.args(["panicky", "--one-file", "--no-progress", "--skip-to-verbose=false"]);
+  let out = cmd.args(["--item-header=false", "--panic-on-fail"]).output().unwrap();
+  let stderr = String::from_utf8_lossy(&out.stderr);
+  assert!(stderr.contains("failed to justify panicky:5:14"), "{stderr}");
+  assert!(stderr.contains("while processing panicky:5:14\n"), "{stderr}");
+  assert!(stderr.contains("while processing panicky:5:1 (item 2)\n"), "{stderr}");
+}
+
+/// A failed inference names the reserved variables in it with their reserved types, and
+/// only those: `x` is not in the failed inference, and `z` is not reserved.
+#[test]
+fn reserved_names() {
+  let env = Env::new("reserved");
+  env.article("reserved", &[], "environ
+begin
+reserve x, y for object, X for set;
+theorem for z being object holds x in X implies z in X & y in X
+proof
+  let z be object;
+  assume x in X;
+  thus z in X & y in X;
+end;
+");
+  let stdout = env.stdout("reserved", &[]);
+  let failed = "failed to justify reserved:8:23.0\n";
+  let names = "  y (c1) is reserved as object[0]\n  X (c2) is reserved as set[1]\n";
+  assert!(stdout.contains(&format!("{failed}{names}   0:")), "{stdout}");
+}
+
+/// A definitional theorem whose export is corrupted to mention a locus is reported at
+/// the inference citing it, rather than reaching the checker and panicking there.
+#[test]
+fn leaked_locus() {
+  let env = Env::new("leaked-locus");
+  env.article("leaka", &["Of"], "environ
+ vocabularies LEAKA;
+begin
+definition
+  let x be object;
+  func f x -> object equals x;
+  coherence;
+end;
+");
+  env.export("leaka");
+  let the = env.path("prel/l/leaka.the");
+  let text = std::fs::read_to_string(&the).unwrap();
+  let corrupt = text.replacen("</Func>\n<Var nr=\"1\"/>", "</Func>\n<LocusVar nr=\"1\"/>", 1);
+  assert_ne!(text, corrupt);
+  std::fs::write(&the, corrupt).unwrap();
+  env.article("leakb", &[], "environ
+ vocabularies LEAKA;
+ notations LEAKA;
+ constructors LEAKA;
+ theorems LEAKA;
+begin
+theorem for x being object holds f x = x by LEAKA:def 1;
+");
+  let out = env.run("leakb", &[]);
+  let stderr = String::from_utf8_lossy(&out.stderr);
+  assert!(!stderr.contains("panicked"), "{stderr}");
+  let want = "leakb.miz:7:42: error: this inference mentions a locus outside of a definition";
+  assert!(stderr.contains(want), "{stderr}");
+}
+
+/// An `assume` or `thus` that does not match the thesis is reported with the classic code
+/// and both formulas, and leaves the thesis as it was, so that the steps after it are
+/// checked against it.
+#[test]
+fn skeleton_mismatch() {
+  let env = Env::new("skeleton");
+  env.article("skel", &[], "environ
+begin
+reserve x, y for object, X for set;
+theorem x in X implies x in X
+proof
+  assume A1: x = x;
+  assume A2: x in X;
+  thus x in X by A2;
+end;
+theorem x in X & x = y implies x in X
+proof
+  thus x = y;
+  assume A1: x in X & x = y;
+  thus x in X by A1;
+end;
+");
+  let out = env.run("skel", &["--write-err-file"]);
+  let stdout = String::from_utf8_lossy(&out.stdout);
+  let stderr = String::from_utf8_lossy(&out.stderr);
+  assert!(!stderr.contains("panicked"), "{stderr}");
+  let assumption = "skel.miz:6:3: error: invalid assumption: got x =[0] x, expected x in[1] X\n";
+  assert!(stderr.contains(assumption), "{stderr}");
+  let conclusion = "skel.miz:12:13: error: invalid conclusion: got x =[0] y, \
+    expected (x in[1] X) ∧ (x =[0] y) → (x in[1] X)\n";
+  assert!(stderr.contains(conclusion), "{stderr}");
+  // the steps after the mismatches are accepted, `thus x = y` itself is not
+  assert!(stdout.contains("success: 2\n") && stdout.contains("failure: 1\n"), "{stdout}");
+  let err = std::fs::read_to_string(env.path("mml/skel.err")).unwrap();
+  assert_eq!(err, "6\t3\t52\n12\t13\t4\n12\t13\t51\n");
+}
+
+/// A constructor whose properties refer to an argument it does not have is rejected when
+/// the environment is read, and the article is not processed against what is left.
+#[test]
+fn bad_property_args() {
+  let env = Env::new("bad-property-args");
+  env.article("propa", &["R~"], "environ
+ vocabularies PROPA;
+begin
+definition
+  let x, y be object;
+  pred x ~ y means x = y;
+  symmetry;
+end;
+");
+  env.export("propa");
+  let dco = env.path("prel/p/propa.dco");
+  let text = std::fs::read_to_string(&dco).unwrap();
+  let corrupt = text.replacen("propertyarg2=\"2\"", "propertyarg2=\"3\"", 1);
+  assert_ne!(text, corrupt);
+  std::fs::write(&dco, corrupt).unwrap();
+  env.article("propb", &[], "environ
+ vocabularies PROPA;
+ notations PROPA;
+ constructors PROPA;
+begin
+reserve x, y for object;
+theorem x ~ y implies y ~ x;
+");
+  let out = env.run("propb", &[]);
+  let stderr = String::from_utf8_lossy(&out.stderr);
+  assert!(!stderr.contains("panicked"), "{stderr}");
+  let want = "propa.dco:19:11: error: properties {Symmetry} use arguments 1 and 3, \
+    but the constructor has 2\n";
+  assert!(stderr.contains(want), "{stderr}");
+  assert!(!out.status.success());
+}
+
+/// `--warn-directives` reports a `constructors` directive already brought in by an earlier
+/// one, and the notations dropped because their article is missing from `constructors`.
+/// Without the option neither is reported.
+#[test]
+fn warn_directives() {
+  let env = Env::new("warn-directives");
+  env.article("dira", &["MThing"], "environ
+ vocabularies DIRA;
+begin
+definition
+  mode Thing -> object means it = it;
+  existence;
+end;
+");
+  env.export("dira");
+  env.article("dirb", &["Og"], "environ
+ vocabularies DIRA, DIRB;
+ notations DIRA;
+ constructors DIRA;
+begin
+definition
+  let x be Thing;
+  func g x -> object equals x;
+  coherence;
+end;
+");
+  env.export("dirb");
+  env.article("dirc", &["Oh"], "environ
+ vocabularies DIRC;
+begin
+definition
+  let x be object;
+  func h x -> object equals x;
+  coherence;
+end;
+");
+  env.export("dirc");
+  // the constructors of DIRB use DIRA, and DIRC is missing
+  env.article("dird", &[], "environ
+ vocabularies DIRA, DIRB, DIRC;
+ notations DIRA, DIRB, DIRC;
+ constructors DIRB, DIRA;
+begin
+reserve x for Thing;
+theorem g x = g x;
+");
+  let out = env.run("dird", &["--warn-directives"]);
+  let stderr = String::from_utf8_lossy(&out.stderr);
+  assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stdout));
+  let warnings = stderr.lines().filter(|line| line.contains("warning")).collect::<Vec<_>>();
+  assert_eq!(warnings.len(), 2, "{stderr}");
+  let redundant = "dird.miz:4:21: warning: constructors of dira are already included by \
+    earlier directives";
+  assert!(warnings[0].ends_with(redundant), "{stderr}");
+  let dropped = "dird.miz:3:24: warning: 1 notations of dirc are not available, because the \
+    constructors directive is missing dirc";
+  assert!(warnings[1].ends_with(dropped), "{stderr}");
+  let quiet = env.run("dird", &[]);
+  assert!(!String::from_utf8_lossy(&quiet.stderr).contains("warning"));
+}
+
+/// `--analyze-only` lists the theorems and definitional theorems with their names, counting
+/// canceled ones, and a definition is available to the statements after it, while proofs
+/// are not checked.
+#[test]
+fn analyze_only() {
+  let env = Env::new("analyze-only");
+  env.article("anal", &["Oh"], "environ
+ vocabularies ANAL;
+begin
+reserve x, y for object;
+theorem x = y implies y = x;
+definition
+  let x be object;
+  func h x -> object equals x;
+  coherence;
+end;
+theorem h x = x
+proof
+  thus contradiction;
+end;
+");
+  let out = env.run("anal", &["--analyze-only"]);
+  let stdout = String::from_utf8_lossy(&out.stdout);
+  let stderr = String::from_utf8_lossy(&out.stderr);
+  assert!(out.status.success() && !stderr.contains("error"), "{stdout}{stderr}");
+  let items = stdout
+    .lines()
+    .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+    .map(|item| (item["name"].as_str().unwrap().to_owned(), item["line"].as_u64().unwrap()))
+    .collect::<Vec<_>>();
+  assert_eq!(items, [("Th1".into(), 5), ("Def1".into(), 8), ("Th2".into(), 11)], "{stdout}");
+  let first = "\"statement\":\"∀ x: object[0], y: object[0] holds (x =[0] y) → (y =[0] x)\"";
+  assert!(stdout.lines().next().unwrap().contains(first), "{stdout}");
+  assert!(stdout.contains("\"statement\":\"∀ x: object[0] holds (h[0] x) =[0] x\""), "{stdout}");
+}
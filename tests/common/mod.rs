@@ -0,0 +1,117 @@
+//! A small Mizar environment for the integration tests, so that they do not need the MML.
+//! `tests/mizshare` has hand-written `prel/` files for HIDDEN, with the modes `object`
+//! and `set` and the predicates `=`, `<>` and `in`, and the `mml.vct` to go with them.
+//! Each test copies it to a fresh directory, adds its articles to `mml/` (with their
+//! vocabularies in `<article>.vct`) and runs the verifier there.
+#![allow(dead_code)]
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+pub struct Env {
+  pub dir: PathBuf,
+}
+
+impl Env {
+  /// A copy of `tests/mizshare` with no articles, unique to the test `name`.
+  pub fn new(name: &str) -> Env {
+    let dir = std::env::temp_dir().join(format!("mizar-rs-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    copy_dir(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/mizshare"), &dir);
+    std::fs::write(dir.join("mml.lar"), "").unwrap();
+    Env { dir }
+  }
+
+  /// Adds the article `art` with the text `miz`. `symbols` is its vocabulary, one symbol
+  /// per entry as in `mml.vct` (e.g. `"Of"` for the functor symbol `f`), and is available
+  /// as `vocabularies ART`.
+  pub fn article(&self, art: &str, symbols: &[&str], miz: &str) -> &Self {
+    let mml = self.dir.join("mml");
+    std::fs::create_dir_all(&mml).unwrap();
+    std::fs::write(mml.join(format!("{art}.miz")), miz).unwrap();
+    let mut vct = format!("#{}\n", art.to_uppercase());
+    for kind in ["G", "K", "L", "M", "O", "R", "U", "V"] {
+      let n = symbols.iter().filter(|s| s.starts_with(kind)).count();
+      vct += &format!("{kind}{n} ")
+    }
+    vct += "\n";
+    for kind in ["G", "K", "L", "M", "O", "R", "U", "V"] {
+      for s in symbols.iter().filter(|s| s.starts_with(kind)) {
+        vct += &format!("{s}\n")
+      }
+    }
+    std::fs::write(mml.join(format!("{art}.vct")), vct).unwrap();
+    let lar = self.dir.join("mml.lar");
+    let mut text = std::fs::read_to_string(&lar).unwrap();
+    text += &format!("{art}\n");
+    std::fs::write(lar, text).unwrap();
+    self
+  }
+
+  /// Exports `art` to `prel/` and adds its vocabulary to `mml.vct`, so that the articles
+  /// after it can import it in their environment.
+  pub fn export(&self, art: &str) -> &Self {
+    let out = self.run(art, &["-ex", "--overwrite-prel"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stdout));
+    let vct = std::fs::read_to_string(self.dir.join(format!("mml/{art}.vct"))).unwrap();
+    let mut text = std::fs::read_to_string(self.dir.join("mml.vct")).unwrap();
+    text += &vct;
+    std::fs::write(self.dir.join("mml.vct"), text).unwrap();
+    self
+  }
+
+  /// The command running the verifier on `art` alone, with the debugging defaults of
+  /// a debug build turned off so that failures are reported rather than panicking.
+  pub fn command(&self, art: &str, args: &[&str]) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_mizar-rs"));
+    cmd.current_dir(&self.dir).env("MIZFILES", &self.dir);
+    cmd.args([art, "--one-file", "--no-progress"]);
+    cmd.args(["--skip-to-verbose=false", "--panic-on-fail=false", "--item-header=false"]);
+    cmd.args(args);
+    cmd
+  }
+
+  pub fn run(&self, art: &str, args: &[&str]) -> Output {
+    self.command(art, args).output().unwrap()
+  }
+
+  /// Runs the verifier on `art`, returning its standard output.
+  pub fn stdout(&self, art: &str, args: &[&str]) -> String {
+    String::from_utf8_lossy(&self.run(art, args).stdout).into_owned()
+  }
+
+  pub fn path(&self, rel: &str) -> PathBuf { self.dir.join(rel) }
+}
+
+impl Drop for Env {
+  fn drop(&mut self) { let _ = std::fs::remove_dir_all(&self.dir); }
+}
+
+/// The fixture articles in `tests/articles/`, with their vocabularies
+pub const FIXTURES: &[(&str, &[&str])] = &[
+  ("eqfuncs", &["Of", "Og"]),
+  ("condclus", &["Va", "Vb", "Vc", "Vd"]),
+  ("funcclus", &["Og", "V-near"]),
+];
+
+/// An `Env` with all the fixture articles.
+pub fn fixtures(name: &str) -> Env {
+  let env = Env::new(name);
+  let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/articles");
+  for &(art, symbols) in FIXTURES {
+    env.article(art, symbols, &std::fs::read_to_string(dir.join(format!("{art}.miz"))).unwrap());
+  }
+  env
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+  std::fs::create_dir_all(to).unwrap();
+  for entry in std::fs::read_dir(from).unwrap() {
+    let entry = entry.unwrap();
+    let to = to.join(entry.file_name());
+    if entry.file_type().unwrap().is_dir() {
+      copy_dir(&entry.path(), &to)
+    } else {
+      std::fs::copy(entry.path(), to).unwrap();
+    }
+  }
+}
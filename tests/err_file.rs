@@ -0,0 +1,56 @@
+//! Checks the `<article>.err` files of `--write-err-file`, which tools built around the
+//! original Mizar verifier read.
+mod common;
+
+use common::Env;
+
+#[test]
+fn err_file_format() {
+  let env = Env::new("err-file");
+  env.article("errs", &[], "environ
+begin
+reserve x, y, z for object;
+theorem x = y implies y = x;
+theorem x = y implies x = z;
+theorem x = y & y = z implies x = z
+proof
+  assume A1: x = y & y = z;
+  thus x = y by A1;
+  thus z = x;
+end;
+now
+  thus thesis;
+end;
+");
+  let out = env.run("errs", &["--write-err-file"]);
+  assert!(!String::from_utf8_lossy(&out.stderr).contains("panicked"));
+  let err = std::fs::read_to_string(env.path("mml/errs.err")).unwrap();
+  let lines = err.lines().map(|l| l.split('\t').collect::<Vec<_>>()).collect::<Vec<_>>();
+  assert!(lines.iter().all(|l| l.len() == 3), "{err:?}");
+  let errs = lines.iter().map(|l| l.iter().map(|f| f.parse::<u32>().unwrap()).collect());
+  let errs = errs.collect::<Vec<Vec<_>>>();
+  assert!(errs.is_sorted(), "{err:?}");
+  assert_eq!(errs, [vec![5, 28, 4], vec![9, 14, 51], vec![10, 13, 4], vec![13, 8, 9999]]);
+  let txt = std::fs::read_to_string(env.path("mml/errs.err.txt")).unwrap();
+  assert_eq!(txt, "13\t8\t'thesis' used outside of a proof\n");
+}
+
+/// An inference given up for lack of time is not reported as a wrong one.
+#[test]
+fn err_file_timeouts() {
+  let env = Env::new("err-file-timeouts");
+  env.article("slow", &["Of"], "environ
+ vocabularies SLOW;
+begin
+reserve x for object;
+definition
+  let x be object;
+  func f x -> object equals x;
+  coherence;
+end;
+theorem f x = x;
+");
+  env.run("slow", &["--write-err-file", "--equate-budget-ms=0"]);
+  let err = std::fs::read_to_string(env.path("mml/slow.err")).unwrap();
+  assert_eq!(err, "8\t12\t9998\n10\t16\t9998\n");
+}
@@ -0,0 +1,57 @@
+//! Runs the reader in this process, on the fixture articles of `tests/common`, for the
+//! library interfaces that the command line tool does not show.
+mod common;
+
+use mizar_rs::analyze::ProgressSink;
+use mizar_rs::ast::Item;
+use mizar_rs::reader::ArticleSource;
+use mizar_rs::types::Article;
+use mizar_rs::{Config, MizPath};
+use std::time::Duration;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Event {
+  Start(usize),
+  Done(usize, bool),
+  Article(usize, bool),
+}
+
+#[derive(Default)]
+struct Recording(Vec<Event>);
+
+impl ProgressSink for Recording {
+  fn on_item_start(&mut self, idx: usize, _: &Item) { self.0.push(Event::Start(idx)) }
+
+  fn on_item_done(&mut self, idx: usize, failed: bool, _: Duration) {
+    self.0.push(Event::Done(idx, failed))
+  }
+
+  fn on_article_done(&mut self, _: Article, items: usize, has_errors: bool) {
+    self.0.push(Event::Article(items, has_errors))
+  }
+}
+
+/// The callbacks of a `ProgressSink` fire once for each top level item, in order, and only
+/// the item with the theorem that does not hold fails.
+#[test]
+fn progress_callbacks() {
+  let env = common::fixtures("progress");
+  std::env::set_var("MIZFILES", &env.dir);
+  let mml_vct = std::fs::read(env.path("mml.vct")).unwrap();
+  let path = MizPath::new("eqfuncs").unwrap();
+  let mut sink = Recording::default();
+  let cfg = Config::default();
+  path
+    .with_reader(&cfg, None, &mml_vct, ArticleSource::File, &mut |v, p| {
+      v.run_analyzer(&path, p, &mut sink)
+    })
+    .unwrap();
+  // the reservation, the two definitions and the seven theorems
+  let items = 10;
+  let mut want = vec![];
+  for idx in 0..items {
+    want.extend([Event::Start(idx), Event::Done(idx, idx == items - 1)])
+  }
+  want.push(Event::Article(items, true));
+  assert_eq!(sink.0, want);
+}
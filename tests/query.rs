@@ -0,0 +1,53 @@
+//! Runs `--dump=environment` on an article importing the `funcclus` fixture, for the
+//! entries of `src/query.rs` as a tool would read them.
+mod common;
+
+use serde_json::{json, Value};
+
+fn entries(stdout: &str) -> Vec<Value> {
+  stdout.lines().filter(|l| l.starts_with('{')).map(|l| serde_json::from_str(l).unwrap()).collect()
+}
+
+#[test]
+fn dump_environment() {
+  let env = common::fixtures("query");
+  env.export("funcclus");
+  env.article("useclus", &[], "environ
+ vocabularies FUNCCLUS;
+ notations FUNCCLUS;
+ constructors FUNCCLUS;
+ registrations FUNCCLUS;
+begin
+");
+  let stdout = env.stdout("useclus", &["--dump=environment"]);
+  let all = entries(&stdout);
+  // `=` and `<>` are two notations for the same predicate, with 2 arguments
+  let pred = |sym, pos| {
+    json!({"entry": "notation", "kind": "pred", "nr": 0, "symbol": sym,
+      "args": 2, "visible": 2, "pos": pos})
+  };
+  let constr = |kind, syms| {
+    json!({"entry": "constructor", "kind": kind, "nr": 0, "args": 2,
+      "superfluous": 0, "redefines": null, "symbols": syms})
+  };
+  for want in [
+    pred("=", true),
+    pred("<>", false),
+    constr("pred", json!(["=", "<>"])),
+    constr("attr", json!(["-near"])),
+    json!({"entry": "cluster", "kind": "registered", "args": 1,
+      "antecedent": [], "consequent": [[0, true]]}),
+  ] {
+    assert!(all.contains(&want), "{want} not in\n{stdout}");
+  }
+  let functor = all.iter().filter(|e| e["kind"] == "functor");
+  assert_eq!(functor.count(), 2, "{stdout}");
+
+  // the filter keeps the notation and the constructor of `g`, and nothing else
+  let stdout = env.stdout("useclus", &["--dump=environment", "--dump-filter=g"]);
+  assert_eq!(entries(&stdout), [
+    json!({"entry": "notation", "kind": "func", "nr": 0, "symbol": "g",
+      "args": 2, "visible": 2, "pos": true}),
+    constr("func", json!(["g"])),
+  ]);
+}
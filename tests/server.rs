@@ -0,0 +1,84 @@
+//! In `--server` mode standard output carries only the responses, one JSON object per
+//! line, so that a client can read them without being confused by diagnostics.
+mod common;
+use common::Env;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Sends `requests` to a server running in `env`, and returns its responses. As in
+/// `Env::command`, failures are reported rather than panicking.
+fn serve(env: &Env, requests: &str) -> Vec<Value> {
+  let mut child = Command::new(env!("CARGO_BIN_EXE_mizar-rs"))
+    .args(["--server", "--skip-to-verbose=false", "--panic-on-fail=false"])
+    .env("MIZFILES", &env.dir)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .unwrap();
+  child.stdin.take().unwrap().write_all(requests.as_bytes()).unwrap();
+  let out = child.wait_with_output().unwrap();
+  let stdout = String::from_utf8(out.stdout).unwrap();
+  (stdout.lines())
+    .map(|line| {
+      serde_json::from_str(line).unwrap_or_else(|e| panic!("not a JSON response ({e}): {line}"))
+    })
+    .collect()
+}
+
+#[test]
+fn stdout_has_only_responses() {
+  // every request fails, since there is no such article
+  let requests = "{\"id\": 1, \"article\": \"nosuchar\"}\nnot json\n\
+    {\"id\": 3, \"article\": \"nosuchar\", \"text\": \"environ begin reserve x for set;\"}\n";
+  let resps = serve(&Env::new("server"), requests);
+  let ids = resps.into_iter().map(|resp| resp["id"].clone()).collect::<Vec<_>>();
+  assert_eq!(ids, [json!(1), Value::Null, json!(3)]);
+}
+
+/// A `problem` request checks a failure blob again: the one of `tests/blobs` is still
+/// not accepted. A request with no blob at the path is answered with an error.
+#[test]
+fn check_problem() {
+  let blob = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/blobs/blobenv.json");
+  let requests = [
+    json!({ "id": 1, "problem": blob }),
+    json!({ "id": 2, "problem": "nosuchblob.json" }),
+    json!({ "id": 3, "article": "nosuchar", "problem": blob }),
+  ];
+  let requests = requests.map(|req| format!("{req}\n")).concat();
+  let resps = serve(&Env::new("server-problem"), &requests);
+  assert_eq!(resps[0], json!({ "id": 1, "ok": false }));
+  assert_eq!(resps[1]["id"], json!(2));
+  assert!(resps[1]["error"].as_str().unwrap().contains("nosuchblob.json"), "{}", resps[1]);
+  assert_eq!(resps[2], json!({ "id": 3, "error": "a request has either an article or a problem" }));
+}
+
+/// The columns of the errors are in bytes from 1 as in the `.err` file, unless the request
+/// asks for UTF-16 columns from 0 as in LSP, where the multi-byte characters of the symbol
+/// `∘𝔸` before the error count as 1 and 2 code units.
+#[test]
+fn utf16_columns() {
+  let env = Env::new("server-utf16");
+  env.article("utfcol", &["O∘𝔸"], "");
+  let text = "environ
+ vocabularies UTFCOL;
+begin
+reserve x, y for object;
+definition
+  let x, y be object;
+  func x ∘𝔸 y -> object equals x;
+  coherence;
+end;
+theorem x ∘𝔸 y = y;
+";
+  let requests = [
+    json!({ "id": 1, "article": "utfcol", "text": text }),
+    json!({ "id": 2, "article": "utfcol", "text": text, "utf16": true }),
+  ];
+  let requests = requests.map(|req| format!("{req}\n")).concat();
+  let resps = serve(&env, &requests);
+  assert_eq!(resps[0], json!({ "id": 1, "ok": false, "errors": [[10, 24, 4]] }));
+  assert_eq!(resps[1], json!({ "id": 2, "ok": false, "errors": [[9, 19, 4]] }));
+}
@@ -0,0 +1,237 @@
+//! Runs the checker on the articles that have a snapshot in `tests/snapshots/`, and checks
+//! that every inference has the recorded outcome. The fixture articles in `tests/articles/`
+//! are checked in the environment of `tests/common`. The MML articles are read from
+//! `miz/mizshare`, so their tests are ignored unless run with `cargo test -- --ignored`
+//! after downloading the MML (see `download-mml.sh`). Set `MIZAR_BLESS=1` to write the
+//! snapshots anew.
+mod common;
+
+use common::{fixtures, FIXTURES};
+use std::path::Path;
+use std::process::{Command, Output};
+
+const ARTICLES: &[&str] = &["xboole_0"];
+
+fn mml_command(art: &str, args: &[&str]) -> Command {
+  let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+  let miz = root.join(format!("miz/mizshare/mml/{art}.miz"));
+  assert!(miz.exists(), "{} not found, run ./download-mml.sh first", miz.display());
+  let mut cmd = Command::new(env!("CARGO_BIN_EXE_mizar-rs"));
+  cmd.current_dir(root).args([art, "--one-file", "--no-progress"]).args(args);
+  cmd
+}
+
+fn stdout(art: &str, out: &Output) -> String {
+  let stderr = String::from_utf8_lossy(&out.stderr);
+  assert!(!stderr.contains("panicked"), "{art} panicked:\n{stderr}");
+  String::from_utf8_lossy(&out.stdout).into_owned()
+}
+
+/// The outcomes recorded in `<dir>/<art>.json`, in order
+fn outcomes(dir: &Path, art: &str) -> Vec<serde_json::Value> {
+  let text = std::fs::read_to_string(dir.join(format!("{art}.json"))).unwrap();
+  text.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+fn check_snapshot(art: &str, mut cmd: Command) {
+  let snapshots = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+  cmd.arg(format!("--snapshot={}", snapshots.display()));
+  let stdout = stdout(art, &cmd.output().unwrap());
+  assert!(!stdout.contains("snapshot mismatch"), "{stdout}");
+}
+
+#[test]
+fn snapshots() {
+  let env = fixtures("snapshots");
+  for &(art, _) in FIXTURES {
+    check_snapshot(art, env.command(art, &[]))
+  }
+}
+
+#[test]
+#[ignore = "needs the MML in miz/mizshare"]
+fn mml_snapshots() {
+  for art in ARTICLES {
+    check_snapshot(art, mml_command(art, &[]))
+  }
+}
+
+/// A snapshot that was never written is a mismatch, rather than one to be written.
+#[test]
+fn missing_snapshot() {
+  let env = fixtures("missing-snapshot");
+  let (art, _) = FIXTURES[0];
+  let dir = env.path("snapshots");
+  let mut cmd = env.command(art, &[&format!("--snapshot={}", dir.display())]);
+  let stdout = stdout(art, &cmd.env_remove("MIZAR_BLESS").output().unwrap());
+  assert!(stdout.contains("no snapshot for"), "{stdout}");
+  assert!(stdout.contains("snapshot mismatch: 1"), "{stdout}");
+  assert!(!dir.join(format!("{art}.json")).exists());
+}
+
+/// The round-up of superclusters is a fixpoint, so the order in which `--shuffle-seed`
+/// tries the clusters (and the premises) must not change which inferences are accepted.
+fn check_shuffled_order(art: &str, tmp: &Path, command: impl Fn(&[&str]) -> Command) {
+  let outcomes = [1, 2].map(|seed| {
+    let dir = tmp.join(seed.to_string());
+    let args = [format!("--snapshot={}", dir.display()), format!("--shuffle-seed={seed}")];
+    let mut cmd = command(&args.each_ref().map(|s| s.as_str()));
+    stdout(art, &cmd.env("MIZAR_BLESS", "1").output().unwrap());
+    (outcomes(&dir, art).into_iter())
+      .map(|o| (o["line"].as_u64(), o["col"].as_u64(), o["ok"].as_bool()))
+      .collect::<Vec<_>>()
+  });
+  assert_eq!(outcomes[0], outcomes[1], "{art}: the outcomes depend on the order");
+}
+
+#[test]
+fn shuffled_order() {
+  let env = fixtures("shuffle");
+  for &(art, _) in FIXTURES {
+    check_shuffled_order(art, &env.path("shuffle"), |args| env.command(art, args))
+  }
+}
+
+#[test]
+#[ignore = "needs the MML in miz/mizshare"]
+fn mml_shuffled_order() {
+  let tmp = std::env::temp_dir().join(format!("mizar-rs-shuffle-{}", std::process::id()));
+  for art in ARTICLES {
+    check_shuffled_order(art, &tmp, |args| mml_command(art, args))
+  }
+  let _ = std::fs::remove_dir_all(tmp);
+}
+
+/// `--share-cluster-antecedents` checks groups of conditional clusters together, but
+/// accepts and rejects the same inferences as checking them one by one.
+#[test]
+fn shared_antecedents() {
+  let env = fixtures("shared");
+  for &(art, _) in FIXTURES {
+    let tmp = env.path("shared");
+    let outcomes = [&[][..], &["--share-cluster-antecedents"]].map(|args| {
+      let mut cmd = env.command(art, &[args, &[&format!("--snapshot={}", tmp.display())]].concat());
+      stdout(art, &cmd.env("MIZAR_BLESS", "1").output().unwrap());
+      let outcomes = outcomes(&tmp, art);
+      std::fs::remove_dir_all(&tmp).unwrap();
+      (outcomes.iter())
+        .map(|o| (o["line"].as_u64(), o["col"].as_u64(), o["ok"].as_bool()))
+        .collect::<Vec<_>>()
+    });
+    assert_eq!(outcomes[0], outcomes[1], "{art}: sharing the antecedents changed the outcomes");
+  }
+  let stdout = env.stdout("condclus", &["--share-cluster-antecedents"]);
+  assert!(stdout.contains("shared cluster antecedent: 9\n"), "{stdout}");
+}
+
+/// Skipping the classes with no type of the head of a variable's type does not change the
+/// outcomes. `uprune` has a class that is skipped, in an accepted and in a failing inference.
+#[test]
+fn head_pruning() {
+  let env = fixtures("head-pruning");
+  env.article("uprune", &["MThing"], "environ
+ vocabularies UPRUNE;
+begin
+definition
+  mode Thing -> object means it = it;
+  existence proof
+    set y = the object;
+    take y;
+    thus thesis;
+  end;
+end;
+reserve x for object, X for set, t for Thing;
+theorem (for t being Thing holds t in X) implies t in X;
+theorem (for t being Thing holds t in X) implies x in X;
+");
+  let arts = FIXTURES.iter().map(|&(art, _)| art).chain(["uprune"]);
+  for art in arts {
+    let tmp = env.path("pruning");
+    let outcomes = ["true", "false"].map(|pruning| {
+      let snapshot = format!("--snapshot={}", tmp.display());
+      let mut cmd = env.command(art, &[&format!("--unify-head-pruning={pruning}"), &snapshot]);
+      stdout(art, &cmd.env("MIZAR_BLESS", "1").output().unwrap());
+      let outcomes = outcomes(&tmp, art);
+      std::fs::remove_dir_all(&tmp).unwrap();
+      (outcomes.iter())
+        .map(|o| (o["line"].as_u64(), o["col"].as_u64(), o["ok"].as_bool()))
+        .collect::<Vec<_>>()
+    });
+    assert_eq!(outcomes[0], outcomes[1], "{art}: pruning by heads changed the outcomes");
+  }
+  let stdout = env.stdout("uprune", &[]);
+  assert!(stdout.contains("eq class types pruned: 1\n"), "{stdout}");
+  assert!(stdout.contains("success: 2\n") && stdout.contains("failure: 1\n"), "{stdout}");
+}
+
+/// Sharing the instantiations of the free variables between the subproblems of the unifier
+/// keeps the outcomes, and finds more of them in the cache.
+#[test]
+fn shared_fvar_cache() {
+  let env = fixtures("shared-fvar-cache");
+  env.article("ushare", &[], "environ
+begin
+reserve x, y, z for object, X, Y for set;
+theorem (for y holds (y in X implies y in Y) & (y in Y implies y = x)) & z in X
+  implies x = z;
+theorem (for y holds (y in X implies y in Y) & (y in Y implies y = x)) & z in X
+  implies z in Y;
+");
+  let mut hits = [0; 2];
+  for art in FIXTURES.iter().map(|&(art, _)| art).chain(["ushare"]) {
+    let tmp = env.path("shared");
+    let outcomes = [(0, "true"), (1, "false")].map(|(i, share)| {
+      let snapshot = format!("--snapshot={}", tmp.display());
+      let mut cmd = env.command(art, &[&format!("--unify-shared-cache={share}"), &snapshot]);
+      let stdout = stdout(art, &cmd.env("MIZAR_BLESS", "1").output().unwrap());
+      let hit = stdout.lines().find_map(|line| line.strip_prefix("unify fvar cache hit: "));
+      hits[i] += hit.map_or(0, |n| n.parse::<u32>().unwrap());
+      let outcomes = outcomes(&tmp, art);
+      std::fs::remove_dir_all(&tmp).unwrap();
+      (outcomes.iter())
+        .map(|o| (o["line"].as_u64(), o["col"].as_u64(), o["ok"].as_bool()))
+        .collect::<Vec<_>>()
+    });
+    assert_eq!(outcomes[0], outcomes[1], "{art}: sharing the cache changed the outcomes");
+  }
+  assert!(hits[0] > hits[1], "cache hits with and without sharing: {hits:?}");
+}
+
+/// With budgets of 0 ms the inferences that are otherwise accepted fail with the timeout
+/// status, rather than hanging or panicking.
+fn check_tiny_budgets(art: &str, tmp: &Path, command: impl Fn(&[&str]) -> Command) {
+  let run = |args: &[&str]| {
+    let mut cmd = command(&[args, &[&format!("--snapshot={}", tmp.display())]].concat());
+    stdout(art, &cmd.env("MIZAR_BLESS", "1").output().unwrap());
+    let outcomes = outcomes(tmp, art);
+    std::fs::remove_dir_all(tmp).unwrap();
+    outcomes
+  };
+  let unlimited = run(&[]);
+  for budget in ["--equate-budget-ms=0", "--unify-budget-ms=0"] {
+    let limited = run(&[budget]);
+    assert_eq!(limited.len(), unlimited.len());
+    for (o, base) in limited.iter().zip(&unlimited) {
+      let ok = o["ok"].as_bool().unwrap();
+      let timed_out = o["timed_out"].as_bool() == Some(true);
+      assert!(ok || timed_out || base["ok"] == false, "{art} {budget}: {o}");
+    }
+  }
+}
+
+#[test]
+fn tiny_budgets() {
+  let env = fixtures("budget");
+  for &(art, _) in FIXTURES {
+    check_tiny_budgets(art, &env.path("budget"), |args| env.command(art, args))
+  }
+}
+
+#[test]
+#[ignore = "needs the MML in miz/mizshare"]
+fn mml_tiny_budgets() {
+  let tmp = std::env::temp_dir().join(format!("mizar-rs-budget-{}", std::process::id()));
+  for art in ARTICLES {
+    check_tiny_budgets(art, &tmp, |args| mml_command(art, args))
+  }
+}
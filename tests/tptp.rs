@@ -0,0 +1,35 @@
+//! Checks the problems of `--export-unsolved-tptp` against the golden files in
+//! `tests/tptp/`, one for each failing conjunct of the articles there. Set `MIZAR_BLESS=1`
+//! to write the golden files anew.
+mod common;
+
+use common::Env;
+use std::path::Path;
+
+/// The articles in `tests/tptp/`, with their vocabularies
+const ARTICLES: &[(&str, &[&str])] = &[("tptpfunc", &["Of"]), ("tptpthe", &[])];
+
+#[test]
+fn golden_problems() {
+  let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/tptp");
+  let bless = std::env::var_os("MIZAR_BLESS").is_some();
+  let env = Env::new("tptp");
+  for &(art, symbols) in ARTICLES {
+    let miz = std::fs::read_to_string(golden.join(format!("{art}.miz"))).unwrap();
+    env.article(art, symbols, &miz);
+    let dir = env.path(&format!("tptp-{art}"));
+    let stdout = env.stdout(art, &[&format!("--export-unsolved-tptp={}", dir.display())]);
+    assert!(stdout.contains("failure: 1\n"), "{stdout}");
+    let files = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().path()).collect::<Vec<_>>();
+    assert_eq!(files.len(), 1, "{art}: {files:?}");
+    for file in files {
+      let path = golden.join(file.file_name().unwrap());
+      let got = std::fs::read_to_string(&file).unwrap();
+      if bless {
+        std::fs::write(&path, &got).unwrap()
+      }
+      let want = std::fs::read_to_string(&path).unwrap_or_default();
+      assert_eq!(got, want, "{art}: the problem differs from {}", path.display());
+    }
+  }
+}
@@ -0,0 +1,12 @@
+% tptpfunc:10:47.1
+fof(aux0, axiom, $true).
+fof(aux1, axiom, i0 = c0).
+fof(aux2, axiom, $true).
+fof(aux3, axiom, i1 = c2).
+fof(aux4, axiom, i2 = f0(i0)).
+fof(aux5, axiom, $true).
+fof(aux6, axiom, i3 = c1).
+fof(lit0, axiom, p1(i0,i1)).
+fof(lit1, axiom, i2 = i3).
+fof(lit2, axiom, ~(i0 = i1)).
+fof(goal, conjecture, $false).
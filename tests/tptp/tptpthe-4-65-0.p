@@ -0,0 +1,11 @@
+% tptpthe:4:65.0
+fof(aux0, axiom, $true).
+fof(aux1, axiom, i1 = c0).
+fof(aux2, axiom, $true).
+fof(aux3, axiom, i0 = the1).
+fof(aux4, axiom, $true).
+fof(aux5, axiom, i2 = c1).
+fof(lit0, axiom, i1 = i0).
+fof(lit1, axiom, ~(p1(i1,i2))).
+fof(lit2, axiom, ~(p1(i1,i0))).
+fof(goal, conjecture, $false).